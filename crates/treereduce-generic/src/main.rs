@@ -0,0 +1,256 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use treereduce::grammar::Grammar;
+use treereduce::languages::{self, LanguageTransform, Replacements};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    C,
+    Go,
+    Java,
+    Javascript,
+    Json,
+    Lua,
+    Python,
+    Rust,
+    Souffle,
+    Sql,
+    Toml,
+    Tsx,
+    Typescript,
+    Verilog,
+    Yaml,
+}
+
+impl Language {
+    fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "c" | "h" | "i" => Some(Language::C),
+            "go" => Some(Language::Go),
+            "java" => Some(Language::Java),
+            "cjs" | "js" | "jsx" | "mjs" => Some(Language::Javascript),
+            "json" => Some(Language::Json),
+            "lua" => Some(Language::Lua),
+            "py" | "pyi" => Some(Language::Python),
+            "rs" => Some(Language::Rust),
+            "dl" => Some(Language::Souffle),
+            "sql" => Some(Language::Sql),
+            "toml" => Some(Language::Toml),
+            "cts" | "mts" | "ts" => Some(Language::Typescript),
+            "tsx" => Some(Language::Tsx),
+            "sv" | "svh" | "v" | "vh" => Some(Language::Verilog),
+            "yaml" | "yml" => Some(Language::Yaml),
+            _ => None,
+        }
+    }
+
+    fn transforms(&self) -> Vec<LanguageTransform> {
+        match self {
+            Language::C => languages::c_transforms(),
+            Language::Go => languages::go_transforms(),
+            Language::Java => languages::java_transforms(),
+            Language::Javascript | Language::Tsx | Language::Typescript => {
+                languages::javascript_transforms()
+            }
+            Language::Json | Language::Toml | Language::Yaml => languages::comma_list_transforms(),
+            Language::Python => languages::python_transforms(),
+            Language::Rust => languages::rust_transforms(),
+            Language::Lua | Language::Souffle | Language::Sql | Language::Verilog => Vec::new(),
+        }
+    }
+
+    fn grammar(&self) -> (tree_sitter::Language, Cow<'static, str>, Replacements) {
+        match self {
+            Language::C => (
+                tree_sitter_c::language(),
+                tree_sitter_c::NODE_TYPES.into(),
+                languages::c_replacements(),
+            ),
+            Language::Go => (
+                tree_sitter_go::language(),
+                tree_sitter_go::NODE_TYPES.into(),
+                HashMap::new(),
+            ),
+            Language::Java => (
+                tree_sitter_java::language(),
+                tree_sitter_java::NODE_TYPES.into(),
+                HashMap::new(),
+            ),
+            Language::Javascript => (
+                tree_sitter_javascript::language(),
+                tree_sitter_javascript::NODE_TYPES.into(),
+                languages::javascript_replacements(),
+            ),
+            Language::Json => (
+                tree_sitter_json::language(),
+                tree_sitter_json::NODE_TYPES.into(),
+                HashMap::new(),
+            ),
+            Language::Lua => (
+                tree_sitter_lua::language(),
+                tree_sitter_lua::NODE_TYPES.into(),
+                HashMap::new(),
+            ),
+            Language::Python => (
+                tree_sitter_python::language(),
+                languages::python_node_types(tree_sitter_python::NODE_TYPES).into(),
+                languages::python_replacements(),
+            ),
+            Language::Rust => (
+                tree_sitter_rust::language(),
+                tree_sitter_rust::NODE_TYPES.into(),
+                languages::rust_replacements(),
+            ),
+            Language::Souffle => (
+                tree_sitter_souffle::language(),
+                tree_sitter_souffle::NODE_TYPES.into(),
+                languages::souffle_replacements(),
+            ),
+            Language::Sql => (
+                tree_sitter_sequel::language(),
+                tree_sitter_sequel::NODE_TYPES.into(),
+                languages::sql_replacements(),
+            ),
+            Language::Toml => (
+                tree_sitter_toml::language(),
+                tree_sitter_toml::NODE_TYPES.into(),
+                HashMap::new(),
+            ),
+            Language::Tsx => (
+                tree_sitter_typescript::language_tsx(),
+                tree_sitter_typescript::TSX_NODE_TYPES.into(),
+                languages::javascript_replacements(),
+            ),
+            Language::Typescript => (
+                tree_sitter_typescript::language_typescript(),
+                tree_sitter_typescript::TYPESCRIPT_NODE_TYPES.into(),
+                languages::javascript_replacements(),
+            ),
+            Language::Verilog => (
+                tree_sitter_verilog::language(),
+                tree_sitter_verilog::NODE_TYPES.into(),
+                HashMap::new(),
+            ),
+            Language::Yaml => (
+                tree_sitter_yaml::language(),
+                tree_sitter_yaml::NODE_TYPES.into(),
+                HashMap::new(),
+            ),
+        }
+    }
+}
+
+/// Minimize a program in any supported language
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Language of the input; default is inferred from the file extension
+    #[arg(help_heading = "Grammar options", long, value_name = "LANG")]
+    language: Option<Language>,
+
+    /// Language to use when it can't be inferred from the file extension
+    #[arg(help_heading = "Grammar options", long, value_name = "LANG")]
+    default_language: Option<Language>,
+
+    /// Compiled tree-sitter grammar (shared library), or the name of one of
+    /// the languages above
+    #[arg(
+        help_heading = "Grammar options",
+        long,
+        value_name = "PATH",
+        conflicts_with = "language"
+    )]
+    grammar: Option<PathBuf>,
+
+    /// Name of the language; default is inferred from the library filename
+    #[arg(
+        help_heading = "Grammar options",
+        long,
+        value_name = "NAME",
+        requires = "grammar"
+    )]
+    grammar_name: Option<String>,
+
+    /// Path to node-types.json; default is next to the library
+    #[arg(
+        help_heading = "Grammar options",
+        long,
+        value_name = "PATH",
+        requires = "grammar"
+    )]
+    node_types: Option<PathBuf>,
+
+    #[command(flatten)]
+    args: treereduce::cli::Args,
+}
+
+/// The language named by `--grammar`, if it isn't a file
+fn builtin(args: &Args) -> Option<Language> {
+    let grammar = args.grammar.as_ref().filter(|p| !p.exists())?;
+    Language::from_str(grammar.to_str()?, true).ok()
+}
+
+fn detect(args: &Args) -> Result<Language> {
+    if let Some(lang) = args.language.or_else(|| builtin(args)) {
+        return Ok(lang);
+    }
+    let detected = args
+        .args
+        .source
+        .as_ref()
+        .and_then(|s| Path::new(s).extension())
+        .and_then(|e| e.to_str())
+        .and_then(Language::from_extension);
+    match detected.or(args.default_language) {
+        Some(lang) => Ok(lang),
+        None => bail!("Couldn't detect the input language, try --language or --default-language"),
+    }
+}
+
+/// The transforms for a grammar loaded with `--grammar`
+fn grammar_transforms(grammar: &Grammar) -> Vec<LanguageTransform> {
+    match grammar.name.as_str() {
+        "cpp" => languages::cpp_transforms(),
+        name => Language::from_str(name, true)
+            .map(|l| l.transforms())
+            .unwrap_or_default(),
+    }
+}
+
+/// The flags of every language's transforms, since the language isn't known
+/// until after parsing
+fn all_transforms() -> Vec<LanguageTransform> {
+    let mut all = languages::cpp_transforms();
+    for lang in Language::value_variants() {
+        all.extend(lang.transforms());
+    }
+    all
+}
+
+fn main() -> Result<()> {
+    let (args, matches): (Args, _) = treereduce::cli::parse_args_with(&all_transforms())?;
+    if let Some(path) = args.grammar.as_ref().filter(|_| builtin(&args).is_none()) {
+        let grammar = Grammar::load(
+            path,
+            args.grammar_name.as_deref(),
+            args.node_types.as_deref(),
+        )
+        .with_context(|| format!("Failed to load grammar {}", path.display()))?;
+        let transforms = treereduce::cli::enabled(grammar_transforms(&grammar), &matches);
+        return treereduce::cli::run(
+            args.args,
+            grammar.language,
+            &grammar.node_types,
+            HashMap::new(),
+            transforms,
+        );
+    }
+    let lang = detect(&args)?;
+    let (language, node_types, replacements) = lang.grammar();
+    let transforms = treereduce::cli::enabled(lang.transforms(), &matches);
+    treereduce::cli::run(args.args, language, &node_types, replacements, transforms)
+}
@@ -0,0 +1,12 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    treereduce::cli::main(
+        tree_sitter_go::language(),
+        tree_sitter_go::NODE_TYPES,
+        HashMap::new(),
+        treereduce::languages::go_transforms(),
+    )
+}
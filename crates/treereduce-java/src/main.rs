@@ -7,5 +7,6 @@ fn main() -> Result<()> {
         tree_sitter_java::language(),
         tree_sitter_java::NODE_TYPES,
         HashMap::new(),
+        treereduce::languages::java_transforms(),
     )
 }
@@ -1,11 +1,10 @@
-use std::collections::HashMap;
-
 use anyhow::Result;
 
 fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_javascript::language(),
         tree_sitter_javascript::NODE_TYPES,
-        HashMap::new(),
+        treereduce::languages::javascript_replacements(),
+        treereduce::languages::javascript_transforms(),
     )
 }
@@ -7,5 +7,6 @@ fn main() -> Result<()> {
         tree_sitter_lua::language(),
         tree_sitter_lua::NODE_TYPES,
         HashMap::new(),
+        Vec::new(),
     )
 }
@@ -0,0 +1,10 @@
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    treereduce::cli::main(
+        tree_sitter_python::language(),
+        &treereduce::languages::python_node_types(tree_sitter_python::NODE_TYPES),
+        treereduce::languages::python_replacements(),
+        treereduce::languages::python_transforms(),
+    )
+}
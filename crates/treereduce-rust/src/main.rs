@@ -1,11 +1,10 @@
-use std::collections::HashMap;
-
 use anyhow::Result;
 
 fn main() -> Result<()> {
     treereduce::cli::main(
         tree_sitter_rust::language(),
         tree_sitter_rust::NODE_TYPES,
-        HashMap::from([("type_identifier", &["()"][..])]),
+        treereduce::languages::rust_replacements(),
+        treereduce::languages::rust_transforms(),
     )
 }
@@ -0,0 +1,10 @@
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    treereduce::cli::main(
+        tree_sitter_sequel::language(),
+        tree_sitter_sequel::NODE_TYPES,
+        treereduce::languages::sql_replacements(),
+        Vec::new(),
+    )
+}
@@ -0,0 +1,12 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    treereduce::cli::main(
+        tree_sitter_toml::language(),
+        tree_sitter_toml::NODE_TYPES,
+        HashMap::new(),
+        treereduce::languages::comma_list_transforms(),
+    )
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Minimize a TypeScript or TSX program
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Parse the input as TSX; default is to do so if the file ends in .tsx
+    #[arg(help_heading = "Grammar options", long)]
+    tsx: bool,
+
+    #[command(flatten)]
+    args: treereduce::cli::Args,
+}
+
+/// Whether `source` is named like a TSX file
+fn is_tsx(source: &str) -> bool {
+    Path::new(source).extension().is_some_and(|e| e == "tsx")
+}
+
+fn main() -> Result<()> {
+    let transforms = treereduce::languages::javascript_transforms();
+    let (args, matches): (Args, _) = treereduce::cli::parse_args_with(&transforms)?;
+    let tsx = args.tsx || args.args.source.as_deref().is_some_and(is_tsx);
+    let (language, node_types) = if tsx {
+        (
+            tree_sitter_typescript::language_tsx(),
+            tree_sitter_typescript::TSX_NODE_TYPES,
+        )
+    } else {
+        (
+            tree_sitter_typescript::language_typescript(),
+            tree_sitter_typescript::TYPESCRIPT_NODE_TYPES,
+        )
+    };
+    treereduce::cli::run(
+        args.args,
+        language,
+        node_types,
+        treereduce::languages::javascript_replacements(),
+        treereduce::cli::enabled(transforms, &matches),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_tsx() {
+        assert!(is_tsx("app.tsx"));
+        assert!(is_tsx("src/components/App.tsx"));
+        assert!(!is_tsx("app.ts"));
+        assert!(!is_tsx("tsx"));
+        assert!(!is_tsx("app.tsx.bak"));
+    }
+}
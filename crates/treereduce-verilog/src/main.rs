@@ -0,0 +1,12 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    treereduce::cli::main(
+        tree_sitter_verilog::language(),
+        tree_sitter_verilog::NODE_TYPES,
+        HashMap::new(),
+        Vec::new(),
+    )
+}
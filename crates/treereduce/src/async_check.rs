@@ -0,0 +1,182 @@
+//! Interestingness checks that are futures, and running reductions on an
+//! async executor.
+//!
+//! [`crate::treereduce_async`] (or [`crate::Reducer::run_async`]) awaits the
+//! verdicts of an [`AsyncCheck`] in the task that awaits the reduction, so it
+//! needs no threads of its own. It reduces with hierarchical delta debugging
+//! only. The rest of the passes run checks from the reducer's worker threads:
+//! [`BlockingCheck`] adapts an [`AsyncCheck`] for them, and a reduction
+//! awaited with [`spawn_blocking`] takes one thread for itself, plus its
+//! `jobs` workers.
+//!
+//! Nothing here depends on a particular async runtime. Futures that need one
+//! (e.g., to spawn processes with tokio) should be spawned on it, and the
+//! returned handle awaited in [`AsyncCheck::interesting`], as
+//! [`TokioCmdCheck`] does (with the `tokio` feature).
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::check::Check;
+
+#[cfg(feature = "tokio")]
+mod command;
+#[cfg(feature = "tokio")]
+pub use command::TokioCmdCheck;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// An interestingness test that runs asynchronously
+pub trait AsyncCheck {
+    fn interesting(&self, input: Vec<u8>) -> BoxFuture<'_, io::Result<bool>>;
+}
+
+/// Adapts an [`AsyncCheck`] to a [`Check`], by blocking the reduction's
+/// worker thread until the future completes
+#[derive(Clone, Debug)]
+pub struct BlockingCheck<T>(pub T);
+
+impl<T: AsyncCheck> Check for BlockingCheck<T> {
+    type State = bool;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        block_on(self.0.interesting(stdin.to_vec()))
+    }
+
+    fn cancel(&self, _state: Self::State) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        Ok(Some(*state))
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        Ok(state)
+    }
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run a future to completion on the current thread
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(out) => return out,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+struct Shared<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// The result of [`spawn_blocking`]
+pub struct Blocking<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// Run `f` (e.g., a call to [`crate::treereduce_multi_pass`]) on a new
+/// thread, and return a future of its result. Panics in `f` are resumed when
+/// the future is polled. Each call starts a thread, so this is for whole
+/// reductions, not for individual checks.
+pub fn spawn_blocking<T, F>(f: F) -> Blocking<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let theirs = Arc::clone(&shared);
+    thread::spawn(move || {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+        let mut shared = theirs.lock().unwrap_or_else(|e| e.into_inner());
+        shared.result = Some(result);
+        if let Some(w) = shared.waker.take() {
+            w.wake();
+        }
+    });
+    Blocking { shared }
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        match shared.result.take() {
+            Some(Ok(out)) => Poll::Ready(out),
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reducer::Reducer;
+
+    struct NonEmpty;
+
+    impl AsyncCheck for NonEmpty {
+        fn interesting(&self, input: Vec<u8>) -> BoxFuture<'_, io::Result<bool>> {
+            Box::pin(async move { Ok(spawn_blocking(move || !input.is_empty()).await) })
+        }
+    }
+
+    struct HasMain;
+
+    impl AsyncCheck for HasMain {
+        fn interesting(&self, input: Vec<u8>) -> BoxFuture<'_, io::Result<bool>> {
+            Box::pin(async move { Ok(input.windows(4).any(|w| w == b"main")) })
+        }
+    }
+
+    fn send<F: Future + Send>(future: F) -> F {
+        future
+    }
+
+    #[test]
+    fn test_run_async() {
+        let src = "int x;\nint main() { int y = 1; return 0; }\nint z;\n";
+        let reducer = Reducer::new(
+            tree_sitter_c::language(),
+            tree_sitter_c::NODE_TYPES,
+            src,
+            HasMain,
+        );
+        let reduced = block_on(send(reducer.run_async())).unwrap();
+        assert!(reduced.text.windows(4).any(|w| w == b"main"));
+        assert!(reduced.text.len() < src.len());
+        assert_eq!(reduced.stats.passes.len(), 1);
+    }
+
+    #[test]
+    fn test_blocking_check() {
+        let chk = BlockingCheck(NonEmpty);
+        assert!(chk.interesting(b"x").unwrap());
+        assert!(!chk.interesting(b"").unwrap());
+        assert_eq!(block_on(spawn_blocking(|| 1 + 1)), 2);
+    }
+}
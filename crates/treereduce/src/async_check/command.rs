@@ -0,0 +1,131 @@
+//! Running commands as [`AsyncCheck`]s with tokio's process I/O (the `tokio`
+//! feature).
+
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::runtime::Handle;
+
+use super::{AsyncCheck, BoxFuture};
+
+/// Runs a command with each candidate on its stdin, without a thread waiting
+/// for it. Interesting if the command exits with one of `exit_codes`.
+///
+/// The command runs in a task on the runtime of `handle`, so its future can
+/// be awaited anywhere, e.g., by [`crate::treereduce_async`], or by
+/// [`super::BlockingCheck`] on the reduction's worker threads.
+#[derive(Clone, Debug)]
+pub struct TokioCmdCheck {
+    handle: Handle,
+    cmd: String,
+    args: Vec<String>,
+    exit_codes: Vec<i32>,
+    timeout: Option<Duration>,
+}
+
+impl TokioCmdCheck {
+    pub fn new(handle: Handle, cmd: String, args: Vec<String>, exit_codes: Vec<i32>) -> Self {
+        TokioCmdCheck {
+            handle,
+            cmd,
+            args,
+            exit_codes,
+            timeout: None,
+        }
+    }
+
+    /// Kill the command after this long, and count it as uninteresting
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+async fn run(
+    cmd: String,
+    args: Vec<String>,
+    exit_codes: Vec<i32>,
+    timeout: Option<Duration>,
+    input: Vec<u8>,
+) -> io::Result<bool> {
+    let mut child = Command::new(&cmd)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("Piped stdin");
+    let wait = async move {
+        // The command may exit without reading all of its input
+        match stdin.write_all(&input).await {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => drop(stdin),
+        }
+        child.wait().await
+    };
+    let status = match timeout {
+        None => wait.await?,
+        Some(t) => match tokio::time::timeout(t, wait).await {
+            Ok(status) => status?,
+            // Dropping the child kills it
+            Err(_) => return Ok(false),
+        },
+    };
+    Ok(status.code().is_some_and(|c| exit_codes.contains(&c)))
+}
+
+impl AsyncCheck for TokioCmdCheck {
+    fn interesting(&self, input: Vec<u8>) -> BoxFuture<'_, io::Result<bool>> {
+        let task = self.handle.spawn(run(
+            self.cmd.clone(),
+            self.args.clone(),
+            self.exit_codes.clone(),
+            self.timeout,
+            input,
+        ));
+        Box::pin(async move {
+            task.await
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::async_check::BlockingCheck;
+    use crate::check::Check;
+
+    #[test]
+    fn test_tokio_cmd_check() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let check = |cmd: &str, args: &[&str]| {
+            let args = args.iter().map(|a| a.to_string()).collect();
+            BlockingCheck(TokioCmdCheck::new(
+                rt.handle().clone(),
+                cmd.to_string(),
+                args,
+                vec![0],
+            ))
+        };
+        assert!(check("true", &[]).interesting(b"x").unwrap());
+        assert!(!check("false", &[]).interesting(b"x").unwrap());
+        let grep = check("grep", &["-q", "main"]);
+        assert!(grep.interesting(b"int main;").unwrap());
+        assert!(!grep.interesting(b"int x;").unwrap());
+        let slow = BlockingCheck(
+            TokioCmdCheck::new(
+                rt.handle().clone(),
+                String::from("sleep"),
+                vec![String::from("10")],
+                vec![0],
+            )
+            .timeout(Duration::from_millis(100)),
+        );
+        assert!(!slow.interesting(b"").unwrap());
+    }
+}
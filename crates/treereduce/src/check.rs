@@ -1,301 +1,826 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
 use std::io::Write;
-use std::io::{self, Read};
-#[cfg(target_family = "unix")]
-use std::os::unix::process::ExitStatusExt;
-use std::path::PathBuf;
-use std::process::{Child, Command, ExitStatus, Stdio};
-use std::time::Duration;
-
-use regex::Regex;
-use tempfile::NamedTempFile;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
 use tracing::debug;
-use wait_timeout::ChildExt;
 
+use crate::control::Control;
+use crate::rope::Rope;
+
+#[cfg(feature = "process")]
+mod cmd;
+#[cfg(feature = "process")]
+pub use cmd::*;
+#[cfg(feature = "process")]
+pub(crate) use cmd::{is_marker, split_marker, DIR_MARKER};
+#[cfg(feature = "process")]
+mod remote;
+#[cfg(feature = "process")]
+pub use remote::*;
+#[cfg(feature = "process")]
+mod server;
+#[cfg(feature = "process")]
+pub use server::*;
+
+/// An interestingness test.
+///
+/// Checks are started, and later waited on (or cancelled), so that several
+/// can be in progress at once. The trait is object-safe, so checks can be
+/// boxed, e.g., as `Box<dyn Check<State = bool> + Send + Sync>`.
 pub trait Check {
     type State;
 
     fn start(&self, stdin: &[u8]) -> io::Result<Self::State>;
 
+    /// Like [`Check::start`], but avoids copying the input if possible
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        self.start(&stdin.to_vec())
+    }
+
     fn cancel(&self, state: Self::State) -> io::Result<()>;
 
     fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>>;
 
     fn wait(&self, state: Self::State) -> io::Result<bool>;
 
+    /// Whether the check found the verdict for `state` without running,
+    /// e.g., in a cache
+    fn is_cached(&self, _state: &Self::State) -> bool {
+        false
+    }
+
     fn interesting(&self, stdin: &[u8]) -> io::Result<bool> {
         self.wait(self.start(stdin)?)
     }
+
+    /// Like [`Check::interesting`], but without any cached verdict
+    fn recheck(&self, stdin: &[u8]) -> io::Result<bool> {
+        self.interesting(stdin)
+    }
 }
 
+impl<T: Check + ?Sized> Check for Box<T> {
+    type State = T::State;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        (**self).start(stdin)
+    }
+
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        (**self).start_rope(stdin)
+    }
+
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        (**self).cancel(state)
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        (**self).try_wait(state)
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        (**self).wait(state)
+    }
+
+    fn is_cached(&self, state: &Self::State) -> bool {
+        (**self).is_cached(state)
+    }
+
+    fn recheck(&self, stdin: &[u8]) -> io::Result<bool> {
+        (**self).recheck(stdin)
+    }
+}
+
+impl<T: Check + ?Sized> Check for Arc<T> {
+    type State = T::State;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        (**self).start(stdin)
+    }
+
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        (**self).start_rope(stdin)
+    }
+
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        (**self).cancel(state)
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        (**self).try_wait(state)
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        (**self).wait(state)
+    }
+
+    fn is_cached(&self, state: &Self::State) -> bool {
+        (**self).is_cached(state)
+    }
+
+    fn recheck(&self, stdin: &[u8]) -> io::Result<bool> {
+        (**self).recheck(stdin)
+    }
+}
+
+/// An in-process check: a function from the input to whether it's
+/// interesting, which runs when the check is started.
+///
+/// To test whether some `parse` function panics:
+///
+/// ```
+/// use std::panic;
+/// use treereduce::FnCheck;
+///
+/// fn parse(_: &[u8]) {}
+///
+/// let check = FnCheck::new(|input: &[u8]| {
+///     panic::catch_unwind(|| parse(input)).is_err()
+/// });
+/// ```
+#[derive(Clone, Copy)]
+pub struct FnCheck<F> {
+    f: F,
+}
+
+impl<F> std::fmt::Debug for FnCheck<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FnCheck")
+    }
+}
+
+impl<F: Fn(&[u8]) -> bool> FnCheck<F> {
+    pub fn new(f: F) -> Self {
+        FnCheck { f }
+    }
+}
+
+impl<F: Fn(&[u8]) -> bool> Check for FnCheck<F> {
+    type State = bool;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        Ok((self.f)(stdin))
+    }
+
+    fn cancel(&self, _state: Self::State) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        Ok(Some(*state))
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        Ok(state)
+    }
+}
+
+/// 64-bit FNV-1a, which (unlike [`std::hash::Hash`]) is stable across runs and
+/// Rust versions, so it can be used in the on-disk cache.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    stable_hash_chunks(std::iter::once(bytes))
+}
+
+fn stable_hash_chunks<'a>(chunks: impl Iterator<Item = &'a [u8]>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in chunks.flatten() {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Key for [`CachedCheck`]: a hash of the input, and its length
+type CacheKey = (u64, usize);
+
+fn cache_key(stdin: &Rope) -> CacheKey {
+    (stable_hash_chunks(stdin.chunks()), stdin.len())
+}
+
+fn parse_verdict(line: &str) -> Option<(CacheKey, bool)> {
+    let mut fields = line.split(' ');
+    let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let len = fields.next()?.parse().ok()?;
+    let verdict = fields.next()? == "1";
+    Some(((hash, len), verdict))
+}
+
+/// Where [`CachedCheck`] persists its verdicts and the best result so far
+#[derive(Debug)]
+struct Persist {
+    /// Verdicts, appended one per line as they're found
+    log: Mutex<File>,
+    /// Smallest interesting input, and its size
+    best: Option<(PathBuf, Mutex<usize>)>,
+}
+
+/// Remembers the verdicts of another check, keyed by a hash of the input.
+///
+/// Clones share the same cache, so it can be shared between threads.
 #[derive(Clone, Debug)]
-pub struct CmdCheck {
-    pub(crate) cmd: String,
-    pub(crate) args: Vec<String>,
-    exit_codes: Vec<i32>,
-    interesting_stderr: Option<Regex>,
-    interesting_stdout: Option<Regex>,
-    uninteresting_stderr: Option<Regex>,
-    uninteresting_stdout: Option<Regex>,
-    temp_dir: PathBuf,
-    pub(crate) needs_file: bool,
-    inherit_stdout: bool,
-    inherit_stderr: bool,
-    timeout: Option<Duration>,
-    // TODO(#6): stdout/stderr regex
-    // Will interact poorly with try_wait...
+pub struct CachedCheck<T> {
+    check: T,
+    enabled: bool,
+    cache: Arc<RwLock<HashMap<CacheKey, bool>>>,
+    hits: Arc<AtomicUsize>,
+    persist: Option<Arc<Persist>>,
+    /// Don't remember verdicts once this is interrupted
+    control: Option<Arc<Control>>,
 }
 
-pub struct CmdCheckState {
-    child: Child,
-    temp_file: Option<NamedTempFile>,
+pub enum CachedCheckState<S> {
+    Cached(bool),
+    Running {
+        key: CacheKey,
+        state: S,
+        /// Kept only if it might need to be saved as the best result
+        stdin: Option<Vec<u8>>,
+    },
 }
 
-fn is_marker(s: &str) -> bool {
-    s.starts_with("@@")
+impl<T> CachedCheck<T> {
+    pub fn new(check: T, enabled: bool) -> Self {
+        CachedCheck {
+            check,
+            enabled,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            hits: Arc::new(AtomicUsize::new(0)),
+            persist: None,
+            control: None,
+        }
+    }
+
+    /// Don't remember verdicts found after `control` is interrupted (see
+    /// [`Control::interrupt`])
+    pub fn control(mut self, control: Arc<Control>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Load verdicts from the file at `path`, and append new ones to it.
+    ///
+    /// The file is a log with a line per verdict, so it stays valid (except
+    /// perhaps for the last line) if the process is interrupted. If `best` is
+    /// `Some((path, size))`, each interesting input smaller than `size` (and
+    /// than the previous ones) is written to `path`.
+    pub fn persist(mut self, path: &Path, best: Option<(PathBuf, usize)>) -> io::Result<Self> {
+        if path.exists() {
+            let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+            for line in BufReader::new(File::open(path)?).lines() {
+                if let Some((key, verdict)) = parse_verdict(&line?) {
+                    cache.insert(key, verdict);
+                }
+            }
+        }
+        let log = OpenOptions::new().create(true).append(true).open(path)?;
+        self.persist = Some(Arc::new(Persist {
+            log: Mutex::new(log),
+            best: best.map(|(path, size)| (path, Mutex::new(size))),
+        }));
+        Ok(self)
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.check
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.check
+    }
+
+    /// Number of times a verdict was found in the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<bool> {
+        // Poisoning just means that another thread panicked, the map is fine
+        let cache = self.cache.read().unwrap_or_else(|e| e.into_inner());
+        cache.get(key).copied()
+    }
+
+    fn insert(&self, key: CacheKey, interesting: bool, stdin: Option<&[u8]>) -> io::Result<()> {
+        if !self.enabled || self.control.as_ref().is_some_and(|c| c.is_interrupted()) {
+            return Ok(());
+        }
+        {
+            let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+            cache.insert(key, interesting);
+        }
+        let persist = match &self.persist {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        {
+            let mut log = persist.log.lock().unwrap_or_else(|e| e.into_inner());
+            writeln!(log, "{:016x} {} {}", key.0, key.1, u8::from(interesting))?;
+        }
+        if let (true, Some((path, size)), Some(stdin)) = (interesting, &persist.best, stdin) {
+            let mut size = size.lock().unwrap_or_else(|e| e.into_inner());
+            if stdin.len() < *size {
+                // Write then rename, so the file is never partially written
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, stdin)?;
+                fs::rename(&tmp, path)?;
+                *size = stdin.len();
+            }
+        }
+        Ok(())
+    }
 }
 
-impl CmdCheck {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        cmd: String,
-        args: Vec<String>,
-        exit_codes: Vec<i32>,
-        temp_dir: Option<String>,
-        interesting_stdout: Option<Regex>,
-        interesting_stderr: Option<Regex>,
-        uninteresting_stdout: Option<Regex>,
-        uninteresting_stderr: Option<Regex>,
-        inherit_stdout: bool,
-        inherit_stderr: bool,
-        timeout: Option<Duration>,
-    ) -> Self {
-        let temp_dir_path: Option<std::path::PathBuf> = temp_dir.as_ref().map(From::from);
-        CmdCheck {
-            needs_file: args.iter().any(|s| is_marker(s)),
-            temp_dir: temp_dir_path.unwrap_or_else(std::env::temp_dir),
-            cmd,
-            args,
-            exit_codes,
-            interesting_stdout,
-            interesting_stderr,
-            uninteresting_stdout,
-            uninteresting_stderr,
-            inherit_stdout,
-            inherit_stderr,
-            timeout,
+impl<T: Check> Check for CachedCheck<T> {
+    type State = CachedCheckState<T::State>;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        self.start_rope(&Rope::from(stdin))
+    }
+
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        if !self.enabled {
+            return Ok(CachedCheckState::Running {
+                key: (0, 0),
+                state: self.check.start_rope(stdin)?,
+                stdin: None,
+            });
         }
+        let key = cache_key(stdin);
+        if let Some(interesting) = self.lookup(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            debug!(interesting, "Cache hit");
+            return Ok(CachedCheckState::Cached(interesting));
+        }
+        let keep = self
+            .persist
+            .as_ref()
+            .map(|p| p.best.is_some())
+            .unwrap_or(false);
+        Ok(CachedCheckState::Running {
+            key,
+            state: self.check.start_rope(stdin)?,
+            stdin: if keep { Some(stdin.to_vec()) } else { None },
+        })
     }
 
-    fn temp_file(&self, marker: &str) -> io::Result<NamedTempFile> {
-        debug_assert!(is_marker(marker));
-        let mut builder = tempfile::Builder::new();
-        if marker.len() > "@@".len() {
-            let mut chars = marker.chars();
-            let one = chars.next();
-            debug_assert!(one == Some('@'));
-            let two = chars.next();
-            debug_assert!(two == Some('@'));
-            let rest: String = chars.collect();
-            builder
-                .prefix("treereduce-tmp-")
-                .suffix(&rest)
-                .tempfile_in(&self.temp_dir)
-        } else {
-            builder.tempfile_in(&self.temp_dir)
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        match state {
+            CachedCheckState::Cached(_) => Ok(()),
+            CachedCheckState::Running { state, .. } => self.check.cancel(state),
         }
     }
 
-    /// Replace `@@` with the path to a temporary file
-    pub(crate) fn args_with_file(&self) -> io::Result<(Option<NamedTempFile>, Vec<String>)> {
-        debug_assert!(self.needs_file);
-        let mut found = false;
-        let mut temp_file = None;
-        let mut args = Vec::new();
-        for arg in &self.args {
-            if is_marker(arg) {
-                debug_assert!(!found);
-                found = true;
-                let f = self.temp_file(arg)?;
-                // TODO(lb): No expect
-                args.push(f.path().to_str().expect("Path not valid UTF-8").to_string());
-                temp_file = Some(f);
-            } else {
-                args.push(arg.clone())
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        match state {
+            CachedCheckState::Cached(b) => Ok(Some(*b)),
+            CachedCheckState::Running { key, state, stdin } => {
+                let result = self.check.try_wait(state)?;
+                if let Some(b) = result {
+                    self.insert(*key, b, stdin.as_deref())?;
+                }
+                Ok(result)
             }
         }
-        debug_assert!(found);
-        Ok((temp_file, args))
     }
 
-    fn exec(&self, stdin_bytes: &[u8]) -> io::Result<CmdCheckState> {
-        let (mut temp_file, args) = if self.needs_file {
-            self.args_with_file()?
-        } else {
-            (None, self.args.clone())
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        match state {
+            CachedCheckState::Cached(b) => Ok(b),
+            CachedCheckState::Running { key, state, stdin } => {
+                let b = self.check.wait(state)?;
+                self.insert(key, b, stdin.as_deref())?;
+                Ok(b)
+            }
+        }
+    }
+
+    fn is_cached(&self, state: &Self::State) -> bool {
+        match state {
+            CachedCheckState::Cached(_) => true,
+            CachedCheckState::Running { state, .. } => self.check.is_cached(state),
+        }
+    }
+
+    /// Replaces the cached verdict, if any
+    fn recheck(&self, stdin: &[u8]) -> io::Result<bool> {
+        let b = self.check.recheck(stdin)?;
+        self.insert(cache_key(&Rope::from(stdin)), b, Some(stdin))?;
+        Ok(b)
+    }
+}
+
+/// How [`RetryCheck`] combines the verdicts of several runs
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Vote {
+    /// Interesting if more than half of the runs were (ties are uninteresting)
+    #[default]
+    Majority,
+    /// Interesting if any run was, so good reductions aren't discarded
+    Any,
+    /// Interesting only if every run was, so bogus reductions aren't accepted
+    All,
+}
+
+impl Vote {
+    /// The verdict, if it's already decided after this many runs out of `runs`
+    fn decide(&self, yes: usize, no: usize, runs: usize) -> Option<bool> {
+        let (need_yes, need_no) = match self {
+            Vote::Majority => (runs / 2 + 1, runs - runs / 2),
+            Vote::Any => (1, runs),
+            Vote::All => (runs, 1),
         };
+        if yes >= need_yes {
+            Some(true)
+        } else if no >= need_no {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
 
-        let child = if let Some(tf) = &mut temp_file {
-            tf.write_all(stdin_bytes)?;
-            Command::new(&self.cmd)
-                .args(args)
-                .stdin(Stdio::piped())
-                .stdout(if self.inherit_stdout {
-                    Stdio::inherit()
-                } else if self.interesting_stdout.is_none() {
-                    Stdio::null()
-                } else {
-                    Stdio::piped()
-                })
-                .stderr(if self.inherit_stderr {
-                    Stdio::inherit()
-                } else if self.interesting_stderr.is_some() {
-                    Stdio::piped()
-                } else {
-                    Stdio::null()
-                })
-                .spawn()?
+impl std::fmt::Display for Vote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Vote::Majority => write!(f, "majority"),
+            Vote::Any => write!(f, "any"),
+            Vote::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Runs another check several times and takes a vote, for flaky checks.
+///
+/// Runs stop as soon as the outcome of the vote is decided.
+#[derive(Clone, Debug)]
+pub struct RetryCheck<T> {
+    check: T,
+    /// Total number of runs (at least one)
+    runs: usize,
+    vote: Vote,
+}
+
+pub struct RetryCheckState<S> {
+    state: Option<S>,
+    stdin: Vec<u8>,
+    yes: usize,
+    no: usize,
+}
+
+impl<T> RetryCheck<T> {
+    /// Run `check` up to `1 + retries` times
+    pub fn new(check: T, retries: usize, vote: Vote) -> Self {
+        RetryCheck {
+            check,
+            runs: retries + 1,
+            vote,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.check
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.check
+    }
+}
+
+impl<T: Check> RetryCheck<T> {
+    /// Record a verdict, and start another run if it isn't decided yet
+    fn record(&self, state: &mut RetryCheckState<T::State>, b: bool) -> io::Result<Option<bool>> {
+        if b {
+            state.yes += 1;
         } else {
-            let mut child = Command::new(&self.cmd)
-                .args(args)
-                .stdin(Stdio::piped())
-                .stdout(if self.inherit_stdout {
-                    Stdio::inherit()
-                } else if self.interesting_stdout.is_some() {
-                    Stdio::piped()
-                } else {
-                    Stdio::null()
-                })
-                .stderr(if self.inherit_stderr {
-                    Stdio::inherit()
-                } else if self.interesting_stderr.is_some() {
-                    Stdio::piped()
-                } else {
-                    Stdio::null()
-                })
-                .spawn()?;
-            {
-                // New block to drop (i.e., close) stdin when done
-                let mut sin = child.stdin.take().unwrap(); // TODO(lb): no unwrap
-                sin.write_all(stdin_bytes)?;
-            }
-            child
+            state.no += 1;
+        }
+        let verdict = self.vote.decide(state.yes, state.no, self.runs);
+        if verdict.is_none() {
+            debug!(yes = state.yes, no = state.no, "Retrying check");
+            state.state = Some(self.check.start(&state.stdin)?);
+        }
+        Ok(verdict)
+    }
+}
+
+impl<T: Check> Check for RetryCheck<T> {
+    type State = RetryCheckState<T::State>;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        self.start_rope(&Rope::from(stdin))
+    }
+
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        Ok(RetryCheckState {
+            state: Some(self.check.start_rope(stdin)?),
+            // Only needed to run it again
+            stdin: if self.runs > 1 {
+                stdin.to_vec()
+            } else {
+                Vec::new()
+            },
+            yes: 0,
+            no: 0,
+        })
+    }
+
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        match state.state {
+            Some(s) => self.check.cancel(s),
+            None => Ok(()),
+        }
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        let b = match &mut state.state {
+            Some(s) => match self.check.try_wait(s)? {
+                Some(b) => b,
+                None => return Ok(None),
+            },
+            None => return Ok(self.vote.decide(state.yes, state.no, self.runs)),
         };
-        Ok(CmdCheckState { child, temp_file })
+        state.state = None;
+        self.record(state, b)
     }
 
-    fn is_interesting(
-        &self,
-        status: &ExitStatus,
-        stdout: Option<impl io::Read>,
-        stderr: Option<impl io::Read>,
-    ) -> (bool, Vec<u8>, Vec<u8>) {
-        #[cfg(not(target_family = "unix"))]
-        let code = status.code();
-        #[cfg(target_family = "unix")]
-        let code = status.code().or_else(|| status.signal().map(|c| c + 128));
-        let mut stdout_bytes = Vec::new();
-        let mut stderr_bytes = Vec::new();
-        if let Some(mut out) = stdout {
-            out.read_to_end(&mut stdout_bytes).unwrap();
+    fn wait(&self, mut state: Self::State) -> io::Result<bool> {
+        loop {
+            // Invariant: a run is in progress until the vote is decided
+            let s = state.state.take().expect("No check in progress");
+            let b = self.check.wait(s)?;
+            if let Some(verdict) = self.record(&mut state, b)? {
+                return Ok(verdict);
+            }
         }
-        if let Some(mut err) = stderr {
-            err.read_to_end(&mut stderr_bytes).unwrap();
+    }
+}
+
+/// Combines several checks: interesting if all of the first group are (or,
+/// for those added with [`CompositeCheck::and_not`], aren't), or else if any
+/// of the second group is.
+///
+/// The checks run one after another, stopping once the outcome is decided.
+#[derive(Clone, Debug)]
+pub struct CompositeCheck<T> {
+    /// Never empty, the first is the main check
+    all: Vec<T>,
+    /// Checks in `all` from this index on must not be interesting
+    not_from: usize,
+    any: Vec<T>,
+}
+
+pub struct CompositeCheckState<S> {
+    /// Index into `all` followed by `any` of the running check
+    index: usize,
+    state: Option<S>,
+    stdin: Vec<u8>,
+    verdict: Option<bool>,
+}
+
+impl<T> CompositeCheck<T> {
+    pub fn new(main: T, and: Vec<T>, or: Vec<T>) -> Self {
+        let mut all = vec![main];
+        all.extend(and);
+        CompositeCheck {
+            not_from: all.len(),
+            all,
+            any: or,
         }
-        let out_str = String::from_utf8_lossy(&stdout_bytes);
-        let err_str = String::from_utf8_lossy(&stderr_bytes);
-        let interesting_code =
-            !self.exit_codes.is_empty() && self.exit_codes.iter().any(|c| Some(*c) == code);
-        let stdout_match = self
-            .interesting_stdout
-            .as_ref()
-            .map(|rx| rx.is_match(&out_str))
-            .unwrap_or(false);
-        let stderr_match = self
-            .interesting_stderr
-            .as_ref()
-            .map(|rx| rx.is_match(&err_str))
-            .unwrap_or(false);
-        let stdout_unmatch = self
-            .uninteresting_stdout
-            .as_ref()
-            .map(|rx| rx.is_match(&out_str))
-            .unwrap_or(false);
-        let stderr_unmatch = self
-            .uninteresting_stderr
-            .as_ref()
-            .map(|rx| rx.is_match(&err_str))
-            .unwrap_or(false);
-        let is_interesting = (interesting_code || stdout_match || stderr_match)
-            && !stdout_unmatch
-            && !stderr_unmatch;
-        debug!(
-            interesting_code,
-            stdout_match,
-            stderr_match,
-            stdout_unmatch,
-            stderr_unmatch,
-            is_interesting,
-            "Interesting? {}",
-            is_interesting
-        );
-        (is_interesting, stdout_bytes, stderr_bytes)
     }
 
-    #[allow(clippy::type_complexity)]
-    pub fn wait_with_output(
-        &self,
-        mut state: CmdCheckState,
-    ) -> io::Result<(bool, Option<ExitStatus>, Vec<u8>, Vec<u8>)> {
-        let status = if let Some(to) = self.timeout {
-            if let Some(s) = state.child.wait_timeout(to)? {
-                s
-            } else {
-                state.child.kill()?;
-                return Ok((false, None, Vec::new(), Vec::new())); // timeout
+    /// Also require that each of `checks` is not interesting, e.g., that a
+    /// program doesn't crash an older version of a compiler
+    pub fn and_not(mut self, checks: Vec<T>) -> Self {
+        self.all.extend(checks);
+        self
+    }
+
+    pub fn main(&self) -> &T {
+        &self.all[0]
+    }
+
+    pub fn main_mut(&mut self) -> &mut T {
+        &mut self.all[0]
+    }
+
+    /// Apply `f` to each of the checks
+    pub fn map(self, mut f: impl FnMut(T) -> T) -> Self {
+        CompositeCheck {
+            all: self.all.into_iter().map(&mut f).collect(),
+            not_from: self.not_from,
+            any: self.any.into_iter().map(f).collect(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        self.all
+            .get(index)
+            .or_else(|| self.any.get(index - self.all.len()))
+    }
+}
+
+impl<T: Check> CompositeCheck<T> {
+    /// Given the verdict of the check at `index`, the index of the next one to
+    /// run, or else the final verdict
+    fn next(&self, index: usize, b: bool) -> Result<usize, bool> {
+        if index < self.all.len() {
+            match (b, index + 1 < self.all.len(), self.any.is_empty()) {
+                (true, true, _) => Ok(index + 1),
+                (true, false, _) => Err(true),
+                (false, _, true) => Err(false),
+                (false, _, false) => Ok(self.all.len()),
             }
+        } else if b || index + 1 == self.all.len() + self.any.len() {
+            Err(b)
         } else {
-            state.child.wait()?
-        };
-        let (b, o, e) = self.is_interesting(&status, state.child.stdout, state.child.stderr);
-        Ok((b, Some(status), o, e))
+            Ok(index + 1)
+        }
+    }
+
+    /// Record the verdict of the running check, and start the next one if
+    /// the outcome isn't decided yet
+    fn advance(
+        &self,
+        state: &mut CompositeCheckState<T::State>,
+        b: bool,
+    ) -> io::Result<Option<bool>> {
+        let negated = (self.not_from..self.all.len()).contains(&state.index);
+        match self.next(state.index, b != negated) {
+            Err(verdict) => {
+                state.verdict = Some(verdict);
+                Ok(state.verdict)
+            }
+            Ok(index) => {
+                // Unwrap: `next` only returns valid indices
+                state.state = Some(self.get(index).unwrap().start(&state.stdin)?);
+                state.index = index;
+                Ok(None)
+            }
+        }
     }
 }
 
-impl Check for CmdCheck {
-    type State = CmdCheckState;
+impl<T: Check> Check for CompositeCheck<T> {
+    type State = CompositeCheckState<T::State>;
 
     fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
-        self.exec(stdin)
+        self.start_rope(&Rope::from(stdin))
+    }
+
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        Ok(CompositeCheckState {
+            index: 0,
+            state: Some(self.main().start_rope(stdin)?),
+            // Only needed to run the other checks
+            stdin: if self.all.len() + self.any.len() > 1 {
+                stdin.to_vec()
+            } else {
+                Vec::new()
+            },
+            verdict: None,
+        })
     }
 
-    fn cancel(&self, mut state: Self::State) -> io::Result<()> {
-        state.child.kill()?;
-        if let Some(tf) = state.temp_file {
-            tf.close()?;
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        match state.state {
+            Some(s) => self.get(state.index).unwrap().cancel(s),
+            None => Ok(()),
         }
-        Ok(())
     }
 
     fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
-        let mut stdout_bytes = Vec::new();
-        let mut stderr_bytes = Vec::new();
-        if let Some(ref mut out) = &mut state.child.stdout {
-            out.read_to_end(&mut stdout_bytes)?;
+        let chk = self.get(state.index).unwrap();
+        let b = match &mut state.state {
+            Some(s) => match chk.try_wait(s)? {
+                Some(b) => b,
+                None => return Ok(None),
+            },
+            None => return Ok(state.verdict),
+        };
+        state.state = None;
+        self.advance(state, b)
+    }
+
+    fn wait(&self, mut state: Self::State) -> io::Result<bool> {
+        loop {
+            // Invariant: a check is running until the outcome is decided
+            let s = state.state.take().expect("No check in progress");
+            let b = self.get(state.index).unwrap().wait(s)?;
+            if let Some(verdict) = self.advance(&mut state, b)? {
+                return Ok(verdict);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interesting if non-empty, counts how many times it ran
+    #[derive(Debug, Default)]
+    struct CountCheck(AtomicUsize);
+
+    impl Check for CountCheck {
+        type State = bool;
+
+        fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(!stdin.is_empty())
+        }
+
+        fn cancel(&self, _state: Self::State) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+            Ok(Some(*state))
         }
-        if let Some(ref mut err) = &mut state.child.stderr {
-            err.read_to_end(&mut stderr_bytes)?;
+
+        fn wait(&self, state: Self::State) -> io::Result<bool> {
+            Ok(state)
         }
-        Ok(state.child.try_wait()?.map(|s| {
-            let (b, _, _) = self.is_interesting(
-                &s,
-                Some(stdout_bytes.as_slice()),
-                Some(stderr_bytes.as_slice()),
-            );
-            b
-        }))
     }
 
-    fn wait(&self, state: Self::State) -> io::Result<bool> {
-        Ok(self.wait_with_output(state)?.0)
+    #[test]
+    fn test_vote() {
+        assert_eq!(Vote::Majority.decide(2, 0, 3), Some(true));
+        assert_eq!(Vote::Majority.decide(1, 1, 3), None);
+        assert_eq!(Vote::Majority.decide(1, 1, 2), Some(false));
+        assert_eq!(Vote::Any.decide(0, 2, 3), None);
+        assert_eq!(Vote::Any.decide(1, 0, 3), Some(true));
+        assert_eq!(Vote::All.decide(0, 1, 3), Some(false));
+        assert_eq!(Vote::All.decide(3, 0, 3), Some(true));
+
+        let chk = RetryCheck::new(CountCheck::default(), 2, Vote::Majority);
+        assert!(chk.interesting(b"x").unwrap());
+        assert_eq!(chk.inner().0.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_composite_check() {
+        let chk = CompositeCheck::new(
+            CountCheck::default(),
+            vec![CountCheck::default()],
+            vec![CountCheck::default(), CountCheck::default()],
+        );
+        assert_eq!(chk.next(0, true), Ok(1));
+        assert_eq!(chk.next(1, true), Err(true));
+        assert_eq!(chk.next(0, false), Ok(2));
+        assert_eq!(chk.next(2, false), Ok(3));
+        assert_eq!(chk.next(3, false), Err(false));
+        assert!(chk.interesting(b"x").unwrap());
+        assert!(!chk.interesting(b"").unwrap());
+        let runs: Vec<_> = chk
+            .all
+            .iter()
+            .chain(&chk.any)
+            .map(|c| c.0.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(runs, vec![2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_and_not() {
+        let has = |c: u8| FnCheck::new(move |s: &[u8]| s.contains(&c));
+        let chk = CompositeCheck::new(has(b'x'), Vec::new(), Vec::new()).and_not(vec![has(b'y')]);
+        assert!(chk.interesting(b"x").unwrap());
+        assert!(!chk.interesting(b"xy").unwrap());
+        assert!(!chk.interesting(b"").unwrap());
+    }
+
+    #[test]
+    fn test_fn_check() {
+        let chk: Box<dyn Check<State = bool> + Send + Sync> =
+            Box::new(FnCheck::new(|s: &[u8]| s.contains(&b'x')));
+        assert!(chk.interesting(b"axb").unwrap());
+        assert!(!chk.interesting(b"ab").unwrap());
+    }
+
+    #[test]
+    fn test_cached_check() {
+        let chk = CachedCheck::new(CountCheck::default(), true);
+        assert!(chk.interesting(b"x").unwrap());
+        assert!(chk.interesting(b"x").unwrap());
+        assert!(!chk.interesting(b"").unwrap());
+        assert_eq!(chk.inner().0.load(Ordering::Relaxed), 2);
+        assert_eq!(chk.hits(), 1);
+        assert!(chk.is_cached(&chk.start(b"x").unwrap()));
+        assert!(!chk.is_cached(&chk.start(b"y").unwrap()));
+    }
+
+    #[test]
+    fn test_cached_check_interrupted() {
+        let control = Arc::new(Control::new());
+        let chk = CachedCheck::new(CountCheck::default(), true).control(Arc::clone(&control));
+        control.interrupt();
+        assert!(chk.interesting(b"x").unwrap());
+        assert!(chk.interesting(b"x").unwrap());
+        assert_eq!(chk.inner().0.load(Ordering::Relaxed), 2);
+        assert_eq!(chk.hits(), 0);
     }
 }
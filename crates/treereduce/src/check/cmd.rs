@@ -0,0 +1,1558 @@
+//! Running commands as interestingness tests.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+#[cfg(target_family = "unix")]
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use tempfile::{NamedTempFile, TempDir};
+use tracing::debug;
+use wait_timeout::ChildExt;
+
+use super::remote::{quote, Lease, Remotes};
+use super::server::{Request, Servers};
+use super::Check;
+use crate::control::Control;
+use crate::rope::Rope;
+
+#[derive(Clone, Debug)]
+pub struct CmdCheck {
+    pub(crate) cmd: String,
+    pub(crate) args: Vec<String>,
+    exit_codes: Vec<RangeInclusive<i32>>,
+    /// If non-empty, only termination by these signals is interesting, rather
+    /// than the exit codes
+    signals: Vec<i32>,
+    interesting_stderr: Option<Regex>,
+    interesting_stdout: Option<Regex>,
+    uninteresting_stderr: Option<Regex>,
+    uninteresting_stdout: Option<Regex>,
+    temp_dir: PathBuf,
+    /// Pass the input in a memfd rather than a temporary file, where possible
+    memfd: bool,
+    pub(crate) needs_file: bool,
+    /// Whether to pipe the input to stdin: if there's no `@@`, or if there's
+    /// a `-`
+    pub(crate) uses_stdin: bool,
+    /// Start from an empty environment
+    env_clear: bool,
+    env_remove: Vec<String>,
+    /// Values may contain placeholders, like the arguments
+    env: Vec<(String, String)>,
+    inherit_stdout: bool,
+    inherit_stderr: bool,
+    timeout: Option<Duration>,
+    /// Kill checks that take this many times longer than the median
+    /// interesting one
+    timeout_multiplier: Option<f64>,
+    /// Whether a check that times out is interesting, see
+    /// [`CmdCheck::timeout_verdict`]
+    timeout_verdict: bool,
+    /// Durations of the last [`RUN_TIME_WINDOW`] interesting checks, oldest
+    /// first
+    run_times: Arc<Mutex<VecDeque<Duration>>>,
+    /// See [`CmdCheck::runs`]
+    runs: Arc<AtomicUsize>,
+    /// Counts runs too, see [`CmdCheck::control`]
+    control: Option<Arc<Control>>,
+    /// Limit on the address space of the check, in bytes
+    mem_limit: Option<u64>,
+    /// Limit on the CPU time of the check, in seconds
+    cpu_limit: Option<u64>,
+    sandbox: Option<Sandbox>,
+    container: Option<Container>,
+    golden: Option<Golden>,
+    /// Capture output, and save a summary of each run for [`take_last_run`]
+    explain: bool,
+    /// Capture output, see [`CmdCheck::fingerprint`]
+    capture: bool,
+    /// Like C-Reduce: run the check with no arguments in a fresh temporary
+    /// directory, which contains only the input, with this name
+    pub(crate) creduce: Option<String>,
+    /// Write the input to this file, rather than to a temporary one
+    pub(crate) in_place: Option<PathBuf>,
+    /// Run the check on one of these hosts, see [`CmdCheck::remotes`]
+    pub(crate) remotes: Option<Arc<Remotes>>,
+    /// Send candidates to these, see [`CmdCheck::server`]
+    server: Option<Arc<Servers>>,
+}
+
+/// Run checks with [bubblewrap](https://github.com/containers/bubblewrap)
+/// (`bwrap`), in new namespaces, with a private `/tmp`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Mount the filesystem read-only (except for `/tmp`)
+    pub read_only: bool,
+}
+
+impl Sandbox {
+    pub const PROGRAM: &'static str = "bwrap";
+
+    /// Arguments to `bwrap` to run `cmd`, which may need to read `file`, in
+    /// the writable directory `dir`
+    fn args(&self, file: Option<&Path>, dir: Option<&Path>, cmd: &str) -> Vec<String> {
+        let root = if self.read_only {
+            "--ro-bind"
+        } else {
+            "--bind"
+        };
+        let mut args: Vec<String> = [
+            root,
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+            "--unshare-all",
+            "--die-with-parent",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        if let Some(f) = file {
+            let f = f.to_string_lossy().into_owned();
+            args.extend([String::from("--ro-bind"), f.clone(), f]);
+        }
+        if let Some(d) = dir {
+            let d = d.to_string_lossy().into_owned();
+            args.extend([String::from("--bind"), d.clone(), d.clone()]);
+            args.extend([String::from("--chdir"), d]);
+        }
+        args.extend([String::from("--"), cmd.to_string()]);
+        args
+    }
+}
+
+/// Run checks in a container (with Docker or Podman), for a fixed toolchain
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Container {
+    /// `docker` or `podman`
+    pub program: String,
+    pub image: String,
+}
+
+/// The file in [`ContainerRun::dir`] that holds the container's ID
+const CIDFILE: &str = "cid";
+
+impl Container {
+    /// Arguments to the container runtime to run `cmd` in the image, with
+    /// `file` (if any) mounted read-only, in the writable directory `dir`
+    /// (if any), with the environment variables `vars` from the runtime's
+    /// environment. The runtime writes the container's ID to `cidfile`.
+    fn args(
+        &self,
+        file: Option<&Path>,
+        dir: Option<&Path>,
+        vars: &[&str],
+        cidfile: &Path,
+        cmd: &str,
+    ) -> Vec<String> {
+        let mut args: Vec<String> = ["run", "--rm", "--init", "--interactive", "--cidfile"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        args.push(cidfile.to_string_lossy().into_owned());
+        if let Some(f) = file {
+            let f = f.to_string_lossy();
+            args.extend([String::from("--volume"), format!("{}:{}:ro", f, f)]);
+        }
+        if let Some(d) = dir {
+            let d = d.to_string_lossy();
+            args.extend([String::from("--volume"), format!("{}:{}", d, d)]);
+            args.extend([String::from("--workdir"), d.into_owned()]);
+        }
+        for var in vars {
+            args.extend([String::from("--env"), var.to_string()]);
+        }
+        args.extend([self.image.clone(), cmd.to_string()]);
+        args
+    }
+}
+
+/// A check running in a [`Container`]
+struct ContainerRun {
+    program: String,
+    /// Holds the [`CIDFILE`]
+    dir: TempDir,
+}
+
+impl ContainerRun {
+    /// Stop the container, which keeps running if only the runtime's client
+    /// (e.g., `docker run`) is killed
+    fn kill(&self) -> io::Result<()> {
+        let id = match std::fs::read_to_string(self.dir.path().join(CIDFILE)) {
+            Ok(id) => id,
+            // Not created yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let status = Command::new(&self.program)
+            .args(["kill", id.trim()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if !status.success() {
+            debug!(id = id.trim(), "Failed to kill container");
+        }
+        Ok(())
+    }
+}
+
+/// Compare the output of checks to that of the original input, instead of
+/// using the exit code and regexes (though the `uninteresting` regexes still
+/// apply)
+#[derive(Clone, Debug)]
+pub struct Golden {
+    /// Compare only the captures of this regex (or its matches, if it has no
+    /// groups) in stdout and stderr, rather than their entire contents
+    regex: Option<Regex>,
+    /// Exit code, stdout, and stderr (or their captures) of the original
+    expected: Option<Outcome>,
+}
+
+type Outcome = (Option<i32>, Vec<u8>, Vec<u8>);
+
+/// The captures of `rx` (or its matches, if it has no groups) in `output`,
+/// each followed by a NUL byte
+fn captures(rx: &Regex, output: &[u8]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(output);
+    let mut summary = Vec::new();
+    for caps in rx.captures_iter(&text) {
+        let skip = usize::from(caps.len() > 1);
+        for m in caps.iter().skip(skip).flatten() {
+            summary.extend_from_slice(m.as_str().as_bytes());
+            summary.push(0);
+        }
+    }
+    summary
+}
+
+impl Golden {
+    pub fn new(regex: Option<Regex>) -> Self {
+        Golden {
+            regex,
+            expected: None,
+        }
+    }
+
+    fn summarize(&self, output: &[u8]) -> Vec<u8> {
+        match &self.regex {
+            None => output.to_vec(),
+            Some(rx) => captures(rx, output),
+        }
+    }
+
+    fn outcome(&self, code: Option<i32>, stdout: &[u8], stderr: &[u8]) -> Outcome {
+        (code, self.summarize(stdout), self.summarize(stderr))
+    }
+}
+
+/// Don't adapt the timeout until this many checks have been interesting
+const MIN_RUN_TIMES: usize = 5;
+/// Never adapt the timeout to less than this
+const MIN_ADAPTIVE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Adapt the timeout to the median of this many of the latest interesting
+/// checks, which tracks checks getting faster as the input shrinks
+const RUN_TIME_WINDOW: usize = 64;
+
+pub struct CmdCheckState {
+    /// `None` for a request to a server
+    child: Option<Child>,
+    request: Option<Request>,
+    temp_file: Option<NamedTempFile>,
+    /// See [`CmdCheck::memfd`]
+    _memfd: Option<std::fs::File>,
+    /// See [`CmdCheck::creduce`]
+    work_dir: Option<TempDir>,
+    /// The host the check runs on, if it's remote
+    _lease: Option<Lease>,
+    container: Option<ContainerRun>,
+    started: Instant,
+}
+
+/// The exit code, or 128 plus the signal that killed the process
+fn exit_code(status: &ExitStatus) -> Option<i32> {
+    #[cfg(target_family = "unix")]
+    {
+        status.code().or_else(|| status.signal().map(|c| c + 128))
+    }
+    #[cfg(not(target_family = "unix"))]
+    {
+        status.code()
+    }
+}
+
+/// What happened when a [`CmdCheck`] ran
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunSummary {
+    /// See [`CmdCheck::signals`]
+    pub code: Option<i32>,
+    pub timed_out: bool,
+    /// Last non-empty line of output, if captured
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl std::fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.timed_out {
+            return write!(f, "timed out");
+        }
+        match self.code {
+            Some(c) => write!(f, "exit code {}", c)?,
+            None => write!(f, "no exit code")?,
+        }
+        if !self.stdout.is_empty() {
+            write!(f, ", stdout: {}", self.stdout)?;
+        }
+        if !self.stderr.is_empty() {
+            write!(f, ", stderr: {}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static LAST_RUN: RefCell<Option<RunSummary>> = const { RefCell::new(None) };
+}
+
+fn save_last_run(summary: RunSummary) {
+    LAST_RUN.with(|r| *r.borrow_mut() = Some(summary));
+}
+
+/// The last run on this thread of a [`CmdCheck`] with [`CmdCheck::explain`]
+/// set, if any. Reductions notify [`crate::observer::Observer`]s on the
+/// thread that checked the candidate. Candidates whose verdicts are cached
+/// don't run the check.
+pub fn take_last_run() -> Option<RunSummary> {
+    LAST_RUN.with(|r| r.borrow_mut().take())
+}
+
+fn last_line(output: &[u8]) -> String {
+    String::from_utf8_lossy(output)
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Placeholders for the path to the input file
+const MARKERS: [&str; 2] = ["@@", "{}"];
+/// Placeholder for the temporary directory
+pub(crate) const DIR_MARKER: &str = "{tmpdir}";
+
+/// The pipe to the stdin of a check spawned with [`Stdio::piped`]
+fn child_stdin(child: &mut Child) -> io::Result<ChildStdin> {
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "The check has no stdin"))
+}
+
+/// Split an argument around its first input file placeholder, if any
+pub(crate) fn split_marker(s: &str) -> Option<(&str, &str)> {
+    MARKERS
+        .iter()
+        .filter_map(|m| s.find(m).map(|i| (&s[..i], &s[i + m.len()..])))
+        .min_by_key(|(before, _)| before.len())
+}
+
+pub(crate) fn is_marker(s: &str) -> bool {
+    split_marker(s).is_some()
+}
+
+/// `-` conventionally means stdin
+fn needs_file(args: &[String], env: &[(String, String)]) -> bool {
+    args.iter()
+        .chain(env.iter().map(|(_, v)| v))
+        .any(|s| is_marker(s))
+}
+
+fn uses_stdin(args: &[String], needs_file: bool) -> bool {
+    !needs_file || args.iter().any(|s| s == "-")
+}
+
+impl CmdCheck {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        cmd: String,
+        args: Vec<String>,
+        exit_codes: Vec<i32>,
+        temp_dir: Option<String>,
+        interesting_stdout: Option<Regex>,
+        interesting_stderr: Option<Regex>,
+        uninteresting_stdout: Option<Regex>,
+        uninteresting_stderr: Option<Regex>,
+        inherit_stdout: bool,
+        inherit_stderr: bool,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let temp_dir_path: Option<std::path::PathBuf> = temp_dir.as_ref().map(From::from);
+        CmdCheck {
+            needs_file: args.iter().any(|s| is_marker(s)),
+            uses_stdin: uses_stdin(&args, args.iter().any(|s| is_marker(s))),
+            env_clear: false,
+            env_remove: Vec::new(),
+            env: Vec::new(),
+            temp_dir: temp_dir_path.unwrap_or_else(std::env::temp_dir),
+            memfd: false,
+            cmd,
+            args,
+            exit_codes: exit_codes.into_iter().map(|c| c..=c).collect(),
+            signals: Vec::new(),
+            interesting_stdout,
+            interesting_stderr,
+            uninteresting_stdout,
+            uninteresting_stderr,
+            inherit_stdout,
+            inherit_stderr,
+            timeout,
+            timeout_multiplier: None,
+            timeout_verdict: false,
+            run_times: Arc::default(),
+            runs: Arc::default(),
+            control: None,
+            mem_limit: None,
+            cpu_limit: None,
+            sandbox: None,
+            container: None,
+            golden: None,
+            explain: false,
+            capture: false,
+            creduce: None,
+            in_place: None,
+            remotes: None,
+            server: None,
+        }
+    }
+
+    /// Only consider termination by one of these signals interesting (also
+    /// when reported as an exit code of 128 plus the signal, as by shells)
+    pub fn signals(mut self, signals: Vec<i32>) -> Self {
+        self.signals = signals;
+        self
+    }
+
+    /// Compare outputs to those of the original, see [`CmdCheck::record_golden`]
+    pub fn golden(mut self, golden: Option<Golden>) -> Self {
+        self.golden = golden;
+        self
+    }
+
+    /// Save a summary of each run, see [`take_last_run`]
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Run the check like C-Reduce runs interestingness scripts: with no
+    /// arguments, in a fresh temporary directory that contains only the
+    /// input, named `file_name`
+    pub fn creduce(mut self, file_name: String) -> Self {
+        // Relative to the current directory, not the temporary one
+        if self.cmd.contains(std::path::MAIN_SEPARATOR) && Path::new(&self.cmd).is_relative() {
+            if let Ok(dir) = std::env::current_dir() {
+                self.cmd = dir.join(&self.cmd).to_string_lossy().into_owned();
+            }
+        }
+        self.args = Vec::new();
+        self.needs_file = false;
+        self.uses_stdin = false;
+        self.creduce = Some(file_name);
+        self
+    }
+
+    /// Write each input to `path` before running the check, e.g., to test a
+    /// file of a larger project with its build command. Checks can't run in
+    /// parallel, and the file holds the last input afterwards.
+    pub fn in_place(mut self, path: PathBuf) -> Self {
+        self.in_place = Some(path);
+        self.uses_stdin = false;
+        self
+    }
+
+    /// Run the check on one of `remotes`, with `ssh`. The command runs in
+    /// the remote user's home directory, with the input on stdin, or in a
+    /// temporary file there (for `@@`).
+    pub fn remotes(mut self, remotes: Arc<Remotes>) -> Self {
+        self.remotes = Some(remotes);
+        self
+    }
+
+    /// Keep the check running, and send it one candidate after another on
+    /// stdin, with the protocol described in [`Servers`]. One server is
+    /// started for each check that runs at once. Servers can't be combined
+    /// with [`CmdCheck::remotes`]. With [`CmdCheck::in_place`], the file is
+    /// written before each candidate is sent.
+    pub fn server(mut self, server: bool) -> Self {
+        self.server = server.then(|| {
+            Arc::new(
+                Servers::new(self.cmd.clone(), self.args.clone(), self.inherit_stderr).env(
+                    self.env_clear,
+                    self.env_remove.clone(),
+                    self.env.clone(),
+                ),
+            )
+        });
+        self
+    }
+
+    /// The shell command that runs the check on a remote host, reading the
+    /// input from stdin
+    fn remote_script(&self) -> String {
+        let mut words = vec![quote(&self.cmd)];
+        for arg in &self.args {
+            words.push(match split_marker(arg).filter(|_| self.needs_file) {
+                None => quote(arg),
+                Some((before, after)) => format!("{}\"$d/input\"{}", quote(before), quote(after)),
+            });
+        }
+        let cmd = words.join(" ");
+        if !self.needs_file {
+            return format!("exec {}", cmd);
+        }
+        // The suffix of the file's name (e.g., `.c`), as for local checks
+        let suffix = self
+            .args
+            .iter()
+            .find_map(|a| split_marker(a))
+            .map(|(_, after)| after)
+            .unwrap_or_default();
+        format!(
+            "d=$(mktemp -d) || exit 255; cat > \"$d/input\"{}; {}; s=$?; rm -rf \"$d\"; exit $s",
+            quote(suffix),
+            cmd
+        )
+    }
+
+    /// Run the check on `stdin`, and return the captures of `rx` (or its
+    /// matches, if it has no groups) in stdout and then stderr, e.g., a hash
+    /// of a sanitizer's stack trace. Returns `None` if there are none.
+    pub fn fingerprint(&self, stdin: &[u8], rx: &Regex) -> io::Result<Option<Vec<u8>>> {
+        let chk = CmdCheck {
+            capture: true,
+            ..self.clone()
+        };
+        let state = chk.exec(&Rope::from(stdin))?;
+        let (_, _, stdout, stderr) = chk.wait_with_output(state)?;
+        let mut fingerprint = captures(rx, &stdout);
+        fingerprint.extend(captures(rx, &stderr));
+        Ok((!fingerprint.is_empty()).then_some(fingerprint))
+    }
+
+    /// Run the check on the original input, and record its output for
+    /// comparison
+    pub fn record_golden(&mut self, stdin: &[u8]) -> io::Result<()> {
+        let state = self.exec(&Rope::from(stdin))?;
+        let (_, status, stdout, stderr) = self.wait_with_output(state)?;
+        if let Some(g) = &mut self.golden {
+            let code = status.as_ref().and_then(exit_code);
+            g.expected = Some(g.outcome(code, &stdout, &stderr));
+        }
+        Ok(())
+    }
+
+    /// Set the environment of the check: clear it (if `clear`), remove the
+    /// variables in `remove`, then set those in `set`
+    pub fn env(mut self, clear: bool, remove: Vec<String>, set: Vec<(String, String)>) -> Self {
+        self.env_clear = clear;
+        self.env_remove = remove;
+        self.env = set;
+        self.needs_file = needs_file(&self.args, &self.env);
+        self.uses_stdin = uses_stdin(&self.args, self.needs_file);
+        self
+    }
+
+    /// Run the check in a sandbox
+    pub fn sandbox(mut self, sandbox: Option<Sandbox>) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Pass the input in a memfd (named by a `/proc` path) rather than a
+    /// temporary file, avoiding the file system entirely. Only on Linux, and
+    /// only used when the `@@` has no suffix and the check isn't sandboxed or
+    /// in a container (which can't see this process's file descriptors).
+    pub fn memfd(mut self, memfd: bool) -> Self {
+        self.memfd = memfd;
+        self
+    }
+
+    /// Consider exit codes in these ranges interesting, instead of the ones
+    /// passed to [`CmdCheck::new`]
+    pub fn exit_code_ranges(mut self, ranges: Vec<RangeInclusive<i32>>) -> Self {
+        self.exit_codes = ranges;
+        self
+    }
+
+    /// Run the check in a container
+    pub fn container(mut self, container: Option<Container>) -> Self {
+        self.container = container;
+        self
+    }
+
+    /// Limit the memory (address space, in bytes) and CPU time (in seconds)
+    /// of the check. Only supported on Unix.
+    pub fn limits(mut self, mem_limit: Option<u64>, cpu_limit: Option<u64>) -> Self {
+        self.mem_limit = mem_limit;
+        self.cpu_limit = cpu_limit;
+        self
+    }
+
+    #[cfg(unix)]
+    fn apply_limits(&self, cmd: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        let (mem, cpu) = (self.mem_limit, self.cpu_limit);
+        if mem.is_none() && cpu.is_none() {
+            return;
+        }
+        // rlim_t isn't u64 on every platform
+        #[allow(clippy::unnecessary_cast)]
+        let rlimit = |l: u64| libc::rlimit {
+            rlim_cur: l as libc::rlim_t,
+            rlim_max: l as libc::rlim_t,
+        };
+        // SAFETY: setrlimit is async-signal-safe, and nothing is allocated
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(m) = mem {
+                    if libc::setrlimit(libc::RLIMIT_AS, &rlimit(m)) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(c) = cpu {
+                    if libc::setrlimit(libc::RLIMIT_CPU, &rlimit(c)) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn apply_limits(&self, _cmd: &mut Command) {}
+
+    /// The same check, but with the arguments `args`
+    pub fn with_args(&self, args: Vec<String>) -> Self {
+        CmdCheck {
+            needs_file: needs_file(&args, &self.env),
+            uses_stdin: uses_stdin(&args, needs_file(&args, &self.env)),
+            args,
+            ..self.clone()
+        }
+    }
+
+    /// Another check with the same options, but running `cmd` with `args`
+    /// and judged only by its exit code
+    pub fn command(&self, cmd: String, args: Vec<String>) -> Self {
+        CmdCheck {
+            needs_file: needs_file(&args, &self.env),
+            uses_stdin: uses_stdin(&args, needs_file(&args, &self.env)),
+            cmd,
+            args,
+            interesting_stdout: None,
+            interesting_stderr: None,
+            uninteresting_stdout: None,
+            uninteresting_stderr: None,
+            run_times: Arc::default(),
+            golden: None,
+            signals: Vec::new(),
+            creduce: None,
+            server: None,
+            ..self.clone()
+        }
+    }
+
+    /// The number of processes spawned to run the check (or candidates sent to
+    /// a server), including those of copies made with [`CmdCheck::command`]
+    /// or [`Clone`]
+    pub fn runs(&self) -> usize {
+        self.runs.load(Ordering::Relaxed)
+    }
+
+    /// Count each run (see [`CmdCheck::runs`]) with [`Control::count_run`]
+    /// too, for [`Control::limit_checks`]
+    pub fn control(mut self, control: Arc<Control>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    fn count_run(&self) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        if let Some(control) = &self.control {
+            control.count_run();
+        }
+    }
+
+    /// Kill checks that take `multiplier` times longer than the median of the
+    /// latest interesting checks (once there have been a few). Off by
+    /// default. The timeout passed to [`CmdCheck::new`] still applies.
+    pub fn timeout_multiplier(mut self, multiplier: Option<f64>) -> Self {
+        self.timeout_multiplier = multiplier;
+        self
+    }
+
+    /// Count a check that times out as interesting, rather than not. For
+    /// checks that are negated (see [`super::CompositeCheck::and_not`]), so
+    /// that a timeout still rejects the candidate.
+    pub fn timeout_verdict(mut self, interesting: bool) -> Self {
+        self.timeout_verdict = interesting;
+        self
+    }
+
+    fn effective_timeout(&self) -> Option<Duration> {
+        let adaptive = self.timeout_multiplier.and_then(|m| {
+            let times = self.run_times.lock().unwrap_or_else(|e| e.into_inner());
+            if times.len() < MIN_RUN_TIMES {
+                return None;
+            }
+            let mut sorted: Vec<Duration> = times.iter().copied().collect();
+            sorted.sort_unstable();
+            let median = sorted[sorted.len() / 2];
+            Some(std::cmp::max(median.mul_f64(m), MIN_ADAPTIVE_TIMEOUT))
+        });
+        match (self.timeout, adaptive) {
+            (Some(t), Some(a)) => Some(std::cmp::min(t, a)),
+            (t, a) => t.or(a),
+        }
+    }
+
+    fn record_run_time(&self, time: Duration) {
+        if self.timeout_multiplier.is_none() {
+            return;
+        }
+        let mut times = self.run_times.lock().unwrap_or_else(|e| e.into_inner());
+        if times.len() == RUN_TIME_WINDOW {
+            times.pop_front();
+        }
+        times.push_back(time);
+    }
+
+    /// A temporary file whose name ends with `suffix` (e.g., `.c`)
+    fn temp_file(&self, suffix: &str) -> io::Result<NamedTempFile> {
+        let mut builder = tempfile::Builder::new();
+        if !suffix.is_empty() {
+            builder
+                .prefix("treereduce-tmp-")
+                .suffix(suffix)
+                .tempfile_in(&self.temp_dir)
+        } else {
+            builder.tempfile_in(&self.temp_dir)
+        }
+    }
+
+    /// Replace `{tmpdir}` with the temporary directory
+    fn expand_dir(&self, arg: &str) -> String {
+        arg.replace(DIR_MARKER, &self.temp_dir.to_string_lossy())
+    }
+
+    /// Replace `@@` or `{}` with the path to a temporary file. Anything after
+    /// the first placeholder in its argument (e.g., `.c` in `@@.c`) is the
+    /// suffix of the file's name. Other placeholders refer to the same file,
+    /// without that suffix.
+    pub(crate) fn args_with_file(&self) -> io::Result<(Option<NamedTempFile>, Vec<String>)> {
+        let (temp_file, args, _) = self.args_and_env_with_file()?;
+        Ok((temp_file, args))
+    }
+
+    /// Like [`CmdCheck::args_with_file`], but also returns the values of the
+    /// environment variables
+    #[allow(clippy::type_complexity)]
+    fn args_and_env_with_file(
+        &self,
+    ) -> io::Result<(Option<NamedTempFile>, Vec<String>, Vec<String>)> {
+        debug_assert!(self.needs_file);
+        let suffix = self.suffix();
+        let f = self.temp_file(&suffix)?;
+        let path = f.path().to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Temporary file path {} isn't UTF-8", f.path().display()),
+            )
+        })?;
+        let (args, env) = self.substitute(&path[..path.len() - suffix.len()]);
+        Ok((Some(f), args, env))
+    }
+
+    /// The suffix of the first placeholder
+    fn suffix(&self) -> String {
+        self.args
+            .iter()
+            .chain(self.env.iter().map(|(_, v)| v))
+            .find_map(|arg| split_marker(&self.expand_dir(arg)).map(|(_, after)| after.to_string()))
+            .unwrap_or_default()
+    }
+
+    /// The arguments and values of the environment variables, with `stem`
+    /// in place of the placeholders
+    fn substitute(&self, stem: &str) -> (Vec<String>, Vec<String>) {
+        let mut args: Vec<String> = self
+            .args
+            .iter()
+            .chain(self.env.iter().map(|(_, v)| v))
+            .map(|arg| {
+                let arg = self.expand_dir(arg);
+                match split_marker(&arg) {
+                    Some((before, after)) => format!("{}{}{}", before, stem, after),
+                    None => arg,
+                }
+            })
+            .collect();
+        let env = args.split_off(self.args.len());
+        (args, env)
+    }
+
+    /// A memfd holding `bytes`, and a path by which other processes can open
+    /// it, if [`CmdCheck::memfd`] applies
+    #[cfg(target_os = "linux")]
+    fn memfd_with(&self, bytes: &Rope) -> io::Result<Option<(std::fs::File, String)>> {
+        use std::os::fd::FromRawFd;
+
+        if !self.memfd
+            || !self.needs_file
+            || self.sandbox.is_some()
+            || self.container.is_some()
+            || !self.suffix().is_empty()
+        {
+            return Ok(None);
+        }
+        // SAFETY: the name is a valid C string
+        let fd = unsafe { libc::memfd_create(b"treereduce\0".as_ptr().cast(), libc::MFD_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd was just created, and nothing else owns it
+        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+        bytes.write_to(&mut file)?;
+        // Opening this gets a new file description, reading from the start
+        let path = format!("/proc/{}/fd/{}", std::process::id(), fd);
+        Ok(Some((file, path)))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn memfd_with(&self, _bytes: &Rope) -> io::Result<Option<(std::fs::File, String)>> {
+        Ok(None)
+    }
+
+    fn stdout(&self) -> Stdio {
+        if self.inherit_stdout {
+            Stdio::inherit()
+        } else if self.interesting_stdout.is_some()
+            || self.uninteresting_stdout.is_some()
+            || self.golden.is_some()
+            || self.explain
+            || self.capture
+        {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        }
+    }
+
+    fn stderr(&self) -> Stdio {
+        if self.inherit_stderr {
+            Stdio::inherit()
+        } else if self.interesting_stderr.is_some()
+            || self.uninteresting_stderr.is_some()
+            || self.golden.is_some()
+            || self.explain
+            || self.capture
+        {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        }
+    }
+
+    fn exec(&self, stdin_bytes: &Rope) -> io::Result<CmdCheckState> {
+        if let Some(servers) = &self.server {
+            if self.remotes.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Check servers can't run remotely",
+                ));
+            }
+            if let Some(p) = &self.in_place {
+                stdin_bytes.write_to(&mut std::fs::File::create(p)?)?;
+            }
+            let request = servers.send(stdin_bytes)?;
+            self.count_run();
+            return Ok(CmdCheckState {
+                child: None,
+                request: Some(request),
+                temp_file: None,
+                _memfd: None,
+                work_dir: None,
+                // Servers are local, and the reducer's slots take the
+                // jobserver tokens for concurrent checks
+                _lease: None,
+                container: None,
+                started: Instant::now(),
+            });
+        }
+        if let Some(remotes) = &self.remotes {
+            return self.exec_remote(remotes, stdin_bytes);
+        }
+        let memfd = self.memfd_with(stdin_bytes)?;
+        let (mut temp_file, args, env) = if let Some((_, path)) = &memfd {
+            let (args, env) = self.substitute(path);
+            (None, args, env)
+        } else if self.needs_file {
+            self.args_and_env_with_file()?
+        } else {
+            let expand = |a: &String| self.expand_dir(a);
+            (
+                None,
+                self.args.iter().map(expand).collect(),
+                self.env.iter().map(|(_, v)| expand(v)).collect(),
+            )
+        };
+
+        let work_dir = match &self.creduce {
+            None => None,
+            Some(name) => {
+                let dir = tempfile::Builder::new()
+                    .prefix("treereduce-tmp-")
+                    .tempdir_in(&self.temp_dir)?;
+                let mut f = std::fs::File::create(dir.path().join(name))?;
+                stdin_bytes.write_to(&mut f)?;
+                Some(dir)
+            }
+        };
+
+        if let Some(p) = &self.in_place {
+            stdin_bytes.write_to(&mut std::fs::File::create(p)?)?;
+        }
+
+        let mut container_run = None;
+        let mut cmd = match (&self.sandbox, &self.container) {
+            (None, None) => Command::new(&self.cmd),
+            (None, Some(container)) => {
+                let mut cmd = Command::new(&container.program);
+                let vars: Vec<&str> = self.env.iter().map(|(var, _)| var.as_str()).collect();
+                let dir = tempfile::Builder::new()
+                    .prefix("treereduce-tmp-")
+                    .tempdir_in(&self.temp_dir)?;
+                cmd.args(container.args(
+                    temp_file.as_ref().map(|f| f.path()),
+                    work_dir.as_ref().map(|d| d.path()),
+                    &vars,
+                    &dir.path().join(CIDFILE),
+                    &self.cmd,
+                ));
+                container_run = Some(ContainerRun {
+                    program: container.program.clone(),
+                    dir,
+                });
+                cmd
+            }
+            (Some(sandbox), _) => {
+                let mut cmd = Command::new(Sandbox::PROGRAM);
+                cmd.args(sandbox.args(
+                    temp_file.as_ref().map(|f| f.path()),
+                    work_dir.as_ref().map(|d| d.path()),
+                    &self.cmd,
+                ));
+                cmd
+            }
+        };
+        if let Some(d) = &work_dir {
+            cmd.current_dir(d.path());
+        }
+        self.apply_limits(&mut cmd);
+        if self.env_clear {
+            cmd.env_clear();
+        }
+        for var in &self.env_remove {
+            cmd.env_remove(var);
+        }
+        for ((var, _), value) in self.env.iter().zip(env) {
+            cmd.env(var, value);
+        }
+        if let Some(tf) = &mut temp_file {
+            stdin_bytes.write_to(tf)?;
+        }
+        let mut child = cmd
+            .args(args)
+            .stdin(if self.uses_stdin {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(self.stdout())
+            .stderr(self.stderr())
+            .spawn()?;
+        self.count_run();
+        if self.uses_stdin {
+            // New block to drop (i.e., close) stdin when done
+            let mut sin = child_stdin(&mut child)?;
+            match stdin_bytes.write_to(&mut sin) {
+                // The check exited (or closed stdin) without reading it all
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                    debug!("Check didn't read all of stdin")
+                }
+                r => r?,
+            }
+        }
+        Ok(CmdCheckState {
+            child: Some(child),
+            request: None,
+            temp_file,
+            _memfd: memfd.map(|(f, _)| f),
+            work_dir,
+            _lease: None,
+            container: container_run,
+            started: Instant::now(),
+        })
+    }
+
+    fn exec_remote(&self, remotes: &Arc<Remotes>, stdin_bytes: &Rope) -> io::Result<CmdCheckState> {
+        let lease = remotes.lease();
+        debug!(host = lease.host(), "Running check remotely");
+        let mut child = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", lease.host(), "--"])
+            .arg(self.remote_script())
+            .stdin(Stdio::piped())
+            .stdout(self.stdout())
+            .stderr(self.stderr())
+            .spawn()?;
+        self.count_run();
+        let mut sin = child_stdin(&mut child)?;
+        match stdin_bytes.write_to(&mut sin) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                debug!("Check didn't read all of stdin")
+            }
+            r => r?,
+        }
+        drop(sin);
+        Ok(CmdCheckState {
+            child: Some(child),
+            request: None,
+            temp_file: None,
+            _memfd: None,
+            work_dir: None,
+            _lease: Some(lease),
+            container: None,
+            started: Instant::now(),
+        })
+    }
+
+    fn is_interesting(
+        &self,
+        status: &ExitStatus,
+        stdout: Option<impl io::Read>,
+        stderr: Option<impl io::Read>,
+    ) -> (bool, Vec<u8>, Vec<u8>) {
+        let code = exit_code(status);
+        let mut stdout_bytes = Vec::new();
+        let mut stderr_bytes = Vec::new();
+        if let Some(mut out) = stdout {
+            out.read_to_end(&mut stdout_bytes).unwrap();
+        }
+        if let Some(mut err) = stderr {
+            err.read_to_end(&mut stderr_bytes).unwrap();
+        }
+        let out_str = String::from_utf8_lossy(&stdout_bytes);
+        let err_str = String::from_utf8_lossy(&stderr_bytes);
+        let golden_match = self
+            .golden
+            .as_ref()
+            .map(|g| g.expected.as_ref() == Some(&g.outcome(code, &stdout_bytes, &stderr_bytes)));
+        let interesting_code = golden_match.is_none()
+            && if self.signals.is_empty() {
+                code.is_some_and(|code| self.exit_codes.iter().any(|c| c.contains(&code)))
+            } else {
+                self.signals.iter().any(|s| Some(s + 128) == code)
+            };
+        let stdout_match = self
+            .interesting_stdout
+            .as_ref()
+            .map(|rx| rx.is_match(&out_str))
+            .unwrap_or(false);
+        let stderr_match = self
+            .interesting_stderr
+            .as_ref()
+            .map(|rx| rx.is_match(&err_str))
+            .unwrap_or(false);
+        let stdout_unmatch = self
+            .uninteresting_stdout
+            .as_ref()
+            .map(|rx| rx.is_match(&out_str))
+            .unwrap_or(false);
+        let stderr_unmatch = self
+            .uninteresting_stderr
+            .as_ref()
+            .map(|rx| rx.is_match(&err_str))
+            .unwrap_or(false);
+        let is_interesting = golden_match
+            .unwrap_or(interesting_code || stdout_match || stderr_match)
+            && !stdout_unmatch
+            && !stderr_unmatch;
+        debug!(
+            ?golden_match,
+            interesting_code,
+            stdout_match,
+            stderr_match,
+            stdout_unmatch,
+            stderr_unmatch,
+            is_interesting,
+            "Interesting? {}",
+            is_interesting
+        );
+        (is_interesting, stdout_bytes, stderr_bytes)
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn wait_with_output(
+        &self,
+        mut state: CmdCheckState,
+    ) -> io::Result<(bool, Option<ExitStatus>, Vec<u8>, Vec<u8>)> {
+        if let (Some(servers), Some(request)) = (&self.server, state.request.take()) {
+            let timeout = self
+                .effective_timeout()
+                .map(|to| to.saturating_sub(state.started.elapsed()));
+            let verdict = servers.reply(request, timeout);
+            if verdict == Some(true) {
+                self.record_run_time(state.started.elapsed());
+            }
+            if self.explain {
+                save_last_run(RunSummary {
+                    code: None,
+                    timed_out: verdict.is_none(),
+                    stdout: String::new(),
+                    stderr: String::new(),
+                });
+            }
+            let verdict = verdict.unwrap_or(self.timeout_verdict);
+            return Ok((verdict, None, Vec::new(), Vec::new()));
+        }
+        let mut child = state.child.take().expect("No check in progress");
+        let status = if let Some(to) = self.effective_timeout() {
+            let left = to.saturating_sub(state.started.elapsed());
+            if let Some(s) = child.wait_timeout(left)? {
+                s
+            } else {
+                if let Some(c) = &state.container {
+                    c.kill()?;
+                }
+                child.kill()?;
+                debug!(timeout = to.as_millis(), "Check timed out");
+                if self.explain {
+                    save_last_run(RunSummary {
+                        code: None,
+                        timed_out: true,
+                        stdout: String::new(),
+                        stderr: String::new(),
+                    });
+                }
+                return Ok((self.timeout_verdict, None, Vec::new(), Vec::new()));
+            }
+        } else {
+            child.wait()?
+        };
+        let elapsed = state.started.elapsed();
+        let (b, o, e) = self.is_interesting(&status, child.stdout, child.stderr);
+        if b {
+            self.record_run_time(elapsed);
+        }
+        if self.explain {
+            save_last_run(RunSummary {
+                code: exit_code(&status),
+                timed_out: false,
+                stdout: last_line(&o),
+                stderr: last_line(&e),
+            });
+        }
+        Ok((b, Some(status), o, e))
+    }
+}
+
+impl Check for CmdCheck {
+    type State = CmdCheckState;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        self.exec(&Rope::from(stdin))
+    }
+
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        self.exec(stdin)
+    }
+
+    fn cancel(&self, mut state: Self::State) -> io::Result<()> {
+        // Dropping a request stops its server, which is mid-candidate
+        drop(state.request);
+        if let Some(c) = &state.container {
+            c.kill()?;
+        }
+        if let Some(child) = &mut state.child {
+            child.kill()?;
+        }
+        if let Some(tf) = state.temp_file {
+            tf.close()?;
+        }
+        Ok(())
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        if let (Some(servers), Some(request)) = (&self.server, state.request.take()) {
+            return match servers.try_reply(request) {
+                Ok(b) => Ok(Some(b)),
+                Err(request) => {
+                    state.request = Some(request);
+                    Ok(None)
+                }
+            };
+        }
+        let Some(child) = &mut state.child else {
+            return Ok(None);
+        };
+        let mut stdout_bytes = Vec::new();
+        let mut stderr_bytes = Vec::new();
+        if let Some(ref mut out) = &mut child.stdout {
+            out.read_to_end(&mut stdout_bytes)?;
+        }
+        if let Some(ref mut err) = &mut child.stderr {
+            err.read_to_end(&mut stderr_bytes)?;
+        }
+        Ok(child.try_wait()?.map(|s| {
+            let (b, _, _) = self.is_interesting(
+                &s,
+                Some(stdout_bytes.as_slice()),
+                Some(stderr_bytes.as_slice()),
+            );
+            b
+        }))
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        Ok(self.wait_with_output(state)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_golden() {
+        let g = Golden::new(Some(Regex::new(r"result: (\d+)").unwrap()));
+        assert_eq!(g.summarize(b"t=1.2s result: 42\nresult: 7"), b"42\x007\x00");
+        assert_eq!(
+            g.outcome(Some(0), b"t=3.4s result: 42", b""),
+            g.outcome(Some(0), b"t=0.1s result: 42", b"")
+        );
+        assert_eq!(Golden::new(None).summarize(b"out"), b"out");
+    }
+
+    #[test]
+    fn test_run_summary() {
+        assert_eq!(last_line(b"a\nerror: b  \n\n"), "error: b");
+        let summary = RunSummary {
+            code: Some(139),
+            stderr: last_line(b"Segmentation fault\n"),
+            ..RunSummary::default()
+        };
+        assert_eq!(
+            summary.to_string(),
+            "exit code 139, stderr: Segmentation fault"
+        );
+    }
+
+    #[test]
+    fn test_args_with_file() {
+        assert_eq!(split_marker("--in={}.c"), Some(("--in=", ".c")));
+        assert_eq!(split_marker("@@"), Some(("", "")));
+        assert_eq!(split_marker("{tmpdir}/x"), None);
+        let args = ["-o", "{tmpdir}/out", "--in=@@.c", "{}.h"];
+        let chk = CmdCheck::new(
+            String::from("cc"),
+            args.iter().map(|s| s.to_string()).collect(),
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        );
+        assert!(chk.needs_file && !chk.uses_stdin);
+        let (file, args) = chk.args_with_file().unwrap();
+        let path = file.unwrap().path().to_string_lossy().into_owned();
+        let dir = std::env::temp_dir();
+        assert_eq!(args[1], format!("{}/out", dir.to_string_lossy()));
+        assert!(path.ends_with(".c"));
+        assert_eq!(args[2], format!("--in={}", path));
+        assert_eq!(args[3], format!("{}.h", path.strip_suffix(".c").unwrap()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memfd() {
+        let chk = CmdCheck::new(
+            String::from("cat"),
+            vec![String::from("@@")],
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        );
+        assert!(chk
+            .memfd_with(&Rope::from(b"x".as_slice()))
+            .unwrap()
+            .is_none());
+        let chk = chk.memfd(true);
+        let (_file, path) = chk
+            .memfd_with(&Rope::from(b"int x;".as_slice()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"int x;");
+        assert_eq!(chk.substitute(&path).0, vec![path]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runs() {
+        let control = Arc::new(Control::new());
+        let chk = CmdCheck::new(
+            String::from("true"),
+            Vec::new(),
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        )
+        .control(Arc::clone(&control));
+        let other = chk.command(String::from("false"), Vec::new());
+        assert!(chk.interesting(b"x").unwrap());
+        assert!(!other.interesting(b"x").unwrap());
+        let state = chk.start(b"y").unwrap();
+        chk.cancel(state).unwrap();
+        assert_eq!(chk.runs(), 3);
+        assert_eq!(control.checks(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_container_args() {
+        let container = Container {
+            program: String::from("podman"),
+            image: String::from("gcc:13"),
+        };
+        let args = container.args(
+            Some(Path::new("/tmp/t.c")),
+            None,
+            &["CC"],
+            Path::new("/tmp/d/cid"),
+            "gcc",
+        );
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--rm",
+                "--init",
+                "--interactive",
+                "--cidfile",
+                "/tmp/d/cid",
+                "--volume",
+                "/tmp/t.c:/tmp/t.c:ro",
+                "--env",
+                "CC",
+                "gcc:13",
+                "gcc"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let chk = CmdCheck::new(
+            String::from("sh"),
+            vec![String::from("-c"), String::from("echo 'hash: 0x1f' >&2")],
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        );
+        let rx = Regex::new(r"hash: (\w+)").unwrap();
+        assert_eq!(chk.fingerprint(b"", &rx).unwrap(), Some(b"0x1f\0".to_vec()));
+        let rx = Regex::new("missing").unwrap();
+        assert_eq!(chk.fingerprint(b"", &rx).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_creduce() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("test.sh");
+        std::fs::write(&script, "#!/bin/sh\ntest $# = 0 && grep -q main in.c\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let chk = CmdCheck::new(
+            script.to_string_lossy().into_owned(),
+            vec![String::from("@@")],
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        )
+        .creduce(String::from("in.c"));
+        assert!(chk.interesting(b"int main;").unwrap());
+        assert!(!chk.interesting(b"int x;").unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("lib.rs");
+        let chk = CmdCheck::new(
+            String::from("grep"),
+            vec![
+                String::from("-q"),
+                String::from("main"),
+                file.to_string_lossy().into_owned(),
+            ],
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        )
+        .in_place(file.clone());
+        assert!(chk.interesting(b"fn main() {}").unwrap());
+        assert!(!chk.interesting(b"fn f() {}").unwrap());
+        assert_eq!(std::fs::read(&file).unwrap(), b"fn f() {}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_verdict() {
+        let chk = CmdCheck::new(
+            String::from("sleep"),
+            vec![String::from("10")],
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            Some(Duration::from_millis(100)),
+        );
+        assert!(!chk.interesting(b"").unwrap());
+        assert!(chk.timeout_verdict(true).interesting(b"").unwrap());
+    }
+
+    #[test]
+    fn test_adaptive_timeout() {
+        let secs = Duration::from_secs;
+        let chk = CmdCheck::new(
+            String::from("true"),
+            Vec::new(),
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            Some(secs(100)),
+        );
+        chk.record_run_time(secs(2));
+        assert_eq!(chk.effective_timeout(), Some(secs(100)));
+        let chk = chk.timeout_multiplier(Some(3.0));
+        for _ in 0..MIN_RUN_TIMES {
+            chk.record_run_time(secs(10));
+        }
+        assert_eq!(chk.effective_timeout(), Some(secs(30)));
+        // Old runs leave the window
+        for _ in 0..RUN_TIME_WINDOW {
+            chk.record_run_time(secs(2));
+        }
+        assert_eq!(chk.run_times.lock().unwrap().len(), RUN_TIME_WINDOW);
+        assert_eq!(chk.effective_timeout(), Some(secs(6)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remote_script() {
+        let chk = CmdCheck::new(
+            String::from("grep"),
+            vec![
+                String::from("-q"),
+                String::from("main"),
+                String::from("@@.rs"),
+            ],
+            vec![0],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            None,
+        );
+        use std::io::Write;
+
+        let script = chk.remote_script();
+        assert!(script.contains("'grep' '-q' 'main' ''\"$d/input\"'.rs'"));
+        // Run it here, as ssh would on the remote host
+        let run = |input: &[u8]| {
+            let mut child = Command::new("sh")
+                .args(["-c", &script])
+                .stdin(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(input).unwrap();
+            child.wait().unwrap().success()
+        };
+        assert!(run(b"fn main() {}"));
+        assert!(!run(b"fn f() {}"));
+    }
+}
@@ -0,0 +1,124 @@
+//! Running checks on other machines over SSH.
+
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RemoteError {
+    #[error("Invalid remote {0}, expected HOST or HOST:N")]
+    Invalid(String),
+    #[error("No remote hosts")]
+    Empty,
+}
+
+/// Hosts that run checks, each up to some number at once. Checks go to the
+/// least busy host. The candidate is sent on the standard input of `ssh`.
+#[derive(Debug)]
+pub struct Remotes {
+    /// Hosts (anything `ssh` accepts) and how many checks each may run
+    hosts: Vec<(String, usize)>,
+    /// Checks running on each host
+    running: Mutex<Vec<usize>>,
+}
+
+/// A check's claim on a host, released on drop
+#[derive(Debug)]
+pub struct Lease {
+    remotes: Arc<Remotes>,
+    index: usize,
+}
+
+impl Lease {
+    pub fn host(&self) -> &str {
+        &self.remotes.hosts[self.index].0
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut running = self
+            .remotes
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        running[self.index] -= 1;
+    }
+}
+
+/// Quote `s` for a POSIX shell
+pub(crate) fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+impl Remotes {
+    pub fn new(hosts: Vec<(String, usize)>) -> Result<Self, RemoteError> {
+        if hosts.is_empty() {
+            return Err(RemoteError::Empty);
+        }
+        let running = Mutex::new(vec![0; hosts.len()]);
+        Ok(Remotes { hosts, running })
+    }
+
+    /// Parse `HOST` (which runs one check at a time) or `HOST:N`
+    pub fn parse_host(spec: &str) -> Result<(String, usize), RemoteError> {
+        let invalid = || RemoteError::Invalid(spec.to_string());
+        let (host, slots) = match spec.rsplit_once(':') {
+            None => (spec, 1),
+            Some((host, n)) => (host, n.parse().map_err(|_| invalid())?),
+        };
+        if host.is_empty() || slots == 0 {
+            return Err(invalid());
+        }
+        Ok((host.to_string(), slots))
+    }
+
+    /// How many checks the hosts may run at once, in total
+    pub fn slots(&self) -> usize {
+        self.hosts.iter().map(|(_, n)| n).sum()
+    }
+
+    /// Claim the host with the most free slots (relative to its number of
+    /// slots)
+    pub fn lease(self: &Arc<Self>) -> Lease {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let index = (0..self.hosts.len())
+            .min_by(|&a, &b| {
+                let load = |i: usize| running[i] as f64 / self.hosts[i].1 as f64;
+                load(a).total_cmp(&load(b))
+            })
+            .unwrap_or(0);
+        running[index] += 1;
+        Lease {
+            remotes: self.clone(),
+            index,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease() {
+        assert_eq!(Remotes::parse_host("a").unwrap(), (String::from("a"), 1));
+        assert_eq!(
+            Remotes::parse_host("me@b:4").unwrap(),
+            (String::from("me@b"), 4)
+        );
+        assert!(Remotes::parse_host("c:0").is_err());
+        let remotes =
+            Arc::new(Remotes::new(vec![(String::from("a"), 1), (String::from("b"), 2)]).unwrap());
+        assert_eq!(remotes.slots(), 3);
+        let first = remotes.lease();
+        assert_eq!(first.host(), "a");
+        let second = remotes.lease();
+        assert_eq!(second.host(), "b");
+        // b is half busy, a is full
+        assert_eq!(remotes.lease().host(), "b");
+        drop(first);
+        assert_eq!(remotes.lease().host(), "a");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
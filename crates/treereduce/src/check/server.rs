@@ -0,0 +1,257 @@
+//! Interestingness tests that stay running and check one candidate after
+//! another, to save the time it takes to start them (e.g., a JVM or an
+//! interpreter loading its libraries), like AFL's forkserver.
+//!
+//! For each candidate, treereduce writes its length in bytes (in decimal) and
+//! a newline to the server's stdin, followed by the candidate. The server
+//! replies with one line on its stdout: `1` if the candidate is interesting,
+//! and anything else (e.g., `0`) if not. Lines left over from earlier
+//! candidates (e.g., by a server that replied with two) are discarded before
+//! each candidate is sent. A server that exits or times out is replaced for
+//! the next candidate.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use tracing::debug;
+
+use crate::rope::Rope;
+
+/// Servers running the same command, each checking one candidate at a time
+pub struct Servers {
+    cmd: String,
+    args: Vec<String>,
+    inherit_stderr: bool,
+    env_clear: bool,
+    env_remove: Vec<String>,
+    env: Vec<(String, String)>,
+    /// Waiting for a candidate
+    idle: Mutex<Vec<Server>>,
+}
+
+impl fmt::Debug for Servers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Servers")
+            .field("cmd", &self.cmd)
+            .field("args", &self.args)
+            .finish()
+    }
+}
+
+struct Server {
+    child: Child,
+    stdin: ChildStdin,
+    /// Verdicts, read from stdout by another thread
+    replies: Receiver<bool>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Server {
+    fn send(&mut self, input: &Rope) -> io::Result<()> {
+        // So that extra lines aren't mistaken for this candidate's verdict
+        let stale = self.replies.try_iter().count();
+        if stale > 0 {
+            debug!(lines = stale, "Discarding extra replies from check server");
+        }
+        writeln!(self.stdin, "{}", input.len())?;
+        input.write_to(&mut self.stdin)?;
+        self.stdin.flush()
+    }
+}
+
+/// A candidate sent to a server, awaiting its verdict
+pub struct Request {
+    server: Server,
+}
+
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("pid", &self.server.child.id())
+            .finish()
+    }
+}
+
+impl Servers {
+    pub fn new(cmd: String, args: Vec<String>, inherit_stderr: bool) -> Self {
+        Servers {
+            cmd,
+            args,
+            inherit_stderr,
+            env_clear: false,
+            env_remove: Vec::new(),
+            env: Vec::new(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Set the environment of the servers, like [`super::CmdCheck::env`]
+    pub fn env(mut self, clear: bool, remove: Vec<String>, set: Vec<(String, String)>) -> Self {
+        self.env_clear = clear;
+        self.env_remove = remove;
+        self.env = set;
+        self
+    }
+
+    fn spawn(&self) -> io::Result<Server> {
+        let mut cmd = Command::new(&self.cmd);
+        if self.env_clear {
+            cmd.env_clear();
+        }
+        for var in &self.env_remove {
+            cmd.env_remove(var);
+        }
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)));
+        let mut child = cmd
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(if self.inherit_stderr {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            })
+            .spawn()?;
+        let missing = || io::Error::new(io::ErrorKind::Other, "Failed to connect to the server");
+        let stdin = child.stdin.take().ok_or_else(missing)?;
+        let stdout = child.stdout.take().ok_or_else(missing)?;
+        let (tx, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line.trim() == "1").is_err() {
+                    break;
+                }
+            }
+        });
+        debug!(pid = child.id(), "Started check server");
+        Ok(Server {
+            child,
+            stdin,
+            replies,
+        })
+    }
+
+    /// Send `input` to an idle server, or a new one
+    pub(crate) fn send(&self, input: &Rope) -> io::Result<Request> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        if let Some(mut server) = idle {
+            if server.send(input).is_ok() {
+                return Ok(Request { server });
+            }
+            // It exited while idle
+        }
+        let mut server = self.spawn()?;
+        server.send(input)?;
+        Ok(Request { server })
+    }
+
+    /// The verdict, if it's ready. A server that exited without one counts
+    /// as a boring verdict.
+    pub(crate) fn try_reply(&self, request: Request) -> Result<bool, Request> {
+        match request.server.replies.try_recv() {
+            Ok(b) => {
+                self.done(request.server);
+                Ok(b)
+            }
+            Err(TryRecvError::Empty) => Err(request),
+            Err(TryRecvError::Disconnected) => {
+                debug!("Check server exited");
+                Ok(false)
+            }
+        }
+    }
+
+    /// Wait for the verdict, or `None` on timeout (which stops the server)
+    pub(crate) fn reply(&self, request: Request, timeout: Option<Duration>) -> Option<bool> {
+        let reply = match timeout {
+            None => request
+                .server
+                .replies
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+            Some(t) => request.server.replies.recv_timeout(t),
+        };
+        match reply {
+            Ok(b) => {
+                self.done(request.server);
+                Some(b)
+            }
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                debug!("Check server exited");
+                Some(false)
+            }
+        }
+    }
+
+    fn done(&self, server: Server) {
+        self.idle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(server);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_servers() {
+        // Interesting if the candidate contains an x
+        let script = r#"while read -r n; do
+            s="$(head -c "$n")"
+            case "$s" in *x*) echo 1 ;; *) echo 0 ;; esac
+        done"#;
+        let servers = Servers::new(
+            String::from("sh"),
+            vec![String::from("-c"), String::from(script)],
+            false,
+        );
+        let check = |s: &[u8]| {
+            let request = servers.send(&Rope::from(s)).unwrap();
+            servers.reply(request, Some(Duration::from_secs(10)))
+        };
+        assert_eq!(check(b"axb"), Some(true));
+        assert_eq!(check(b"ab\n"), Some(false));
+        assert_eq!(check(b""), Some(false));
+        // One server did it all
+        assert_eq!(servers.idle.lock().unwrap().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_servers_extra_lines() {
+        // Replies twice to candidates that contain a y
+        let script = r#"while read -r n; do
+            s="$(head -c "$n")"
+            case "$s" in *y*) echo 1; echo 1 ;; *) echo 0 ;; esac
+        done"#;
+        let servers = Servers::new(
+            String::from("sh"),
+            vec![String::from("-c"), String::from(script)],
+            false,
+        );
+        let check = |s: &[u8]| {
+            let request = servers.send(&Rope::from(s)).unwrap();
+            servers.reply(request, Some(Duration::from_secs(10)))
+        };
+        assert_eq!(check(b"y"), Some(true));
+        // Give the extra line time to arrive
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(check(b"a"), Some(false));
+    }
+}
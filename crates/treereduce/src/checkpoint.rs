@@ -0,0 +1,115 @@
+//! Saving the progress of a reduction, so that it can be resumed after an
+//! interruption.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::observer::{Attempt, Observer, PassDone};
+
+/// Where a reduction is in its pipeline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Position {
+    /// 1-indexed round of the pipeline (see [`crate::Config::fixpoint`])
+    pub round: usize,
+    /// Index of the stage in the pipeline
+    pub stage: usize,
+    /// Number of passes of the stage that are done
+    pub pass: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Checkpoint {
+    pub position: Position,
+    /// The pipeline, to check that a resumed reduction uses the same one
+    pub pipeline: String,
+    /// The smallest interesting input so far
+    pub text: Vec<u8>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Replaces the file atomically, so that it's never partly written
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// Writes a [`Checkpoint`] after each pass, and at most every `interval`
+/// during passes
+#[derive(Debug)]
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    checkpoint: Mutex<(Checkpoint, Instant)>,
+}
+
+impl Checkpointer {
+    pub fn new(path: PathBuf, interval: Duration, checkpoint: Checkpoint) -> Self {
+        Checkpointer {
+            path,
+            interval,
+            checkpoint: Mutex::new((checkpoint, Instant::now())),
+        }
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) {
+        if let Err(e) = checkpoint.save(&self.path) {
+            warn!("Failed to write checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl Observer for Checkpointer {
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        let mut guard = self.checkpoint.lock().unwrap_or_else(|e| e.into_inner());
+        let (checkpoint, saved) = &mut *guard;
+        checkpoint.text = attempt.text.to_vec();
+        if saved.elapsed() >= self.interval {
+            self.save(checkpoint);
+            *saved = Instant::now();
+        }
+    }
+
+    fn pass_done(&self, pass: &PassDone<'_>) {
+        let mut guard = self.checkpoint.lock().unwrap_or_else(|e| e.into_inner());
+        let (checkpoint, saved) = &mut *guard;
+        checkpoint.position = pass.position;
+        checkpoint.text = pass.text.to_vec();
+        self.save(checkpoint);
+        *saved = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint() {
+        let path =
+            std::env::temp_dir().join(format!("treereduce-checkpoint-{}.json", std::process::id()));
+        let checkpoint = Checkpoint {
+            position: Position {
+                round: 1,
+                stage: 2,
+                pass: 1,
+            },
+            pipeline: String::from("delete,hoist"),
+            text: b"int main() {}\xff".to_vec(),
+        };
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), checkpoint);
+        fs::remove_file(&path).unwrap();
+    }
+}
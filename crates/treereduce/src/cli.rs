@@ -2,23 +2,63 @@ use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
+use std::io::{IsTerminal, Read, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
-use clap::{ArgGroup, Parser};
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, ArgMatches, Parser};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use regex::Regex;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 use tracing_subscriber::fmt::format::FmtSpan;
 use tree_sitter::Tree;
 
-use crate::check::{Check, CmdCheck};
+use crate::check::{
+    is_marker, stable_hash, CachedCheck, Check, CmdCheck, CompositeCheck, Container, Golden,
+    Remotes, RetryCheck, Sandbox, Vote,
+};
+use crate::checkpoint::{Checkpoint, Checkpointer};
+use crate::control::Control;
+use crate::jobserver::Jobserver;
+use crate::languages::LanguageTransform;
+use crate::observer::Observers;
 use crate::original::Original;
-use crate::reduce;
+use crate::pipeline::{Pass, Pipeline, Stage};
+use crate::provenance::Provenance;
+use crate::reduce::{self, Algorithm, Injections, Metric, Preserve, Priority, Schedule};
+use crate::reducer::{DEFAULT_MIN_REDUCTION, DEFAULT_NUM_PASSES};
+use crate::snapshot::{Every, Snapshotter};
+use crate::stats::{StageTotal, Stats};
+use crate::trace::Tracer;
+use crate::transform::Transforms;
+use crate::variants::{numbered, Variants};
 
+mod config;
+mod corpus;
+mod dry_run;
+mod explain;
+mod flags;
 mod formatter;
+mod package;
+mod pretty;
+mod progress;
+mod project;
+mod selftest;
+mod signals;
+mod subcommand;
+mod template;
+#[cfg(unix)]
+mod tui;
+mod watch;
+
+pub use config::{parse_args, parse_args_with, DEFAULT_CONFIG};
+
+/// How often to save checkpoints during passes
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(clap::ValueEnum, Debug, Clone, PartialEq, Eq)]
 pub enum OnParseError {
@@ -60,23 +100,85 @@ fn handle_parse_errors(path: &str, tree: &Tree, on_parse_error: &OnParseError) {
     }
 }
 
-const DEFAULT_MIN_REDUCTION: usize = 2;
 const FAST_MIN_REDUCTION: usize = 4;
 const SLOW_MIN_REDUCTION: usize = 1;
-const DEFAULT_NUM_PASSES: usize = 2;
 const FAST_NUM_PASSES: usize = 1;
 
 /// Minimize a program
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[command(author, version, about, long_about = None,
           group(ArgGroup::new("fast-xor-slow").arg("fast").arg("slow")),
           group(ArgGroup::new("passes-xor-stable").arg("passes").arg("stable")))]
 pub struct Args {
-    /// Source code to consume; if empty, parse from stdin
+    /// Source code to consume; if empty or '-', read from stdin
     #[arg(
         short, long, default_value = None, value_name = "FILE")]
     pub source: Option<String>,
+
+    /// Read options from this TOML file (by default, treereduce.toml in the
+    /// current directory, if there is one)
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// Don't read treereduce.toml
+    #[arg(long, conflicts_with = "config")]
+    pub no_config: bool,
     // todo: pathbuf, default_value_os_t
+    /// Reduce each file in DIR with the same interestingness check, sharing
+    /// its cache, and write the results to --corpus-output
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["source", "resume", "checkpoint", "cache_dir", "golden", "golden_regex", "diff", "dry_run", "tui", "snapshot_every", "explain_rejected"]
+    )]
+    pub corpus: Option<String>,
+
+    /// Directory for the results of --corpus, and a summary (summary.json)
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = "treereduce.corpus",
+        requires = "corpus"
+    )]
+    pub corpus_output: String,
+
+    /// Only reduce one file (the smallest) of each group in --corpus with
+    /// the same captures of REGEX (or matches, if it has no groups) in the
+    /// output of the interestingness check, e.g., a stack hash
+    #[arg(long, value_name = "REGEX", requires = "corpus")]
+    pub corpus_dedup: Option<String>,
+
+    /// Test the reducer on each file in DIR instead of reducing it: reduce it
+    /// as if every candidate were interesting, and report candidates that
+    /// grew or didn't parse, and results with new parse errors or changes
+    /// that weren't checked. The check is ignored.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["source", "corpus", "files", "replay", "resume", "checkpoint", "cache_dir", "dry_run", "tui", "watch", "template"]
+    )]
+    pub selftest: Option<String>,
+
+    /// Reduce several files together, in place, e.g., the sources of a
+    /// crate. The check runs as-is (without '@@'), so it should read the
+    /// files, like a build command. The originals are saved as FILE.orig.
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 1..,
+        conflicts_with_all = ["source", "corpus", "resume", "checkpoint", "cache_dir", "golden", "golden_regex", "diff", "dry_run", "tui", "snapshot_every", "explain_rejected", "creduce", "format_cmd", "stop_at_bytes", "stop_at_percent", "inline_includes"]
+    )]
+    pub files: Vec<PathBuf>,
+
+    /// Apply the edits recorded with --trace to the input and print the
+    /// result, instead of reducing it. The check (if any) verifies the result.
+    #[arg(
+        long,
+        value_name = "TRACE",
+        conflicts_with_all = ["corpus", "files", "trace", "resume", "checkpoint", "dry_run", "tui"]
+    )]
+    pub replay: Option<String>,
+
     /// Behavior on parse errors
     #[arg(long, default_value_t = OnParseError::Warn, value_name = "CHOICE")]
     on_parse_error: OnParseError,
@@ -85,25 +187,114 @@ pub struct Args {
     #[arg(short, long, default_value_t = num_cpus::get())]
     pub jobs: usize,
 
-    /// Log messages in JSON format
-    #[arg(long, default_value_t = false)]
+    /// Maximum number of concurrent interestingness checks (default: --jobs)
+    #[arg(long, value_name = "N")]
+    pub oracle_jobs: Option<usize>,
+
+    /// Take a token from this make jobserver (fifo:PATH or R,W) for each
+    /// concurrent check past the first (default: the one in MAKEFLAGS)
+    #[arg(long, value_name = "AUTH")]
+    pub jobserver: Option<String>,
+
+    /// Log messages in JSON format, with spans for each pass and candidate
+    #[arg(long, visible_alias = "log-json", default_value_t = false)]
     pub json: bool,
 
     /// File to output, use '-' for stdout
     #[arg(short, long, default_value = "treereduce.out")]
     pub output: String,
 
+    /// Write a unified diff of the input and the result to FILE (or to
+    /// stdout, if FILE is '-' or not given)
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = "-"
+    )]
+    pub diff: Option<String>,
+
+    /// Write a JSON map from byte ranges of the result to the byte ranges of
+    /// the input they came from to FILE, for highlighting what's left
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["corpus", "files", "replay", "resume", "selftest"])]
+    pub annotations: Option<String>,
+
+    /// Bundle the result, a script that runs the check on it, and the stats
+    /// into PATH (a directory, or a tarball if it ends in .tar.gz or .tar),
+    /// with a README.md to attach to a bug report
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["corpus", "files", "replay", "selftest", "dry_run"])]
+    pub package: Option<String>,
+
+    /// Include the output of CMD (e.g., 'clang --version') in the versions
+    /// listed by --package
+    #[arg(long, value_name = "CMD", requires = "package")]
+    pub package_tool: Vec<String>,
+
+    /// Bundle FILE, an existing result, into the PATH of --package instead of
+    /// reducing the input. The check verifies it, unless --no-verify.
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "package",
+        conflicts_with_all = ["corpus", "files", "replay", "selftest", "resume", "checkpoint", "dry_run", "tui", "watch"]
+    )]
+    pub package_result: Option<String>,
+
+    /// Format the result with CMD (split on whitespace, e.g., 'clang-format'
+    /// or 'rustfmt --emit stdout'), which reads stdin and writes stdout; the
+    /// formatted result is kept if it's still interesting
+    #[arg(long, value_name = "CMD")]
+    pub format_cmd: Option<String>,
+
+    /// Also write the K smallest interesting variants with parse trees unlike
+    /// the result's (and each other's) to STEM.1.EXT through STEM.K.EXT for
+    /// an OUTPUT of STEM.EXT, smallest first
+    #[arg(long, value_name = "K", conflicts_with_all = ["corpus", "files", "replay"])]
+    pub keep_variants: Option<usize>,
+
     /// Print statistics
     #[arg(long, default_value_t = false)]
     pub stats: bool,
 
+    /// Write statistics to FILE, as JSON
+    #[arg(long, value_name = "FILE")]
+    pub stats_json: Option<String>,
+
+    /// Don't show progress, even if stderr is a terminal
+    #[arg(long, default_value_t = false)]
+    pub no_progress: bool,
+
+    /// Show the progress of the reduction in a full-screen view, with keys to
+    /// pause it, skip passes, or finish early
+    #[arg(long, default_value_t = false, conflicts_with = "json")]
+    pub tui: bool,
+
     #[clap(flatten)]
     verbose: Verbosity<InfoLevel>,
 
-    /// Exit code to consider interesting
-    #[arg(help_heading = "Interestingness check options",
-          long, default_values_t = vec![0], value_name = "CODE")]
-    interesting_exit_code: Vec<i32>,
+    /// Exit codes to consider interesting: numbers, ranges like '128-159', or
+    /// 'nonzero'. A check killed by a signal exits with 128 plus the signal
+    /// (see also --interesting-signal).
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        visible_alias = "interesting-exit-codes",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        default_value = "0",
+        value_name = "CODES"
+    )]
+    interesting_exit_code: Vec<String>,
+
+    /// Only termination by these signals (e.g., SIGSEGV,SIGABRT) is
+    /// interesting, instead of the exit code (Unix only)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_delimiter = ',',
+        value_name = "SIGNAL"
+    )]
+    interesting_signal: Vec<String>,
 
     /// Regex to match interesting stdout
     #[arg(
@@ -121,25 +312,28 @@ pub struct Args {
     )]
     interesting_stderr: Option<String>,
 
-    /// Regex to match *uninteresting* stdout, overrides interesting regex
+    /// Regex to match *uninteresting* stdout, overrides the exit code and
+    /// interesting regexes
     #[arg(
         help_heading = "Interestingness check options",
         long,
-        value_name = "REGEX",
-        requires = "interesting_stdout"
+        visible_alias = "stdout-not-regex",
+        value_name = "REGEX"
     )]
     uninteresting_stdout: Option<String>,
 
-    /// Regex to match *uninteresting* stderr, overrides interesting regex
+    /// Regex to match *uninteresting* stderr, overrides the exit code and
+    /// interesting regexes
     #[arg(
         help_heading = "Interestingness check options",
         long,
-        value_name = "REGEX",
-        requires = "interesting_stderr"
+        visible_alias = "stderr-not-regex",
+        value_name = "REGEX"
     )]
     uninteresting_stderr: Option<String>,
 
-    /// Don't verify interestingness of the initial test case
+    /// Don't verify interestingness of the initial test case, or of the
+    /// result once it's written
     #[arg(
         help_heading = "Interestingness check options",
         long,
@@ -163,48 +357,560 @@ pub struct Args {
     )]
     pub inherit_stderr: bool,
 
+    /// Don't cache the verdicts of the interestingness check
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false
+    )]
+    pub no_cache: bool,
+
+    /// Save verdicts of the interestingness check and the best result so far
+    /// in DIR, and reuse them in later runs
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "DIR",
+        conflicts_with = "no_cache"
+    )]
+    pub cache_dir: Option<String>,
+
+    /// After reducing, wait for the input or the test script to change, and
+    /// then reduce again (until interrupted)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false,
+        requires = "source",
+        conflicts_with_all = ["corpus", "files", "tui", "replay", "resume"]
+    )]
+    pub watch: bool,
+
+    /// Start the check once per job, and send it one candidate after another
+    /// on stdin, each after a line with its length, reading '1' (if it's
+    /// interesting) or '0' from stdout (see docs)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["golden", "golden_regex", "interesting_stdout", "interesting_stderr", "uninteresting_stdout", "uninteresting_stderr", "interesting_signal", "remote", "check_container", "sandbox", "creduce", "reduce_args"]
+    )]
+    pub server: bool,
+
     /// Directory in which to place temporary (@@) files
     #[arg(
         help_heading = "Interestingness check options",
         long, default_value = None, value_name = "DIR")]
     pub temp_dir: Option<String>,
 
-    /// Timeout for the interestingness check (seconds)
+    /// Keep candidate files in memory: in a memfd if the @@ has no suffix (on
+    /// Linux), else in /dev/shm unless --temp-dir is given
+    #[arg(help_heading = "Interestingness check options", long)]
+    pub in_memory: bool,
+
+    /// Timeout for the interestingness check (seconds)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value = None,
+        value_name = "SECS"
+    )]
+    pub timeout: Option<u64>,
+
+    /// Limit the memory (address space) of the interestingness check (Unix only)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "MB"
+    )]
+    pub oracle_mem_limit: Option<u64>,
+
+    /// Limit the CPU time of the interestingness check (Unix only)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "SECS"
+    )]
+    pub oracle_cpu_limit: Option<u64>,
+
+    /// Run the interestingness check in a sandbox with a private /tmp, using
+    /// bubblewrap (bwrap)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false
+    )]
+    pub sandbox: bool,
+
+    /// Run the check like C-Reduce runs interestingness scripts: with no
+    /// arguments, in a fresh temporary directory containing only the
+    /// candidate, under the file name of the input
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false,
+        requires = "source"
+    )]
+    pub creduce: bool,
+
+    /// First, delete as many of the check's arguments as possible (except
+    /// those with '@@', '{}', or '-'), then reduce the input with the rest
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        conflicts_with_all = ["creduce", "golden", "golden_regex", "corpus", "files", "replay", "resume", "dry_run"]
+    )]
+    pub reduce_args: bool,
+
+    /// Run the interestingness check on HOST over ssh, up to N at once
+    /// (default: 1); repeat to spread checks over several hosts
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "HOST[:N]",
+        conflicts_with_all = ["sandbox", "creduce", "files", "oracle_mem_limit", "oracle_cpu_limit", "env", "env_remove", "clear_env"]
+    )]
+    pub remote: Vec<String>,
+
+    /// Run the interestingness check in a container from IMAGE, with the
+    /// candidate mounted read-only
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "IMAGE",
+        conflicts_with_all = ["sandbox", "remote", "creduce", "files", "clear_env", "env_remove", "oracle_mem_limit", "oracle_cpu_limit"]
+    )]
+    pub check_container: Option<String>,
+
+    /// Program that runs containers for --check-container (default: podman,
+    /// or else docker)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "PROGRAM",
+        requires = "check_container"
+    )]
+    pub container_runtime: Option<String>,
+
+    /// Make the filesystem read-only (except /tmp) in the sandbox
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false,
+        requires = "sandbox"
+    )]
+    pub sandbox_read_only: bool,
+
+    /// Interesting if the exit code, stdout, and stderr are the same as for
+    /// the original input
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false
+    )]
+    pub golden: bool,
+
+    /// Compare only the captures of REGEX in stdout and stderr to the
+    /// original's (implies --golden)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "REGEX"
+    )]
+    pub golden_regex: Option<String>,
+
+    /// Set an environment variable for the interestingness check; VALUE may
+    /// contain '@@' or '{}'
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "KEY=VALUE"
+    )]
+    pub env: Vec<String>,
+
+    /// Remove an environment variable for the interestingness check
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "KEY"
+    )]
+    pub env_remove: Vec<String>,
+
+    /// Run the interestingness check in an empty environment (except for
+    /// --env)
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = false
+    )]
+    pub clear_env: bool,
+
+    /// Another command (split on whitespace) that must also be interesting,
+    /// judged by its exit code; may be given several times
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "CMD"
+    )]
+    pub and_check: Vec<String>,
+
+    /// Another command (split on whitespace) that is interesting on its own,
+    /// even if the main check isn't; may be given several times
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "CMD"
+    )]
+    pub or_check: Vec<String>,
+
+    /// Another command (split on whitespace) that must NOT be interesting,
+    /// e.g., the same test with the last good version of a compiler; may be
+    /// given several times
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_name = "CMD"
+    )]
+    pub not_check: Vec<String>,
+
+    /// Run the interestingness check up to N more times to confirm each
+    /// verdict, for flaky checks
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        default_value_t = 0,
+        value_name = "N"
+    )]
+    pub oracle_retries: usize,
+
+    /// How to combine the verdicts of retried checks
+    #[arg(
+        help_heading = "Interestingness check options",
+        long,
+        value_enum,
+        default_value_t = Vote::Majority
+    )]
+    pub oracle_vote: Vote,
+
+    /// Kill checks that take this many times longer than the median of the
+    /// latest interesting ones (e.g., 3)
+    #[arg(help_heading = "Interestingness check options", long, value_name = "X")]
+    pub timeout_multiplier: Option<f64>,
+
+    /// Same as --passes 1, --min-reduction 4
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub fast: bool,
+
+    /// Same as --stable, --fixpoint, --min-reduction 1, --tokens
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub slow: bool,
+
+    /// How many reduction passes to make
+    #[arg(help_heading = "Reduction options", long, default_value_t = DEFAULT_NUM_PASSES)]
+    pub passes: usize,
+
+    /// Minimum size reduction to attempt
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        default_value_t = DEFAULT_MIN_REDUCTION,
+        value_name = "BYTES"
+    )]
+    pub min_reduction: usize,
+
+    /// How to search for reductions; hdd (hierarchical delta debugging)
+    /// ignores --pipeline and the other pass options
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_enum,
+        default_value_t = Algorithm::Treereduce,
+        conflicts_with_all = ["checkpoint", "resume"]
+    )]
+    pub algorithm: Algorithm,
+
+    /// How to order the stages of --pipeline in each round; adaptive runs the
+    /// stages whose passes find the most candidates interesting first, and
+    /// leaves out passes that found nothing until the input changes
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_enum,
+        default_value_t = Schedule::Static
+    )]
+    pub schedule: Schedule,
+
+    /// What to minimize; only candidates that are smaller by this metric are
+    /// accepted
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_enum,
+        default_value_t = Metric::Bytes
+    )]
+    pub metric: Metric,
+
+    /// Order in which to try reductions
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_enum,
+        default_value_t = Priority::Size
+    )]
+    pub priority: Priority,
+
+    /// Scale the priority (size) of reductions of nodes of kind KIND by W,
+    /// e.g., by 0 to try them only after all others
+    #[arg(help_heading = "Reduction options", long, value_name = "KIND=W")]
+    pub weight: Vec<String>,
+
+    /// Read --weight settings from FILE, one KIND=W per line
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub weights: Option<String>,
+
+    /// First, cut whole lines from the start and end of the input by
+    /// bisection, without regard to its syntax; fast on huge inputs
+    #[arg(help_heading = "Reduction options", long)]
+    pub bisect: bool,
+
+    /// Produce the same result on every run (with the same input, options,
+    /// and interestingness test), regardless of --jobs
+    #[arg(help_heading = "Reduction options", long)]
+    pub deterministic: bool,
+
+    /// Seed for --deterministic; different seeds try reductions of the same
+    /// size in different orders
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        default_value_t = 0,
+        value_name = "N",
+        requires = "deterministic"
+    )]
+    pub seed: u64,
+
+    /// Try up to N independent deletions at once, then one at a time if that
+    /// fails
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        default_value_t = 1,
+        value_name = "N"
+    )]
+    pub batch: usize,
+
+    /// Don't try replacing nodes with their descendants
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub no_hoist: bool,
+
+    /// Try replacing nodes of kind KIND (or its subtypes) with TEXT
+    #[arg(help_heading = "Reduction options", long, value_name = "KIND=TEXT")]
+    pub replace: Vec<String>,
+
+    /// Rename identifiers to short names
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub rename: bool,
+
+    /// First, replace each #include "FILE" with the contents of FILE (if it
+    /// exists, next to the input or in an --include-dir), recursively
+    #[arg(help_heading = "Reduction options", long)]
+    pub inline_includes: bool,
+
+    /// Directory to search for --inline-includes; may be given several times
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "DIR",
+        requires = "inline_includes"
+    )]
+    pub include_dir: Vec<PathBuf>,
+
+    /// The language's transforms, less those disabled by their flags (see
+    /// [`parse_args_with`])
+    #[arg(skip)]
+    transforms: Transforms,
+
+    /// Don't try replacing literals with smaller ones
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub no_shrink_literals: bool,
+
+    /// Don't start by deleting comments and docstrings
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub keep_comments: bool,
+
+    /// Never delete comments (or docstrings) matching REGEX, e.g., 'KEEP' or
+    /// 'SPDX-License-Identifier'
+    #[arg(help_heading = "Reduction options", long, value_name = "REGEX")]
+    pub keep_comment_regex: Option<String>,
+
+    /// Finish with a pass that deletes tokens and collapses whitespace
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub tokens: bool,
+
+    /// Only reduce nodes captured by the tree-sitter query in FILE
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub only_query: Option<String>,
+
+    /// Afterwards, reduce the regions captured by the tree-sitter injection
+    /// query in FILE (@injection.content) with the grammars of their
+    /// languages, see --inject
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "FILE",
+        requires = "inject"
+    )]
+    pub injection_query: Option<String>,
+
+    /// Grammar for an injected language: NAME=LIBRARY, a compiled tree-sitter
+    /// grammar, with its node-types.json next to it
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "NAME=LIBRARY",
+        requires = "injection_query"
+    )]
+    pub inject: Vec<String>,
+
+    /// Never change nodes captured by the tree-sitter query in FILE
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub skip_query: Option<String>,
+
+    /// Never change this region: 'bytes:START-END', 'lines:FIRST-LAST', or
+    /// 'query:FILE'
+    #[arg(help_heading = "Reduction options", long, value_name = "REGION")]
+    pub preserve: Vec<String>,
+
+    /// Reduce toward the skeleton in FILE: keep the parts of the input
+    /// shaped like parts of it, and rename identifiers to its names
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["corpus", "files"]
+    )]
+    pub template: Option<String>,
+
+    /// Passes to run, e.g., 'delete+hoist:2,rename,tokens:1' (see docs)
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "STAGES",
+        conflicts_with_all = ["pipeline_file", "no_hoist", "rename", "no_shrink_literals", "tokens", "keep_comments"]
+    )]
+    pub pipeline: Option<String>,
+
+    /// Read --pipeline from a file, one stage per line
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["no_hoist", "rename", "no_shrink_literals", "tokens", "keep_comments"]
+    )]
+    pub pipeline_file: Option<String>,
+
+    /// Re-run the whole pipeline until a round makes no progress
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub fixpoint: bool,
+
+    /// Reject candidates with more parse errors (ERROR or MISSING nodes) than
+    /// the smallest interesting one so far, without running the check
+    #[arg(help_heading = "Reduction options", long)]
+    pub no_new_errors: bool,
+
+    /// Finally, check that deleting any one remaining node makes the result
+    /// uninteresting, and delete the nodes that don't
+    #[arg(help_heading = "Reduction options", long)]
+    pub min_check: bool,
+
+    /// After each pass, check its result again, bypassing the cache, and back
+    /// out of the pass's accepted edits until it's interesting, for checks
+    /// whose behavior drifts
+    #[arg(help_heading = "Reduction options", long)]
+    pub verify_passes: bool,
+
+    /// Run passes until no progress is made - may be slow
+    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
+    pub stable: bool,
+
+    /// Stop after this long (e.g., 90s, 30m, or 2h), and output the smallest
+    /// result so far
+    #[arg(help_heading = "Reduction options", long, value_name = "DURATION")]
+    pub max_time: Option<String>,
+
+    /// Stop after running the interestingness test N times (counting
+    /// --oracle-retries and the other checks, but not cached verdicts), and
+    /// output the smallest result so far
+    #[arg(help_heading = "Reduction options", long, value_name = "N")]
+    pub max_oracle_calls: Option<usize>,
+
+    /// Stop after N passes in total (see --passes for the limit per stage),
+    /// and output the smallest result so far
+    #[arg(help_heading = "Reduction options", long, value_name = "N")]
+    pub max_passes: Option<usize>,
+
+    /// Stop once the result is at most N bytes
+    #[arg(help_heading = "Reduction options", long, value_name = "N")]
+    pub stop_at_bytes: Option<usize>,
+
+    /// Stop once the result is at most P percent of the size of the input
+    #[arg(
+        help_heading = "Reduction options",
+        long,
+        value_name = "P",
+        conflicts_with = "stop_at_bytes"
+    )]
+    pub stop_at_percent: Option<f64>,
+
+    /// Save progress to FILE after each pass, and periodically during them
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub checkpoint: Option<String>,
+
+    /// Record each accepted edit to FILE (as JSON lines), see --replay
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub trace: Option<String>,
+
+    /// Print the candidates that each pass would try, without running the
+    /// interestingness check
     #[arg(
-        help_heading = "Interestingness check options",
+        help_heading = "Reduction options",
         long,
-        default_value = None,
-        value_name = "SECS"
+        default_value_t = false,
+        conflicts_with_all = ["cache_dir", "tui"]
     )]
-    pub timeout: Option<u64>,
+    pub dry_run: bool,
 
-    /// Same as --passes 1, --min-reduction 4
+    /// Log the verdict on each candidate, with the exit code and last line of
+    /// output of the interestingness check
     #[arg(help_heading = "Reduction options", long, default_value_t = false)]
-    pub fast: bool,
+    pub explain: bool,
 
-    /// Same as --stable, --min-reduction 1
-    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
-    pub slow: bool,
+    /// Like --explain, and also save rejected candidates in DIR
+    #[arg(help_heading = "Reduction options", long, value_name = "DIR")]
+    pub explain_rejected: Option<String>,
 
-    /// How many reduction passes to make
-    #[arg(help_heading = "Reduction options", long, default_value_t = DEFAULT_NUM_PASSES)]
-    pub passes: usize,
+    /// Resume from a --checkpoint FILE, and keep saving progress to it
+    #[arg(help_heading = "Reduction options", long, value_name = "FILE")]
+    pub resume: Option<String>,
 
-    /// Minimum size reduction to attempt
+    /// Save the smallest result so far to --snapshot-dir every N edits, or
+    /// every N seconds with a suffix of 's' (e.g., 30s)
+    #[arg(help_heading = "Reduction options", long, value_name = "N")]
+    pub snapshot_every: Option<String>,
+
+    /// Directory for --snapshot-every
     #[arg(
         help_heading = "Reduction options",
         long,
-        default_value_t = DEFAULT_MIN_REDUCTION,
-        value_name = "BYTES"
+        value_name = "DIR",
+        default_value = "treereduce.snapshots",
+        requires = "snapshot_every"
     )]
-    pub min_reduction: usize,
-
-    /// Run passes until no progress is made - may be slow
-    #[arg(help_heading = "Reduction options", long, default_value_t = false)]
-    pub stable: bool,
+    pub snapshot_dir: String,
 
-    /// Interestingness check; fed test case on stdin or via '@@' file
-    #[arg(value_name = "CMD", required = true, num_args = 1..)]
+    /// Interestingness check; fed test case on stdin (or '-') or via '@@' (or
+    /// '{}') file, '{tmpdir}' is the temporary directory
+    #[arg(value_name = "CMD", required_unless_present_any = ["replay", "selftest"], num_args = 1..)]
     pub check: Vec<String>,
 }
 
@@ -212,6 +918,20 @@ fn read_file(file: &str) -> Result<String> {
     fs::read_to_string(file).with_context(|| format!("Failed to read file {}", file))
 }
 
+/// The directory for temporary files, which is RAM-backed with `--in-memory`
+fn temp_dir(args: &Args) -> Option<String> {
+    if args.temp_dir.is_some() || !args.in_memory {
+        return args.temp_dir.clone();
+    }
+    let shm = std::path::Path::new("/dev/shm");
+    if shm.is_dir() {
+        Some(shm.to_string_lossy().into_owned())
+    } else {
+        warn!("No /dev/shm, keeping temporary files in the default directory");
+        None
+    }
+}
+
 fn make_temp_dir(dir: &Option<String>) -> Result<()> {
     if let Some(d) = dir {
         // Just best-effort, to error out early
@@ -221,7 +941,7 @@ fn make_temp_dir(dir: &Option<String>) -> Result<()> {
     Ok(())
 }
 
-fn parse(language: tree_sitter::Language, code: &str) -> Result<tree_sitter::Tree> {
+fn parse(language: tree_sitter::Language, code: &[u8]) -> Result<tree_sitter::Tree> {
     let mut parser = tree_sitter::Parser::new();
     parser
         .set_language(language)
@@ -230,17 +950,21 @@ fn parse(language: tree_sitter::Language, code: &str) -> Result<tree_sitter::Tre
 }
 
 #[inline]
-fn stdin_string() -> Result<String> {
-    let mut stdin_str: String = String::new();
-    io::stdin().read_to_string(&mut stdin_str)?;
-    Ok(stdin_str)
+fn stdin_bytes() -> Result<Vec<u8>> {
+    let mut stdin_bytes = Vec::new();
+    io::stdin().read_to_end(&mut stdin_bytes)?;
+    Ok(stdin_bytes)
 }
 
-fn check(args: &Args) -> Result<CmdCheck> {
+/// The main check. With `--golden`, runs it on `src` to record its output.
+fn check(args: &Args, src: &[u8]) -> Result<CmdCheck> {
     if args.check.is_empty() {
         error!("Internal error: empty interestingness check!");
         std::process::exit(1);
     }
+    if cfg!(not(unix)) && (args.oracle_mem_limit.is_some() || args.oracle_cpu_limit.is_some()) {
+        bail!("--oracle-mem-limit and --oracle-cpu-limit are only supported on Unix");
+    }
     let mut argv: Vec<_> = args.check.iter().collect();
     let cmd = argv[0];
     argv.remove(0);
@@ -260,11 +984,18 @@ fn check(args: &Args) -> Result<CmdCheck> {
         Some(r) => Some(Regex::new(r).context("Invalid uninteresting stderr regex")?),
         None => None,
     };
-    Ok(CmdCheck::new(
+    let golden = match &args.golden_regex {
+        Some(r) => Some(Golden::new(Some(
+            Regex::new(r).context("Invalid golden output regex")?,
+        ))),
+        None if args.golden => Some(Golden::new(None)),
+        None => None,
+    };
+    let mut chk = CmdCheck::new(
         cmd.to_string(),
         argv.iter().map(|s| s.to_string()).collect(),
-        args.interesting_exit_code.clone(),
-        args.temp_dir.clone(),
+        Vec::new(),
+        temp_dir(args),
         stdout_regex,
         stderr_regex,
         un_stdout_regex,
@@ -272,35 +1003,234 @@ fn check(args: &Args) -> Result<CmdCheck> {
         args.inherit_stdout,
         args.inherit_stderr,
         args.timeout.map(Duration::from_secs),
-    ))
+    )
+    .timeout_multiplier(args.timeout_multiplier.filter(|m| *m > 0.0))
+    .limits(
+        args.oracle_mem_limit
+            .map(|mb| mb.saturating_mul(1024 * 1024)),
+        args.oracle_cpu_limit,
+    )
+    .sandbox(sandbox(args)?)
+    .container(container(args)?)
+    .memfd(args.in_memory)
+    .exit_code_ranges(exit_codes(&args.interesting_exit_code)?)
+    .signals(
+        args.interesting_signal
+            .iter()
+            .map(|s| signal(s))
+            .collect::<Result<_>>()?,
+    )
+    .env(
+        args.clear_env,
+        args.env_remove.clone(),
+        args.env
+            .iter()
+            .map(|kv| match kv.split_once('=') {
+                Some((k, v)) => Ok((k.to_string(), v.to_string())),
+                None => bail!("Invalid --env {}, expected KEY=VALUE", kv),
+            })
+            .collect::<Result<_>>()?,
+    )
+    .golden(golden)
+    .explain(args.explain || args.explain_rejected.is_some())
+    .server(args.server);
+    if args.server && chk.needs_file {
+        bail!("--server sends candidates on stdin, so the check can't take @@");
+    }
+    if args.creduce {
+        if !argv.is_empty() {
+            bail!("--creduce runs the check without arguments");
+        }
+        let name = args
+            .source
+            .as_deref()
+            .and_then(|s| Path::new(s).file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("input"));
+        chk = chk.creduce(name);
+    }
+    if let Some(remotes) = remotes(args)? {
+        chk = chk.remotes(Arc::new(remotes));
+    }
+    if (args.golden || args.golden_regex.is_some()) && !args.dry_run {
+        chk.record_golden(src)
+            .context("Failed to record the output of the original input")?;
+    }
+    Ok(chk)
+}
+
+fn remotes(args: &Args) -> Result<Option<Remotes>> {
+    if args.remote.is_empty() {
+        return Ok(None);
+    }
+    let hosts = args
+        .remote
+        .iter()
+        .map(|r| Remotes::parse_host(r))
+        .collect::<Result<_, _>>()?;
+    Ok(Some(Remotes::new(hosts)?))
+}
+
+/// Parse a signal name (with or without `SIG`) or number
+#[cfg(unix)]
+fn signal(name: &str) -> Result<i32> {
+    if let Ok(n) = name.parse() {
+        return Ok(n);
+    }
+    let upper = name.to_uppercase();
+    Ok(match upper.strip_prefix("SIG").unwrap_or(&upper) {
+        "ABRT" => libc::SIGABRT,
+        "ALRM" => libc::SIGALRM,
+        "BUS" => libc::SIGBUS,
+        "FPE" => libc::SIGFPE,
+        "HUP" => libc::SIGHUP,
+        "ILL" => libc::SIGILL,
+        "INT" => libc::SIGINT,
+        "KILL" => libc::SIGKILL,
+        "PIPE" => libc::SIGPIPE,
+        "QUIT" => libc::SIGQUIT,
+        "SEGV" => libc::SIGSEGV,
+        "SYS" => libc::SIGSYS,
+        "TERM" => libc::SIGTERM,
+        "TRAP" => libc::SIGTRAP,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "XCPU" => libc::SIGXCPU,
+        "XFSZ" => libc::SIGXFSZ,
+        _ => bail!("Unknown signal {}", name),
+    })
+}
+
+#[cfg(not(unix))]
+fn signal(_name: &str) -> Result<i32> {
+    bail!("--interesting-signal is only supported on Unix")
+}
+
+/// Parse exit codes like `1`, `128-159`, or `nonzero`
+fn exit_codes(specs: &[String]) -> Result<Vec<RangeInclusive<i32>>> {
+    let mut ranges = Vec::new();
+    for spec in specs {
+        if spec == "nonzero" {
+            ranges.push(i32::MIN..=-1);
+            ranges.push(1..=i32::MAX);
+        } else if let Ok(code) = spec.parse() {
+            ranges.push(code..=code);
+        } else {
+            // The lower bound may be negative, e.g., '-5--1'
+            let range = spec
+                .get(1..)
+                .and_then(|s| s.find('-'))
+                .map(|i| i + 1)
+                .map(|i| (&spec[..i], &spec[i + 1..]))
+                .and_then(|(lo, hi)| Some(lo.parse().ok()?..=hi.parse().ok()?))
+                .filter(|r: &RangeInclusive<i32>| !r.is_empty());
+            match range {
+                Some(r) => ranges.push(r),
+                None => bail!(
+                    "Invalid exit code {}, expected a number, a range like 128-159, or nonzero",
+                    spec
+                ),
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+/// The main check, combined with `--and-check` and `--or-check`
+fn composite_check(args: &Args, src: &[u8]) -> Result<CompositeCheck<CmdCheck>> {
+    let main = check(args, src)?;
+    let other = |cmd: &String| {
+        let mut words = cmd.split_whitespace().map(String::from);
+        match words.next() {
+            Some(c) => Ok(main.command(c, words.collect())),
+            None => bail!("Empty --and-check, --or-check, or --not-check"),
+        }
+    };
+    let and = args.and_check.iter().map(other).collect::<Result<_>>()?;
+    let or = args.or_check.iter().map(other).collect::<Result<_>>()?;
+    // A timeout rejects the candidate, like it does for the other checks
+    let not = args
+        .not_check
+        .iter()
+        .map(|c| Ok(other(c)?.timeout_verdict(true)))
+        .collect::<Result<_>>()?;
+    Ok(CompositeCheck::new(main, and, or).and_not(not))
+}
+
+fn container(args: &Args) -> Result<Option<Container>> {
+    let Some(image) = &args.check_container else {
+        return Ok(None);
+    };
+    let found = |program: &str| {
+        process::Command::new(program)
+            .arg("--version")
+            .stdout(process::Stdio::null())
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false)
+    };
+    let program = match &args.container_runtime {
+        Some(program) if found(program) => program.clone(),
+        Some(program) => bail!("Container runtime {} not found", program),
+        None => match ["podman", "docker"].into_iter().find(|p| found(p)) {
+            Some(program) => program.to_string(),
+            None => bail!("--check-container requires podman or docker"),
+        },
+    };
+    Ok(Some(Container {
+        program,
+        image: image.clone(),
+    }))
+}
+
+fn sandbox(args: &Args) -> Result<Option<Sandbox>> {
+    if !args.sandbox {
+        return Ok(None);
+    }
+    let found = process::Command::new(Sandbox::PROGRAM)
+        .arg("--version")
+        .stdout(process::Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false);
+    if !found {
+        bail!("--sandbox requires {} (bubblewrap)", Sandbox::PROGRAM);
+    }
+    Ok(Some(Sandbox {
+        read_only: args.sandbox_read_only,
+    }))
 }
 
 fn check_initial_input_is_interesting(
-    chk: &CmdCheck,
+    retry: &RetryCheck<CompositeCheck<CmdCheck>>,
     tree: &Tree,
     src: &[u8],
     source: &Option<String>,
 ) -> Result<()> {
     let mut test: Vec<u8> = Vec::with_capacity(src.len());
     tree_sitter_edit::render(&mut test, tree, src, &crate::edits::Edits::new())?;
-    if !chk
+    if !retry
         .interesting(&test)
         .context("Failed to check that initial input was interesting")?
     {
-        let (tmp_file, command_line) = if chk.needs_file {
+        let chk = retry.inner().main();
+        let (tmp_file, command_line) = if chk.creduce.is_some() {
+            (None, Vec::new())
+        } else if chk.needs_file {
             chk.args_with_file()?
         } else {
             (None, chk.args.clone())
         };
-        let tmp_path = match tmp_file {
-            Some(t) => String::from(t.path().to_string_lossy()),
-            None => String::from("${tmp}/your-test-case"),
+        let tmp_path = match (tmp_file, &chk.creduce) {
+            (Some(t), _) => String::from(t.path().to_string_lossy()),
+            (None, Some(name)) => format!("${{tmp}}/{}", name),
+            (None, None) => String::from("${tmp}/your-test-case"),
         };
         let mut args = command_line
             .iter()
             .map(|s| format!("\"{}\"", s))
             .collect::<Vec<_>>();
-        if !chk.needs_file {
+        if chk.uses_stdin {
             args.push("<".to_string());
             args.push(tmp_path.clone());
         }
@@ -323,21 +1253,69 @@ The last line should print 0 (or any other code passed to `--interesting-exit-co
         );
         eprintln!("{}", s);
         error!(s);
-        std::process::exit(1);
+        bail!("Initial test was not interesting");
+    }
+    Ok(())
+}
+
+/// Check the result again, as written, to catch flaky interestingness tests
+/// (or the test using the output file) before anyone relies on the result
+fn check_result_is_interesting<T: Check>(
+    check: &T,
+    output: &str,
+    result: &[u8],
+    source: &Option<String>,
+) -> Result<()> {
+    let written = if output == "-" {
+        result.to_vec()
+    } else {
+        fs::read(output).with_context(|| format!("Failed to read back result {}", output))?
+    };
+    if written != result {
+        bail!("The result in {} changed after it was written", output);
+    }
+    if check
+        .interesting(&written)
+        .context("Failed to check that the result was interesting")?
+    {
+        return Ok(());
+    }
+    let hint = match source {
+        Some(s) if !check.interesting(&fs::read(s)?)? => format!(
+            "The original input {} isn't interesting anymore either. Did something that the interestingness test depends on change?",
+            s
+        ),
+        _ => String::from(
+            "The interestingness test may be nondeterministic, try `--oracle-retries`.",
+        ),
+    };
+    bail!("The result isn't interesting when checked again. {}", hint)
+}
+
+fn print_diff(diff: &str, input: &[u8], result: &[u8], path: &str, output: &str) -> Result<()> {
+    let output = if output == "-" { "<stdout>" } else { output };
+    if diff == "-" {
+        crate::diff::write_unified(&mut io::stdout().lock(), input, result, path, output, 3)?;
+    } else {
+        let mut file = io::BufWriter::new(
+            File::create(diff).with_context(|| format!("Failed to create diff {}", diff))?,
+        );
+        crate::diff::write_unified(&mut file, input, result, path, output, 3)?;
+        file.flush()?;
     }
     Ok(())
 }
 
 #[inline]
-fn print_result(output: &str, src: &str) -> Result<()> {
+fn print_result(output: &str, src: &[u8]) -> Result<()> {
     if output == "-" {
         // https://nnethercote.github.io/perf-book/io.html#locking
         let stdout = std::io::stdout();
         let mut lock = stdout.lock();
-        lock.write_all(src.as_bytes())?;
+        lock.write_all(src)?;
     } else {
         let mut file = File::create(output)?;
-        file.write_all(src.as_bytes())?;
+        file.write_all(src)?;
     }
     Ok(())
 }
@@ -380,10 +1358,25 @@ fn log_tracing_level(level: &log::Level) -> tracing::Level {
 
 #[inline]
 fn init_tracing(args: &Args) {
+    let tui = args.tui;
     // TODO(lb): Make this less verbose, drop time
     let builder = tracing_subscriber::fmt::fmt()
-        .with_span_events(FmtSpan::ENTER | FmtSpan::CLOSE)
+        // Closing spans logs their duration, e.g., that of each candidate
+        .with_span_events(if args.json {
+            FmtSpan::CLOSE
+        } else {
+            FmtSpan::NONE
+        })
         .with_target(false)
+        .with_writer(move || -> Box<dyn Write> {
+            // Logs would mess up the TUI
+            if tui {
+                return Box::new(io::sink());
+            }
+            progress::clear();
+            // Not stdout, which may be the output (with `-o -`)
+            Box::new(io::stderr())
+        })
         .with_max_level(log_tracing_level(
             &args.verbose.log_level().unwrap_or(log::Level::Info),
         ));
@@ -394,60 +1387,787 @@ fn init_tracing(args: &Args) {
     }
 }
 
+/// Add replacements from `--replace`. This leaks the strings, but it only
+/// happens once.
+fn add_replacements(
+    args: &Args,
+    mut replacements: HashMap<&'static str, &'static [&'static str]>,
+) -> Result<HashMap<&'static str, &'static [&'static str]>> {
+    for r in &args.replace {
+        let (kind, text) = match r.split_once('=') {
+            Some(kt) => kt,
+            None => bail!("Invalid --replace {}, expected KIND=TEXT", r),
+        };
+        let kind: &'static str = Box::leak(kind.to_string().into_boxed_str());
+        let text: &'static str = Box::leak(text.to_string().into_boxed_str());
+        let mut texts = replacements.get(kind).copied().unwrap_or_default().to_vec();
+        texts.push(text);
+        replacements.insert(kind, Box::leak(texts.into_boxed_slice()));
+    }
+    Ok(replacements)
+}
+
+/// The jobserver from `--jobserver`, or else from `MAKEFLAGS` (if it's usable)
+fn jobserver(args: &Args) -> Result<Option<Arc<Jobserver>>> {
+    if let Some(auth) = &args.jobserver {
+        return Ok(Some(Arc::new(Jobserver::open(auth)?)));
+    }
+    match Jobserver::from_env() {
+        Ok(jobserver) => Ok(jobserver.map(Arc::new)),
+        Err(e) => {
+            warn!("Not using the jobserver in MAKEFLAGS: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+/// Weights from `--weights` and `--weight`, in that order (so the latter win)
+fn weights(args: &Args) -> Result<HashMap<String, f64>> {
+    let file = match &args.weights {
+        None => String::new(),
+        Some(path) => read_file(path)?,
+    };
+    let lines = file
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    let mut weights = HashMap::new();
+    for w in lines.chain(args.weight.iter().map(String::as_str)) {
+        let (kind, weight) = match w.split_once('=') {
+            Some(kw) => kw,
+            None => bail!("Invalid weight {}, expected KIND=W", w),
+        };
+        let weight: f64 = weight
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid weight {}", w))?;
+        if weight < 0.0 || !weight.is_finite() {
+            bail!("Invalid weight {}, expected a non-negative number", w);
+        }
+        weights.insert(kind.trim().to_string(), weight);
+    }
+    Ok(weights)
+}
+
+/// Parse a `--preserve` region
+fn preserve(region: &str) -> Result<Preserve> {
+    let range = |r: &str| -> Result<(usize, usize)> {
+        let (start, end) = r.split_once('-').unwrap_or((r, r));
+        Ok((start.trim().parse()?, end.trim().parse()?))
+    };
+    let (kind, value) = region.split_once(':').unwrap_or(("", region));
+    match kind {
+        "bytes" => {
+            let (start, end) =
+                range(value).with_context(|| format!("Invalid byte range {}", value))?;
+            Ok(Preserve::Bytes(start..end))
+        }
+        "lines" => {
+            let (first, last) =
+                range(value).with_context(|| format!("Invalid line range {}", value))?;
+            Ok(Preserve::Lines(first, last))
+        }
+        "query" => Ok(Preserve::Query(read_file(value)?)),
+        _ => bail!(
+            "Invalid --preserve {}, expected bytes:START-END, lines:FIRST-LAST, or query:FILE",
+            region
+        ),
+    }
+}
+
+/// The pipeline from `--pipeline` or `--pipeline-file`, or else from the
+/// individual pass flags
+fn pipeline(args: &Args) -> Result<Pipeline> {
+    if let Some(p) = &args.pipeline {
+        return p
+            .parse()
+            .with_context(|| format!("Invalid --pipeline {}", p));
+    }
+    if let Some(f) = &args.pipeline_file {
+        return read_file(f)?
+            .parse()
+            .with_context(|| format!("Invalid pipeline in {}", f));
+    }
+    let mut passes = vec![Pass::Delete];
+    if !args.no_hoist {
+        passes.push(Pass::Hoist);
+    }
+    if args.rename || args.template.is_some() {
+        passes.push(Pass::Rename);
+    }
+    if !args.no_shrink_literals {
+        passes.push(Pass::Literals);
+    }
+    let mut stages = Vec::new();
+    if !args.keep_comments {
+        stages.push(Stage::new(vec![Pass::Comments], Some(1)));
+    }
+    stages.push(Stage::new(passes, None));
+    if args.tokens || args.slow {
+        stages.push(Stage::new(vec![Pass::Tokens], Some(1)));
+    }
+    Ok(Pipeline { stages })
+}
+
+/// Parse a number of seconds, minutes, or hours, e.g., `90`, `90s`, `1.5m`, or
+/// `2h`
+fn duration(s: &str) -> Result<Duration> {
+    let (n, unit) = match s.char_indices().find(|(_, c)| c.is_alphabetic()) {
+        Some((i, _)) => (&s[..i], &s[i..]),
+        None => (s, "s"),
+    };
+    let secs = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => bail!("Unknown unit {}, expected s, m, or h", unit),
+    };
+    let n: f64 = n.trim().parse().context("Expected a number")?;
+    if !n.is_finite() || n < 0.0 {
+        bail!("Expected a non-negative number");
+    }
+    Ok(Duration::from_secs_f64(n * secs))
+}
+
+/// Inline the includes of `src`, which is in the file `path`, if any
+fn inline_includes(args: &Args, path: Option<&Path>, src: &[u8]) -> Result<Vec<u8>> {
+    let dir = path
+        .and_then(Path::parent)
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let inlined = crate::include::inline_includes(src, dir, &args.include_dir)
+        .context("Failed to inline includes")?;
+    info!(
+        "Inlined includes, from {} to {} bytes",
+        src.len(),
+        inlined.len()
+    );
+    Ok(inlined)
+}
+
+/// Of the input file, if any
+fn extension(args: &Args) -> Option<String> {
+    args.source
+        .as_deref()
+        .and_then(|s| Path::new(s).extension())
+        .map(|e| e.to_string_lossy().into_owned())
+}
+
+/// The interestingness check: retried, then cached
+type Oracle = CachedCheck<RetryCheck<CompositeCheck<CmdCheck>>>;
+
+/// The words of the check that are files (e.g., the test script), other than
+/// the input
+fn check_files(args: &Args) -> Vec<PathBuf> {
+    args.check
+        .iter()
+        .filter(|w| !is_marker(w) && Path::new(w).is_file())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Persist the check's cache in a subdirectory of `dir` specific to the
+/// check (and the contents of `scripts`). Returns the best result from a
+/// previous run on `src`, if there is one and it's still interesting.
+fn persist(
+    dir: &str,
+    check: Oracle,
+    scripts: &[PathBuf],
+    src: &[u8],
+) -> Result<(Oracle, Option<Vec<u8>>)> {
+    // Editing the test script makes the old verdicts stale
+    let mut key = format!("{:?}", check.inner()).into_bytes();
+    for script in scripts {
+        key.extend(fs::read(script).unwrap_or_default());
+    }
+    let check_hash = stable_hash(&key);
+    let dir = Path::new(dir).join(format!("{:016x}", check_hash));
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+    let best = dir.join(format!("{:016x}.best", stable_hash(src)));
+    let check = check
+        .persist(&dir.join("verdicts"), Some((best.clone(), src.len())))
+        .with_context(|| format!("Failed to load cache from {}", dir.display()))?;
+    let mut resumed = None;
+    if best.exists() {
+        let prev = fs::read(&best)?;
+        if prev.len() < src.len() && check.interesting(&prev)? {
+            info!(
+                "Resuming from previous result in {} ({} bytes)",
+                best.display(),
+                prev.len()
+            );
+            resumed = Some(prev);
+        }
+    }
+    Ok((check, resumed))
+}
+
+fn injections(args: &Args) -> Result<Option<Injections>> {
+    let Some(query) = &args.injection_query else {
+        return Ok(None);
+    };
+    let mut languages = HashMap::new();
+    for inject in &args.inject {
+        let Some((name, path)) = inject.split_once('=') else {
+            bail!("Invalid --inject {}, expected NAME=LIBRARY", inject);
+        };
+        #[cfg(any(unix, windows))]
+        {
+            let grammar = crate::grammar::Grammar::load(Path::new(path), Some(name), None)
+                .with_context(|| format!("Failed to load grammar {}", path))?;
+            let node_types = crate::node_types::NodeTypes::new(&grammar.node_types)?;
+            languages.insert(name.to_string(), (grammar.language, node_types));
+        }
+        #[cfg(not(any(unix, windows)))]
+        bail!("--inject {} is only supported on Unix and Windows", path);
+    }
+    Ok(Some(Injections {
+        query: read_file(query)?,
+        languages,
+    }))
+}
+
+/// The transforms that weren't disabled by their flags in `matches`, from
+/// [`parse_args_with`]
+pub fn enabled(transforms: Vec<LanguageTransform>, matches: &ArgMatches) -> Transforms {
+    let mut enabled = Transforms::default();
+    for t in transforms {
+        let disabled = t.flag().is_some_and(|f| {
+            matches
+                .try_get_one::<bool>(f)
+                .ok()
+                .flatten()
+                .copied()
+                .unwrap_or(false)
+        });
+        if !disabled {
+            enabled.push(t.transform);
+        }
+    }
+    enabled
+}
+
+/// Apply `--max-time` and the other limits to a reduction of `size` bytes
+/// that started at `start`
+fn limit(args: &Args, control: &Control, start: Instant, size: usize) -> Result<()> {
+    if let Some(t) = &args.max_time {
+        let t = duration(t).with_context(|| format!("Invalid --max-time {}", t))?;
+        control.stop_at(start + t);
+    }
+    if let Some(n) = args.max_oracle_calls {
+        control.limit_checks(n);
+    }
+    if let Some(n) = args.max_passes {
+        control.limit_passes(n);
+    }
+    if let Some(n) = args.stop_at_bytes {
+        control.stop_at_size(n);
+    }
+    if let Some(p) = args.stop_at_percent {
+        control.stop_at_size((size as f64 * p / 100.0).floor() as usize);
+    }
+    Ok(())
+}
+
 #[inline]
 fn configure(
     args: &Args,
     replacements: HashMap<&'static str, &'static [&'static str]>,
-) -> Result<reduce::Config<CmdCheck>> {
+    src: &[u8],
+) -> Result<reduce::Config<Oracle>> {
+    let control = Arc::new(Control::new());
+    let check = composite_check(args, src)?.map(|c| c.control(Arc::clone(&control)));
+    // As many checks at once as the hosts have slots
+    let remote_slots = check.main().remotes.as_ref().map(|r| r.slots());
     Ok(reduce::Config {
         delete_non_optional: args.slow,
-        check: check(args)?,
+        check: CachedCheck::new(
+            RetryCheck::new(check, args.oracle_retries, args.oracle_vote),
+            !args.no_cache,
+        )
+        .control(Arc::clone(&control)),
+        fixpoint: args.fixpoint || args.slow,
         jobs: args.jobs,
+        oracle_jobs: args.oracle_jobs.or(remote_slots).unwrap_or(0),
+        jobserver: jobserver(args)?,
         min_reduction: min_reduction(args),
-        replacements,
+        batch: args.batch,
+        only_query: args.only_query.as_deref().map(read_file).transpose()?,
+        pipeline: pipeline(args)?,
+        priority: args.priority,
+        preserve: args
+            .preserve
+            .iter()
+            .map(|p| preserve(p))
+            .collect::<Result<_>>()?,
+        keep_comments: args
+            .keep_comment_regex
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .context("Invalid --keep-comment-regex")?,
+        names: Vec::new(),
+        replacements: add_replacements(args, replacements)?,
+        weights: weights(args)?,
+        skip_query: args.skip_query.as_deref().map(read_file).transpose()?,
+        observers: Observers::default(),
+        transforms: args.transforms.clone(),
+        resume: None,
+        control,
+        no_new_errors: args.no_new_errors,
+        one_minimal: args.min_check,
+        verify_passes: args.verify_passes,
+        deterministic: args.deterministic.then_some(args.seed),
+        bisect: args.bisect,
+        algorithm: args.algorithm,
+        schedule: args.schedule,
+        metric: args.metric,
+        injections: injections(args)?,
     })
 }
 
+/// Reduce a program in `language`, with its node types, replacements, and
+/// transforms (see [`crate::languages`]). Each transform with a flag gets a
+/// command-line option to disable it.
 pub fn main(
     language: tree_sitter::Language,
-    node_types_json_str: &'static str,
+    node_types_json_str: &str,
+    replacements: HashMap<&'static str, &'static [&'static str]>,
+    transforms: Vec<LanguageTransform>,
+) -> Result<()> {
+    let (args, matches) = parse_args_with(&transforms)?;
+    let transforms = enabled(transforms, &matches);
+    run(
+        args,
+        language,
+        node_types_json_str,
+        replacements,
+        transforms,
+    )
+}
+
+/// Like [`main`], but with already-parsed arguments, for drivers that add
+/// their own options (see [`clap::Args`] and `#[command(flatten)]`).
+pub fn run(
+    args: Args,
+    language: tree_sitter::Language,
+    node_types_json_str: &str,
     replacements: HashMap<&'static str, &'static [&'static str]>,
+    transforms: Transforms,
 ) -> Result<()> {
-    let args = Args::parse();
     debug_assert!(args.passes == DEFAULT_NUM_PASSES || !args.stable);
     debug_assert!(!(args.fast && args.slow));
-    debug_assert!(!(args.uninteresting_stdout.is_some() && args.interesting_stdout.is_none()));
-    debug_assert!(!(args.uninteresting_stderr.is_some() && args.interesting_stderr.is_none()));
 
+    let args = Args {
+        source: args.source.filter(|s| s != "-"),
+        transforms,
+        ..args
+    };
+    let start = Instant::now();
     init_tracing(&args);
     make_temp_dir(&args.temp_dir)?;
-    let conf = configure(&args, replacements)?;
+    if args.watch {
+        return watch::watch(&args, |args| {
+            reduce_input(
+                args,
+                Instant::now(),
+                language,
+                node_types_json_str,
+                replacements.clone(),
+            )
+        });
+    }
+    reduce_input(args, start, language, node_types_json_str, replacements)
+}
 
-    let (path, src) = if let Some(p) = &args.source {
-        (p.to_string(), read_file(p)?)
+fn reduce_input(
+    args: Args,
+    start: Instant,
+    language: tree_sitter::Language,
+    node_types_json_str: &str,
+    replacements: HashMap<&'static str, &'static [&'static str]>,
+) -> Result<()> {
+    if !args.files.is_empty() {
+        return project::run(
+            &args,
+            &args.files,
+            language,
+            node_types_json_str,
+            replacements,
+        );
+    }
+    if let Some(dir) = &args.selftest {
+        return selftest::run(
+            &args,
+            Path::new(dir),
+            language,
+            node_types_json_str,
+            replacements,
+        );
+    }
+    if let Some(dir) = &args.corpus {
+        return corpus::run(
+            &args,
+            Path::new(dir),
+            language,
+            node_types_json_str,
+            replacements,
+        );
+    }
+    if let Some(r) = &args.package_result {
+        return package_result(&args, Path::new(r));
+    }
+    let (path, mut src) = if let Some(p) = &args.source {
+        (
+            p.to_string(),
+            fs::read(p).with_context(|| format!("Failed to read file {}", p))?,
+        )
+    } else {
+        ("<stdin>".to_string(), stdin_bytes()?)
+    };
+    if args.inline_includes {
+        src = inline_includes(&args, args.source.as_deref().map(Path::new), &src)?;
+    }
+    if let Some(p) = &args.replay {
+        return replay(&args, Path::new(p), src);
+    }
+    let args = if args.reduce_args {
+        Args {
+            check: flags::reduce(&args, &src)?,
+            ..args
+        }
     } else {
-        ("<stdin>".to_string(), stdin_string()?)
+        args
+    };
+    // Before resuming from a previous result
+    let input = args.diff.is_some().then(|| src.clone());
+    let mut conf = configure(&args, replacements, &src)?;
+    let preserve_queries = conf.preserve.iter().filter_map(|p| match p {
+        Preserve::Query(q) => Some(q),
+        _ => None,
+    });
+    for query in [&conf.only_query, &conf.skip_query]
+        .into_iter()
+        .flatten()
+        .chain(preserve_queries)
+        .chain(conf.injections.iter().map(|i| &i.query))
+    {
+        tree_sitter::Query::new(language, query).context("Invalid tree-sitter query")?;
+    }
+
+    if let Some(dir) = &args.cache_dir {
+        let (check, resumed) = persist(dir, conf.check, &check_files(&args), &src)?;
+        conf.check = check;
+        match resumed {
+            // The annotations are relative to the input
+            Some(_) if args.annotations.is_some() => {
+                warn!("Not resuming from the result in {}, for --annotations", dir)
+            }
+            Some(r) => src = r,
+            None => (),
+        }
+    }
+
+    if let Some(p) = &args.resume {
+        let checkpoint = Checkpoint::load(Path::new(p))
+            .with_context(|| format!("Failed to read checkpoint {}", p))?;
+        if checkpoint.pipeline != conf.pipeline.to_string() {
+            bail!(
+                "Checkpoint {} is for a different pipeline: {}",
+                p,
+                checkpoint.pipeline
+            );
+        }
+        src = checkpoint.text;
+        conf.resume = Some(checkpoint.position);
+    }
+    if let Some(p) = args.checkpoint.as_ref().or(args.resume.as_ref()) {
+        conf.observers.push(Arc::new(Checkpointer::new(
+            PathBuf::from(p),
+            CHECKPOINT_INTERVAL,
+            Checkpoint {
+                position: conf.resume.unwrap_or_default(),
+                pipeline: conf.pipeline.to_string(),
+                text: src.clone(),
+            },
+        )));
+    }
+
+    if let Some(every) = &args.snapshot_every {
+        let every: Every = every
+            .parse()
+            .with_context(|| format!("Invalid --snapshot-every {}", every))?;
+        let snapshotter = Snapshotter::new(Path::new(&args.snapshot_dir), extension(&args), every)
+            .with_context(|| format!("Failed to create {}", args.snapshot_dir))?;
+        conf.observers.push(Arc::new(snapshotter));
+    }
+
+    let variants = match args.keep_variants {
+        None => None,
+        Some(_) if args.output == "-" => bail!("--keep-variants needs an --output file"),
+        Some(k) => {
+            let variants = Arc::new(Variants::new(k, language));
+            conf.observers.push(variants.clone());
+            Some(variants)
+        }
     };
 
+    let tracer = match &args.trace {
+        None => None,
+        Some(p) => {
+            let tracer = Tracer::new(Path::new(p), src.clone())
+                .with_context(|| format!("Failed to create trace {}", p))?;
+            let tracer = Arc::new(tracer);
+            conf.observers.push(tracer.clone());
+            Some(tracer)
+        }
+    };
+
+    limit(&args, &conf.control, start, src.len())?;
+
+    if args.explain || args.explain_rejected.is_some() {
+        let dir = args.explain_rejected.as_deref().map(Path::new);
+        let explainer = explain::Explainer::new(dir, extension(&args))
+            .context("Failed to create --explain-rejected directory")?;
+        conf.observers.push(Arc::new(explainer));
+    }
+
     let tree = parse(language, &src)?;
     handle_parse_errors(&path, &tree, &args.on_parse_error);
-    if !args.no_verify {
-        check_initial_input_is_interesting(&conf.check, &tree, src.as_bytes(), &args.source)?;
+    if let Some(p) = &args.template {
+        let skeleton = fs::read(p).with_context(|| format!("Failed to read template {}", p))?;
+        let skeleton_tree = parse(language, &skeleton)?;
+        let template = template::template(&skeleton_tree, &skeleton, &tree, &src);
+        info!(
+            "Keeping {} regions shaped like parts of the template",
+            template.preserve.len()
+        );
+        conf.preserve
+            .extend(template.preserve.into_iter().map(Preserve::Bytes));
+        conf.names = template.names;
     }
-
     let max_passes = passes(&args);
     let node_types = crate::node_types::NodeTypes::new(node_types_json_str)?;
-    let orig = Original::new(tree, src.into_bytes());
-    let (reduced, stats) =
+    if args.dry_run {
+        let orig = Original::new(tree, src);
+        return dry_run::dry_run(language, &node_types, orig, conf, max_passes);
+    }
+    if !args.no_verify {
+        check_initial_input_is_interesting(conf.check.inner(), &tree, &src, &args.source)?;
+    }
+
+    if !args.no_progress && !args.tui && !args.json && io::stderr().is_terminal() {
+        conf.observers
+            .push(Arc::new(progress::ProgressBar::new(src.len())));
+    }
+    // SIGUSR1 saves the smallest result so far to the output, as long as
+    // that's not stdout
+    let best = if args.output == "-" {
+        "treereduce.best"
+    } else {
+        &args.output
+    };
+    let status = Arc::new(signals::Status::new(src.len(), PathBuf::from(best)));
+    conf.observers.push(status.clone());
+    let signals = signals::Signals::start(Arc::clone(&conf.control), status);
+    #[cfg(unix)]
+    let tui = if args.tui {
+        let (tui, session) =
+            tui::Tui::start(&src, Arc::clone(&conf.control)).context("Failed to start the TUI")?;
+        conf.observers.push(tui);
+        Some(session)
+    } else {
+        None
+    };
+    #[cfg(not(unix))]
+    if args.tui {
+        bail!("--tui is only supported on Unix");
+    }
+
+    let orig = Original::new(tree, src);
+    let (reduced, mut stats) =
         reduce::treereduce_multi_pass(language, &node_types, orig, &conf, max_passes)?;
-    let text = std::str::from_utf8(&reduced.text)?.to_string();
-    print_result(&args.output, &text)?;
+    progress::clear();
+    drop(signals);
+    #[cfg(unix)]
+    drop(tui);
+    info!("Interestingness check cache hits: {}", conf.check.hits());
+    if let (Some(t), Some(p)) = (&tracer, &args.trace) {
+        t.finish(&reduced.text)
+            .with_context(|| format!("Failed to write trace {}", p))?;
+    }
+    let mut result = reduced.text;
+    if let Some(cmd) = &args.format_cmd {
+        let formatted = pretty::format(cmd, result.clone(), &conf.check)?;
+        // The formatter's changes aren't edits, so they can only be diffed
+        if args.annotations.is_some() {
+            stats
+                .provenance
+                .follow(&crate::diff::retained(&result, &formatted));
+        }
+        result = formatted;
+    }
+    print_result(&args.output, &result)?;
+    if let Some(variants) = &variants {
+        for (i, variant) in variants.variants(&result).iter().enumerate() {
+            let path = numbered(Path::new(&args.output), i + 1);
+            fs::write(&path, variant)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    if let (Some(diff), Some(input)) = (&args.diff, &input) {
+        print_diff(diff, input, &result, &path, &args.output)?;
+    }
+    if let Some(p) = &args.annotations {
+        let annotations = Annotations::new(&stats.provenance, &path, &args.output);
+        fs::write(p, serde_json::to_string_pretty(&annotations)?)
+            .with_context(|| format!("Failed to write annotations to {}", p))?;
+    }
 
-    if args.stats {
+    if args.stats && args.output == "-" {
+        // Keep stdout for the result
+        stats.write_text(&mut io::stderr().lock())?;
+    } else if args.stats {
         // https://nnethercote.github.io/perf-book/io.html#locking
         let stdout = std::io::stdout();
         let mut lock = stdout.lock();
         stats.write_text(&mut lock)?;
     }
+    // Before checking the result again
+    let json = JsonStats::new(
+        &stats,
+        conf.check.hits(),
+        conf.check.inner().inner().main().runs(),
+    );
+    if let Some(p) = &args.stats_json {
+        fs::write(p, serde_json::to_string_pretty(&json)?)
+            .with_context(|| format!("Failed to write stats to {}", p))?;
+    }
+    if !args.no_verify {
+        check_result_is_interesting(conf.check.inner(), &args.output, &result, &args.source)?;
+    }
+    if let Some(p) = &args.package {
+        package::package(p, &args, &result, Some(&json))?;
+    }
     Ok(())
 }
+
+/// Apply the edits in `trace` to `src`, and print the result
+fn replay(args: &Args, trace: &Path, src: Vec<u8>) -> Result<()> {
+    let steps = crate::trace::load(trace)
+        .with_context(|| format!("Failed to read trace {}", trace.display()))?;
+    let result = crate::trace::replay(src, &steps)?;
+    info!("Replayed {} edits", steps.len());
+    print_result(&args.output, &result)?;
+    if !args.check.is_empty()
+        && !args.no_verify
+        && !composite_check(args, &result)?.interesting(&result)?
+    {
+        bail!("The result of the replay isn't interesting");
+    }
+    Ok(())
+}
+
+/// Package an existing result, see `--package-result`
+fn package_result(args: &Args, result: &Path) -> Result<()> {
+    let bytes = fs::read(result).with_context(|| format!("Failed to read {}", result.display()))?;
+    if !args.no_verify {
+        // The original input, if any, for --golden
+        let src = match &args.source {
+            Some(s) => fs::read(s).with_context(|| format!("Failed to read file {}", s))?,
+            None => bytes.clone(),
+        };
+        if !composite_check(args, &src)?.interesting(&bytes)? {
+            bail!("{} isn't interesting", result.display());
+        }
+    }
+    if let Some(p) = &args.package {
+        package::package(p, args, &bytes, None)?;
+    }
+    Ok(())
+}
+
+/// A region of the input that's in the result
+#[derive(serde::Serialize)]
+struct Retained {
+    input: Range<usize>,
+    output: Range<usize>,
+}
+
+/// See `--annotations`
+#[derive(serde::Serialize)]
+struct Annotations<'a> {
+    input: &'a str,
+    output: &'a str,
+    retained: Vec<Retained>,
+}
+
+impl<'a> Annotations<'a> {
+    fn new(provenance: &Provenance, path: &'a str, output: &'a str) -> Self {
+        Annotations {
+            input: path,
+            output: if output == "-" { "<stdout>" } else { output },
+            retained: provenance
+                .segments()
+                .iter()
+                .map(|(input, output)| Retained {
+                    input: input.clone(),
+                    output: output.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonStats<'a> {
+    #[serde(flatten)]
+    stats: &'a Stats,
+    by_stage: Vec<StageTotal>,
+    /// Candidates checked, including cache hits
+    checks: usize,
+    /// Processes spawned for the interestingness test (or candidates sent
+    /// to a `--server`), including `--oracle-retries` and the other checks
+    oracle_invocations: usize,
+    cache_hits: usize,
+    cache_hit_rate: f64,
+}
+
+impl<'a> JsonStats<'a> {
+    /// `runs` is from [`CmdCheck::runs`]
+    fn new(stats: &'a Stats, cache_hits: usize, runs: usize) -> Self {
+        let checks = stats.checks();
+        JsonStats {
+            stats,
+            by_stage: stats.by_stage(),
+            checks,
+            oracle_invocations: runs,
+            cache_hits,
+            cache_hit_rate: if checks == 0 {
+                0.0
+            } else {
+                cache_hits as f64 / checks as f64
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let codes = |s: &str| exit_codes(&s.split(',').map(String::from).collect::<Vec<_>>());
+        assert_eq!(codes("1,128-159").unwrap(), vec![1..=1, 128..=159]);
+        assert_eq!(
+            codes("-1,-5--2,-3-3").unwrap(),
+            vec![-1..=-1, -5..=-2, -3..=3]
+        );
+        assert_eq!(codes("nonzero").unwrap(), vec![i32::MIN..=-1, 1..=i32::MAX]);
+        assert!(codes("5-1").is_err());
+        assert!(codes("1-").is_err());
+        assert!(codes("x").is_err());
+    }
+}
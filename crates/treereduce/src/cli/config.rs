@@ -0,0 +1,181 @@
+//! Options from a `treereduce.toml`, so that a reduction can be set up once
+//! and committed next to the test harness.
+//!
+//! The keys are the long names of the command-line options, and `check` is
+//! the interestingness check:
+//!
+//! ```toml
+//! check = ["clang", "-c", "@@.c"]
+//! interesting-stderr = "Assertion .* failed"
+//! jobs = 8
+//! preserve = ["query:keep.scm"]
+//! ```
+//!
+//! Options given on the command line take precedence, except for options
+//! that may be given more than once, which add to those in the file.
+//! Subcommands are expanded to their options first (see [`super::subcommand`]).
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches};
+
+use super::subcommand;
+use crate::languages::LanguageTransform;
+
+/// Found in the current directory, unless `--config` or `--no-config`
+pub const DEFAULT_CONFIG: &str = "treereduce.toml";
+
+#[derive(Debug)]
+struct ConfigFile {
+    check: Vec<String>,
+    /// As command-line arguments
+    options: Vec<OsString>,
+}
+
+/// The file given by `--config`, [`DEFAULT_CONFIG`] if it exists, or nothing
+/// with `--no-config`
+fn find(argv: &[OsString]) -> Option<PathBuf> {
+    let mut args = argv.iter().skip(1).take_while(|a| *a != "--");
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--no-config" {
+            return None;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    let default = Path::new(DEFAULT_CONFIG);
+    default.exists().then(|| default.to_path_buf())
+}
+
+fn scalar(path: &Path, key: &str, value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        _ => bail!(
+            "Invalid value for {} in {}, expected a string or number",
+            key,
+            path.display()
+        ),
+    }
+}
+
+impl ConfigFile {
+    fn load(path: PathBuf, command: &Command) -> Result<Self> {
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let table: toml::Table = text
+            .parse()
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        let mut check = Vec::new();
+        let mut options = Vec::new();
+        for (key, value) in &table {
+            if key == "check" {
+                let invalid = || {
+                    format!(
+                        "Invalid check in {}, expected a list of strings",
+                        path.display()
+                    )
+                };
+                let words = value.as_array().with_context(invalid)?;
+                for word in words {
+                    check.push(word.as_str().with_context(invalid)?.to_string());
+                }
+                continue;
+            }
+            if key == "config" || key == "no-config" {
+                bail!("{} can't be set in a config file", key);
+            }
+            if !command.get_arguments().any(|a| a.get_long() == Some(key)) {
+                bail!("Unknown option {} in {}", key, path.display());
+            }
+            match value {
+                toml::Value::Boolean(true) => options.push(OsString::from(format!("--{}", key))),
+                toml::Value::Boolean(false) => {}
+                toml::Value::Array(values) => {
+                    for v in values {
+                        let v = scalar(&path, key, v)?;
+                        options.push(OsString::from(format!("--{}={}", key, v)));
+                    }
+                }
+                v => {
+                    let v = scalar(&path, key, v)?;
+                    options.push(OsString::from(format!("--{}={}", key, v)));
+                }
+            }
+        }
+        Ok(ConfigFile { check, options })
+    }
+}
+
+/// The options of `P`, and a flag to disable each of `transforms` that has
+/// one
+fn command<P: CommandFactory>(transforms: &[LanguageTransform]) -> Command {
+    let mut command = P::command().after_help(subcommand::HELP);
+    let mut seen = Vec::new();
+    for (flag, help) in transforms.iter().filter_map(|t| t.flag) {
+        if seen.contains(&flag) {
+            continue;
+        }
+        seen.push(flag);
+        command = command.arg(
+            Arg::new(flag)
+                .long(flag)
+                .help(help)
+                .help_heading("Reduction options")
+                .action(ArgAction::SetTrue),
+        );
+    }
+    command
+}
+
+/// Parse the command line, with the options from the config file (see the
+/// module documentation). Works for drivers that flatten
+/// [`Args`](super::Args) into their own options, too.
+pub fn parse_args<P: CommandFactory + FromArgMatches>() -> Result<P> {
+    Ok(parse_args_with(&[])?.0)
+}
+
+/// Like [`parse_args`], with a flag for each of `transforms` that can be
+/// disabled. Pass the matches to [`enabled`](super::enabled).
+pub fn parse_args_with<P: CommandFactory + FromArgMatches>(
+    transforms: &[LanguageTransform],
+) -> Result<(P, ArgMatches)> {
+    let argv = subcommand::expand(std::env::args_os().collect())?;
+    let command = command::<P>(transforms);
+    let file = match find(&argv) {
+        None => {
+            let matches = command.get_matches_from(argv);
+            return Ok((P::from_arg_matches(&matches)?, matches));
+        }
+        Some(path) => ConfigFile::load(path, &command)?,
+    };
+    let mut full: Vec<OsString> = argv.iter().take(1).cloned().collect();
+    full.extend(file.options);
+    full.extend(argv.into_iter().skip(1));
+    // Later occurrences of an option win, so the command line overrides
+    let command = command.args_override_self(true);
+    if !file.check.is_empty() {
+        let matches: ArgMatches = command
+            .clone()
+            .mut_arg("check", |a| {
+                a.required(false)
+                    .required_unless_present(None::<&'static str>)
+            })
+            .get_matches_from(full.clone());
+        if matches.get_many::<String>("check").is_none() {
+            full.push(OsString::from("--"));
+            full.extend(file.check.into_iter().map(OsString::from));
+        }
+    }
+    let matches = command.get_matches_from(full);
+    Ok((P::from_arg_matches(&matches)?, matches))
+}
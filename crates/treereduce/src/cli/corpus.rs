@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+use tracing::{info, info_span, warn};
+
+use super::{
+    configure, inline_includes, limit, parse, passes, pretty, progress, Args, OnParseError, Oracle,
+};
+use crate::check::Check;
+use crate::control::Control;
+use crate::node_types::NodeTypes;
+use crate::original::Original;
+use crate::reduce::{self, Config};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Status {
+    Reduced,
+    /// The input wasn't interesting to begin with
+    Uninteresting,
+    /// Another file had the same fingerprint
+    Duplicate,
+    Failed,
+}
+
+/// One line of the summary
+#[derive(Debug, Serialize)]
+struct Entry {
+    file: String,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<String>,
+    start_size: usize,
+    end_size: usize,
+    seconds: f64,
+}
+
+/// The files to reduce, in order
+pub(super) fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read a file, and inline its includes with `--inline-includes`
+pub(super) fn read(args: &Args, path: &Path) -> Result<Vec<u8>> {
+    let src = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if args.inline_includes {
+        return inline_includes(args, Some(path), &src);
+    }
+    Ok(src)
+}
+
+/// Reduce one file. Returns the result, or `None` if the input wasn't
+/// interesting.
+fn reduce_one(
+    args: &Args,
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    conf: &Config<Oracle>,
+    src: Vec<u8>,
+) -> Result<Option<Vec<u8>>> {
+    // The limits apply to each file
+    let control = Arc::new(Control::new());
+    let mut conf = Config {
+        control: Arc::clone(&control),
+        ..conf.clone()
+    };
+    let check = conf.check.inner_mut().inner_mut();
+    *check = check.clone().map(|c| c.control(Arc::clone(&control)));
+    limit(args, &conf.control, Instant::now(), src.len())?;
+    if !args.no_verify && !conf.check.interesting(&src)? {
+        return Ok(None);
+    }
+    if !args.no_progress && !args.json && io::stderr().is_terminal() {
+        conf.observers
+            .push(Arc::new(progress::ProgressBar::new(src.len())));
+    }
+    let tree = parse(language, &src)?;
+    if tree.root_node().has_error() {
+        match args.on_parse_error {
+            OnParseError::Ignore => (),
+            OnParseError::Warn => warn!("Parse error"),
+            OnParseError::Error => anyhow::bail!("Parse error"),
+        }
+    }
+    let orig = Original::new(tree, src);
+    let (reduced, _) =
+        reduce::treereduce_multi_pass(language, node_types, orig, &conf, passes(args))?;
+    progress::clear();
+    let mut result = reduced.text;
+    if let Some(cmd) = &args.format_cmd {
+        result = pretty::format(cmd, result, &conf.check)?;
+    }
+    if !args.no_verify && !conf.check.inner().interesting(&result)? {
+        anyhow::bail!("The result isn't interesting when checked again");
+    }
+    Ok(Some(result))
+}
+
+fn write_summary(w: &mut impl Write, entries: &[Entry]) -> io::Result<()> {
+    let width = entries.iter().map(|e| e.file.len()).max().unwrap_or(0);
+    for e in entries {
+        match e.status {
+            Status::Reduced => writeln!(
+                w,
+                "{:width$}  {} -> {} bytes ({:.1}s)",
+                e.file, e.start_size, e.end_size, e.seconds
+            )?,
+            Status::Uninteresting => writeln!(w, "{:width$}  not interesting", e.file)?,
+            Status::Duplicate => writeln!(
+                w,
+                "{:width$}  duplicate of {}",
+                e.file,
+                e.duplicate_of.as_deref().unwrap_or_default()
+            )?,
+            Status::Failed => writeln!(
+                w,
+                "{:width$}  failed: {}",
+                e.file,
+                e.error.as_deref().unwrap_or_default()
+            )?,
+        }
+    }
+    let reduced = entries
+        .iter()
+        .filter(|e| e.status == Status::Reduced)
+        .count();
+    writeln!(w, "Reduced {} of {} files", reduced, entries.len())
+}
+
+pub(super) fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The fingerprint of each file, and the file that represents each
+/// fingerprint: the smallest one
+#[allow(clippy::type_complexity)]
+fn fingerprints(
+    args: &Args,
+    conf: &Config<Oracle>,
+    files: &[PathBuf],
+    rx: &Regex,
+) -> Result<(Vec<Option<Vec<u8>>>, HashMap<Vec<u8>, usize>)> {
+    let chk = conf.check.inner().inner().main();
+    let mut prints = Vec::with_capacity(files.len());
+    let mut representatives: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut sizes = Vec::with_capacity(files.len());
+    for (i, path) in files.iter().enumerate() {
+        let src = read(args, path)?;
+        let print = chk
+            .fingerprint(&src, rx)
+            .with_context(|| format!("Failed to fingerprint {}", path.display()))?;
+        sizes.push(src.len());
+        if let Some(p) = &print {
+            let rep = representatives.entry(p.clone()).or_insert(i);
+            if src.len() < sizes[*rep] {
+                *rep = i;
+            }
+        }
+        prints.push(print);
+    }
+    info!(
+        "Found {} distinct fingerprints in {} files",
+        representatives.len(),
+        files.len()
+    );
+    Ok((prints, representatives))
+}
+
+/// Reduce each file in `dir`, one after another, with the same check (and
+/// so, the same cache)
+pub(super) fn run(
+    args: &Args,
+    dir: &Path,
+    language: tree_sitter::Language,
+    node_types_json_str: &str,
+    replacements: HashMap<&'static str, &'static [&'static str]>,
+) -> Result<()> {
+    let out = Path::new(&args.corpus_output);
+    fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let node_types = NodeTypes::new(node_types_json_str)?;
+    // With --golden disallowed, the check doesn't depend on the input
+    let conf = configure(args, replacements, &[])?;
+    let files = files(dir)?;
+    let (prints, representatives) = match &args.corpus_dedup {
+        None => (vec![None; files.len()], HashMap::new()),
+        Some(r) => {
+            let rx = Regex::new(r).context("Invalid --corpus-dedup regex")?;
+            fingerprints(args, &conf, &files, &rx)?
+        }
+    };
+
+    let mut entries = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let name = path.file_name().unwrap_or_default().to_owned();
+        let file = name.to_string_lossy().into_owned();
+        let _span = info_span!("file", file = file.as_str()).entered();
+        let fingerprint = prints[i].as_ref().map(|p| {
+            String::from_utf8_lossy(p)
+                .trim_end_matches('\0')
+                .replace('\0', " ")
+        });
+        let rep = prints[i].as_ref().map(|p| representatives[p]);
+        if let Some(rep) = rep.filter(|r| *r != i) {
+            let size = fs::metadata(path)?.len() as usize;
+            entries.push(Entry {
+                file,
+                status: Status::Duplicate,
+                error: None,
+                fingerprint,
+                duplicate_of: Some(file_name(&files[rep])),
+                start_size: size,
+                end_size: size,
+                seconds: 0.0,
+            });
+            continue;
+        }
+        let src = read(args, path)?;
+        info!("Reducing {} ({} bytes)", file, src.len());
+        let start_size = src.len();
+        let start = Instant::now();
+        let (status, error, end_size) = match reduce_one(args, language, &node_types, &conf, src) {
+            Ok(Some(result)) => {
+                let dest = out.join(&name);
+                fs::write(&dest, &result)
+                    .with_context(|| format!("Failed to write {}", dest.display()))?;
+                (Status::Reduced, None, result.len())
+            }
+            Ok(None) => {
+                warn!("{} isn't interesting, skipping it", file);
+                (Status::Uninteresting, None, start_size)
+            }
+            Err(e) => {
+                warn!("Failed to reduce {}: {:#}", file, e);
+                (Status::Failed, Some(format!("{:#}", e)), start_size)
+            }
+        };
+        entries.push(Entry {
+            file,
+            status,
+            error,
+            fingerprint,
+            duplicate_of: None,
+            start_size,
+            end_size,
+            seconds: start.elapsed().as_secs_f64(),
+        });
+    }
+
+    let summary = out.join("summary.json");
+    fs::write(&summary, serde_json::to_string_pretty(&entries)?)
+        .with_context(|| format!("Failed to write {}", summary.display()))?;
+    info!("Interestingness check cache hits: {}", conf.check.hits());
+    write_summary(&mut io::stdout().lock(), &entries)?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::check::FnCheck;
+use crate::node_types::NodeTypes;
+use crate::observer::{Attempt, Observer, Observers, PassDone};
+use crate::original::Original;
+use crate::reduce::{self, Config};
+
+/// Prints the candidates of each pass
+#[derive(Debug, Default)]
+struct Listing {
+    attempts: Mutex<Vec<String>>,
+}
+
+impl Observer for Listing {
+    fn started(&self, attempt: &Attempt<'_>) {
+        self.attempts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(attempt.description.to_string());
+    }
+
+    fn pass_done(&self, pass: &PassDone<'_>) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "# {} (pass {})", pass.stage, pass.iteration);
+        for a in attempts.drain(..) {
+            let _ = writeln!(stdout, "{}", a);
+        }
+    }
+}
+
+/// Run the passes as if no candidate were interesting, printing each one
+pub fn dry_run<T>(
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    orig: Original,
+    conf: Config<T>,
+    max_passes: Option<usize>,
+) -> Result<()> {
+    let mut conf = conf.with_check(FnCheck::new(|_: &[u8]| false));
+    // So that candidates are listed in the order they'd be tried
+    conf.jobs = 1;
+    conf.observers = Observers::default();
+    conf.observers.push(Arc::new(Listing::default()));
+    reduce::treereduce_multi_pass(language, node_types, orig, &conf, max_passes)?;
+    Ok(())
+}
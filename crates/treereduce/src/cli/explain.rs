@@ -0,0 +1,90 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tracing::{info, warn};
+
+use crate::check::take_last_run;
+use crate::observer::{Attempt, Observer};
+
+/// Logs the verdict on each candidate and why, and optionally saves the
+/// rejected ones
+#[derive(Debug)]
+pub struct Explainer {
+    /// Where to save rejected candidates
+    dir: Option<PathBuf>,
+    /// Of the saved candidates
+    extension: Option<String>,
+    saved: AtomicUsize,
+}
+
+impl Explainer {
+    pub fn new(dir: Option<&Path>, extension: Option<String>) -> io::Result<Self> {
+        if let Some(d) = dir {
+            fs::create_dir_all(d)?;
+        }
+        Ok(Explainer {
+            dir: dir.map(Path::to_path_buf),
+            extension,
+            saved: AtomicUsize::new(0),
+        })
+    }
+
+    fn why() -> String {
+        take_last_run()
+            .map(|r| r.to_string())
+            .unwrap_or_else(|| String::from("cached"))
+    }
+
+    /// Returns the path of the saved candidate
+    fn save(&self, attempt: &Attempt<'_>) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let n = self.saved.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut path = dir.join(format!("{:06}", n));
+        if let Some(e) = &self.extension {
+            path.set_extension(e);
+        }
+        match File::create(&path).and_then(|mut f| attempt.text.write_to(&mut f)) {
+            Ok(()) => Some(path),
+            Err(e) => {
+                warn!("Failed to save candidate to {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+impl Observer for Explainer {
+    fn started(&self, _attempt: &Attempt<'_>) {
+        // In case this candidate's verdict was cached
+        take_last_run();
+    }
+
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        info!(
+            edit = attempt.description,
+            size = attempt.text.len(),
+            "Accepted {} ({})",
+            attempt.description,
+            Self::why()
+        );
+    }
+
+    fn rejected(&self, attempt: &Attempt<'_>) {
+        let why = Self::why();
+        match self.save(attempt) {
+            None => info!(
+                edit = attempt.description,
+                "Rejected {} ({})", attempt.description, why
+            ),
+            Some(p) => info!(
+                edit = attempt.description,
+                "Rejected {} ({}), saved to {}",
+                attempt.description,
+                why,
+                p.display()
+            ),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+use tracing::info;
+
+use super::{composite_check, Args};
+use crate::check::{is_marker, Check};
+use crate::reduce::ddmin::ddmin;
+
+/// Delete as many of the arguments of the check as possible (with ddmin),
+/// while it's still interesting on `src`. Arguments with `@@` (or `{}`) and
+/// `-` are kept. Returns the check's command line.
+pub(super) fn reduce(args: &Args, src: &[u8]) -> Result<Vec<String>> {
+    let base = composite_check(args, src)?;
+    let argv = base.main().args.clone();
+    let reducible: Vec<usize> = (0..argv.len())
+        .filter(|&i| !is_marker(&argv[i]) && argv[i] != "-")
+        .collect();
+    let kept = |deleted: &[usize]| -> Vec<String> {
+        (0..argv.len())
+            .filter(|i| !deleted.iter().any(|d| reducible[*d] == *i))
+            .map(|i| argv[i].clone())
+            .collect()
+    };
+    let mut checks = 0;
+    let deleted = ddmin(reducible.len(), |deleted| {
+        checks += 1;
+        let mut check = base.clone();
+        *check.main_mut() = base.main().with_args(kept(deleted));
+        check.interesting(src).map(Some)
+    })
+    .context("Failed to reduce the arguments of the check")?;
+    let kept = kept(&deleted);
+    info!(
+        "Reduced the check's arguments from {} to {} in {} checks: {}",
+        argv.len(),
+        kept.len(),
+        checks,
+        kept.join(" ")
+    );
+    let mut check = vec![args.check[0].clone()];
+    check.extend(kept);
+    Ok(check)
+}
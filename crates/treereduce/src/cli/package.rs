@@ -0,0 +1,402 @@
+//! Bundling the result into a reproducer for a bug report (`--package`, or
+//! `package RESULT PATH` for an existing result): the reduced input, a script
+//! that runs the check on it, what counts as interesting, tool versions, and
+//! stats, with a README.md to paste into the report.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use tracing::{info, warn};
+
+use super::{extension, Args, JsonStats};
+use crate::check::{is_marker, quote, split_marker, DIR_MARKER};
+
+/// Lines of each tool's version to keep
+const VERSION_LINES: usize = 3;
+
+/// Quote `word` for a shell, unless that's not needed
+fn shell_word(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@+%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        quote(word)
+    }
+}
+
+/// A fence for a Markdown code block containing `text`
+fn fence(text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+/// Replace the placeholders in `word` with `stem` and `.`
+fn substitute(word: &str, stem: &str) -> String {
+    let word = word.replace(DIR_MARKER, ".");
+    match split_marker(&word) {
+        Some((before, after)) => format!("{}{}{}", before, stem, after),
+        None => word,
+    }
+}
+
+/// The name of the reduced file, and the check and `--env` variables with it
+/// in place of the placeholders
+fn command(args: &Args) -> (String, Vec<String>, Vec<String>) {
+    // A suffix of the placeholder (e.g., '@@.c') is part of the name
+    let marker_suffix = args.check.iter().find_map(|w| {
+        let (_, after) = split_marker(w)?;
+        let len = after
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| !c.is_ascii_alphanumeric())
+            .map_or(after.len(), |(i, _)| i);
+        after.starts_with('.').then(|| after[..len].to_string())
+    });
+    let name = match (&marker_suffix, extension(args)) {
+        (Some(suffix), _) => format!("reduced{}", suffix),
+        (None, Some(ext)) => format!("reduced.{}", ext),
+        (None, None) => String::from("reduced"),
+    };
+    let stem = if marker_suffix.is_some() {
+        "reduced"
+    } else {
+        &name
+    };
+    let words = args.check.iter().map(|w| substitute(w, stem)).collect();
+    let env = args.env.iter().map(|v| substitute(v, stem)).collect();
+    (name, words, env)
+}
+
+fn script(args: &Args, name: &str, words: &[String], env: &[String]) -> Result<String> {
+    let mut script =
+        String::from("#!/bin/sh\n# Run the interestingness check on the reduced input\n");
+    script.push_str("cd \"$(dirname \"$0\")\"\n");
+    for var in env {
+        writeln!(script, "export {}", shell_word(var))?;
+    }
+    let cmd: Vec<String> = words.iter().map(|w| shell_word(w)).collect();
+    // Like the check, which reads stdin unless it takes a file
+    let file = args.check.iter().chain(&args.env).any(|w| is_marker(w));
+    if file && !args.check.iter().any(|w| w == "-") {
+        writeln!(script, "{}", cmd.join(" "))?;
+    } else {
+        writeln!(script, "{} < {}", cmd.join(" "), shell_word(name))?;
+    }
+    Ok(script)
+}
+
+/// What makes the check's result interesting, one item per line
+fn criteria(args: &Args) -> Vec<String> {
+    let mut criteria = Vec::new();
+    if !args.interesting_signal.is_empty() {
+        criteria.push(format!(
+            "it's killed by {}",
+            args.interesting_signal.join(" or ")
+        ));
+    } else if args.golden || args.golden_regex.is_some() {
+        criteria.push(String::from(
+            "its exit code, stdout, and stderr are the same as for the original input",
+        ));
+    } else {
+        criteria.push(format!(
+            "its exit code is {}",
+            args.interesting_exit_code.join(" or ")
+        ));
+    }
+    if let Some(r) = &args.golden_regex {
+        criteria.push(format!("(comparing only the captures of `{}`)", r));
+    }
+    for (stream, regex) in [
+        ("stdout", &args.interesting_stdout),
+        ("stderr", &args.interesting_stderr),
+    ] {
+        if let Some(r) = regex {
+            criteria.push(format!("its {} matches `{}`", stream, r));
+        }
+    }
+    for (stream, regex) in [
+        ("stdout", &args.uninteresting_stdout),
+        ("stderr", &args.uninteresting_stderr),
+    ] {
+        if let Some(r) = regex {
+            criteria.push(format!("its {} doesn't match `{}`", stream, r));
+        }
+    }
+    if let Some(t) = args.timeout {
+        criteria.push(format!("it finishes within {} seconds", t));
+    }
+    for cmd in &args.and_check {
+        criteria.push(format!("`{}` is also interesting", cmd));
+    }
+    for cmd in &args.or_check {
+        criteria.push(format!("or, `{}` is interesting", cmd));
+    }
+    for cmd in &args.not_check {
+        criteria.push(format!("`{}` isn't interesting", cmd));
+    }
+    criteria
+}
+
+/// Where the check ran, if `reproduce.sh` doesn't run it the same way
+fn notes(args: &Args) -> Vec<String> {
+    let mut notes = Vec::new();
+    if let Some(image) = &args.check_container {
+        notes.push(format!(
+            "The check ran in a container from `{}`, but `reproduce.sh` runs it directly: run it in that image to reproduce the result.",
+            image
+        ));
+    }
+    if !args.remote.is_empty() {
+        notes.push(format!(
+            "The check ran on {} over ssh, but `reproduce.sh` runs it locally.",
+            args.remote
+                .iter()
+                .map(|r| format!("`{}`", r))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    notes
+}
+
+/// The first lines of the output of `cmd`, e.g., `clang --version`
+fn version(cmd: &str) -> Result<String> {
+    let mut words = cmd.split_whitespace();
+    let Some(program) = words.next() else {
+        bail!("Empty --package-tool");
+    };
+    let output = Command::new(program)
+        .args(words)
+        .output()
+        .with_context(|| format!("Failed to run {}", cmd))?;
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    Ok(String::from_utf8_lossy(&text)
+        .lines()
+        .take(VERSION_LINES)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn readme(
+    args: &Args,
+    result: &[u8],
+    stats: Option<&JsonStats>,
+    name: &str,
+    words: &[String],
+) -> Result<String> {
+    let mut md = String::new();
+    let text = String::from_utf8_lossy(result);
+    let source = args.source.as_deref().unwrap_or("<stdin>");
+    writeln!(md, "## Reproducer\n")?;
+    match stats {
+        Some(json) => writeln!(
+            md,
+            "Reduced from `{}` ({} bytes) to {} bytes with treereduce {}, in {} checks.\n",
+            source,
+            json.stats.start_size,
+            result.len(),
+            env!("CARGO_PKG_VERSION"),
+            json.checks
+        )?,
+        None => match &args.source {
+            Some(s) => writeln!(
+                md,
+                "Reduced from `{}` ({} bytes) to {} bytes.\n",
+                s,
+                fs::metadata(s)
+                    .with_context(|| format!("Failed to read {}", s))?
+                    .len(),
+                result.len()
+            )?,
+            None => writeln!(md, "Reduced to {} bytes.\n", result.len())?,
+        },
+    }
+    let f = fence(&text);
+    let lang = extension(args).unwrap_or_default();
+    writeln!(md, "{}{}\n{}", f, lang, text.trim_end_matches('\n'))?;
+    writeln!(md, "{}\n", f)?;
+    let cmd: Vec<String> = words.iter().map(|w| shell_word(w)).collect();
+    writeln!(
+        md,
+        "The check runs this on `{}` (see `reproduce.sh`):\n\n```sh\n{}\n```\n",
+        name,
+        cmd.join(" ")
+    )?;
+    writeln!(md, "The input is interesting when:\n")?;
+    for c in criteria(args) {
+        writeln!(md, "- {}", c)?;
+    }
+    writeln!(md)?;
+    for note in notes(args) {
+        writeln!(md, "{}\n", note)?;
+    }
+    writeln!(md, "### Versions\n")?;
+    writeln!(md, "- treereduce {}", env!("CARGO_PKG_VERSION"))?;
+    for tool in &args.package_tool {
+        match version(tool) {
+            Ok(v) => {
+                writeln!(
+                    md,
+                    "- `{}`:\n\n  ```\n  {}\n  ```",
+                    tool,
+                    v.replace('\n', "\n  ")
+                )?;
+            }
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+    Ok(md)
+}
+
+/// `stats` are the stats of the reduction, if the result is new
+fn write_dir(dir: &Path, args: &Args, result: &[u8], stats: Option<&JsonStats>) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let (name, words, env) = command(args);
+    let mut files = vec![
+        (name.clone(), result.to_vec()),
+        (
+            String::from("reproduce.sh"),
+            script(args, &name, &words, &env)?.into_bytes(),
+        ),
+        (
+            String::from("README.md"),
+            readme(args, result, stats, &name, &words)?.into_bytes(),
+        ),
+    ];
+    if let Some(json) = stats {
+        files.push((String::from("stats.json"), serde_json::to_vec_pretty(json)?));
+    }
+    for (file, contents) in files {
+        let path = dir.join(file);
+        fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir.join("reproduce.sh"), fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Write the reproducer to `path`: a directory, or a tarball (with `tar`) if
+/// it ends in `.tar.gz`, `.tgz`, or `.tar`. `stats` are the stats of the
+/// reduction, unless the result is an existing one.
+pub(super) fn package(
+    path: &str,
+    args: &Args,
+    result: &[u8],
+    stats: Option<&JsonStats>,
+) -> Result<()> {
+    let tarball = [".tar.gz", ".tgz", ".tar"]
+        .into_iter()
+        .find_map(|ext| path.strip_suffix(ext).map(|stem| (stem, ext != ".tar")));
+    let Some((stem, gzip)) = tarball else {
+        write_dir(Path::new(path), args, result, stats)?;
+        info!("Wrote the reproducer to {}", path);
+        return Ok(());
+    };
+    let tmp = tempfile::tempdir().context("Failed to create a temporary directory")?;
+    let name = Path::new(stem)
+        .file_name()
+        .map_or_else(|| PathBuf::from("reproducer"), PathBuf::from);
+    write_dir(&tmp.path().join(&name), args, result, stats)?;
+    let status = Command::new("tar")
+        .arg(if gzip { "-czf" } else { "-cf" })
+        .arg(std::env::current_dir()?.join(path))
+        .arg("-C")
+        .arg(tmp.path())
+        .arg(&name)
+        .status()
+        .context("Failed to run tar")?;
+    if !status.success() {
+        bail!("tar failed to write {}", path);
+    }
+    info!("Wrote the reproducer to {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn args(argv: &[&str]) -> Args {
+        Args::try_parse_from(["treereduce"].iter().chain(argv)).unwrap()
+    }
+
+    #[test]
+    fn test_shell_word() {
+        assert_eq!(shell_word("cc"), "cc");
+        assert_eq!(shell_word("-fsyntax-only"), "-fsyntax-only");
+        assert_eq!(shell_word(""), "''");
+        assert_eq!(shell_word("a b"), "'a b'");
+        assert_eq!(shell_word("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_fence() {
+        assert_eq!(fence("int x;"), "```");
+        assert_eq!(fence("a ``` b ` c"), "````");
+    }
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(substitute("@@.c", "reduced"), "reduced.c");
+        assert_eq!(substitute("--file={}", "reduced.c"), "--file=reduced.c");
+        assert_eq!(substitute("{tmpdir}/a.o", "reduced"), "./a.o");
+        assert_eq!(substitute("-O2", "reduced"), "-O2");
+    }
+
+    #[test]
+    fn test_command() {
+        let (name, words, env) = command(&args(&["-s", "in.c", "--", "cc", "@@.c"]));
+        assert_eq!(name, "reduced.c");
+        assert_eq!(words, vec!["cc", "reduced.c"]);
+        assert!(env.is_empty());
+        let (name, words, _) = command(&args(&["-s", "in.c", "--", "cc", "@@"]));
+        assert_eq!(name, "reduced.c");
+        assert_eq!(words, vec!["cc", "reduced.c"]);
+        let (name, words, _) = command(&args(&["--", "python3", "check.py"]));
+        assert_eq!(name, "reduced");
+        assert_eq!(words, vec!["python3", "check.py"]);
+    }
+
+    #[test]
+    fn test_script() {
+        let a = args(&["-s", "in.c", "--", "cc", "-c", "@@.c"]);
+        let (name, words, env) = command(&a);
+        assert_eq!(
+            script(&a, &name, &words, &env).unwrap(),
+            "#!/bin/sh\n# Run the interestingness check on the reduced input\ncd \"$(dirname \"$0\")\"\ncc -c reduced.c\n"
+        );
+        let a = args(&["-s", "in.c", "--", "sh", "-c", "cc -x c -"]);
+        let (name, words, env) = command(&a);
+        assert!(script(&a, &name, &words, &env)
+            .unwrap()
+            .ends_with("sh -c 'cc -x c -' < reduced.c\n"));
+    }
+
+    #[test]
+    fn test_notes() {
+        assert!(notes(&args(&["--", "cc", "@@.c"])).is_empty());
+        let a = args(&["--check-container", "gcc:13", "--", "cc", "@@.c"]);
+        assert_eq!(notes(&a).len(), 1);
+        assert!(notes(&a)[0].contains("`gcc:13`"));
+        let a = args(&["--remote", "a", "--remote", "b:2", "--", "cc", "@@.c"]);
+        assert!(notes(&a)[0].contains("`a`, `b:2`"));
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use tracing::{info, warn};
+
+use crate::check::Check;
+
+/// Run the formatter `cmd` (split on whitespace) on `text`, which is passed
+/// on stdin. Returns its stdout, or `None` if it failed.
+fn run(cmd: &str, text: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    let mut words = cmd.split_whitespace();
+    let program = match words.next() {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("Formatter has no stdin");
+    // In another thread, so that the formatter can't block on a full stdout
+    let output = thread::scope(|s| {
+        s.spawn(move || stdin.write_all(text));
+        child.wait_with_output()
+    })?;
+    Ok(output.status.success().then_some(output.stdout))
+}
+
+/// Format `text` with `cmd`, if the result is still interesting
+pub fn format<T: Check>(cmd: &str, text: Vec<u8>, check: &T) -> anyhow::Result<Vec<u8>> {
+    let formatted = match run(cmd, &text) {
+        Err(e) => {
+            warn!("Failed to run formatter {}: {}", cmd, e);
+            return Ok(text);
+        }
+        Ok(None) => {
+            warn!("Formatter {} failed, not formatting the result", cmd);
+            return Ok(text);
+        }
+        Ok(Some(f)) if f == text => return Ok(text),
+        Ok(Some(f)) => f,
+    };
+    if check.interesting(&formatted)? {
+        info!("Formatted the result with {}", cmd);
+        Ok(formatted)
+    } else {
+        info!("The formatted result wasn't interesting, not formatting it");
+        Ok(text)
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::observer::{Attempt, Observer, PassDone};
+
+/// Don't redraw more often than this
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether the bar is on the screen, so that log messages can clear it first
+static DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// Erase the bar, if it's drawn. It's redrawn on the next update.
+pub fn clear() {
+    if DRAWN.swap(false, Ordering::AcqRel) {
+        let _ = io::stderr().write_all(b"\r\x1b[2K");
+    }
+}
+
+#[derive(Debug)]
+struct Counts {
+    accepted: usize,
+    rejected: usize,
+    drawn: Option<Instant>,
+}
+
+/// A one-line summary of progress on stderr
+#[derive(Debug)]
+pub struct ProgressBar {
+    orig_size: usize,
+    start: Instant,
+    counts: Mutex<Counts>,
+}
+
+impl ProgressBar {
+    pub fn new(orig_size: usize) -> Self {
+        ProgressBar {
+            orig_size,
+            start: Instant::now(),
+            counts: Mutex::new(Counts {
+                accepted: 0,
+                rejected: 0,
+                drawn: None,
+            }),
+        }
+    }
+
+    fn draw(&self, counts: &mut Counts, current_size: usize, pending: Option<usize>) {
+        if counts.drawn.is_some_and(|d| d.elapsed() < REDRAW_INTERVAL) {
+            return;
+        }
+        counts.drawn = Some(Instant::now());
+        let checks = counts.accepted + counts.rejected;
+        let smaller = 100usize.saturating_sub((100 * current_size) / self.orig_size.max(1));
+        let mut line = format!(
+            "{} / {} bytes ({}% smaller) | {} checks, {}% accepted",
+            current_size,
+            self.orig_size,
+            smaller,
+            checks,
+            (100 * counts.accepted) / checks.max(1),
+        );
+        let secs = self.start.elapsed().as_secs_f64();
+        if let Some(p) = pending.filter(|_| checks > 0 && secs > 0.0) {
+            let eta = p as f64 * secs / checks as f64;
+            line.push_str(&format!(" | ~{}s left in pass", eta.ceil()));
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K{}", line);
+        let _ = stderr.flush();
+        DRAWN.store(true, Ordering::Release);
+    }
+}
+
+impl Observer for ProgressBar {
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.accepted += 1;
+        self.draw(&mut counts, attempt.current_size, Some(attempt.pending));
+    }
+
+    fn rejected(&self, attempt: &Attempt<'_>) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.rejected += 1;
+        self.draw(&mut counts, attempt.current_size, Some(attempt.pending));
+    }
+
+    fn pass_done(&self, pass: &PassDone<'_>) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.drawn = None;
+        self.draw(&mut counts, pass.end_size, None);
+    }
+}
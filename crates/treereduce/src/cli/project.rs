@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use tracing::{info, info_span};
+
+use super::{composite_check, configure, handle_parse_errors, limit, parse, passes, Args, Oracle};
+use crate::check::{CachedCheck, Check, RetryCheck};
+use crate::node_types::NodeTypes;
+use crate::original::Original;
+use crate::reduce::{self, Config};
+
+/// Where the original of `path` is saved
+fn backup(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".orig");
+    PathBuf::from(name)
+}
+
+/// Reduce the file at `path`, which contains `src`, with the other files as
+/// they are. Afterwards, the file contains the result.
+fn reduce_file(
+    args: &Args,
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    conf: &Config<Oracle>,
+    path: &Path,
+    src: Vec<u8>,
+) -> Result<Vec<u8>> {
+    // Verdicts depend on the other files, so each reduction has its own cache
+    let check = composite_check(args, &[])?.map(|c| {
+        c.in_place(path.to_path_buf())
+            .control(Arc::clone(&conf.control))
+    });
+    let conf = Config {
+        check: CachedCheck::new(
+            RetryCheck::new(check, args.oracle_retries, args.oracle_vote),
+            !args.no_cache,
+        )
+        .control(Arc::clone(&conf.control)),
+        ..conf.clone()
+    };
+    let tree = parse(language, &src)?;
+    handle_parse_errors(&path.to_string_lossy(), &tree, &args.on_parse_error);
+    let orig = Original::new(tree, src.clone());
+    let result = reduce::treereduce_multi_pass(language, node_types, orig, &conf, passes(args));
+    // The file contains the last candidate that was checked
+    let text = match result {
+        Ok((reduced, _)) => reduced.text,
+        Err(e) => {
+            fs::write(path, &src)?;
+            return Err(e.into());
+        }
+    };
+    fs::write(path, &text).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(text)
+}
+
+fn write_summary(
+    w: &mut impl Write,
+    files: &[PathBuf],
+    sizes: &[(usize, usize)],
+) -> io::Result<()> {
+    let names: Vec<_> = files.iter().map(|p| p.to_string_lossy()).collect();
+    let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+    for (name, (start, end)) in names.iter().zip(sizes) {
+        writeln!(w, "{:width$}  {} -> {} bytes", name, start, end)?;
+    }
+    let (start, end) = sizes
+        .iter()
+        .fold((0, 0), |(s, e), (start, end)| (s + start, e + end));
+    writeln!(
+        w,
+        "Reduced {} files from {} to {} bytes",
+        files.len(),
+        start,
+        end
+    )
+}
+
+/// Reduce `files` together, in place: reduce each in turn, with the others
+/// as they are, until none gets smaller. The check runs as-is, e.g., a build
+/// of the project containing the files. The originals are saved next to them
+/// (as `FILE.orig`).
+pub(super) fn run(
+    args: &Args,
+    files: &[PathBuf],
+    language: tree_sitter::Language,
+    node_types_json_str: &str,
+    replacements: HashMap<&'static str, &'static [&'static str]>,
+) -> Result<()> {
+    let node_types = NodeTypes::new(node_types_json_str)?;
+    let mut conf = configure(args, replacements, &[])?;
+    if conf.check.inner().inner().main().needs_file {
+        bail!("With --files, the check reads the files themselves, not '@@'");
+    }
+    // Checks share the files, so they can't run in parallel
+    conf.jobs = 1;
+    conf.oracle_jobs = 1;
+
+    let mut texts = Vec::with_capacity(files.len());
+    for path in files {
+        let text = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let orig = backup(path);
+        if !orig.exists() {
+            fs::write(&orig, &text)
+                .with_context(|| format!("Failed to write {}", orig.display()))?;
+        }
+        texts.push(text);
+    }
+    if !args.no_verify && !conf.check.inner().interesting(&[])? {
+        bail!("The check isn't interesting on the files as they are");
+    }
+    let start_sizes: Vec<usize> = texts.iter().map(Vec::len).collect();
+    limit(
+        args,
+        &conf.control,
+        Instant::now(),
+        start_sizes.iter().sum(),
+    )?;
+
+    for round in 1.. {
+        let before: usize = texts.iter().map(Vec::len).sum();
+        for (path, text) in files.iter().zip(texts.iter_mut()) {
+            if conf.control.is_stopped() {
+                break;
+            }
+            let _span = info_span!("file", file = %path.display()).entered();
+            info!("Reducing {} ({} bytes)", path.display(), text.len());
+            let src = std::mem::take(text);
+            *text = reduce_file(args, language, &node_types, &conf, path, src)?;
+        }
+        let after: usize = texts.iter().map(Vec::len).sum();
+        info!(
+            round,
+            "Reduced the files from {} to {} bytes", before, after
+        );
+        if after == before || conf.control.is_stopped() {
+            break;
+        }
+    }
+
+    if !args.no_verify && !conf.check.inner().interesting(&[])? {
+        bail!("The reduced files aren't interesting when checked again");
+    }
+    let sizes: Vec<_> = start_sizes
+        .into_iter()
+        .zip(texts.iter().map(Vec::len))
+        .collect();
+    write_summary(&mut io::stdout().lock(), files, &sizes)?;
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//! Testing the reducer itself on a corpus (`selftest --corpus DIR`, or
+//! `--selftest DIR`), e.g., when adding a language. Each file is reduced with
+//! a check that finds everything interesting, which exercises as many
+//! reductions as possible, while checking that:
+//!
+//! - no accepted candidate (or result of a pass) is bigger than the one
+//!   before it,
+//! - every accepted candidate parses (if the input did), and
+//! - the result has no more parse errors than the input, and is the last
+//!   candidate that was accepted (or the result of the last pass), i.e., the
+//!   reducer didn't change it without checking it.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{bail, Result};
+use tracing::{info, info_span, warn};
+
+use super::corpus::{file_name, files, read};
+use super::{configure, parse, passes, Args};
+use crate::check::{CachedCheck, FnCheck};
+use crate::node_types::NodeTypes;
+use crate::observer::{Attempt, Observer, PassDone};
+use crate::original::Original;
+use crate::reduce::{self, count_errors, Algorithm};
+
+/// Violations to report about one file, at most
+const MAX_VIOLATIONS: usize = 10;
+
+/// Checks the invariants as candidates are accepted
+#[derive(Debug)]
+struct Invariants {
+    language: tree_sitter::Language,
+    /// Whether to check that candidates parse
+    parses: bool,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The last accepted candidate, or result of a pass
+    last: Vec<u8>,
+    accepted: usize,
+    violations: Vec<String>,
+}
+
+impl State {
+    fn violation(&mut self, v: String) {
+        warn!("{}", v);
+        if self.violations.len() < MAX_VIOLATIONS {
+            self.violations.push(v);
+        }
+    }
+
+    /// `text` is the new candidate, after `what`
+    fn shrunk(&mut self, what: &str, text: &[u8]) {
+        if text.len() > self.last.len() {
+            let v = format!(
+                "{} grew the candidate from {} to {} bytes",
+                what,
+                self.last.len(),
+                text.len()
+            );
+            self.violation(v);
+        }
+        self.last = text.to_vec();
+    }
+}
+
+impl Invariants {
+    fn new(language: tree_sitter::Language, src: &[u8], parses: bool) -> Self {
+        Invariants {
+            language,
+            parses,
+            state: Mutex::new(State {
+                last: src.to_vec(),
+                accepted: 0,
+                violations: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl Observer for Invariants {
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        let text = attempt.text.to_vec();
+        let parse_error =
+            self.parses && parse(self.language, &text).map_or(true, |t| t.root_node().has_error());
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.accepted += 1;
+        state.shrunk(attempt.description, &text);
+        if parse_error {
+            state.violation(format!("{} caused a parse error", attempt.description));
+        }
+    }
+
+    fn pass_done(&self, pass: &PassDone<'_>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.shrunk(&format!("Pass {}", pass.stage), pass.text);
+    }
+}
+
+/// The result for one file
+#[derive(Debug)]
+struct Entry {
+    file: String,
+    start_size: usize,
+    end_size: usize,
+    accepted: usize,
+    violations: Vec<String>,
+    seconds: f64,
+}
+
+fn write_report(w: &mut impl Write, entries: &[Entry]) -> io::Result<()> {
+    let width = entries.iter().map(|e| e.file.len()).max().unwrap_or(0);
+    for e in entries {
+        let status = if e.violations.is_empty() {
+            "ok"
+        } else {
+            "FAILED"
+        };
+        writeln!(
+            w,
+            "{:width$}  {}: {} -> {} bytes, {} accepted ({:.1}s)",
+            e.file, status, e.start_size, e.end_size, e.accepted, e.seconds
+        )?;
+        for v in &e.violations {
+            writeln!(w, "{:width$}    {}", "", v)?;
+        }
+    }
+    let passed = entries.iter().filter(|e| e.violations.is_empty()).count();
+    writeln!(w, "Passed {} of {} files", passed, entries.len())
+}
+
+fn test_one(
+    args: &Args,
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    replacements: HashMap<&'static str, &'static [&'static str]>,
+    src: Vec<u8>,
+) -> Result<Entry> {
+    let start = Instant::now();
+    let tree = parse(language, &src)?;
+    let start_errors = count_errors(&tree);
+    let invariants = Arc::new(Invariants::new(
+        language,
+        &src,
+        !tree.root_node().has_error(),
+    ));
+    // The command is never run, the check is replaced
+    let args = Args {
+        check: vec![String::from("true")],
+        ..args.clone()
+    };
+    let mut conf = configure(&args, replacements, &src)?
+        .with_check(CachedCheck::new(FnCheck::new(|_: &[u8]| true), true));
+    conf.observers.push(invariants.clone());
+    let start_size = src.len();
+    let orig = Original::new(tree, src);
+    let (reduced, _) =
+        reduce::treereduce_multi_pass(language, node_types, orig, &conf, passes(&args))?;
+    let state = invariants.state.lock().unwrap_or_else(|e| e.into_inner());
+    let mut violations = state.violations.clone();
+    let end_errors = count_errors(&reduced.tree);
+    if end_errors > start_errors {
+        violations.push(format!(
+            "The result has {} parse errors, the input had {}",
+            end_errors, start_errors
+        ));
+    }
+    // These change the result without notifying observers
+    let unobserved =
+        conf.algorithm == Algorithm::Hdd || conf.injections.is_some() || conf.one_minimal;
+    if !unobserved && reduced.text != state.last {
+        violations.push(String::from(
+            "The result isn't the last accepted candidate, or the result of the last pass",
+        ));
+    }
+    Ok(Entry {
+        file: String::new(),
+        start_size,
+        end_size: reduced.text.len(),
+        accepted: state.accepted,
+        violations,
+        seconds: start.elapsed().as_secs_f64(),
+    })
+}
+
+/// Reduce each file in `dir` with a check that's always interesting, and
+/// report the files where the reducer broke an invariant
+pub(super) fn run(
+    args: &Args,
+    dir: &Path,
+    language: tree_sitter::Language,
+    node_types_json_str: &str,
+    replacements: HashMap<&'static str, &'static [&'static str]>,
+) -> Result<()> {
+    let node_types = NodeTypes::new(node_types_json_str)?;
+    let mut entries = Vec::new();
+    for path in files(dir)? {
+        let file = file_name(&path);
+        let _span = info_span!("file", file = file.as_str()).entered();
+        let src = read(args, &path)?;
+        info!("Testing {} ({} bytes)", file, src.len());
+        let start_size = src.len();
+        let entry = test_one(args, language, &node_types, replacements.clone(), src)
+            .unwrap_or_else(|e| Entry {
+                file: String::new(),
+                start_size,
+                end_size: start_size,
+                accepted: 0,
+                violations: vec![format!("Failed to reduce: {:#}", e)],
+                seconds: 0.0,
+            });
+        entries.push(Entry { file, ..entry });
+    }
+    write_report(&mut io::stdout().lock(), &entries)?;
+    let failed = entries.iter().filter(|e| !e.violations.is_empty()).count();
+    if failed > 0 {
+        bail!(
+            "The self-test failed on {} of {} files",
+            failed,
+            entries.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shrunk() {
+        let mut state = State {
+            last: b"abc".to_vec(),
+            accepted: 0,
+            violations: Vec::new(),
+        };
+        state.shrunk("delete", b"ab");
+        assert!(state.violations.is_empty());
+        state.shrunk("hoist", b"abcd");
+        assert_eq!(
+            state.violations,
+            vec!["hoist grew the candidate from 2 to 4 bytes"]
+        );
+        assert_eq!(state.last, b"abcd");
+    }
+}
@@ -0,0 +1,199 @@
+//! Controlling a reduction with signals: `SIGINT` finishes early with the
+//! smallest result so far, `SIGUSR1` reports the status and saves that
+//! result, and `SIGUSR2` pauses or resumes checking new candidates.
+//!
+//! Ctrl-C in a terminal sends `SIGINT` to the checks in progress too, so
+//! their verdicts are discarded (see [`Control::interrupt`]).
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::control::Control;
+use crate::observer::{Attempt, Observer};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+static REPORT: AtomicBool = AtomicBool::new(false);
+static TOGGLE_PAUSE: AtomicBool = AtomicBool::new(false);
+/// The reduction's [`Control`], kept alive by [`Signals`] while it's set
+static CONTROL: AtomicPtr<Control> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Whether there was a `SIGINT`, so that `--watch` doesn't start again
+pub(super) fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::Acquire) > 0
+}
+
+#[cfg(unix)]
+extern "C" fn handle(signal: libc::c_int) {
+    // Only async-signal-safe operations here: atomics and _exit
+    match signal {
+        libc::SIGINT => {
+            let control = CONTROL.load(Ordering::Acquire);
+            if !control.is_null() {
+                // SAFETY: Signals keeps the Control alive while it's set, and
+                // interrupt only stores an atomic
+                unsafe { (*control).interrupt() };
+            }
+            if INTERRUPTS.fetch_add(1, Ordering::AcqRel) > 0 {
+                // SAFETY: _exit is async-signal-safe
+                unsafe { libc::_exit(130) };
+            }
+        }
+        libc::SIGUSR1 => REPORT.store(true, Ordering::Release),
+        libc::SIGUSR2 => TOGGLE_PAUSE.store(true, Ordering::Release),
+        _ => {}
+    }
+}
+
+#[cfg(unix)]
+const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGUSR1, libc::SIGUSR2];
+
+#[cfg(unix)]
+fn install() {
+    for signal in SIGNALS {
+        // SAFETY: handle only touches atomics, and sa_mask is initialized by
+        // sigemptyset before use
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                warn!("Failed to handle signal {}", signal);
+            }
+        }
+    }
+}
+
+/// Restore the default actions, e.g., so that `SIGINT` quits right away
+#[cfg(unix)]
+fn uninstall() {
+    for signal in SIGNALS {
+        // SAFETY: SIG_DFL is always a valid action
+        unsafe {
+            if libc::signal(signal, libc::SIG_DFL) == libc::SIG_ERR {
+                warn!("Failed to restore the action for signal {}", signal);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn install() {}
+
+#[cfg(not(unix))]
+fn uninstall() {}
+
+/// The smallest result so far, for `SIGUSR1`
+#[derive(Debug)]
+pub(super) struct Status {
+    input_size: usize,
+    best: Mutex<Option<Vec<u8>>>,
+    accepted: AtomicUsize,
+    /// Where to save the smallest result
+    path: PathBuf,
+}
+
+impl Status {
+    pub(super) fn new(input_size: usize, path: PathBuf) -> Self {
+        Status {
+            input_size,
+            best: Mutex::new(None),
+            accepted: AtomicUsize::new(0),
+            path,
+        }
+    }
+
+    fn report(&self, control: &Control) {
+        let best = self.best.lock().unwrap_or_else(|e| e.into_inner());
+        let size = best.as_ref().map_or(self.input_size, Vec::len);
+        info!(
+            "{} bytes ({:.1}% of the input), {} checks, {} accepted{}",
+            size,
+            100.0 * size as f64 / self.input_size.max(1) as f64,
+            control.checks(),
+            self.accepted.load(Ordering::Acquire),
+            if control.is_paused() { ", paused" } else { "" }
+        );
+        if let Some(text) = best.as_ref() {
+            match fs::write(&self.path, text) {
+                Ok(()) => info!(
+                    "Saved the smallest result so far to {}",
+                    self.path.display()
+                ),
+                Err(e) => warn!("Failed to write {}: {}", self.path.display(), e),
+            }
+        }
+    }
+}
+
+impl Observer for Status {
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        self.accepted.fetch_add(1, Ordering::AcqRel);
+        *self.best.lock().unwrap_or_else(|e| e.into_inner()) = Some(attempt.text.to_vec());
+    }
+}
+
+/// Acts on signals until dropped
+#[derive(Debug)]
+pub(super) struct Signals {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Signals {
+    pub(super) fn start(control: Arc<Control>, status: Arc<Status>) -> Self {
+        CONTROL.store(Arc::as_ptr(&control).cast_mut(), Ordering::Release);
+        install();
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut stopped = false;
+                while !done.load(Ordering::Acquire) {
+                    if interrupted() && !stopped {
+                        stopped = true;
+                        info!("Interrupted, finishing early (interrupt again to quit now)");
+                        control.stop();
+                    }
+                    if REPORT.swap(false, Ordering::AcqRel) {
+                        status.report(&control);
+                    }
+                    if TOGGLE_PAUSE.swap(false, Ordering::AcqRel) {
+                        if control.is_paused() {
+                            info!("Resuming");
+                            control.resume();
+                        } else {
+                            info!("Pausing after the checks in progress");
+                            control.pause();
+                        }
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+            })
+        };
+        Signals {
+            done,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Signals {
+    fn drop(&mut self) {
+        uninstall();
+        // Before the thread drops its reference to the Control
+        CONTROL.store(std::ptr::null_mut(), Ordering::Release);
+        self.done.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Subcommands, like `treereduce-c replay TRACE ORIGINAL`, which are
+//! shorthands for options that do something other than reduce the input.
+//! Each is rewritten to those options before parsing, so it takes the same
+//! options (and `treereduce.toml`) as a reduction.
+
+use std::ffi::OsString;
+
+use anyhow::{bail, Result};
+
+/// Listed after the options in `--help`
+pub(super) const HELP: &str = "\
+Subcommands (instead of reducing the input):
+  replay TRACE ORIGINAL [OPTIONS] [-- CMD...]
+          Apply the edits in TRACE (see --trace) to ORIGINAL, like --replay
+  selftest --corpus DIR [OPTIONS]
+          Test the reducer on each file in DIR, like --selftest
+  package RESULT PATH [OPTIONS] -- CMD...
+          Bundle RESULT, an existing result, into PATH for a bug report, like
+          --package-result and --package";
+
+struct Subcommand {
+    name: &'static str,
+    /// Names of the operands, which come right after the name
+    operands: &'static [&'static str],
+    /// The options that the subcommand stands for, each followed by an
+    /// operand
+    options: &'static [&'static str],
+    /// Required options of the subcommand that stand for other options, with
+    /// the names of their values
+    renamed: &'static [(&'static str, &'static str, &'static str)],
+}
+
+const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "replay",
+        operands: &["TRACE", "ORIGINAL"],
+        options: &["--replay", "--source"],
+        renamed: &[],
+    },
+    Subcommand {
+        name: "selftest",
+        operands: &[],
+        options: &[],
+        renamed: &[("--corpus", "DIR", "--selftest")],
+    },
+    Subcommand {
+        name: "package",
+        operands: &["RESULT", "PATH"],
+        options: &["--package-result", "--package"],
+        renamed: &[],
+    },
+];
+
+impl Subcommand {
+    fn usage(&self) -> String {
+        let mut words: Vec<String> = self.operands.iter().map(|o| o.to_string()).collect();
+        for (option, value, _) in self.renamed {
+            words.push(format!("{} {}", option, value));
+        }
+        words.join(" ")
+    }
+
+    /// Replace the renamed options (before any `--`), or `None` if one is
+    /// missing
+    fn rename(&self, args: Vec<OsString>) -> Option<Vec<OsString>> {
+        let mut renamed = vec![false; self.renamed.len()];
+        let mut after_dashes = false;
+        let args = args
+            .into_iter()
+            .map(|arg| {
+                let s = arg.to_string_lossy().into_owned();
+                if after_dashes || s == "--" {
+                    after_dashes = true;
+                    return arg;
+                }
+                for (i, (from, _, to)) in self.renamed.iter().enumerate() {
+                    if s == *from {
+                        renamed[i] = true;
+                        return OsString::from(*to);
+                    }
+                    if let Some(value) = s.strip_prefix(from).and_then(|v| v.strip_prefix('=')) {
+                        renamed[i] = true;
+                        return OsString::from(format!("{}={}", to, value));
+                    }
+                }
+                arg
+            })
+            .collect();
+        renamed.iter().all(|r| *r).then_some(args)
+    }
+}
+
+/// Rewrite a subcommand (the first argument) to the options it stands for
+pub(super) fn expand(argv: Vec<OsString>) -> Result<Vec<OsString>> {
+    let Some(name) = argv.get(1).and_then(|a| a.to_str()) else {
+        return Ok(argv);
+    };
+    let Some(sub) = SUBCOMMANDS.iter().find(|s| s.name == name) else {
+        return Ok(argv);
+    };
+    let usage = format!(
+        "Usage: {} {} {} [OPTIONS]",
+        argv[0].to_string_lossy(),
+        sub.name,
+        sub.usage()
+    );
+    let n = sub.operands.len();
+    let operands: Vec<OsString> = argv.iter().skip(2).take(n).cloned().collect();
+    if operands.len() < n
+        || operands
+            .iter()
+            .any(|o| o.to_string_lossy().starts_with('-'))
+    {
+        bail!(usage);
+    }
+    let mut expanded = vec![argv[0].clone()];
+    for (option, operand) in sub.options.iter().zip(operands) {
+        expanded.push(OsString::from(*option));
+        expanded.push(operand);
+    }
+    let Some(rest) = sub.rename(argv.into_iter().skip(2 + n).collect()) else {
+        bail!(usage);
+    };
+    expanded.extend(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<OsString> {
+        s.split(' ').map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_expand() {
+        let expand = |s| expand(args(s)).unwrap();
+        assert_eq!(
+            expand("treereduce-c replay t.jsonl p.c -o r.c -- cc @@.c"),
+            args("treereduce-c --replay t.jsonl --source p.c -o r.c -- cc @@.c")
+        );
+        assert_eq!(
+            expand("treereduce-c -s p.c cc"),
+            args("treereduce-c -s p.c cc")
+        );
+        assert_eq!(expand("treereduce-c"), args("treereduce-c"));
+        assert!(super::expand(args("treereduce-c replay t.jsonl")).is_err());
+        assert!(super::expand(args("treereduce-c replay t.jsonl -o r.c")).is_err());
+        assert_eq!(
+            expand("treereduce selftest --language c --corpus=dir"),
+            args("treereduce --language c --selftest=dir")
+        );
+        assert_eq!(
+            expand("treereduce-c selftest --corpus dir -- --corpus"),
+            args("treereduce-c --selftest dir -- --corpus")
+        );
+        assert!(super::expand(args("treereduce-c selftest dir")).is_err());
+        assert_eq!(
+            expand("treereduce-c package r.c bug.tar.gz -s p.c -- cc @@.c"),
+            args("treereduce-c --package-result r.c --package bug.tar.gz -s p.c -- cc @@.c")
+        );
+    }
+}
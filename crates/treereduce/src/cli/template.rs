@@ -0,0 +1,93 @@
+//! Reducing toward a skeleton (`--template`): parts of the input with the
+//! same structure as parts of the skeleton are kept, and identifiers are
+//! renamed to the skeleton's names.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use tree_sitter::{Node, Tree};
+
+use crate::reduce::rename::is_identifier;
+
+#[derive(Debug, Default)]
+pub(super) struct Template {
+    /// Byte ranges of the input to keep
+    pub(super) preserve: Vec<Range<usize>>,
+    /// The skeleton's identifiers, in order of first occurrence
+    pub(super) names: Vec<String>,
+}
+
+/// A hash of the kinds and tokens of `node` and its descendants, but not the
+/// names of identifiers. Calls `found` with each named node with children.
+fn shape(node: Node, text: &[u8], found: &mut impl FnMut(Node, u64)) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if is_identifier(&node) {
+        node.kind().hash(&mut hasher);
+    } else if node.child_count() == 0 {
+        text[node.byte_range()].hash(&mut hasher);
+    } else {
+        node.kind().hash(&mut hasher);
+        for child in node.children(&mut node.walk()) {
+            shape(child, text, found).hash(&mut hasher);
+        }
+    }
+    let hash = hasher.finish();
+    if node.is_named() && node.child_count() > 0 {
+        found(node, hash);
+    }
+    hash
+}
+
+fn names(root: Node, text: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if is_identifier(&node) {
+            let name = String::from_utf8_lossy(&text[node.byte_range()]).into_owned();
+            if seen.insert(name.clone()) {
+                names.push(name);
+            }
+        }
+        let children: Vec<Node> = node.children(&mut node.walk()).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    names
+}
+
+/// The outermost nodes of `tree` with the same shape as some node of
+/// `skeleton_tree` (besides its root), and the names in the skeleton
+pub(super) fn template(
+    skeleton_tree: &Tree,
+    skeleton: &[u8],
+    tree: &Tree,
+    text: &[u8],
+) -> Template {
+    let skel_root = skeleton_tree.root_node();
+    let mut shapes = HashSet::new();
+    shape(skel_root, skeleton, &mut |node, hash| {
+        if node.id() != skel_root.id() {
+            shapes.insert(hash);
+        }
+    });
+    let mut matched = Vec::new();
+    shape(tree.root_node(), text, &mut |node, hash| {
+        if shapes.contains(&hash) {
+            matched.push(node.byte_range());
+        }
+    });
+    // Outermost first, then drop the ones inside them
+    matched.sort_by_key(|r| (r.start, std::cmp::Reverse(r.end)));
+    let mut preserve: Vec<Range<usize>> = Vec::new();
+    for range in matched {
+        if preserve.last().map_or(true, |p| range.end > p.end) {
+            preserve.push(range);
+        }
+    }
+    Template {
+        preserve,
+        names: names(skel_root, skeleton),
+    }
+}
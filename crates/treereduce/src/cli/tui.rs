@@ -0,0 +1,256 @@
+//! A full-screen view of a running reduction, with hotkeys to control it.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::control::Control;
+use crate::observer::{Attempt, Observer, PassDone};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+/// Number of recently accepted edits to show
+const RECENT: usize = 5;
+
+#[derive(Debug, Default)]
+struct State {
+    current_size: usize,
+    accepted: usize,
+    rejected: usize,
+    passes: usize,
+    last_pass: Option<String>,
+    /// Descriptions of the candidates being checked, by ID
+    running: HashMap<usize, String>,
+    recent: VecDeque<String>,
+    best: Vec<u8>,
+    done: bool,
+}
+
+#[derive(Debug)]
+pub struct Tui {
+    orig_size: usize,
+    start: Instant,
+    control: Arc<Control>,
+    state: Mutex<State>,
+}
+
+/// Restores the terminal when dropped
+pub struct Session {
+    tui: Arc<Tui>,
+    tty: File,
+    termios: libc::termios,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Tui {
+    /// Take over the terminal, and start threads to draw the UI and handle
+    /// keys
+    pub fn start(orig: &[u8], control: Arc<Control>) -> io::Result<(Arc<Tui>, Session)> {
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let termios = raw_mode(&tty)?;
+        tty.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        let tui = Arc::new(Tui {
+            orig_size: orig.len(),
+            start: Instant::now(),
+            control,
+            state: Mutex::new(State {
+                current_size: orig.len(),
+                best: orig.to_vec(),
+                ..State::default()
+            }),
+        });
+        let draw = {
+            let tui = Arc::clone(&tui);
+            let mut tty = tty.try_clone()?;
+            thread::spawn(move || {
+                while !tui.lock().done {
+                    let _ = tui.draw(&mut tty);
+                    thread::sleep(REDRAW_INTERVAL);
+                }
+            })
+        };
+        let keys = {
+            let tui = Arc::clone(&tui);
+            let mut tty = tty.try_clone()?;
+            // Reads time out, so that this can notice when the reduction is done
+            thread::spawn(move || {
+                let mut key = [0u8];
+                while !tui.lock().done {
+                    if let Ok(1) = tty.read(&mut key) {
+                        tui.key(key[0]);
+                    }
+                }
+            })
+        };
+        let session = Session {
+            tui: Arc::clone(&tui),
+            tty,
+            termios,
+            threads: vec![draw, keys],
+        };
+        Ok((tui, session))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn key(&self, key: u8) {
+        match key {
+            b'q' => self.control.stop(),
+            b's' => self.control.skip_pass(),
+            b'p' if self.control.is_paused() => self.control.resume(),
+            b'p' => self.control.pause(),
+            _ => {}
+        }
+    }
+
+    fn draw(&self, tty: &mut File) -> io::Result<()> {
+        let (rows, cols) = size(tty);
+        let state = self.lock();
+        let status = if self.control.is_stopped() {
+            "finishing"
+        } else if self.control.is_paused() {
+            "paused"
+        } else {
+            "running"
+        };
+        let checks = state.accepted + state.rejected;
+        let mut lines = vec![
+            String::from("treereduce | q: finish early, p: pause/resume, s: skip pass"),
+            String::new(),
+            format!(
+                "Status  : {} for {}s",
+                status,
+                self.start.elapsed().as_secs()
+            ),
+            format!(
+                "Size    : {} / {} bytes ({}% smaller)",
+                state.current_size,
+                self.orig_size,
+                100usize.saturating_sub((100 * state.current_size) / self.orig_size.max(1))
+            ),
+            format!(
+                "Checks  : {} ({}% accepted)",
+                checks,
+                (100 * state.accepted) / checks.max(1)
+            ),
+            format!(
+                "Passes  : {} done{}",
+                state.passes,
+                state
+                    .last_pass
+                    .as_ref()
+                    .map(|p| format!(", last: {}", p))
+                    .unwrap_or_default()
+            ),
+            String::new(),
+            format!("Checking ({}):", state.running.len()),
+        ];
+        let mut running: Vec<_> = state.running.iter().collect();
+        running.sort();
+        lines.extend(running.iter().map(|(_, d)| format!("  {}", d)));
+        lines.push(String::from("Recently accepted:"));
+        lines.extend(state.recent.iter().map(|d| format!("  {}", d)));
+        lines.push(String::new());
+        lines.push(String::from("Smallest so far:"));
+        let best = String::from_utf8_lossy(&state.best);
+        lines.extend(best.lines().map(|l| format!("  {}", l)));
+        drop(state);
+
+        let mut screen = String::from("\x1b[H");
+        for line in lines.iter().take(rows) {
+            let line: String = line.chars().take(cols).collect();
+            screen.push_str(&line);
+            screen.push_str("\x1b[K\r\n");
+        }
+        screen.push_str("\x1b[J");
+        tty.write_all(screen.as_bytes())?;
+        tty.flush()
+    }
+}
+
+impl Observer for Tui {
+    fn started(&self, attempt: &Attempt<'_>) {
+        let mut state = self.lock();
+        state
+            .running
+            .insert(attempt.id, attempt.description.to_string());
+    }
+
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        let mut state = self.lock();
+        state.running.remove(&attempt.id);
+        state.accepted += 1;
+        state.current_size = attempt.current_size;
+        state.best = attempt.text.to_vec();
+        state.recent.push_front(attempt.description.to_string());
+        state.recent.truncate(RECENT);
+    }
+
+    fn rejected(&self, attempt: &Attempt<'_>) {
+        let mut state = self.lock();
+        state.running.remove(&attempt.id);
+        state.rejected += 1;
+    }
+
+    fn pass_done(&self, pass: &PassDone<'_>) {
+        let mut state = self.lock();
+        state.passes += 1;
+        state.last_pass = Some(pass.stage.to_string());
+        // Checks that went stale never finish
+        state.running.clear();
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.tui.lock().done = true;
+        for t in self.threads.drain(..) {
+            let _ = t.join();
+        }
+        let _ = self.tty.write_all(b"\x1b[?25h\x1b[?1049l");
+        // SAFETY: Restores the settings from `raw_mode`
+        unsafe {
+            libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.termios);
+        }
+    }
+}
+
+/// Read keys without waiting for a newline or echoing them, with a timeout.
+/// Returns the previous settings.
+fn raw_mode(tty: &File) -> io::Result<libc::termios> {
+    let fd = tty.as_raw_fd();
+    // SAFETY: termios is plain data, and is initialized by tcgetattr
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let orig = termios;
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 0;
+        // Tenths of a second
+        termios.c_cc[libc::VTIME] = 2;
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(orig)
+    }
+}
+
+/// Rows and columns of the terminal
+fn size(tty: &File) -> (usize, usize) {
+    // SAFETY: winsize is plain data, and is initialized by ioctl
+    unsafe {
+        let mut ws: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(tty.as_raw_fd(), libc::TIOCGWINSZ, &mut ws) == 0 && ws.ws_row > 0 {
+            (usize::from(ws.ws_row), usize::from(ws.ws_col))
+        } else {
+            (24, 80)
+        }
+    }
+}
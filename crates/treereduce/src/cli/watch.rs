@@ -0,0 +1,72 @@
+//! Reducing again when the input or the interestingness test changes
+//! (`--watch`), e.g., while tightening the test.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use tracing::{error, info};
+
+use super::signals::interrupted;
+use super::{check_files, Args};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Run `reduce` (without `--watch`), then again each time a watched file
+/// changes, until interrupted. Failed runs are logged, since the next change
+/// may fix them. The verdicts are kept in `--cache-dir` (by default, in a new
+/// temporary directory that's deleted afterwards), so that they're reused if
+/// the test didn't change.
+pub(super) fn watch(args: &Args, mut reduce: impl FnMut(Args) -> Result<()>) -> Result<()> {
+    let mut paths: Vec<PathBuf> = args.source.iter().map(PathBuf::from).collect();
+    paths.extend(check_files(args));
+    // Deleted on return
+    let tmp_cache = if args.cache_dir.is_none() && !args.no_cache {
+        let tmp = args
+            .temp_dir
+            .as_deref()
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let dir = tempfile::Builder::new()
+            .prefix("treereduce-watch-")
+            .tempdir_in(&tmp)
+            .with_context(|| format!("Failed to create a directory in {}", tmp.display()))?;
+        Some(dir)
+    } else {
+        None
+    };
+    let cache_dir = args
+        .cache_dir
+        .clone()
+        .or_else(|| tmp_cache.as_ref().map(|d| d.path().display().to_string()));
+    let args = Args {
+        watch: false,
+        cache_dir,
+        ..args.clone()
+    };
+    let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+    loop {
+        if let Err(e) = reduce(args.clone()) {
+            error!("{:#}", e);
+        }
+        // After the run, so that writing the output doesn't count
+        let seen = modified(&paths);
+        info!("Waiting for changes to {}", names.join(", "));
+        while modified(&paths) == seen {
+            if interrupted() {
+                return Ok(());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        info!("Reducing again");
+    }
+}
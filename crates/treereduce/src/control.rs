@@ -0,0 +1,202 @@
+//! Controlling a reduction while it runs, e.g., from another thread.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// When to stop
+#[derive(Clone, Copy, Debug, Default)]
+struct Limits {
+    deadline: Option<Instant>,
+    checks: Option<usize>,
+    passes: Option<usize>,
+    /// Size in bytes
+    size: Option<usize>,
+}
+
+/// Shared with a running reduction through [`crate::Config::control`]. Checks
+/// that are in progress when the reduction is paused or stopped finish first.
+#[derive(Debug, Default)]
+pub struct Control {
+    stop: AtomicBool,
+    /// Discard verdicts, see [`Control::interrupt`]
+    interrupted: AtomicBool,
+    skip: AtomicBool,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+    limits: Mutex<Limits>,
+    /// Number of runs of checks, see [`Control::count_run`]
+    checks: AtomicUsize,
+    /// Number of passes done
+    passes: AtomicUsize,
+    /// Bytes of the input outside the part being reduced
+    offset: AtomicUsize,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn limits(&self) -> MutexGuard<'_, Limits> {
+        self.limits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Finish early, with the smallest result so far
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Release);
+        self.resume();
+    }
+
+    /// Don't trust the verdicts of the checks in progress, or of later ones:
+    /// they're discarded (as uninteresting), and not cached. E.g., `SIGINT`
+    /// from Ctrl-C in a terminal reaches the checks too, which may make them
+    /// look interesting (or not). Only sets a flag, so a signal handler can
+    /// call it; call [`Control::stop`] too.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Release);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Acquire)
+    }
+
+    /// The verdict of a check, unless it's discarded because of
+    /// [`Control::interrupt`]
+    pub(crate) fn verdict(&self, interesting: bool) -> bool {
+        interesting && !self.is_interrupted()
+    }
+
+    /// Stop once `deadline` passes
+    pub fn stop_at(&self, deadline: Instant) {
+        self.limits().deadline = Some(deadline);
+    }
+
+    /// Stop after the checks have run this many times, as counted with
+    /// [`Control::count_run`]. Cached verdicts don't count. Runs in progress
+    /// when the limit is reached finish first (with any retries), so a few
+    /// more may be counted.
+    pub fn limit_checks(&self, checks: usize) {
+        self.limits().checks = Some(checks);
+    }
+
+    /// Stop after this many passes, in total
+    pub fn limit_passes(&self, passes: usize) {
+        self.limits().passes = Some(passes);
+    }
+
+    /// Stop once the result is at most this many bytes
+    pub fn stop_at_size(&self, size: usize) {
+        self.limits().size = Some(size);
+    }
+
+    /// The result is now this size
+    pub(crate) fn reduced_to(&self, size: usize) {
+        let size = size + self.offset.load(Ordering::Acquire);
+        if self.limits().size.is_some_and(|s| size <= s) {
+            self.stop.store(true, Ordering::Release);
+        }
+    }
+
+    /// Sizes passed to [`Control::reduced_to`] don't count this many bytes,
+    /// e.g., outside of a region being reduced on its own
+    pub(crate) fn set_offset(&self, bytes: usize) {
+        self.offset.store(bytes, Ordering::Release);
+    }
+
+    pub fn checks(&self) -> usize {
+        self.checks.load(Ordering::Acquire)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        if self.stop.load(Ordering::Acquire) {
+            return true;
+        }
+        let limits = *self.limits();
+        limits.deadline.is_some_and(|d| Instant::now() >= d)
+            || limits.checks.is_some_and(|c| self.checks() >= c)
+            || limits
+                .passes
+                .is_some_and(|p| self.passes.load(Ordering::Acquire) >= p)
+    }
+
+    /// Count a run of a check, e.g., a process spawned for it. Checks given
+    /// this control with [`crate::check::CmdCheck::control`] call it; others
+    /// (e.g., a [`crate::check::FnCheck`]) can call it for
+    /// [`Control::limit_checks`] to apply to them.
+    pub fn count_run(&self) {
+        self.checks.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Whether the limit on runs (if any) allows starting another check
+    pub(crate) fn may_check(&self) -> bool {
+        !self.limits().checks.is_some_and(|max| self.checks() >= max)
+    }
+
+    pub(crate) fn pass_done(&self) {
+        self.passes.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// End the current pass, and go on to the next stage of the pipeline
+    pub fn skip_pass(&self) {
+        self.skip.store(true, Ordering::Release);
+        self.resume();
+    }
+
+    /// Whether the current pass was skipped, resetting it for the next one
+    pub(crate) fn take_skip(&self) -> bool {
+        self.skip.swap(false, Ordering::AcqRel)
+    }
+
+    pub fn pause(&self) {
+        *self.paused.lock().unwrap_or_else(|e| e.into_inner()) = true;
+    }
+
+    pub fn resume(&self) {
+        *self.paused.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        self.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Block while paused, then return whether to keep running the pass
+    pub(crate) fn proceed(&self) -> bool {
+        let mut paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        while *paused {
+            paused = self.resumed.wait(paused).unwrap_or_else(|e| e.into_inner());
+        }
+        !self.is_stopped() && !self.skip.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_checks() {
+        let control = Control::new();
+        control.limit_checks(2);
+        assert!(control.may_check());
+        control.count_run();
+        assert!(control.may_check());
+        assert!(!control.is_stopped());
+        control.count_run();
+        assert!(!control.may_check());
+        assert!(control.is_stopped());
+        assert_eq!(control.checks(), 2);
+    }
+
+    #[test]
+    fn test_stop_at_size() {
+        let control = Control::new();
+        control.reduced_to(10);
+        control.stop_at_size(5);
+        control.reduced_to(6);
+        assert!(!control.is_stopped());
+        control.reduced_to(5);
+        assert!(control.is_stopped());
+    }
+}
@@ -0,0 +1,253 @@
+//! Unified diffs, e.g., of the original input and the reduced one.
+
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Don't compute a minimal diff of more lines than this (after removing the
+/// common prefix and suffix), it takes one bit per pair of lines
+const MAX_CELLS: usize = 1 << 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Lines, including their terminators
+fn lines(text: &[u8]) -> Vec<&[u8]> {
+    text.split_inclusive(|b| *b == b'\n').collect()
+}
+
+/// Tokens: runs of word characters, runs of whitespace, and other bytes on
+/// their own
+fn tokens(text: &[u8]) -> Vec<&[u8]> {
+    let class = |b: u8| {
+        if b.is_ascii_alphanumeric() || b == b'_' || !b.is_ascii() {
+            1
+        } else if b.is_ascii_whitespace() {
+            2
+        } else {
+            0
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    for i in 1..=text.len() {
+        let c = class(text[i - 1]);
+        if i == text.len() || c == 0 || class(text[i]) != c {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+    }
+    tokens
+}
+
+/// A shortest edit script, via the longest common subsequence
+fn ops(old: &[&[u8]], new: &[&[u8]]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+    let (n, m) = (a.len(), b.len());
+
+    let mut ops = vec![Op::Equal; prefix];
+    if n.saturating_mul(m) > MAX_CELLS {
+        ops.extend(std::iter::repeat(Op::Delete).take(n));
+        ops.extend(std::iter::repeat(Op::Insert).take(m));
+    } else {
+        // Whether deleting a[i] keeps a longest common subsequence of
+        // a[i..] and b[j..], one bit per (i, j)
+        let mut delete = vec![0u64; (n * m).div_ceil(64)];
+        let mut next = vec![0usize; m + 1];
+        let mut cur = vec![0usize; m + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                cur[j] = if a[i] == b[j] {
+                    next[j + 1] + 1
+                } else if next[j] >= cur[j + 1] {
+                    let k = i * m + j;
+                    delete[k / 64] |= 1 << (k % 64);
+                    next[j]
+                } else {
+                    cur[j + 1]
+                };
+            }
+            std::mem::swap(&mut cur, &mut next);
+        }
+        let deletes =
+            |i: usize, j: usize| delete[(i * m + j) / 64] & (1 << ((i * m + j) % 64)) != 0;
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && a[i] == b[j] {
+                ops.push(Op::Equal);
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && deletes(i, j)) {
+                ops.push(Op::Delete);
+                i += 1;
+            } else {
+                ops.push(Op::Insert);
+                j += 1;
+            }
+        }
+    }
+    ops.extend(std::iter::repeat(Op::Equal).take(suffix));
+    ops
+}
+
+fn write_line(w: &mut impl Write, prefix: u8, line: &[u8]) -> io::Result<()> {
+    w.write_all(&[prefix])?;
+    w.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        w.write_all(b"\n\\ No newline at end of file\n")?;
+    }
+    Ok(())
+}
+
+/// Write a unified diff from `old` to `new`, with `context` lines of context
+/// around each change. Writes nothing if they're the same.
+pub fn write_unified(
+    w: &mut impl Write,
+    old: &[u8],
+    new: &[u8],
+    old_name: &str,
+    new_name: &str,
+    context: usize,
+) -> io::Result<()> {
+    let (a, b) = (lines(old), lines(new));
+    let ops = ops(&a, &b);
+    if ops.iter().all(|op| *op == Op::Equal) {
+        return Ok(());
+    }
+    writeln!(w, "--- {}", old_name)?;
+    writeln!(w, "+++ {}", new_name)?;
+
+    // Line numbers in `a` and `b` before each op
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        positions.push((i, j));
+        match op {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|k| ops[*k] != Op::Equal).collect();
+    let mut c = 0;
+    while c < changes.len() {
+        // Extend the hunk while the next change is close enough
+        let start = changes[c].saturating_sub(context);
+        let mut end = changes[c] + 1;
+        while c + 1 < changes.len() && changes[c + 1] <= end + 2 * context {
+            c += 1;
+            end = changes[c] + 1;
+        }
+        let end = (end + context).min(ops.len());
+        c += 1;
+
+        let (i0, j0) = positions[start];
+        let (i1, j1) = positions[end];
+        // Empty ranges start at the line before
+        let from = |p: usize, len: usize| if len == 0 { p } else { p + 1 };
+        writeln!(
+            w,
+            "@@ -{},{} +{},{} @@",
+            from(i0, i1 - i0),
+            i1 - i0,
+            from(j0, j1 - j0),
+            j1 - j0
+        )?;
+        for k in start..end {
+            let (i, j) = positions[k];
+            match ops[k] {
+                Op::Equal => write_line(w, b' ', a[i])?,
+                Op::Delete => write_line(w, b'-', a[i])?,
+                Op::Insert => write_line(w, b'+', b[j])?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The byte ranges of `new` that are unchanged from `old`, and where they
+/// were in `old`, in order. Found by diffing tokens, so a token that moved
+/// counts as deleted and inserted.
+pub fn retained(old: &[u8], new: &[u8]) -> Vec<(Range<usize>, Range<usize>)> {
+    let (a, b) = (tokens(old), tokens(new));
+    let mut retained: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for op in ops(&a, &b) {
+        match op {
+            Op::Equal => {
+                let len = a[i].len();
+                match retained.last_mut() {
+                    Some((o, n)) if o.end == old_pos && n.end == new_pos => {
+                        o.end += len;
+                        n.end += len;
+                    }
+                    _ => retained.push((old_pos..old_pos + len, new_pos..new_pos + len)),
+                }
+                old_pos += len;
+                new_pos += len;
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => {
+                old_pos += a[i].len();
+                i += 1;
+            }
+            Op::Insert => {
+                new_pos += b[j].len();
+                j += 1;
+            }
+        }
+    }
+    retained
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unified(old: &str, new: &str) -> String {
+        let mut out = Vec::new();
+        write_unified(&mut out, old.as_bytes(), new.as_bytes(), "a", "b", 1).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_unified() {
+        assert_eq!(unified("x\n", "x\n"), "");
+        assert_eq!(
+            unified("a\nb\nc\nd\ne\nf\ng\n", "a\nc\nd\ne\nf\nG\n"),
+            "--- a\n+++ b\n@@ -1,3 +1,2 @@\n a\n-b\n c\n@@ -6,2 +5,2 @@\n f\n-g\n+G\n"
+        );
+        assert_eq!(
+            unified("int x;\nint y;", ""),
+            "--- a\n+++ b\n@@ -1,2 +0,0 @@\n-int x;\n-int y;\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_retained() {
+        assert_eq!(retained(b"", b""), vec![]);
+        assert_eq!(
+            retained(b"int x = 1 + 2;", b"int x = 2;"),
+            vec![(0..8, 0..8), (12..14, 8..10)]
+        );
+        assert_eq!(retained(b"foo", b"bar"), vec![]);
+    }
+}
@@ -1,13 +1,18 @@
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
 use tree_sitter::{Node, Tree};
 use tree_sitter_edit::Editor;
 
 use crate::id::NodeId;
+use crate::rope::Rope;
 
 #[derive(Clone, Debug)]
 pub struct Edits {
     omit: HashSet<NodeId>,
     replace: HashMap<NodeId, String>,
+    /// Map from a node to a descendant that should be rendered in its place
+    hoist: HashMap<NodeId, NodeId>,
 }
 
 impl Edits {
@@ -15,11 +20,40 @@ impl Edits {
         Edits {
             omit: HashSet::new(),
             replace: HashMap::new(),
+            hoist: HashMap::new(),
         }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.omit.is_empty()
+        self.omit.is_empty() && self.replace.is_empty() && self.hoist.is_empty()
+    }
+
+    /// The edits in `self` that aren't in `other`
+    pub fn difference(&self, other: &Edits) -> Edits {
+        Edits {
+            omit: self.omit.difference(&other.omit).copied().collect(),
+            replace: self
+                .replace
+                .iter()
+                .filter(|(id, s)| other.replace.get(id) != Some(s))
+                .map(|(id, s)| (*id, s.clone()))
+                .collect(),
+            hoist: self
+                .hoist
+                .iter()
+                .filter(|(id, d)| other.hoist.get(id) != Some(d))
+                .map(|(id, d)| (*id, *d))
+                .collect(),
+        }
+    }
+
+    /// Add the edits in `other`, which win where they edit the same nodes
+    pub fn merge(mut self, other: &Edits) -> Self {
+        self.omit.extend(&other.omit);
+        self.replace
+            .extend(other.replace.iter().map(|(id, s)| (*id, s.clone())));
+        self.hoist.extend(&other.hoist);
+        self
     }
 
     pub fn omit(mut self, node: &Node) -> Self {
@@ -49,6 +83,17 @@ impl Edits {
         self
     }
 
+    pub fn hoist(mut self, node: &Node, descendant: &Node) -> Self {
+        self.hoist
+            .insert(NodeId::new(node), NodeId::new(descendant));
+        self
+    }
+
+    pub fn hoist_id(mut self, node_id: NodeId, descendant_id: NodeId) -> Self {
+        self.hoist.insert(node_id, descendant_id);
+        self
+    }
+
     pub fn should_omit(&self, node: &Node) -> bool {
         self.omit.contains(&NodeId::new(node))
     }
@@ -64,6 +109,195 @@ impl Edits {
     pub fn should_replace_id(&self, node_id: &NodeId) -> bool {
         self.replace.contains_key(node_id)
     }
+
+    pub fn replacement_id(&self, node_id: &NodeId) -> Option<&str> {
+        self.replace.get(node_id).map(String::as_str)
+    }
+
+    pub fn should_hoist(&self, node: &Node) -> bool {
+        self.hoist.contains_key(&NodeId::new(node))
+    }
+
+    pub fn should_hoist_id(&self, node_id: &NodeId) -> bool {
+        self.hoist.contains_key(node_id)
+    }
+
+    /// Whether this node no longer appears in the rendered output because of
+    /// an edit to one of its ancestors.
+    pub fn is_detached(&self, node: &Node) -> bool {
+        let mut below = vec![NodeId::new(node)];
+        let mut current = node.parent();
+        while let Some(ancestor) = current {
+            let id = NodeId::new(&ancestor);
+            if self.should_omit_id(&id) || self.should_replace_id(&id) {
+                return true;
+            }
+            if let Some(descendant) = self.hoist.get(&id) {
+                if !below.contains(descendant) {
+                    return true;
+                }
+            }
+            below.push(id);
+            current = ancestor.parent();
+        }
+        false
+    }
+
+    /// The outermost edited ranges of the source, in order, along with their
+    /// replacements.
+    pub fn byte_edits(&self, source: &[u8], tree: &Tree) -> Vec<(Range<usize>, Vec<u8>)> {
+        let mut byte_edits = Vec::new();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            if self.has_edit(tree, &node) {
+                byte_edits.push((
+                    node.start_byte()..node.end_byte(),
+                    self.edit(source, tree, &node),
+                ));
+            } else {
+                let children: Vec<Node> = node.children(&mut node.walk()).collect();
+                stack.extend(children.into_iter().rev());
+            }
+        }
+        byte_edits
+    }
+
+    /// Render the edited source without copying the unedited parts
+    pub fn render_rope<'a>(&self, source: &'a [u8], tree: &Tree) -> Rope<'a> {
+        let mut rope = Rope::new();
+        let mut pos = 0;
+        for (range, with) in self.byte_edits(source, tree) {
+            rope.push_borrowed(&source[pos..range.start]);
+            rope.push_owned(with);
+            pos = range.end;
+        }
+        rope.push_borrowed(&source[pos..]);
+        rope
+    }
+
+    /// The offset in the rendered output that corresponds to `offset` in the
+    /// source. `offset` shouldn't be inside of an edited node.
+    pub fn map_offset(&self, source: &[u8], tree: &Tree, offset: usize) -> usize {
+        let root = tree.root_node();
+        let mut out = Vec::with_capacity(offset);
+        out.extend_from_slice(&source[..std::cmp::min(root.start_byte(), offset)]);
+        self.render_prefix(source, tree, &root, offset, &mut out);
+        if root.end_byte() < offset {
+            out.extend_from_slice(&source[root.end_byte()..offset]);
+        }
+        out.len()
+    }
+
+    /// The ranges of the source that are copied to the rendered output, and
+    /// where they are in the output, in order
+    pub fn retained(&self, source: &[u8], tree: &Tree) -> Vec<(Range<usize>, Range<usize>)> {
+        let root = tree.root_node();
+        let mut retained = Vec::new();
+        let mut len = 0;
+        retain(&mut retained, &mut len, 0..root.start_byte());
+        self.retain_node(tree, &root, &mut len, &mut retained);
+        retain(&mut retained, &mut len, root.end_byte()..source.len());
+        retained
+    }
+
+    fn retain_node(
+        &self,
+        tree: &Tree,
+        node: &Node,
+        len: &mut usize,
+        retained: &mut Vec<(Range<usize>, Range<usize>)>,
+    ) {
+        let id = NodeId::new(node);
+        if self.should_omit_id(&id) {
+            return;
+        }
+        if let Some(s) = self.replace.get(&id) {
+            *len += s.len();
+            return;
+        }
+        if let Some(descendant_id) = self.hoist.get(&id) {
+            let descendant = find_descendant(node, descendant_id).expect("Hoisted non-descendant");
+            self.retain_node(tree, &descendant, len, retained);
+            return;
+        }
+        let mut pos = node.start_byte();
+        for child in node.children(&mut node.walk()) {
+            retain(retained, len, pos..child.start_byte());
+            self.retain_node(tree, &child, len, retained);
+            pos = child.end_byte();
+        }
+        retain(retained, len, pos..node.end_byte());
+    }
+
+    fn render_prefix(
+        &self,
+        source: &[u8],
+        tree: &Tree,
+        node: &Node,
+        offset: usize,
+        out: &mut Vec<u8>,
+    ) {
+        if node.end_byte() <= offset {
+            self.render_node(source, tree, node, out);
+            return;
+        }
+        if node.start_byte() >= offset || self.has_edit(tree, node) {
+            return;
+        }
+        let mut pos = node.start_byte();
+        for child in node.children(&mut node.walk()) {
+            if child.start_byte() >= offset {
+                break;
+            }
+            out.extend_from_slice(&source[pos..child.start_byte()]);
+            self.render_prefix(source, tree, &child, offset, out);
+            pos = child.end_byte();
+        }
+        if pos < offset {
+            out.extend_from_slice(&source[pos..offset]);
+        }
+    }
+
+    fn render_node(&self, source: &[u8], tree: &Tree, node: &Node, out: &mut Vec<u8>) {
+        if self.has_edit(tree, node) {
+            out.extend(self.edit(source, tree, node));
+            return;
+        }
+        let mut pos = node.start_byte();
+        for child in node.children(&mut node.walk()) {
+            out.extend_from_slice(&source[pos..child.start_byte()]);
+            self.render_node(source, tree, &child, out);
+            pos = child.end_byte();
+        }
+        out.extend_from_slice(&source[pos..node.end_byte()]);
+    }
+}
+
+/// Copy `range` of the source to the end of the output, which is `len` long
+fn retain(retained: &mut Vec<(Range<usize>, Range<usize>)>, len: &mut usize, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    let n = range.len();
+    match retained.last_mut() {
+        Some((src, out)) if src.end == range.start && out.end == *len => {
+            src.end += n;
+            out.end += n;
+        }
+        _ => retained.push((range, *len..*len + n)),
+    }
+    *len += n;
+}
+
+fn find_descendant<'tree>(node: &Node<'tree>, id: &NodeId) -> Option<Node<'tree>> {
+    let mut stack = vec![*node];
+    while let Some(n) = stack.pop() {
+        if NodeId::new(&n) == *id {
+            return Some(n);
+        }
+        stack.extend(n.children(&mut n.walk()));
+    }
+    None
 }
 
 impl Default for Edits {
@@ -74,19 +308,22 @@ impl Default for Edits {
 
 impl Editor for Edits {
     fn has_edit(&self, _tree: &Tree, node: &Node) -> bool {
-        self.should_omit(node) || self.should_replace(node)
+        self.should_omit(node) || self.should_replace(node) || self.should_hoist(node)
     }
 
-    fn edit(&self, _source: &[u8], tree: &Tree, node: &Node) -> Vec<u8> {
+    fn edit(&self, source: &[u8], tree: &Tree, node: &Node) -> Vec<u8> {
         debug_assert!(self.has_edit(tree, node));
+        let id = NodeId::new(node);
         if self.should_omit(node) {
             Vec::new()
+        } else if let Some(s) = self.replace.get(&id) {
+            s.clone().into_bytes()
         } else {
-            self.replace
-                .get(&NodeId::new(node))
-                .unwrap()
-                .clone()
-                .into_bytes()
+            let descendant_id = &self.hoist[&id];
+            let descendant = find_descendant(node, descendant_id).expect("Hoisted non-descendant");
+            let mut out = Vec::with_capacity(descendant.end_byte() - descendant.start_byte());
+            self.render_node(source, tree, &descendant, &mut out);
+            out
         }
     }
 }
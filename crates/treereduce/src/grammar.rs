@@ -0,0 +1,173 @@
+//! Load compiled tree-sitter grammars from shared libraries (`.so`, `.dylib`,
+//! or `.dll`) at runtime.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use libloading::{Library, Symbol};
+use thiserror::Error;
+use tree_sitter::{Language, LANGUAGE_VERSION, MIN_COMPATIBLE_LANGUAGE_VERSION};
+
+#[derive(Debug, Error)]
+pub enum GrammarError {
+    #[error("Failed to load grammar library {path}: {reason}")]
+    Open { path: PathBuf, reason: String },
+    #[error("Failed to find symbol {symbol} in grammar library: {reason}")]
+    Symbol { symbol: String, reason: String },
+    #[error("Can't load {0}: grammars compiled to WASM aren't supported, use a shared library")]
+    Wasm(PathBuf),
+    #[error("Can't infer the grammar name from {0}, try passing it explicitly")]
+    Name(PathBuf),
+    #[error(
+        "Incompatible grammar ABI version {version}, expected {min} through {max}; try regenerating the grammar"
+    )]
+    Version {
+        version: usize,
+        min: usize,
+        max: usize,
+    },
+    #[error("Failed to read node types from {path}")]
+    NodeTypes {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// A tree-sitter grammar loaded from a shared library.
+///
+/// The library is never unloaded, since the [`Language`] may be copied and
+/// used for the remainder of the process.
+#[derive(Clone, Debug)]
+pub struct Grammar {
+    /// Name of the language, e.g., `c`
+    pub name: String,
+    pub language: Language,
+    pub node_types: String,
+}
+
+/// Guess the language name from the library name, e.g., `libtree-sitter-c.so`
+/// becomes `c`.
+fn infer_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let stem = stem.strip_prefix("lib").unwrap_or(stem);
+    let stem = stem.strip_prefix("tree-sitter-").unwrap_or(stem);
+    let stem = stem.strip_prefix("tree_sitter_").unwrap_or(stem);
+    if stem.is_empty() {
+        return None;
+    }
+    Some(stem.replace('-', "_"))
+}
+
+/// Find `node-types.json` next to the library, or in a `src/` directory next
+/// to it (the layout of tree-sitter grammar repositories).
+fn find_node_types(path: &Path) -> PathBuf {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let beside = dir.join("node-types.json");
+    if beside.exists() {
+        return beside;
+    }
+    let in_src = dir.join("src").join("node-types.json");
+    if in_src.exists() {
+        return in_src;
+    }
+    beside
+}
+
+fn check_version(language: &Language) -> Result<(), GrammarError> {
+    let version = language.version();
+    if !(MIN_COMPATIBLE_LANGUAGE_VERSION..=LANGUAGE_VERSION).contains(&version) {
+        return Err(GrammarError::Version {
+            version,
+            min: MIN_COMPATIBLE_LANGUAGE_VERSION,
+            max: LANGUAGE_VERSION,
+        });
+    }
+    Ok(())
+}
+
+impl Grammar {
+    /// Load the grammar in the shared library at `path`.
+    ///
+    /// `name` is the name of the language, used to find the
+    /// `tree_sitter_<name>` symbol. If `None`, the name is inferred from the
+    /// library filename. Likewise, if `node_types` is `None`, the
+    /// `node-types.json` is searched for next to the library. Grammars
+    /// compiled to WASM aren't supported.
+    pub fn load(
+        path: &Path,
+        name: Option<&str>,
+        node_types: Option<&Path>,
+    ) -> Result<Self, GrammarError> {
+        // Running them needs tree-sitter's WASM store (from version 0.22)
+        if path.extension().is_some_and(|e| e == "wasm") {
+            return Err(GrammarError::Wasm(path.to_path_buf()));
+        }
+        let name = match name {
+            Some(n) => n.to_string(),
+            None => infer_name(path).ok_or_else(|| GrammarError::Name(path.to_path_buf()))?,
+        };
+        let node_types_path = node_types
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| find_node_types(path));
+        let node_types =
+            fs::read_to_string(&node_types_path).map_err(|source| GrammarError::NodeTypes {
+                path: node_types_path,
+                source,
+            })?;
+
+        let symbol = format!("tree_sitter_{}", name);
+
+        // SAFETY: The library is trusted to be a tree-sitter grammar, and so to
+        // export a function `tree_sitter_<name>` that takes no arguments and
+        // returns a `TSLanguage*`, which is what `Language` wraps. The library
+        // is never closed, so the returned language stays valid.
+        let language = unsafe {
+            let library = Library::new(path).map_err(|e| GrammarError::Open {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            })?;
+            let language = {
+                let func: Symbol<unsafe extern "C" fn() -> Language> = library
+                    .get(symbol.as_bytes())
+                    .map_err(|e| GrammarError::Symbol {
+                        symbol: symbol.clone(),
+                        reason: e.to_string(),
+                    })?;
+                func()
+            };
+            std::mem::forget(library);
+            language
+        };
+        check_version(&language)?;
+        Ok(Grammar {
+            name,
+            language,
+            node_types,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_name() {
+        let name = |s: &str| infer_name(Path::new(s));
+        assert_eq!(name("libtree-sitter-c.so"), Some(String::from("c")));
+        assert_eq!(
+            name("/grammars/tree-sitter-c-sharp.dylib"),
+            Some(String::from("c_sharp"))
+        );
+        assert_eq!(name("python.so"), Some(String::from("python")));
+        assert_eq!(name("lib.so"), None);
+    }
+
+    #[test]
+    fn test_wasm() {
+        let err = Grammar::load(Path::new("tree-sitter-c.wasm"), None, None).unwrap_err();
+        assert!(matches!(err, GrammarError::Wasm(_)));
+    }
+}
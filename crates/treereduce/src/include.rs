@@ -0,0 +1,111 @@
+//! Inlining local `#include "..."` files into C and C++ inputs, so that the
+//! result of the reduction is self-contained.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The file named by a line like `#include "foo.h"`, if it is one
+fn included(line: &[u8]) -> Option<&str> {
+    let line = std::str::from_utf8(line).ok()?.trim_start();
+    let rest = line.strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    rest.find('"').map(|end| &rest[..end])
+}
+
+fn is_pragma_once(line: &[u8]) -> bool {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|l| l.trim_start().strip_prefix('#'))
+        .is_some_and(|rest| rest.split_whitespace().eq(["pragma", "once"]))
+}
+
+/// Where `name`, included from a file in `dir`, is: next to the file, or
+/// else in one of `include_dirs`
+fn resolve(name: &str, dir: &Path, include_dirs: &[PathBuf]) -> Option<PathBuf> {
+    std::iter::once(dir)
+        .chain(include_dirs.iter().map(PathBuf::as_path))
+        .map(|d| d.join(name))
+        .find(|p| p.is_file())
+}
+
+struct Inliner<'a> {
+    include_dirs: &'a [PathBuf],
+    /// Each file is only inlined once, as if it had an include guard
+    seen: HashSet<PathBuf>,
+}
+
+impl Inliner<'_> {
+    /// `nested` is whether `text` is an included file
+    fn inline(
+        &mut self,
+        text: &[u8],
+        dir: &Path,
+        nested: bool,
+        out: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        for line in text.split_inclusive(|b| *b == b'\n') {
+            if nested && is_pragma_once(line) {
+                continue;
+            }
+            let path = included(line).and_then(|name| resolve(name, dir, self.include_dirs));
+            let Some(path) = path else {
+                out.extend_from_slice(line);
+                continue;
+            };
+            if !self.seen.insert(fs::canonicalize(&path)?) {
+                continue;
+            }
+            let contents = fs::read(&path)?;
+            let parent = path.parent().unwrap_or(dir).to_path_buf();
+            self.inline(&contents, &parent, true, out)?;
+            if !out.is_empty() && !out.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replace each `#include "..."` of a file that exists (relative to `dir`,
+/// the directory of the input, or one of `include_dirs`) with its contents,
+/// recursively. Includes with angle brackets are left alone. Each file is
+/// inlined at most once, so `#pragma once` is dropped from included files.
+pub fn inline_includes(text: &[u8], dir: &Path, include_dirs: &[PathBuf]) -> io::Result<Vec<u8>> {
+    let mut inliner = Inliner {
+        include_dirs,
+        seen: HashSet::new(),
+    };
+    let mut out = Vec::with_capacity(text.len());
+    inliner.inline(text, dir, false, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_includes() {
+        assert_eq!(included(b" # include \"a.h\" // x\n"), Some("a.h"));
+        assert_eq!(included(b"#include <stdio.h>\n"), None);
+
+        let dir = std::env::temp_dir().join(format!("treereduce-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(
+            dir.join("a.h"),
+            "#pragma once\n#include \"sub/b.h\"\nint a;",
+        )
+        .unwrap();
+        fs::write(dir.join("sub/b.h"), "int b;\n").unwrap();
+        let text = b"#include <stdio.h>\n#include \"a.h\"\n#include \"sub/b.h\"\n#include \"c.h\"\nint main;\n";
+        let inlined = inline_includes(text, &dir, &[]).unwrap();
+        assert_eq!(
+            String::from_utf8(inlined).unwrap(),
+            "#include <stdio.h>\nint b;\nint a;\n#include \"c.h\"\nint main;\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+//! Sharing CPUs with a GNU make jobserver, so that treereduce doesn't run
+//! more interestingness tests than the enclosing build allows.
+//!
+//! A jobserver is a pipe (or named pipe) of tokens, one byte each. A process
+//! may run one job for free, and takes a token for each additional one,
+//! writing it back when the job is done.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum JobserverError {
+    #[error("Invalid jobserver {0}, expected fifo:PATH or R,W (file descriptors)")]
+    Invalid(String),
+    #[error(
+        "Jobserver file descriptors {0} aren't open; is the make rule marked recursive with '+'?"
+    )]
+    Closed(String),
+    #[error("Failed to open jobserver {auth}")]
+    Open {
+        auth: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Jobservers are only supported on Unix")]
+    Unsupported,
+}
+
+#[derive(Debug)]
+pub struct Jobserver {
+    read: File,
+    write: File,
+}
+
+/// A token from a [`Jobserver`], returned on drop
+#[derive(Debug)]
+pub struct Token<'a> {
+    jobserver: &'a Jobserver,
+    byte: u8,
+}
+
+impl Drop for Token<'_> {
+    fn drop(&mut self) {
+        // If this fails, the enclosing build just runs fewer jobs
+        let _ = (&self.jobserver.write).write_all(&[self.byte]);
+    }
+}
+
+/// The jobserver in `MAKEFLAGS`, as passed by make (the last one wins)
+fn auth(makeflags: &str) -> Option<&str> {
+    makeflags
+        .split_whitespace()
+        .filter_map(|f| {
+            f.strip_prefix("--jobserver-auth=")
+                .or_else(|| f.strip_prefix("--jobserver-fds="))
+        })
+        .last()
+}
+
+#[cfg(unix)]
+fn open_fds(auth: &str) -> Result<Jobserver, JobserverError> {
+    use std::os::fd::{BorrowedFd, RawFd};
+
+    let invalid = || JobserverError::Invalid(auth.to_string());
+    let (r, w) = auth.split_once(',').ok_or_else(invalid)?;
+    let r: RawFd = r.parse().map_err(|_| invalid())?;
+    let w: RawFd = w.parse().map_err(|_| invalid())?;
+    if r < 0 || w < 0 {
+        // make passes -1,-1 when the rule isn't recursive
+        return Err(JobserverError::Closed(auth.to_string()));
+    }
+    let dup = |fd: RawFd| -> Result<File, JobserverError> {
+        // SAFETY: fcntl doesn't touch memory, and only checks that fd is open
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            return Err(JobserverError::Closed(auth.to_string()));
+        }
+        // SAFETY: fd was just checked to be open, and is only borrowed long
+        // enough to duplicate it
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        fd.try_clone_to_owned()
+            .map(File::from)
+            .map_err(|source| JobserverError::Open {
+                auth: auth.to_string(),
+                source,
+            })
+    };
+    Ok(Jobserver {
+        read: dup(r)?,
+        write: dup(w)?,
+    })
+}
+
+#[cfg(not(unix))]
+fn open_fds(_auth: &str) -> Result<Jobserver, JobserverError> {
+    Err(JobserverError::Unsupported)
+}
+
+/// Wait until the (possibly non-blocking) `file` can be read
+#[cfg(unix)]
+fn wait_readable(file: &File) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mut pollfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: pollfd is valid for the duration of the call
+    if unsafe { libc::poll(&mut pollfd, 1, -1) } == -1 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn wait_readable(_file: &File) -> io::Result<()> {
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    Ok(())
+}
+
+impl Jobserver {
+    /// Connect to a jobserver given like make's `--jobserver-auth`:
+    /// `fifo:PATH` for a named pipe, or `R,W` for inherited file descriptors
+    pub fn open(auth: &str) -> Result<Self, JobserverError> {
+        if let Some(path) = auth.strip_prefix("fifo:") {
+            let open = || {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path)
+                    .map_err(|source| JobserverError::Open {
+                        auth: auth.to_string(),
+                        source,
+                    })
+            };
+            return Ok(Jobserver {
+                read: open()?,
+                write: open()?,
+            });
+        }
+        open_fds(auth)
+    }
+
+    /// Connect to the jobserver of the enclosing make, if there is one
+    pub fn from_env() -> Result<Option<Self>, JobserverError> {
+        match std::env::var("MAKEFLAGS") {
+            Err(_) => Ok(None),
+            Ok(flags) => auth(&flags).map(Jobserver::open).transpose(),
+        }
+    }
+
+    /// Wait for a token
+    pub fn acquire(&self) -> io::Result<Token<'_>> {
+        let mut byte = [0];
+        loop {
+            match (&self.read).read(&mut byte) {
+                Ok(1) => {
+                    return Ok(Token {
+                        jobserver: self,
+                        byte: byte[0],
+                    })
+                }
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "The jobserver closed",
+                    ))
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => wait_readable(&self.read)?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth() {
+        assert_eq!(auth(""), None);
+        assert_eq!(auth("-j4 --jobserver-fds=3,4 -j"), Some("3,4"));
+        assert_eq!(
+            auth("s -- --jobserver-auth=3,4 --jobserver-auth=fifo:/tmp/js"),
+            Some("fifo:/tmp/js")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo() {
+        assert!(matches!(
+            Jobserver::open("oops"),
+            Err(JobserverError::Invalid(_))
+        ));
+        let path =
+            std::env::temp_dir().join(format!("treereduce-jobserver-{}", std::process::id()));
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        // SAFETY: c_path is a valid C string
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        let jobserver = Jobserver::open(&format!("fifo:{}", path.display())).unwrap();
+        (&jobserver.write).write_all(b"+").unwrap();
+        let token = jobserver.acquire().unwrap();
+        assert_eq!(token.byte, b'+');
+        drop(token);
+        assert_eq!(jobserver.acquire().unwrap().byte, b'+');
+        std::fs::remove_file(path).unwrap();
+    }
+}
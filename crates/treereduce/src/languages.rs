@@ -0,0 +1,1111 @@
+//! Language-specific reduction settings, shared by the `treereduce-<LANG>`
+//! binaries and the generic `treereduce` binary.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use tree_sitter::Node;
+
+use crate::transform::Transform;
+
+pub type Replacements = HashMap<&'static str, &'static [&'static str]>;
+
+/// A transform for one language, and the command-line flag that disables it
+#[derive(Clone)]
+pub struct LanguageTransform {
+    pub(crate) transform: Arc<dyn Transform>,
+    /// Long name and help of the flag, e.g., `no-go-transforms`
+    pub(crate) flag: Option<(&'static str, &'static str)>,
+}
+
+impl LanguageTransform {
+    /// Always applied
+    pub fn new(transform: impl Transform + 'static) -> Self {
+        LanguageTransform {
+            transform: Arc::new(transform),
+            flag: None,
+        }
+    }
+
+    /// Applied unless `--<flag>` is passed
+    pub fn optional(
+        transform: impl Transform + 'static,
+        flag: &'static str,
+        help: &'static str,
+    ) -> Self {
+        LanguageTransform {
+            transform: Arc::new(transform),
+            flag: Some((flag, help)),
+        }
+    }
+
+    pub fn flag(&self) -> Option<&'static str> {
+        self.flag.map(|(f, _)| f)
+    }
+}
+
+fn preprocessor() -> LanguageTransform {
+    LanguageTransform::optional(
+        CPreprocessor,
+        "no-preprocessor",
+        "Don't try replacing C preprocessor conditionals with one of their branches, their conditions with 0 or 1, or macro bodies with 0",
+    )
+}
+
+fn function_stubs(language: FunctionBodies) -> LanguageTransform {
+    LanguageTransform::optional(
+        language,
+        "no-function-stubs",
+        "Don't try replacing function and method bodies with stubs like '{}' or '{ return 0; }'",
+    )
+}
+
+pub fn c_transforms() -> Vec<LanguageTransform> {
+    vec![preprocessor(), function_stubs(FunctionBodies::C)]
+}
+
+/// For C++ grammars loaded with `--grammar`
+pub fn cpp_transforms() -> Vec<LanguageTransform> {
+    vec![
+        preprocessor(),
+        LanguageTransform::optional(
+            CppTemplates,
+            "no-cpp-transforms",
+            "Don't try removing C++ template arguments, parameters, or default arguments, or replacing nested template instantiations with their arguments",
+        ),
+        function_stubs(FunctionBodies::C),
+    ]
+}
+
+pub fn go_transforms() -> Vec<LanguageTransform> {
+    vec![
+        LanguageTransform::optional(
+            GoTransform,
+            "no-go-transforms",
+            "Don't try deleting Go imports and variables along with their uses, or the last uses of one along with its declaration",
+        ),
+        function_stubs(FunctionBodies::Go),
+    ]
+}
+
+pub fn java_transforms() -> Vec<LanguageTransform> {
+    vec![function_stubs(FunctionBodies::Java)]
+}
+
+/// For JavaScript, and TypeScript (and TSX)
+pub fn javascript_transforms() -> Vec<LanguageTransform> {
+    vec![function_stubs(FunctionBodies::JavaScript)]
+}
+
+pub fn python_transforms() -> Vec<LanguageTransform> {
+    vec![
+        LanguageTransform::optional(
+            PythonTransform,
+            "no-python-transforms",
+            "Don't try replacing Python if, for, while, with, and try statements with their (dedented) bodies",
+        ),
+        LanguageTransform::new(PythonDocstrings),
+        function_stubs(FunctionBodies::Python),
+    ]
+}
+
+pub fn rust_transforms() -> Vec<LanguageTransform> {
+    vec![LanguageTransform::optional(
+        RustTransform,
+        "no-rust-transforms",
+        "Don't try removing Rust attributes (or entries of #[derive]), generic parameters, or where clauses, or replacing function bodies with todo!() or loop {}",
+    )]
+}
+
+/// For JSON, TOML, and YAML
+pub fn comma_list_transforms() -> Vec<LanguageTransform> {
+    vec![LanguageTransform::new(CommaLists)]
+}
+
+pub fn c_replacements() -> Replacements {
+    // ("parameter_declaration", &["int x"][..]),
+    HashMap::from([
+        ("_expression", &["0"][..]),
+        ("compound_statement", &["{}"][..]),
+        ("number_literal", &["0"][..]),
+        ("parameter_list", &["()"][..]),
+        ("primitive_type", &["int"][..]),
+        ("return_statement", &["return;"][..]),
+        ("string_literal", &["\"\""][..]),
+        // Notes:
+        //
+        // - function_definition is optional wherever it appears, no need
+        //   to replace
+    ])
+}
+
+/// Reduces C (and C++) preprocessor conditionals and macros as units, since
+/// tree-sitter parses the lines inside an `#if` as its children, alongside
+/// the directives. Replaces `#if`s and `#ifdef`s with the lines of one of
+/// their branches, conditions with `0` and `1`, and macro bodies with `0`.
+///
+/// Only applies to nodes of the kinds in the C grammar, so it's harmless
+/// with others.
+pub struct CPreprocessor;
+
+/// The offset after the end of the line containing `offset`
+fn next_line(text: &[u8], offset: usize) -> usize {
+    text[offset..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map(|i| offset + i + 1)
+        .unwrap_or(text.len())
+}
+
+/// The lines from the one after `start` until `end`
+fn lines(text: &[u8], start: usize, end: usize) -> String {
+    let start = next_line(text, start).min(end);
+    String::from_utf8_lossy(&text[start..end]).into_owned()
+}
+
+impl Transform for CPreprocessor {
+    fn name(&self) -> &str {
+        "c-preprocessor"
+    }
+
+    fn replacements(&self, node: &Node<'_>, text: &[u8]) -> Vec<String> {
+        let is_condition = node.parent().is_some_and(|p| {
+            matches!(p.kind(), "preproc_if" | "preproc_elif")
+                && p.child_by_field_name("condition") == Some(*node)
+        });
+        if is_condition {
+            return vec![String::from("0"), String::from("1")];
+        }
+        match node.kind() {
+            "preproc_if" | "preproc_ifdef" => {
+                let directive = node
+                    .child_by_field_name("condition")
+                    .or_else(|| node.child_by_field_name("name"));
+                let Some(directive) = directive else {
+                    return Vec::new();
+                };
+                let endif = node
+                    .child(node.child_count() - 1)
+                    .filter(|c| c.kind() == "#endif")
+                    .map(|c| c.start_byte())
+                    .unwrap_or(node.end_byte());
+                match node.child_by_field_name("alternative") {
+                    None => vec![lines(text, directive.end_byte(), endif)],
+                    Some(alt) if alt.kind() == "preproc_else" => vec![
+                        lines(text, directive.end_byte(), alt.start_byte()),
+                        lines(text, alt.start_byte(), endif),
+                    ],
+                    Some(alt) => vec![lines(text, directive.end_byte(), alt.start_byte())],
+                }
+            }
+            "preproc_arg" => vec![String::from("0")],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Edits that people make by hand to Rust bug reports: removing attributes,
+/// or entries of `#[derive(...)]` one at a time, generic parameters, and
+/// `where` clauses, and replacing function bodies with `todo!()` or
+/// `loop {}`.
+pub struct RustTransform;
+
+/// Split the text between the outer delimiters of `text` on commas that
+/// aren't nested in other delimiters
+fn split_args(text: &str) -> Vec<&str> {
+    let inner = &text[1..text.len() - 1];
+    let mut args = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                args.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    args.push(inner[start..].trim());
+    args.retain(|a| !a.is_empty());
+    args
+}
+
+impl RustTransform {
+    /// `#[derive(...)]` without each of its entries
+    fn derives(node: &Node<'_>, text: &[u8]) -> Vec<String> {
+        let Some(attr) = node.named_child(0).filter(|a| a.kind() == "attribute") else {
+            return Vec::new();
+        };
+        let (Some(name), Some(args)) = (attr.named_child(0), attr.child_by_field_name("arguments"))
+        else {
+            return Vec::new();
+        };
+        if name.utf8_text(text) != Ok("derive") {
+            return Vec::new();
+        }
+        let Ok(args) = args.utf8_text(text) else {
+            return Vec::new();
+        };
+        if args.len() < 2 {
+            return Vec::new();
+        }
+        let derives = split_args(args);
+        if derives.len() < 2 {
+            return Vec::new();
+        }
+        (0..derives.len())
+            .map(|skip| {
+                let kept: Vec<&str> = derives
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != skip)
+                    .map(|(_, d)| *d)
+                    .collect();
+                format!("#[derive({})]", kept.join(", "))
+            })
+            .collect()
+    }
+}
+
+impl Transform for RustTransform {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn replacements(&self, node: &Node<'_>, text: &[u8]) -> Vec<String> {
+        match node.kind() {
+            "attribute_item" => {
+                let mut replacements = vec![String::new()];
+                replacements.extend(Self::derives(node, text));
+                replacements
+            }
+            "inner_attribute_item" | "type_parameters" | "where_clause" => vec![String::new()],
+            "block"
+                if node.parent().is_some_and(|p| {
+                    p.kind() == "function_item" && p.child_by_field_name("body") == Some(*node)
+                }) =>
+            {
+                vec![String::from("{ todo!() }"), String::from("{ loop {} }")]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Reduces C++ templates, for use with a C++ grammar: removes template
+/// arguments and parameters one at a time, replaces type arguments with
+/// `int`, removes default arguments, and replaces nested instantiations
+/// (e.g., `A<B<C>>`) with their arguments (`B<C>`).
+pub struct CppTemplates;
+
+/// The list `node` (e.g., `<A, B, C>`) without each of its elements
+fn without_each(node: &Node<'_>, text: &[u8]) -> Vec<String> {
+    let elems: Vec<Node> = node.named_children(&mut node.walk()).collect();
+    (0..elems.len())
+        .map(|skip| {
+            let kept: Vec<String> = elems
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != skip)
+                .map(|(_, e)| {
+                    String::from_utf8_lossy(&text[e.start_byte()..e.end_byte()]).into_owned()
+                })
+                .collect();
+            format!("<{}>", kept.join(", "))
+        })
+        .collect()
+}
+
+impl Transform for CppTemplates {
+    fn name(&self) -> &str {
+        "cpp-templates"
+    }
+
+    fn replacements(&self, node: &Node<'_>, text: &[u8]) -> Vec<String> {
+        match node.kind() {
+            "template_argument_list" | "template_parameter_list" => without_each(node, text),
+            "type_descriptor"
+                if node
+                    .parent()
+                    .is_some_and(|p| p.kind() == "template_argument_list") =>
+            {
+                vec![String::from("int")]
+            }
+            "optional_type_parameter_declaration" | "optional_parameter_declaration" => {
+                // Everything before the `=`
+                let eq = node
+                    .children(&mut node.walk())
+                    .find(|c| c.kind() == "=")
+                    .map(|c| c.start_byte());
+                match eq {
+                    Some(eq) => vec![String::from_utf8_lossy(&text[node.start_byte()..eq])
+                        .trim_end()
+                        .to_string()],
+                    None => Vec::new(),
+                }
+            }
+            "template_type" => {
+                let Some(args) = node.child_by_field_name("arguments") else {
+                    return Vec::new();
+                };
+                let mut nested = Vec::new();
+                for arg in args.named_children(&mut args.walk()) {
+                    // Type arguments are wrapped in a type_descriptor
+                    let ty = arg
+                        .named_child(0)
+                        .filter(|_| arg.kind() == "type_descriptor");
+                    if let Some(t) = ty.filter(|t| t.kind() == "template_type") {
+                        nested.push(
+                            String::from_utf8_lossy(&text[t.start_byte()..t.end_byte()])
+                                .into_owned(),
+                        );
+                    }
+                }
+                nested
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Pairs deletions in Go with the ones they need to compile: deleting an
+/// import or variable also deletes the statements that use it, and deleting
+/// the last uses of one also deletes its declaration (which would otherwise
+/// be "declared and not used").
+pub struct GoTransform;
+
+/// Nodes that hold statements (or top-level declarations)
+fn go_statements(kind: &str) -> bool {
+    matches!(
+        kind,
+        "block"
+            | "statement_list"
+            | "source_file"
+            | "expression_case"
+            | "default_case"
+            | "type_case"
+            | "communication_case"
+    )
+}
+
+/// `outer` is `inner` or one of its ancestors
+fn contains(outer: &Node<'_>, inner: &Node<'_>) -> bool {
+    outer.start_byte() <= inner.start_byte() && inner.end_byte() <= outer.end_byte()
+}
+
+/// The statement (or top-level declaration) that contains `node`
+fn go_statement(node: Node<'_>) -> Option<Node<'_>> {
+    let mut node = node;
+    loop {
+        let parent = node.parent()?;
+        if go_statements(parent.kind()) {
+            return Some(node);
+        }
+        node = parent;
+    }
+}
+
+/// The function that contains `node`, or the root
+fn go_scope<'tree>(node: &Node<'tree>) -> Node<'tree> {
+    let mut node = *node;
+    while let Some(parent) = node.parent() {
+        if matches!(
+            parent.kind(),
+            "function_declaration" | "method_declaration" | "func_literal"
+        ) {
+            return parent;
+        }
+        node = parent;
+    }
+    node
+}
+
+fn go_identifiers<'tree>(node: &Node<'tree>) -> Vec<Node<'tree>> {
+    let mut identifiers = Vec::new();
+    let mut cursor = node.walk();
+    let mut stack = vec![*node];
+    while let Some(n) = stack.pop() {
+        if matches!(n.kind(), "identifier" | "package_identifier") {
+            identifiers.push(n);
+        }
+        stack.extend(n.children(&mut cursor));
+    }
+    identifiers
+}
+
+/// The names that an import or variable declaration binds
+fn go_declared(node: &Node<'_>, text: &[u8]) -> Vec<String> {
+    let mut cursor = node.walk();
+    let names: Vec<String> = match node.kind() {
+        "import_declaration" | "import_spec_list" | "var_declaration" => {
+            let children: Vec<_> = node.children(&mut cursor).collect();
+            return children.iter().flat_map(|c| go_declared(c, text)).collect();
+        }
+        "import_spec" => {
+            let name = match node.child_by_field_name("name") {
+                Some(name) => name.utf8_text(text).ok(),
+                None => node
+                    .child_by_field_name("path")
+                    .and_then(|p| p.utf8_text(text).ok())
+                    .and_then(|p| p.trim_matches(|c| c == '"' || c == '`').rsplit('/').next()),
+            };
+            name.into_iter().map(String::from).collect()
+        }
+        "var_spec" => node
+            .children_by_field_name("name", &mut cursor)
+            .filter_map(|n| n.utf8_text(text).ok())
+            .map(String::from)
+            .collect(),
+        "short_var_declaration" => match node.child_by_field_name("left") {
+            Some(left) => left
+                .children(&mut cursor)
+                .filter(|n| n.kind() == "identifier")
+                .filter_map(|n| n.utf8_text(text).ok())
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    names
+        .into_iter()
+        .filter(|n| !n.is_empty() && n != "_" && n != ".")
+        .collect()
+}
+
+/// The imports of a file (if `scope` is the root), or the variable
+/// declarations in a function
+fn go_declarations<'tree>(scope: &Node<'tree>) -> Vec<Node<'tree>> {
+    let mut declarations = Vec::new();
+    let mut cursor = scope.walk();
+    if scope.parent().is_none() {
+        let imports: Vec<_> = scope
+            .children(&mut cursor)
+            .filter(|c| c.kind() == "import_declaration")
+            .collect();
+        for import in imports {
+            for child in import.named_children(&mut cursor) {
+                match child.kind() {
+                    "import_spec" => declarations.push(child),
+                    "import_spec_list" => declarations.extend(
+                        child
+                            .named_children(&mut child.walk())
+                            .filter(|s| s.kind() == "import_spec"),
+                    ),
+                    _ => (),
+                }
+            }
+        }
+        return declarations;
+    }
+    let mut stack = vec![*scope];
+    while let Some(n) = stack.pop() {
+        if matches!(n.kind(), "var_spec" | "short_var_declaration") {
+            declarations.push(n);
+        }
+        stack.extend(n.children(&mut cursor));
+    }
+    declarations
+}
+
+/// What to delete to delete `decl`: its declaration, if it's the only spec
+fn go_unit(decl: Node<'_>) -> Node<'_> {
+    match decl.parent() {
+        Some(p)
+            if matches!(p.kind(), "import_declaration" | "var_declaration")
+                && p.named_child_count() == 1 =>
+        {
+            p
+        }
+        _ => decl,
+    }
+}
+
+impl GoTransform {
+    /// The statements that use what `node` declares
+    fn uses<'tree>(node: &Node<'tree>, text: &[u8]) -> Vec<Node<'tree>> {
+        let names = go_declared(node, text);
+        if names.is_empty() {
+            return Vec::new();
+        }
+        let mut uses = Vec::new();
+        for id in go_identifiers(&go_scope(node)) {
+            if contains(node, &id) || !names.iter().any(|n| id.utf8_text(text) == Ok(n.as_str())) {
+                continue;
+            }
+            match go_statement(id) {
+                Some(s) if !contains(&s, node) => uses.push(s),
+                // E.g., used in the condition of the `if` that declares it
+                _ => return Vec::new(),
+            }
+        }
+        uses
+    }
+
+    /// The declarations whose uses are all in `node`
+    fn declarations<'tree>(node: &Node<'tree>, text: &[u8]) -> Vec<Node<'tree>> {
+        let used: HashSet<&str> = go_identifiers(node)
+            .iter()
+            .filter_map(|i| i.utf8_text(text).ok())
+            .collect();
+        let mut declarations = Vec::new();
+        let mut root = *node;
+        while let Some(p) = root.parent() {
+            root = p;
+        }
+        let mut scopes = vec![root];
+        let scope = go_scope(node);
+        if scope != root {
+            scopes.push(scope);
+        }
+        for scope in scopes {
+            let candidates: Vec<_> = go_declarations(&scope)
+                .into_iter()
+                .filter(|d| !contains(node, d) && !contains(d, node))
+                .map(|d| (d, go_declared(&d, text)))
+                .filter(|(_, names)| names.iter().any(|n| used.contains(n.as_str())))
+                .collect();
+            if candidates.is_empty() {
+                continue;
+            }
+            let identifiers = go_identifiers(&scope);
+            for (decl, names) in candidates {
+                let only_in_node = identifiers
+                    .iter()
+                    .filter(|i| !contains(&decl, i))
+                    .filter(|i| names.iter().any(|n| i.utf8_text(text) == Ok(n.as_str())))
+                    .all(|i| contains(node, i));
+                if only_in_node {
+                    declarations.push(go_unit(decl));
+                }
+            }
+        }
+        declarations
+    }
+}
+
+impl Transform for GoTransform {
+    fn name(&self) -> &str {
+        "go"
+    }
+
+    fn replacements(&self, _node: &Node<'_>, _text: &[u8]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn dependents<'tree>(&self, node: &Node<'tree>, text: &[u8]) -> Vec<Node<'tree>> {
+        let statement = node.parent().is_some_and(|p| go_statements(p.kind()))
+            || matches!(node.kind(), "import_spec" | "var_spec");
+        if !statement {
+            return Vec::new();
+        }
+        let mut dependents = Self::uses(node, text);
+        dependents.extend(Self::declarations(node, text));
+        // Outermost first, so nested ones can be dropped
+        dependents.sort_by_key(|n| (n.start_byte(), Reverse(n.end_byte())));
+        let mut disjoint: Vec<Node<'tree>> = Vec::new();
+        for d in dependents {
+            if contains(node, &d) || disjoint.last().is_some_and(|l| contains(l, &d)) {
+                continue;
+            }
+            disjoint.push(d);
+        }
+        disjoint
+    }
+}
+
+/// Deletes elements of comma-separated lists (e.g., JSON objects and arrays,
+/// and YAML and TOML inline ones) along with a comma next to them, so that
+/// the list stays well-formed.
+pub struct CommaLists;
+
+impl Transform for CommaLists {
+    fn name(&self) -> &str {
+        "comma-lists"
+    }
+
+    fn replacements(&self, _node: &Node<'_>, _text: &[u8]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn dependents<'tree>(&self, node: &Node<'tree>, _text: &[u8]) -> Vec<Node<'tree>> {
+        let in_list = node.is_named()
+            && node.parent().is_some_and(|p| {
+                matches!(
+                    p.kind(),
+                    "array" | "object" | "flow_mapping" | "flow_sequence" | "inline_table"
+                )
+            });
+        if !in_list {
+            return Vec::new();
+        }
+        let comma = |n: Option<Node<'tree>>| n.filter(|n| n.kind() == ",");
+        comma(node.next_sibling())
+            .or_else(|| comma(node.prev_sibling()))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Replaces the bodies of functions and methods with the smallest stubs that
+/// are valid in their language (e.g., `{}`, `{ return 0; }`, or `pass`), so
+/// that whatever only the body used can be deleted. Rust function bodies are
+/// left to [`RustTransform`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FunctionBodies {
+    /// And C++
+    C,
+    Go,
+    Java,
+    /// And TypeScript
+    JavaScript,
+    Python,
+}
+
+impl FunctionBodies {
+    fn stubs(&self) -> &'static [&'static str] {
+        match self {
+            FunctionBodies::C | FunctionBodies::JavaScript => &["{}", "{ return 0; }"],
+            // A panic ends a function with results
+            FunctionBodies::Go => &["{}", "{ panic(0) }"],
+            FunctionBodies::Java => &["{}", "{ return 0; }", "{ throw null; }"],
+            FunctionBodies::Python => &["pass"],
+        }
+    }
+}
+
+impl Transform for FunctionBodies {
+    fn name(&self) -> &str {
+        "function-bodies"
+    }
+
+    fn replacements(&self, node: &Node<'_>, _text: &[u8]) -> Vec<String> {
+        let is_body = node.parent().is_some_and(|p| {
+            matches!(
+                p.kind(),
+                "arrow_function"
+                    | "constructor_declaration"
+                    | "func_literal"
+                    | "function_declaration"
+                    | "function_definition"
+                    | "function_expression"
+                    | "generator_function_declaration"
+                    | "method_declaration"
+                    | "method_definition"
+            ) && p.child_by_field_name("body") == Some(*node)
+        });
+        if !is_body {
+            return Vec::new();
+        }
+        self.stubs()
+            .iter()
+            .filter(|s| s.len() < node.end_byte() - node.start_byte())
+            .map(|s| s.to_string())
+            .collect()
+    }
+}
+
+/// For JavaScript, and TypeScript (and TSX), whose grammars extend it
+pub fn javascript_replacements() -> Replacements {
+    HashMap::from([
+        ("arguments", &["()"][..]),
+        ("expression", &["0"][..]),
+        ("formal_parameters", &["()"][..]),
+        ("number", &["0"][..]),
+        ("statement_block", &["{}"][..]),
+        ("string", &["''"][..]),
+    ])
+}
+
+/// Reduces Python with regard to indentation: replaces `if`, `for`, `while`,
+/// `with`, and `try` statements with their bodies, moved left to the
+/// statement's column.
+pub struct PythonTransform;
+
+/// `text`, which starts at column `from`, with its other lines moved left to
+/// start at column `to`. `None` if a line isn't indented that far.
+fn dedent(text: &str, from: usize, to: usize) -> Option<String> {
+    let shift = from.checked_sub(to)?;
+    let mut lines = text.split('\n');
+    let mut dedented = String::from(lines.next()?);
+    for line in lines {
+        dedented.push('\n');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = line.get(..shift)?;
+        if !indent.bytes().all(|b| b == b' ' || b == b'\t') {
+            return None;
+        }
+        dedented.push_str(&line[shift..]);
+    }
+    Some(dedented)
+}
+
+impl Transform for PythonTransform {
+    fn name(&self) -> &str {
+        "python"
+    }
+
+    fn replacements(&self, node: &Node<'_>, text: &[u8]) -> Vec<String> {
+        let field = match node.kind() {
+            "if_statement" => "consequence",
+            "for_statement" | "while_statement" | "with_statement" | "try_statement" => "body",
+            _ => return Vec::new(),
+        };
+        let Some(body) = node.child_by_field_name(field) else {
+            return Vec::new();
+        };
+        let Ok(body_text) = body.utf8_text(text) else {
+            return Vec::new();
+        };
+        // The block may start before the indentation of its first line
+        let trimmed = body_text.trim_start();
+        let lead = &body_text[..body_text.len() - trimmed.len()];
+        let from = match lead.rfind('\n') {
+            Some(i) => lead.len() - i - 1,
+            None => body.start_position().column + lead.len(),
+        };
+        dedent(trimmed.trim_end(), from, node.start_position().column)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Has the comments pass delete Python docstrings: strings on their own as
+/// the first statement of a module, function, or class body.
+pub struct PythonDocstrings;
+
+/// A module, or the body of a function or class
+fn has_docstring(node: &Node) -> bool {
+    match node.kind() {
+        "module" => true,
+        "block" => node
+            .parent()
+            .is_some_and(|p| matches!(p.kind(), "function_definition" | "class_definition")),
+        _ => false,
+    }
+}
+
+impl Transform for PythonDocstrings {
+    fn name(&self) -> &str {
+        "python-docstrings"
+    }
+
+    fn replacements(&self, _node: &Node<'_>, _text: &[u8]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn is_comment(&self, node: &Node<'_>, _text: &[u8]) -> bool {
+        node.kind() == "expression_statement"
+            && node.named_child_count() == 1
+            && node.named_child(0).is_some_and(|c| c.kind() == "string")
+            && node.parent().is_some_and(|p| {
+                has_docstring(&p)
+                    && p.named_children(&mut p.walk())
+                        .find(|c| c.kind() != "comment")
+                        .is_some_and(|c| c.id() == node.id())
+            })
+    }
+}
+
+pub fn python_replacements() -> Replacements {
+    HashMap::from([
+        ("argument_list", &["()"][..]),
+        ("block", &["pass"][..]),
+        ("expression", &["0"][..]),
+        ("integer", &["0"][..]),
+        ("parameters", &["()"][..]),
+        ("string", &["\"\""][..]),
+    ])
+}
+
+/// `NODE_TYPES` of tree-sitter-python, except that blocks need a statement:
+/// deleting the last one would leave its `if` or `def` without a body. The
+/// `pass` replacement (see [`python_replacements`]) empties blocks instead.
+pub fn python_node_types(node_types: &str) -> String {
+    let Ok(mut types) = serde_json::from_str::<serde_json::Value>(node_types) else {
+        return node_types.to_string();
+    };
+    for ty in types.as_array_mut().into_iter().flatten() {
+        if ty["type"] == "block" {
+            if let Some(children) = ty.get_mut("children") {
+                children["required"] = serde_json::Value::Bool(true);
+            }
+        }
+    }
+    types.to_string()
+}
+
+/// Statements and optional clauses (`WHERE`, `ORDER BY`, joins, ...) are
+/// deleted like any other optional node.
+pub fn sql_replacements() -> Replacements {
+    HashMap::from([("literal", &["0"][..]), ("subquery", &["(SELECT 0)"][..])])
+}
+
+pub fn rust_replacements() -> Replacements {
+    HashMap::from([("type_identifier", &["()"][..])])
+}
+
+pub fn souffle_replacements() -> Replacements {
+    HashMap::from([
+        // ("atom", &["0 = 0"][..]),
+        // ("disjunction", &[""][..]),
+        // ("conjunction", &[""][..]),
+        // ("ident", &["0", "\"\""][..]),
+        ("string", &["\"\""][..]),
+        ("number", &["0"][..]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_preprocessor() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        let text = "#ifdef X\nint x;\n#else\nint y;\n#endif\n#if A\n#define B 42\n#endif\n";
+        let tree = parser.parse(text, None).unwrap();
+        let root = tree.root_node();
+        let ifdef = root.child(0).unwrap();
+        assert_eq!(
+            CPreprocessor.replacements(&ifdef, text.as_bytes()),
+            vec!["int x;\n", "int y;\n"]
+        );
+        let cond = root
+            .child(1)
+            .unwrap()
+            .child_by_field_name("condition")
+            .unwrap();
+        assert_eq!(
+            CPreprocessor.replacements(&cond, text.as_bytes()),
+            vec!["0", "1"]
+        );
+    }
+
+    #[test]
+    fn test_python() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_python::language()).unwrap();
+        let text = "def f(x):\n    if x:\n        for y in x:\n            g(y)\n\n        h()\n    else:\n        pass\n";
+        let tree = parser.parse(text, None).unwrap();
+        let body = tree
+            .root_node()
+            .child(0)
+            .unwrap()
+            .child_by_field_name("body")
+            .unwrap();
+        let stmt = body.named_child(0).unwrap();
+        assert_eq!(stmt.kind(), "if_statement");
+        assert_eq!(
+            PythonTransform.replacements(&stmt, text.as_bytes()),
+            vec!["for y in x:\n        g(y)\n\n    h()"]
+        );
+        assert_eq!(dedent("a\n  b\n c", 2, 0), None);
+
+        let node_types = python_node_types(tree_sitter_python::NODE_TYPES);
+        let nt = crate::node_types::NodeTypes::new(&node_types).unwrap();
+        let call = stmt
+            .child_by_field_name("consequence")
+            .unwrap()
+            .named_child(1)
+            .unwrap();
+        assert!(nt.optional_node(&call));
+        let pass = stmt
+            .child_by_field_name("alternative")
+            .unwrap()
+            .child_by_field_name("body")
+            .unwrap()
+            .named_child(0)
+            .unwrap();
+        assert!(!nt.optional_node(&pass));
+    }
+
+    #[test]
+    fn test_go() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_go::language()).unwrap();
+        let text = "package main\n\nimport (\n\t\"fmt\"\n\t\"os\"\n)\n\nfunc main() {\n\tx := 1\n\tfmt.Println(x)\n\tos.Exit(0)\n}\n";
+        let tree = parser.parse(text, None).unwrap();
+        let root = tree.root_node();
+        let find = |snippet: &str| {
+            let start = text.find(snippet).unwrap();
+            root.descendant_for_byte_range(start, start + snippet.len())
+                .unwrap()
+        };
+        let texts = |nodes: Vec<Node<'_>>| -> Vec<String> {
+            nodes
+                .iter()
+                .map(|n| n.utf8_text(text.as_bytes()).unwrap().to_string())
+                .collect()
+        };
+        let os = find("\"os\"").parent().unwrap();
+        assert_eq!(os.kind(), "import_spec");
+        assert_eq!(
+            texts(GoTransform.dependents(&os, text.as_bytes())),
+            vec!["os.Exit(0)"]
+        );
+        let x = find("x := 1");
+        assert_eq!(x.kind(), "short_var_declaration");
+        assert_eq!(
+            texts(GoTransform.dependents(&x, text.as_bytes())),
+            vec!["fmt.Println(x)"]
+        );
+        let println = find("fmt.Println(x)").parent().unwrap();
+        assert_eq!(println.kind(), "expression_statement");
+        assert_eq!(
+            texts(GoTransform.dependents(&println, text.as_bytes())),
+            vec!["\"fmt\"", "x := 1"]
+        );
+    }
+
+    #[test]
+    fn test_cpp_templates() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_cpp::language()).unwrap();
+        let text = "template <typename T = int, int N = 3> struct S {};\nA<B<C>, int> x;\n";
+        let tree = parser.parse(text, None).unwrap();
+        let find = |snippet: &str, kind: &str| {
+            let start = text.find(snippet).unwrap();
+            let mut node = tree
+                .root_node()
+                .descendant_for_byte_range(start, start + snippet.len())
+                .unwrap();
+            while node.kind() != kind {
+                node = node.parent().unwrap();
+            }
+            node
+        };
+        let replacements = |node: Node<'_>| CppTemplates.replacements(&node, text.as_bytes());
+        let params = find("<typename T = int, int N = 3>", "template_parameter_list");
+        assert_eq!(
+            without_each(&params, text.as_bytes()),
+            vec!["<int N = 3>", "<typename T = int>"]
+        );
+        assert_eq!(replacements(params), without_each(&params, text.as_bytes()));
+        assert_eq!(
+            replacements(find(
+                "typename T = int",
+                "optional_type_parameter_declaration"
+            )),
+            vec!["typename T"]
+        );
+        assert_eq!(
+            replacements(find("int N = 3", "optional_parameter_declaration")),
+            vec!["int N"]
+        );
+        assert_eq!(
+            replacements(find("<B<C>, int>", "template_argument_list")),
+            vec!["<int>", "<B<C>>"]
+        );
+        assert_eq!(replacements(find("B<C>", "type_descriptor")), vec!["int"]);
+        assert_eq!(
+            replacements(find("A<B<C>, int>", "template_type")),
+            vec!["B<C>"]
+        );
+    }
+
+    #[test]
+    fn test_function_bodies() {
+        let stubs =
+            |language: FunctionBodies, grammar: tree_sitter::Language, text: &str, outer: &str| {
+                let mut parser = tree_sitter::Parser::new();
+                parser.set_language(grammar).unwrap();
+                let tree = parser.parse(text, None).unwrap();
+                let start = text.find(outer).unwrap();
+                let function = tree
+                    .root_node()
+                    .descendant_for_byte_range(start, start + outer.len())
+                    .unwrap();
+                let body = function.child_by_field_name("body").unwrap();
+                language.replacements(&body, text.as_bytes())
+            };
+        assert_eq!(
+            stubs(
+                FunctionBodies::C,
+                tree_sitter_c::language(),
+                "int f(int x) { return x + 1; }\n",
+                "int f(int x) { return x + 1; }"
+            ),
+            vec!["{}", "{ return 0; }"]
+        );
+        let go = "package main\n\nfunc f(x int) int { return x + 1 }\n";
+        assert_eq!(
+            stubs(
+                FunctionBodies::Go,
+                tree_sitter_go::language(),
+                go,
+                "func f(x int) int { return x + 1 }"
+            ),
+            vec!["{}", "{ panic(0) }"]
+        );
+        assert_eq!(
+            stubs(
+                FunctionBodies::Python,
+                tree_sitter_python::language(),
+                "def f(x):\n    return x\n",
+                "def f(x):\n    return x"
+            ),
+            vec!["pass"]
+        );
+        assert_eq!(
+            stubs(
+                FunctionBodies::JavaScript,
+                tree_sitter_javascript::language(),
+                "function f(x) { return x + 1; }\n",
+                "function f(x) { return x + 1; }"
+            ),
+            vec!["{}", "{ return 0; }"]
+        );
+        let java = "class A { int f(int x) { return x + 1; } }\n";
+        assert_eq!(
+            stubs(
+                FunctionBodies::Java,
+                tree_sitter_java::language(),
+                java,
+                "int f(int x) { return x + 1; }"
+            ),
+            vec!["{}", "{ return 0; }", "{ throw null; }"]
+        );
+    }
+
+    #[test]
+    fn test_comma_lists() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_json::language()).unwrap();
+        let text = r#"{"a": [1, 2], "b": 3}"#;
+        let tree = parser.parse(text, None).unwrap();
+        let object = tree.root_node().named_child(0).unwrap();
+        let commas = |node: Node<'_>| -> Vec<usize> {
+            CommaLists
+                .dependents(&node, text.as_bytes())
+                .iter()
+                .map(|c| c.start_byte())
+                .collect()
+        };
+        let a = object.named_child(0).unwrap();
+        assert_eq!(commas(a), vec![12]);
+        assert_eq!(commas(object.named_child(1).unwrap()), vec![12]);
+        let array = a.child_by_field_name("value").unwrap();
+        assert_eq!(commas(array.named_child(1).unwrap()), vec![8]);
+        assert!(commas(array).is_empty());
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args("(Debug, Clone , PartialEq)"),
+            vec!["Debug", "Clone", "PartialEq"]
+        );
+        assert_eq!(
+            split_args("(a(b, c), d<e, f>,)"),
+            vec!["a(b, c)", "d<e, f>"]
+        );
+        assert!(split_args("()").is_empty());
+    }
+}
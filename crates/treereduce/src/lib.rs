@@ -1,11 +1,31 @@
+pub mod async_check;
 pub mod check;
+pub mod checkpoint;
+pub mod control;
+pub mod diff;
 pub mod edits;
+#[cfg(any(unix, windows))]
+pub mod grammar;
 mod id;
+pub mod include;
+pub mod jobserver;
+pub mod languages;
 mod node_types;
+pub mod observer;
 mod original;
+pub mod pipeline;
+pub mod provenance;
 pub mod reduce;
-mod stats;
-mod versioned;
+pub mod reducer;
+pub mod rope;
+pub mod snapshot;
+pub mod stats;
+pub mod trace;
+pub mod transform;
+pub mod variants;
+pub mod versioned;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
 
 #[cfg(feature = "cli")]
 pub mod cli;
@@ -15,3 +35,4 @@ pub use edits::*;
 pub use node_types::*;
 pub use original::*;
 pub use reduce::*;
+pub use reducer::*;
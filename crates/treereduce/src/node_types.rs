@@ -53,7 +53,9 @@ pub struct FieldInfo {
 #[derive(Clone, Debug)]
 pub struct NodeTypes {
     children: HashMap<String, Children>,
+    fields: HashMap<String, HashMap<String, Field>>,
     subtypes: HashMap<String, Vec<String>>,
+    supertypes: HashMap<String, Vec<String>>,
     reverse_fields: HashMap<String, Vec<FieldInfo>>,
 }
 
@@ -77,6 +79,16 @@ impl NodeTypes {
             .iter()
             .map(|n| (n.ty.clone(), subtypes(&n.ty, &nodes)))
             .collect();
+        let mut supertypes: HashMap<String, Vec<String>> = HashMap::new();
+        for (supertype, subs) in &subtypes {
+            // The first entry is the type itself
+            for sub in subs.iter().skip(1) {
+                supertypes
+                    .entry(sub.clone())
+                    .or_default()
+                    .push(supertype.clone());
+            }
+        }
         let mut reverse_fields = HashMap::new();
 
         // For each type of node...
@@ -111,7 +123,12 @@ impl NodeTypes {
                 .iter()
                 .map(|n| (n.ty.clone(), n.children.clone()))
                 .collect(),
+            fields: nodes
+                .iter()
+                .map(|n| (n.ty.clone(), n.fields.clone()))
+                .collect(),
             subtypes,
+            supertypes,
             reverse_fields,
         })
     }
@@ -128,12 +145,70 @@ impl NodeTypes {
         true
     }
 
+    fn is_subtype(&self, kind: &str, types: &[Subtype]) -> bool {
+        types.iter().any(|t| {
+            t.ty == kind
+                || self
+                    .subtypes
+                    .get(&t.ty)
+                    .map(|subs| subs.iter().any(|s| s == kind))
+                    .unwrap_or(false)
+        })
+    }
+
+    /// Whether deleting this node could yield a syntactically valid tree,
+    /// according to whether the field or child it occupies is required.
+    ///
+    /// Anonymous nodes (i.e., tokens) that aren't in a field are required.
     /// Defaults to `true` if the real answer can't be determined.
     pub fn optional_node(&self, node: &tree_sitter::Node) -> bool {
-        if let Some(p) = node.parent() {
-            self.optional(node.kind(), p.kind())
-        } else {
-            true
+        let parent = match node.parent() {
+            Some(p) => p,
+            None => return true,
+        };
+        let mut cursor = parent.walk();
+        let mut siblings = Vec::new();
+        let mut field_name = None;
+        if cursor.goto_first_child() {
+            loop {
+                let sibling = cursor.node();
+                if sibling == *node {
+                    field_name = cursor.field_name();
+                }
+                siblings.push((sibling, cursor.field_name()));
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+
+        if let Some(name) = field_name {
+            return match self.fields.get(parent.kind()).and_then(|f| f.get(name)) {
+                Some(field) => {
+                    !field.required
+                        || (field.multiple
+                            && siblings.iter().filter(|(_, f)| *f == Some(name)).count() > 1)
+                }
+                None => self.optional(node.kind(), parent.kind()),
+            };
+        }
+        if !node.is_named() {
+            return false;
+        }
+        match self.children.get(parent.kind()) {
+            Some(children) if self.is_subtype(node.kind(), &children.types) => {
+                !children.required
+                    || (children.multiple
+                        && siblings
+                            .iter()
+                            .filter(|(s, f)| {
+                                f.is_none() && self.is_subtype(s.kind(), &children.types)
+                            })
+                            .count()
+                            > 1)
+            }
+            // Not a child according to the grammar, e.g., a comment
+            _ => true,
         }
     }
 
@@ -153,6 +228,26 @@ impl NodeTypes {
     pub fn subtypes(&self, kind: &String) -> &[String] {
         self.subtypes.get(kind).expect("Invalid node kind")
     }
+
+    /// Supertypes of this kind (e.g., `_expression`), not including itself
+    pub fn supertypes(&self, kind: &str) -> &[String] {
+        self.supertypes
+            .get(kind)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Whether a node of kind `kind` could plausibly be replaced by one of
+    /// kind `other`, i.e., they're the same kind or share a supertype.
+    pub fn compatible(&self, kind: &str, other: &str) -> bool {
+        if kind == other {
+            return true;
+        }
+        match (self.supertypes.get(kind), self.supertypes.get(other)) {
+            (Some(sups), Some(other_sups)) => sups.iter().any(|s| other_sups.contains(s)),
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -165,4 +260,37 @@ mod tests {
         assert!(nt.optional("_expression", "return_statement"));
         assert!(!nt.optional("compound_statement", "function_definition"));
     }
+
+    fn parse_c(code: &str) -> tree_sitter::Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    #[test]
+    fn test_optional_node() {
+        let nt = NodeTypes::new(tree_sitter_c::NODE_TYPES).unwrap();
+        let tree = parse_c("int f() { return 0; }");
+        let def = tree.root_node().child(0).unwrap();
+        assert_eq!(def.kind(), "function_definition");
+        assert!(nt.optional_node(&def));
+        let body = def.child_by_field_name("body").unwrap();
+        assert!(!nt.optional_node(&body));
+        let ret = body.named_child(0).unwrap();
+        assert_eq!(ret.kind(), "return_statement");
+        assert!(nt.optional_node(&ret));
+        let zero = ret.named_child(0).unwrap();
+        assert!(nt.optional_node(&zero));
+        let semi = ret.child(2).unwrap();
+        assert_eq!(semi.kind(), ";");
+        assert!(!nt.optional_node(&semi));
+    }
+
+    #[test]
+    fn test_compatible() {
+        let nt = NodeTypes::new(tree_sitter_c::NODE_TYPES).unwrap();
+        assert!(nt.compatible("if_statement", "compound_statement"));
+        assert!(nt.compatible("binary_expression", "identifier"));
+        assert!(!nt.compatible("binary_expression", "compound_statement"));
+    }
 }
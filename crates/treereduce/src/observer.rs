@@ -0,0 +1,116 @@
+//! Hooks for following the progress of a reduction, e.g., to display it.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::checkpoint::Position;
+use crate::pipeline::Stage;
+use crate::rope::Rope;
+
+/// A candidate that was checked for interestingness
+#[derive(Debug)]
+pub struct Attempt<'a> {
+    /// Unique to the reduction in this pass
+    pub id: usize,
+    /// Kind of reduction, e.g., `delete` or `hoist`
+    pub kind: &'a str,
+    /// What the reduction did, e.g., ``delete `statement` at 10..25``
+    pub description: &'a str,
+    /// The candidate
+    pub text: &'a Rope<'a>,
+    /// Size of the smallest interesting candidate, including this one if it
+    /// was accepted
+    pub current_size: usize,
+    /// Number of tasks (reductions to try, or nodes to explore) left in this
+    /// pass
+    pub pending: usize,
+}
+
+/// One iteration of a stage of the pipeline
+#[derive(Debug)]
+pub struct PassDone<'a> {
+    pub stage: &'a Stage,
+    /// 1-indexed iteration of this stage
+    pub iteration: usize,
+    /// Position in the pipeline after this pass
+    pub position: Position,
+    pub start_size: usize,
+    pub end_size: usize,
+    pub duration: Duration,
+    /// The result of the pass
+    pub text: &'a [u8],
+}
+
+/// Called from the reduction's worker threads, so should be quick
+pub trait Observer: Send + Sync {
+    /// The candidate is about to be checked. It may be checked again (and
+    /// this called again with the same `id`) if another edit was accepted
+    /// in the meantime.
+    fn started(&self, _attempt: &Attempt<'_>) {}
+
+    /// The candidate was interesting, and is now the smallest so far
+    fn accepted(&self, _attempt: &Attempt<'_>) {}
+
+    /// The check found the candidate interesting, whether or not it's then
+    /// accepted (it isn't if another edit was accepted in the meantime)
+    fn interesting(&self, _attempt: &Attempt<'_>) {}
+
+    /// The candidate wasn't interesting
+    fn rejected(&self, _attempt: &Attempt<'_>) {}
+
+    fn pass_done(&self, _pass: &PassDone<'_>) {}
+}
+
+/// Observers to notify of each event, in order
+#[derive(Clone, Default)]
+pub struct Observers(Vec<Arc<dyn Observer>>);
+
+impl Observers {
+    pub fn push(&mut self, observer: Arc<dyn Observer>) {
+        self.0.push(observer);
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Observer for Observers {
+    fn started(&self, attempt: &Attempt<'_>) {
+        for o in &self.0 {
+            o.started(attempt);
+        }
+    }
+
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        for o in &self.0 {
+            o.accepted(attempt);
+        }
+    }
+
+    fn interesting(&self, attempt: &Attempt<'_>) {
+        for o in &self.0 {
+            o.interesting(attempt);
+        }
+    }
+
+    fn rejected(&self, attempt: &Attempt<'_>) {
+        for o in &self.0 {
+            o.rejected(attempt);
+        }
+    }
+
+    fn pass_done(&self, pass: &PassDone<'_>) {
+        for o in &self.0 {
+            o.pass_done(pass);
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
@@ -0,0 +1,199 @@
+//! Which reduction passes to run, and in what order.
+//!
+//! A pipeline is a sequence of stages. Each stage runs one or more passes
+//! together (sharing a single task queue) until no progress is made or its
+//! iteration limit is reached. The textual syntax is a comma- or
+//! newline-separated list of stages, each of the form `PASS[+PASS...][:N]`,
+//! e.g., `delete+hoist:2,rename,tokens:1`. In files, `#` starts a comment.
+
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PipelineError {
+    #[error("Unknown pass {0}, expected one of delete, hoist, rename, literals, comments, tokens")]
+    Pass(String),
+    #[error("Invalid iteration limit {0}")]
+    Limit(String),
+    #[error("The tokens pass can't run in the same stage as other passes")]
+    Tokens,
+    #[error("Empty pipeline")]
+    Empty,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pass {
+    /// Delete and replace nodes
+    Delete,
+    /// Replace nodes with their descendants
+    Hoist,
+    /// Rename identifiers to short names
+    Rename,
+    /// Replace literals with smaller ones
+    Literals,
+    /// Delete comments and docstrings
+    Comments,
+    /// Delete individual tokens and collapse whitespace
+    Tokens,
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Pass::Delete => "delete",
+            Pass::Hoist => "hoist",
+            Pass::Rename => "rename",
+            Pass::Literals => "literals",
+            Pass::Comments => "comments",
+            Pass::Tokens => "tokens",
+        })
+    }
+}
+
+impl FromStr for Pass {
+    type Err = PipelineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(Pass::Delete),
+            "hoist" => Ok(Pass::Hoist),
+            "rename" => Ok(Pass::Rename),
+            "literals" => Ok(Pass::Literals),
+            "comments" => Ok(Pass::Comments),
+            "tokens" => Ok(Pass::Tokens),
+            _ => Err(PipelineError::Pass(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stage {
+    pub passes: Vec<Pass>,
+    /// If `None`, use the default number of passes (see `--passes`)
+    pub max_iterations: Option<usize>,
+}
+
+impl Stage {
+    pub fn new(passes: Vec<Pass>, max_iterations: Option<usize>) -> Self {
+        Stage {
+            passes,
+            max_iterations,
+        }
+    }
+
+    #[inline]
+    pub fn has(&self, pass: Pass) -> bool {
+        self.passes.contains(&pass)
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let passes: Vec<String> = self.passes.iter().map(Pass::to_string).collect();
+        f.write_str(&passes.join("+"))?;
+        if let Some(n) = self.max_iterations {
+            write!(f, ":{}", n)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Stage {
+    type Err = PipelineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (passes, limit) = match s.split_once(':') {
+            Some((p, l)) => (p, Some(l.trim())),
+            None => (s, None),
+        };
+        let max_iterations = limit
+            .map(|l| l.parse().map_err(|_| PipelineError::Limit(l.to_string())))
+            .transpose()?;
+        let mut stage = Stage::new(Vec::new(), max_iterations);
+        for pass in passes.split('+') {
+            let pass = pass.trim().parse()?;
+            if !stage.has(pass) {
+                stage.passes.push(pass);
+            }
+        }
+        if stage.has(Pass::Tokens) && stage.passes.len() > 1 {
+            return Err(PipelineError::Tokens);
+        }
+        Ok(stage)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+impl Default for Pipeline {
+    /// Delete, hoist, and shrink literals together
+    fn default() -> Self {
+        Pipeline {
+            stages: vec![Stage::new(
+                vec![Pass::Delete, Pass::Hoist, Pass::Literals],
+                None,
+            )],
+        }
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stages: Vec<String> = self.stages.iter().map(Stage::to_string).collect();
+        f.write_str(&stages.join(","))
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = PipelineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stages = s
+            .lines()
+            .map(|l| l.split_once('#').map(|(l, _)| l).unwrap_or(l))
+            .flat_map(|l| l.split(','))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Stage::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if stages.is_empty() {
+            return Err(PipelineError::Empty);
+        }
+        Ok(Pipeline { stages })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let p: Pipeline = "delete+hoist:2, rename\n# comment\ntokens:1 # last"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            p.stages,
+            vec![
+                Stage::new(vec![Pass::Delete, Pass::Hoist], Some(2)),
+                Stage::new(vec![Pass::Rename], None),
+                Stage::new(vec![Pass::Tokens], Some(1)),
+            ]
+        );
+        assert_eq!(p.to_string(), "delete+hoist:2,rename,tokens:1");
+        assert_eq!(
+            "delete+tokens".parse::<Pipeline>(),
+            Err(PipelineError::Tokens)
+        );
+        assert_eq!(
+            "delete:x".parse::<Pipeline>(),
+            Err(PipelineError::Limit(String::from("x")))
+        );
+        assert_eq!(" # nothing".parse::<Pipeline>(), Err(PipelineError::Empty));
+    }
+}
@@ -0,0 +1,111 @@
+//! Where the bytes of a reduced input came from in the original one, tracked
+//! through the edits that produced it (rather than found by diffing them).
+
+use std::ops::Range;
+
+/// The byte ranges of a text that are copied from the input, and where they
+/// are in the text, in order. Everything else in the text was inserted by an
+/// edit (e.g., a replacement).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Provenance {
+    segments: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl Provenance {
+    /// The input itself, `len` bytes long
+    pub fn new(len: usize) -> Self {
+        Provenance {
+            segments: if len == 0 {
+                Vec::new()
+            } else {
+                vec![(0..len, 0..len)]
+            },
+        }
+    }
+
+    /// The ranges of the input, and where they are in the text, in order
+    pub fn segments(&self) -> &[(Range<usize>, Range<usize>)] {
+        &self.segments
+    }
+
+    /// The text was replaced by a new one. `retained` are the ranges of the
+    /// text that are copied to the new one, and where they are in it, in
+    /// order (e.g., from [`crate::edits::Edits::retained`]).
+    pub fn follow(&mut self, retained: &[(Range<usize>, Range<usize>)]) {
+        let mut segments: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+        for (old, new) in retained.iter().filter(|(old, _)| !old.is_empty()) {
+            let first = self.segments.partition_point(|(_, t)| t.end <= old.start);
+            for (input, text) in &self.segments[first..] {
+                if text.start >= old.end {
+                    break;
+                }
+                let start = text.start.max(old.start);
+                let len = text.end.min(old.end) - start;
+                let from = input.start + (start - text.start);
+                let to = new.start + (start - old.start);
+                match segments.last_mut() {
+                    Some((i, t)) if i.end == from && t.end == to => {
+                        i.end += len;
+                        t.end += len;
+                    }
+                    _ => segments.push((from..from + len, to..to + len)),
+                }
+            }
+        }
+        self.segments = segments;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edits::Edits;
+
+    fn parse(text: &str) -> tree_sitter::Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        parser.parse(text, None).unwrap()
+    }
+
+    #[test]
+    fn test_follow() {
+        let mut p = Provenance::new(10);
+        p.follow(&[(0..2, 0..2), (5..10, 3..8)]);
+        assert_eq!(p.segments(), &[(0..2, 0..2), (5..10, 3..8)]);
+        // Cutting across both segments keeps them apart
+        p.follow(&[(1..4, 0..3)]);
+        assert_eq!(p.segments(), &[(1..2, 0..1), (5..6, 2..3)]);
+        p.follow(&[]);
+        assert_eq!(p.segments(), &[]);
+        assert_eq!(Provenance::new(0).segments(), &[]);
+    }
+
+    #[test]
+    fn test_edits() {
+        let text = "int x = 1 + 2;\nint y;\n";
+        let tree = parse(text);
+        let root = tree.root_node();
+        let decl = root.child(0).unwrap();
+        let value = decl
+            .child_by_field_name("declarator")
+            .unwrap()
+            .child_by_field_name("value")
+            .unwrap();
+        let right = value.child_by_field_name("right").unwrap();
+        let edits = Edits::new()
+            .hoist(&value, &right)
+            .replace(&root.child(1).unwrap(), String::from("long y;"));
+        assert_eq!(
+            edits.render_rope(text.as_bytes(), &tree).to_vec(),
+            b"int x = 2;\nlong y;\n"
+        );
+        let retained = edits.retained(text.as_bytes(), &tree);
+        assert_eq!(
+            retained,
+            vec![(0..8, 0..8), (12..15, 8..11), (21..22, 18..19)]
+        );
+        let mut p = Provenance::new(text.len());
+        p.follow(&retained);
+        assert_eq!(p.segments(), &retained[..]);
+    }
+}
@@ -1,33 +1,59 @@
 // TODO(#22): Awareness of binding structure
 // TODO(#23): Awareness of matched delimiters
 
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fmt::Debug;
-use std::io;
+use std::ops::Range;
 use std::sync::atomic::{self, AtomicUsize};
-use std::sync::{Condvar, Mutex, RwLock, TryLockError};
+use std::sync::{Arc, Condvar, Mutex, RwLock, TryLockError};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use tracing::{debug, debug_span, info};
-use tree_sitter::{Language, Node, Tree};
+use regex::Regex;
+use tracing::{debug, debug_span, info, info_span, warn};
+use tree_sitter::{InputEdit, Language, Node, Point, Tree};
 use tree_sitter_edit::render;
 
-use crate::check::Check;
+use crate::check::{stable_hash, Check};
+use crate::checkpoint::Position;
+use crate::control::Control;
 use crate::edits::Edits;
 use crate::id::NodeId;
+use crate::jobserver::Jobserver;
 use crate::node_types::NodeTypes;
+use crate::observer::{Attempt, Observer, Observers, PassDone};
 use crate::original::Original;
+use crate::pipeline::{Pass, Pipeline, Stage};
+use crate::provenance::Provenance;
+use crate::rope::Rope;
 use crate::stats::{self, Stats};
-use crate::versioned::Versioned;
+use crate::transform::Transforms;
+use crate::versioned::{History, Versioned};
 
+mod bisect;
+mod comments;
+pub(crate) mod ddmin;
 mod error;
+mod hdd;
+mod injection;
+mod literal;
+mod merge;
+pub(crate) mod rename;
+mod schedule;
+mod slots;
+mod target;
 mod task;
+mod tokens;
 
-use error::ReductionError;
+pub use error::{MultiPassReductionError, ReductionError};
+pub use hdd::treereduce_async;
+pub use injection::Injections;
+use schedule::{Scheduler, Verdicts};
+pub use target::Preserve;
 use task::{PrioritizedTask, Reduction, Task, TaskId};
 
-use self::error::MultiPassReductionError;
+/// Number of versions of the accepted edits to keep during a pass
+const EDIT_HISTORY: usize = 16;
 
 #[inline]
 fn node_size(node: &Node) -> usize {
@@ -61,7 +87,13 @@ impl Tasks {
         }
     }
 
-    fn push(&self, task: Task, priority: usize) -> Result<(), ReductionError> {
+    fn push(
+        &self,
+        task: Task,
+        priority: usize,
+        rank: usize,
+        tiebreak: u64,
+    ) -> Result<(), ReductionError> {
         {
             let mut w = self.heap.write()?;
             let id = self.task_id.fetch_add(1, atomic::Ordering::SeqCst);
@@ -69,6 +101,8 @@ impl Tasks {
                 task,
                 id: TaskId { id },
                 priority,
+                rank,
+                tiebreak,
             };
             debug!(
                 event = "push",
@@ -87,15 +121,20 @@ impl Tasks {
         Ok(())
     }
 
-    fn push_all(&self, tasks: impl Iterator<Item = (Task, usize)>) -> Result<(), ReductionError> {
+    fn push_all(
+        &self,
+        tasks: impl Iterator<Item = (Task, usize, usize, u64)>,
+    ) -> Result<(), ReductionError> {
         {
             let mut w = self.heap.write()?;
-            for (task, priority) in tasks {
+            for (task, priority, rank, tiebreak) in tasks {
                 let id = self.task_id.fetch_add(1, atomic::Ordering::SeqCst);
                 let ptask = PrioritizedTask {
                     task,
                     id: TaskId { id },
                     priority,
+                    rank,
+                    tiebreak,
                 };
                 debug!(
                     event = "push",
@@ -129,6 +168,24 @@ impl Tasks {
         Ok(ptask)
     }
 
+    /// Put back tasks that were popped, but not tried
+    fn restore(&self, ptasks: Vec<PrioritizedTask>) -> Result<(), ReductionError> {
+        self.heap.write()?.extend(ptasks);
+        Ok(())
+    }
+
+    /// Pop the highest-priority task, but only if it satisfies `pred`
+    fn pop_if(
+        &self,
+        pred: impl FnOnce(&PrioritizedTask) -> bool,
+    ) -> Result<Option<PrioritizedTask>, ReductionError> {
+        let mut w = self.heap.write()?;
+        if w.peek().map(pred).unwrap_or(false) {
+            return Ok(w.pop());
+        }
+        Ok(None)
+    }
+
     fn wait_for_push(&self, dur: Duration) -> Result<(), ReductionError> {
         match self.push_signal_mutex.try_lock() {
             Err(TryLockError::WouldBlock) => Ok(()),
@@ -146,14 +203,59 @@ struct Ctx<'a, T>
 where
     T: Check + Send + Sync + 'static,
 {
+    delete: bool,
     delete_non_optional: bool,
+    hoist: bool,
+    shrink_literals: bool,
+    comments: bool,
+    tokens_only: bool,
     node_types: &'a NodeTypes,
     tasks: Tasks,
-    edits: RwLock<Versioned<Edits>>,
+    /// The accepted edits, and a few earlier versions of them
+    edits: RwLock<History<Edits>>,
+    /// Interesting reductions found on old versions of the edits, to be
+    /// tried together on the current version
+    merges: Mutex<merge::Merges>,
     orig: Original,
     check: &'a T,
     min_task_size: usize,
+    batch: usize,
+    priority: Priority,
+    /// Depth of each node, if needed by `priority`
+    depths: HashMap<NodeId, usize>,
+    /// Seed for breaking ties between tasks, if runs are deterministic
+    deterministic: Option<u64>,
+    /// Preorder index of each node, if runs are deterministic
+    positions: HashMap<NodeId, usize>,
+    /// Verdicts on candidates that were checked ahead of time (and whether
+    /// the check ran, rather than finding it in the cache), if runs are
+    /// deterministic
+    speculated: Mutex<HashMap<Vec<u8>, (bool, bool)>>,
+    /// Reject candidates with more parse errors than `errors`
+    no_new_errors: bool,
+    /// Parse errors in the smallest interesting candidate so far, if
+    /// `no_new_errors`
+    errors: AtomicUsize,
+    metric: Metric,
+    /// Size of the smallest interesting candidate so far, by `metric`
+    current_metric: AtomicUsize,
+    oracle_slots: slots::Slots,
+    idle_threads: AtomicUsize,
     replacements: &'a HashMap<&'static str, &'static [&'static str]>,
+    weights: &'a HashMap<String, f64>,
+    targets: target::Targets,
+    observers: &'a Observers,
+    transforms: &'a Transforms,
+    control: &'a Control,
+    /// Size of the smallest interesting candidate so far
+    current_size: AtomicUsize,
+    /// Number of candidates that were (or weren't) interesting
+    accepted: AtomicUsize,
+    rejected: AtomicUsize,
+    /// Runs of the check, not counting verdicts found in the cache
+    checks: AtomicUsize,
+    /// By pass, for the scheduler
+    verdicts: Mutex<HashMap<Pass, Verdicts>>,
 }
 
 struct ThreadCtx<'a, T>
@@ -190,25 +292,51 @@ impl<'a, T> Ctx<'a, T>
 where
     T: Check + Send + Sync + 'static,
 {
-    fn render(&self, edits: &Edits) -> io::Result<(bool, Vec<u8>)> {
-        let mut text: Vec<u8> = Vec::with_capacity(self.orig.text.len() / 2);
-        let changed = render(&mut text, &self.orig.tree, &self.orig.text, edits)?;
-        Ok((changed, text))
+    fn render<'b>(&'b self, edits: &Edits) -> Rope<'b> {
+        edits.render_rope(&self.orig.text, &self.orig.tree)
     }
 
-    fn _language(&self) -> Language {
+    fn language(&self) -> Language {
         self.orig.tree.language()
     }
 
-    fn _parse(&self, src: &[u8]) -> Tree {
+    fn parse(&self, src: &[u8]) -> Tree {
         let mut parser = tree_sitter::Parser::new();
         // TODO(lb): Incremental re-parsing
         parser
-            .set_language(self._language())
+            .set_language(self.language())
             .expect("Error loading language");
         parser.parse(src, None).expect("Failed to parse")
     }
 
+    /// The parse errors in `text` (if they're counted), and its size by the
+    /// metric (unless that's bytes)
+    fn measure(&self, text: &[u8]) -> (Option<usize>, Option<usize>) {
+        let tree = (self.no_new_errors || self.metric.needs_tree()).then(|| self.parse(text));
+        let errors = tree
+            .as_ref()
+            .filter(|_| self.no_new_errors)
+            .map(count_errors);
+        let size = match (self.metric, &tree) {
+            (Metric::Bytes, _) => None,
+            (Metric::Lines, _) => Some(lines(text)),
+            (metric, Some(tree)) => Some(metric.measure(tree, text)),
+            (_, None) => None,
+        };
+        (errors, size)
+    }
+
+    /// Not smaller by the metric than the smallest interesting candidate so
+    /// far?
+    fn no_progress(&self, size: Option<usize>) -> bool {
+        size.is_some_and(|s| s >= self.current_metric.load(atomic::Ordering::Acquire))
+    }
+
+    /// More parse errors than the smallest interesting candidate so far?
+    fn new_errors(&self, errors: Option<usize>) -> bool {
+        errors.is_some_and(|e| e > self.errors.load(atomic::Ordering::Acquire))
+    }
+
     /// Pop the highest-priority task from the task heap.
     fn pop_task(&self) -> Result<Option<PrioritizedTask>, ReductionError>
     where
@@ -217,33 +345,180 @@ where
         // TODO(lb): What's the problem?
         // let point_o_one_seconds = Duration::new(0, 10000000);
         // Ok(self.tasks.wait_pop(point_o_one_seconds)?.map(|pt| pt.task))
-        let task = self.tasks.pop()?;
-        debug_assert!(
-            task.as_ref().map(|t| t.priority).unwrap_or(usize::MAX) >= self.min_task_size
-        );
-        Ok(task)
+        self.tasks.pop()
+    }
+
+    /// How to order tasks before their priority (size), see [`Priority`]
+    fn rank(&self, task: &Task) -> usize {
+        let id = match task {
+            Task::Explore(id) => Some(*id),
+            Task::Reduce(r) => r.node_ids().first().copied(),
+        };
+        let depth = || id.and_then(|id| self.depths.get(&id)).copied().unwrap_or(0);
+        match self.priority {
+            Priority::Size => 0,
+            Priority::Depth => depth(),
+            Priority::Breadth => usize::MAX - depth(),
+        }
+    }
+
+    /// How to order tasks of the same rank and priority. [`NodeId`]s differ
+    /// between runs, so this hashes the nodes' positions in the tree instead,
+    /// with a hash that's stable across builds.
+    fn tiebreak(&self, task: &Task) -> u64 {
+        let Some(seed) = self.deterministic else {
+            return 0;
+        };
+        let mut bytes = seed.to_le_bytes().to_vec();
+        bytes.extend(task.kind().as_bytes());
+        bytes.push(0);
+        let mut node_ids = match task {
+            Task::Explore(id) => vec![*id],
+            Task::Reduce(r) => r.node_ids(),
+        };
+        if let Task::Reduce(Reduction::Hoist(_, descendant)) = task {
+            node_ids.push(*descendant);
+        }
+        for id in node_ids {
+            let position = self.positions.get(&id).map_or(u64::MAX, |p| *p as u64);
+            bytes.extend(position.to_le_bytes());
+        }
+        if let Task::Reduce(Reduction::Replace { with, .. } | Reduction::Rename { with, .. }) = task
+        {
+            bytes.extend(with.as_bytes());
+        }
+        stable_hash(&bytes)
     }
 
     fn push_task(&self, node: &Node, task: Task) -> Result<(), ReductionError> {
-        self.push_prioritized_task(node_size(node), task)
+        self.push_weighted_task(Some(node.kind()), node_size(node), task)
+    }
+
+    /// `size` scaled by the weight of `kind`, see [`Config::weights`]
+    fn weigh(&self, kind: Option<&str>, size: usize) -> usize {
+        match kind.and_then(|k| self.weights.get(k)) {
+            None => size,
+            Some(w) => (size as f64 * w).round() as usize,
+        }
     }
 
     fn push_prioritized_task(&self, priority: usize, task: Task) -> Result<(), ReductionError> {
-        if priority < self.min_task_size {
+        self.push_weighted_task(None, priority, task)
+    }
+
+    /// Push a task that saves `size` bytes by reducing a node of kind `kind`
+    fn push_weighted_task(
+        &self,
+        kind: Option<&str>,
+        size: usize,
+        task: Task,
+    ) -> Result<(), ReductionError> {
+        if size < self.min_task_size {
             return Ok(());
         }
+        if let Task::Reduce(r) = &task {
+            if !self.targets.allows(&r.node_ids()) {
+                return Ok(());
+            }
+        }
         // TODO(lb): Benchmark leaving this at 0
-        self.tasks.push(task, priority)
+        let rank = self.rank(&task);
+        let tiebreak = self.tiebreak(&task);
+        self.tasks
+            .push(task, self.weigh(kind, size), rank, tiebreak)
+    }
+
+    /// Push tasks that delete contiguous chunks of the list-like children of
+    /// `node`, largest first.
+    fn push_chunks(&self, node: &Node) -> Result<(), ReductionError> {
+        if node.is_error() {
+            return self.push_error_chunks(node);
+        }
+        let kinds = self.node_types.list_types(node);
+        if kinds.is_empty() {
+            return Ok(());
+        }
+        let elem_kinds: HashSet<&str> = kinds
+            .iter()
+            .flat_map(|k| self.node_types.subtypes(k))
+            .map(|k| k.as_str())
+            .collect();
+        let children: Vec<Node> = node.children(&mut self.orig.tree.walk()).collect();
+        let elems: Vec<usize> = children
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| elem_kinds.contains(c.kind()))
+            .map(|(i, _)| i)
+            .collect();
+        for chunk in ddmin::chunks(elems.len()) {
+            let first = elems[chunk.start];
+            let mut last = elems[chunk.end - 1];
+            // Take a trailing separator (e.g., a comma) along with the chunk
+            if let (Some(sep), Some(next)) = (children.get(last + 1), children.get(last + 2)) {
+                if !sep.is_named() && elem_kinds.contains(next.kind()) {
+                    last += 1;
+                }
+            }
+            let size = children[last].end_byte() - children[first].start_byte();
+            let ids = children[first..=last].iter().map(NodeId::new).collect();
+            let kind = Some(children[first].kind());
+            self.push_weighted_task(kind, size, Task::Reduce(Reduction::DeleteAll(ids)))?;
+        }
+        Ok(())
+    }
+
+    /// Push tasks that delete chunks of the children of an `ERROR` node (see
+    /// [`error_chunks`]). The grammar doesn't say what an `ERROR` may
+    /// contain, so without these, garbage in invalid inputs shrinks one child
+    /// at a time.
+    fn push_error_chunks(&self, node: &Node) -> Result<(), ReductionError> {
+        let children: Vec<Node> = node
+            .children(&mut self.orig.tree.walk())
+            .filter(|c| !c.is_missing())
+            .collect();
+        let rows: Vec<usize> = children.iter().map(|c| c.start_position().row).collect();
+        for chunk in error_chunks(&rows) {
+            let first = children[chunk.start];
+            let size = children[chunk.end - 1].end_byte() - first.start_byte();
+            let ids = children[chunk].iter().map(NodeId::new).collect();
+            self.push_weighted_task(
+                Some(first.kind()),
+                size,
+                Task::Reduce(Reduction::DeleteAll(ids)),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The nearest descendants of `node` that could replace it
+    fn hoistable<'b>(&self, node: &Node<'b>) -> Vec<Node<'b>> {
+        let mut found = Vec::new();
+        let mut stack: Vec<Node> = node.children(&mut node.walk()).collect();
+        while let Some(n) = stack.pop() {
+            if n.is_named() && self.node_types.compatible(node.kind(), n.kind()) {
+                found.push(n);
+            } else {
+                stack.extend(n.children(&mut n.walk()));
+            }
+        }
+        found
     }
 
     fn push_explore_children(&self, node: Node) -> Result<(), ReductionError>
     where
         T: Check,
     {
+        self.push_chunks(&node)?;
         self.tasks.push_all(
             node.children(&mut self.orig.tree.walk())
                 .filter(|child| node_size(child) > self.min_task_size)
-                .map(|child| (Task::Explore(NodeId::new(&child)), node_size(&child))),
+                .map(|child| {
+                    let task = Task::Explore(NodeId::new(&child));
+                    let rank = self.rank(&task);
+                    let tiebreak = self.tiebreak(&task);
+                    let priority = self.weigh(Some(child.kind()), node_size(&child));
+                    (task, priority, rank, tiebreak)
+                }),
         )?;
         for _ in 0..node.child_count() {
             self.tasks.push_signal.notify_one();
@@ -251,12 +526,21 @@ where
         Ok(())
     }
 
-    fn add_task_edit(&self, task: &Task) -> Result<Option<Versioned<Edits>>, ReductionError> {
-        let edits = self.edits.read()?;
+    fn add_task_edit(
+        &self,
+        task: &Task,
+        nodes: &[Node],
+    ) -> Result<Option<Versioned<Edits>>, ReductionError> {
+        let history = self.edits.read()?;
+        let edits = history.current();
+        // All of the nodes were removed by edits to their ancestors
+        if !nodes.is_empty() && nodes.iter().all(|n| edits.get().is_detached(n)) {
+            return Ok(None);
+        }
         match task {
             Task::Explore(_) => {
                 debug_assert!(false);
-                Ok(Some(self.edits.read()?.clone()))
+                Ok(Some(edits.clone()))
             }
             Task::Reduce(Reduction::Delete(node_id)) => {
                 if edits.get().should_omit_id(node_id) {
@@ -273,27 +557,192 @@ where
                 }
                 Ok(Some(edits.mutate_clone(|e| e.omit_ids(node_ids))))
             }
-            Task::Reduce(Reduction::Replace { node_id, with }) => Ok(Some(
-                edits.mutate_clone(|e| e.replace_id(*node_id, with.clone())),
-            )),
+            Task::Reduce(Reduction::Replace { node_id, with }) => {
+                if edits.get().replacement_id(node_id) == Some(with.as_str()) {
+                    return Ok(None);
+                }
+                Ok(Some(
+                    edits.mutate_clone(|e| e.replace_id(*node_id, with.clone())),
+                ))
+            }
+            Task::Reduce(Reduction::Rename { node_ids, with }) => {
+                Ok(Some(edits.mutate_clone(|e| {
+                    node_ids
+                        .iter()
+                        .fold(e, |e, node_id| e.replace_id(*node_id, with.clone()))
+                })))
+            }
+            Task::Reduce(Reduction::Hoist(node_id, descendant_id)) => {
+                if edits.get().should_hoist_id(node_id) {
+                    return Ok(None);
+                }
+                Ok(Some(
+                    edits.mutate_clone(|e| e.hoist_id(*node_id, *descendant_id)),
+                ))
+            }
+            Task::Reduce(Reduction::Batch(reductions)) => {
+                let node_ids: Vec<NodeId> =
+                    reductions.iter().flat_map(Reduction::node_ids).collect();
+                if node_ids.iter().all(|id| edits.get().should_omit_id(id)) {
+                    return Ok(None);
+                }
+                Ok(Some(edits.mutate_clone(|e| e.omit_ids(&node_ids))))
+            }
+        }
+    }
+
+    /// The pass of the stage that `task` comes from
+    fn pass(&self, task: &Task, nodes: &[Node]) -> Option<Pass> {
+        let literal = |with: &str| {
+            nodes.first().is_some_and(|n| {
+                literal::shrink(n.kind(), &self.orig.text[n.start_byte()..n.end_byte()])
+                    .iter()
+                    .any(|w| w == with)
+            })
+        };
+        let comments = !nodes.is_empty()
+            && nodes
+                .iter()
+                .all(|n| comments::is_comment(n, &self.orig.text, self.transforms));
+        match task {
+            _ if self.tokens_only => Some(Pass::Tokens),
+            Task::Explore(_) => None,
+            Task::Reduce(Reduction::Hoist(..)) => Some(Pass::Hoist),
+            Task::Reduce(Reduction::Rename { .. }) => Some(Pass::Rename),
+            Task::Reduce(Reduction::Replace { with, .. })
+                if self.shrink_literals && (!self.delete || literal(with)) =>
+            {
+                Some(Pass::Literals)
+            }
+            Task::Reduce(Reduction::Replace { .. }) => Some(Pass::Delete),
+            Task::Reduce(_) if self.comments && (!self.delete || comments) => Some(Pass::Comments),
+            Task::Reduce(_) => Some(Pass::Delete),
         }
     }
 
+    /// Update the verdicts of `pass`, if any
+    fn record(
+        &self,
+        pass: Option<Pass>,
+        f: impl FnOnce(&mut Verdicts),
+    ) -> Result<(), ReductionError> {
+        if let Some(pass) = pass {
+            f(self.verdicts.lock()?.entry(pass).or_default());
+        }
+        Ok(())
+    }
+
+    /// Count a run of the check (rather than a cached verdict) for `pass`,
+    /// for the scheduler (see `checks` for the runs themselves)
+    fn ran(&self, pass: Option<Pass>, interesting: bool) -> Result<(), ReductionError> {
+        self.record(pass, |v| {
+            v.checks += 1;
+            v.interesting += usize::from(interesting);
+        })
+    }
+
+    /// `verdict` is `None` if the check is just starting
+    fn notify(&self, ptask: &PrioritizedTask, nodes: &[Node], text: &Rope, verdict: Option<bool>) {
+        match verdict {
+            None => {}
+            Some(true) => self.accepted.fetch_add(1, atomic::Ordering::Relaxed),
+            Some(false) => self.rejected.fetch_add(1, atomic::Ordering::Relaxed),
+        };
+        self.observe(ptask, nodes, text, |o, attempt| match verdict {
+            None => o.started(attempt),
+            Some(true) => o.accepted(attempt),
+            Some(false) => o.rejected(attempt),
+        });
+    }
+
+    /// The check found the candidate interesting, see
+    /// [`Observer::interesting`]
+    fn found(&self, ptask: &PrioritizedTask, nodes: &[Node], text: &Rope) {
+        self.observe(ptask, nodes, text, |o, attempt| o.interesting(attempt));
+    }
+
+    fn observe(
+        &self,
+        ptask: &PrioritizedTask,
+        nodes: &[Node],
+        text: &Rope,
+        f: impl FnOnce(&Observers, &Attempt<'_>),
+    ) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let attempt = Attempt {
+            id: ptask.id.get(),
+            kind: &ptask.task.kind(),
+            description: &describe(&ptask.task, nodes),
+            text,
+            current_size: self.current_size.load(atomic::Ordering::Acquire),
+            pending: self.tasks.heap.read().map(|h| h.len()).unwrap_or(0),
+        };
+        f(&self.observers, &attempt);
+    }
+
+    /// The candidate `rendered` (with `errors` parse errors, if they're
+    /// counted, and of size `measured` by the metric) is now the smallest so
+    /// far
+    fn accept(
+        &self,
+        ptask: &PrioritizedTask,
+        nodes: &[Node],
+        rendered: &Rope,
+        (errors, measured): (Option<usize>, Option<usize>),
+    ) -> Result<(), ReductionError> {
+        let id = ptask.id.get();
+        let kind = ptask.task.kind();
+        let priority = ptask.priority;
+        let size = rendered.len();
+        self.current_size.store(size, atomic::Ordering::Release);
+        if let Some(e) = errors {
+            self.errors.store(e, atomic::Ordering::Release);
+        }
+        if let Some(m) = measured {
+            self.current_metric.store(m, atomic::Ordering::Release);
+        }
+        self.control.reduced_to(size);
+        info!(id, kind, priority, size, "Reduced to size: {}", size);
+        self.notify(ptask, nodes, rendered, Some(true));
+        self.record(self.pass(&ptask.task, nodes), |v| v.accepted += 1)?;
+        debug!(
+            event = "interesting",
+            id,
+            kind,
+            priority,
+            "Interesting {}, new minimal program:\n{}",
+            kind,
+            String::from_utf8_lossy(&rendered.to_vec())
+        );
+        // The other candidates were variations of the previous one
+        self.speculated.lock()?.clear();
+        Ok(())
+    }
+
     /// Check if the given edits yield an interesting tree. If so, and if the
     /// edits haven't been concurrently modified by another call to this
     /// function, replace the edits with the new ones.
-    fn interesting(&self, ptask: &PrioritizedTask) -> Result<Interesting, ReductionError>
+    fn interesting(
+        &self,
+        ptask: &PrioritizedTask,
+        nodes: &[Node],
+    ) -> Result<Interesting, ReductionError>
     where
         T: Check,
     {
         let id = ptask.id.get();
         let kind = ptask.task.kind();
         let priority = ptask.priority;
-        // TODO(lb): Fields?
-        let _span = debug_span!("Trying", id, kind, priority);
+        let span = info_span!("attempt", id, kind, priority, edit = tracing::field::Empty);
+        if !span.is_disabled() {
+            span.record("edit", describe(&ptask.task, nodes).as_str());
+        }
+        let _span = span.entered();
         '_outer: loop {
             let task = &ptask.task;
-            let edits = if let Some(es) = self.add_task_edit(task)? {
+            let edits = if let Some(es) = self.add_task_edit(task, nodes)? {
                 es
             } else {
                 debug!(
@@ -310,26 +759,59 @@ where
             // if !self.edits.read()?.old_version(&edits) {
             //     return Ok(InterestingCheck::TryAgain);
             // }
-            let (_changed, rendered) = self.render(edits.get())?;
+            let rendered = self.render(edits.get());
 
             // For debugging:
             // let s = std::str::from_utf8(&rendered).unwrap();
             // eprintln!("{}", s);
 
-            // TODO(lb): Don't introduce parse errors
-            // let reparsed = self.parse(&rendered);
-            // assert!({
-            //     if reparsed.root_node().has_error() {
-            //         self.orig.tree.root_node().has_error()
-            //     } else {
-            //         true
-            //     }
-            // });
+            let measured = self.measure(&rendered.to_vec());
+            if self.new_errors(measured.0) {
+                debug!(id, kind, priority, "New parse errors in {}", ptask);
+                self.notify(ptask, nodes, &rendered, Some(false));
+                return Ok(Interesting::No);
+            }
+            if self.no_progress(measured.1) {
+                debug!(id, kind, priority, "Not smaller by the metric: {}", ptask);
+                self.notify(ptask, nodes, &rendered, Some(false));
+                return Ok(Interesting::No);
+            }
 
             // Wait for the process to finish, exit early (try this reduction again)
             // if another thread beat us to it.
 
-            let state = self.check.start(&rendered)?;
+            let speculated = if self.deterministic.is_some() {
+                self.speculated.lock()?.remove(&rendered.to_vec())
+            } else {
+                None
+            };
+            if let Some((interesting, ran)) = speculated {
+                if ran {
+                    self.ran(self.pass(task, nodes), interesting)?;
+                }
+                self.notify(ptask, nodes, &rendered, None);
+                if interesting {
+                    self.found(ptask, nodes, &rendered);
+                    let mut w = self.edits.write()?;
+                    if !w.current().old_version(&edits) {
+                        continue;
+                    }
+                    w.push(edits);
+                    self.accept(ptask, nodes, &rendered, measured)?;
+                    return Ok(Interesting::Yes);
+                }
+                self.notify(ptask, nodes, &rendered, Some(false));
+                return Ok(Interesting::No);
+            }
+
+            if !self.control.may_check() {
+                debug!(id, kind, priority, "Out of checks, skipping {}", ptask);
+                return Ok(Interesting::Stale);
+            }
+            let slot = self.oracle_slots.acquire()?;
+            self.notify(ptask, nodes, &rendered, None);
+            let state = self.check.start_rope(&rendered)?;
+            let ran = !self.check.is_cached(&state);
 
             // TODO(lb): Why is this slow?
             // while self.check.try_wait(&mut state)?.is_none() {
@@ -353,11 +835,17 @@ where
 
             let interesting: bool;
             {
-                let _span = debug_span!("Waiting for command", id = id);
-                interesting = self.check.wait(state)?;
+                let _span = debug_span!("Waiting for command", id = id).entered();
+                interesting = self.control.verdict(self.check.wait(state)?);
+            }
+            drop(slot);
+            if ran {
+                self.checks.fetch_add(1, atomic::Ordering::Relaxed);
+                self.ran(self.pass(task, nodes), interesting)?;
             }
 
             if interesting {
+                self.found(ptask, nodes, &rendered);
                 match self.edits.try_write() {
                     Err(_) => {
                         debug!(
@@ -371,23 +859,17 @@ where
                         continue;
                     }
                     Ok(mut w) => {
-                        let _span = debug_span!("Saving edits", id = id);
-                        if !w.old_version(&edits) {
+                        let _span = debug_span!("Saving edits", id = id).entered();
+                        if !w.current().old_version(&edits) {
+                            if let Some(stale) = self.stale(&w, ptask, nodes, &edits) {
+                                drop(w);
+                                return self.merge(stale);
+                            }
                             debug!(event = "retry", id, kind, priority, "Retrying {}", ptask);
                             continue;
                         }
-                        *w = edits;
-                        let size = rendered.len();
-                        info!(id, kind, priority, size, "Reduced to size: {}", size);
-                        debug!(
-                            event = "interesting",
-                            id,
-                            kind,
-                            priority,
-                            "Interesting {}, new minimal program:\n{}",
-                            kind,
-                            std::str::from_utf8(&rendered).unwrap_or("<not UTF-8>")
-                        );
+                        w.push(edits);
+                        self.accept(ptask, nodes, &rendered, measured)?;
                         return Ok(Interesting::Yes);
                     }
                 }
@@ -396,10 +878,169 @@ where
                     event = "uninteresting",
                     id, kind, priority, "Uninteresting {}", ptask
                 );
+                self.notify(ptask, nodes, &rendered, Some(false));
                 return Ok(Interesting::No);
             }
         }
     }
+
+    /// If the interesting `edits` (for `ptask`) were found on an old version
+    /// of the accepted edits, and don't conflict with the ones accepted since,
+    /// the reduction to merge with the other stale ones
+    fn stale(
+        &self,
+        history: &History<Edits>,
+        ptask: &PrioritizedTask,
+        nodes: &[Node],
+        edits: &Versioned<Edits>,
+    ) -> Option<merge::Stale> {
+        // Merged candidates are checked in whatever order they turn up
+        if self.deterministic.is_some() {
+            return None;
+        }
+        // Accepting these explores more of the tree
+        if matches!(
+            ptask.task,
+            Task::Explore(_) | Task::Reduce(Reduction::Hoist(..))
+        ) {
+            return None;
+        }
+        let base = edits.parent()?;
+        let since = history.diff(base, history.current().version())?;
+        let since = merge::Intervals::new(
+            since
+                .byte_edits(&self.orig.text, &self.orig.tree)
+                .into_iter()
+                .map(|(r, _)| r),
+        );
+        let ranges = merge::Intervals::new(nodes.iter().map(Node::byte_range));
+        if since.overlaps(&ranges) {
+            return None;
+        }
+        Some(merge::Stale {
+            ptask: ptask.clone(),
+            edits: edits.get().difference(history.get(base)?),
+            ranges,
+        })
+    }
+
+    /// Queue `stale` to be tried on the current version of the edits, along
+    /// with the other stale reductions that don't conflict with it or each
+    /// other, in one check. Once there are two, this thread checks batches
+    /// until none are left (unless another thread is already doing so).
+    /// Reductions that aren't accepted are put back as tasks, to be tried on
+    /// their own, as are those that find nothing to merge with (see
+    /// [`Ctx::flush_stale`]).
+    fn merge(&self, stale: merge::Stale) -> Result<Interesting, ReductionError> {
+        let Some(mut batch) = self.merges.lock()?.add(stale) else {
+            return Ok(Interesting::Stale);
+        };
+        loop {
+            if !self.try_merged(&batch)? {
+                self.tasks
+                    .restore(batch.into_iter().map(|s| s.ptask).collect())?;
+            }
+            let mut merges = self.merges.lock()?;
+            if merges.waiting.is_empty() || self.control.is_stopped() {
+                merges.merging = false;
+                let rest = std::mem::take(&mut merges.waiting);
+                self.tasks
+                    .restore(rest.into_iter().map(|s| s.ptask).collect())?;
+                return Ok(Interesting::Stale);
+            }
+            batch = merges.take(&mut merge::Intervals::default());
+        }
+    }
+
+    /// Put back the stale reductions that are waiting to be merged as tasks,
+    /// unless a thread is merging (and will take them next). Returns whether
+    /// there were any.
+    fn flush_stale(&self) -> Result<bool, ReductionError> {
+        let rest = self.merges.lock()?.flush();
+        let flushed = !rest.is_empty();
+        self.tasks
+            .restore(rest.into_iter().map(|s| s.ptask).collect())?;
+        Ok(flushed)
+    }
+
+    /// Check the current edits with those of `batch`, and accept them if
+    /// they're interesting (and still current)
+    fn try_merged(&self, batch: &[merge::Stale]) -> Result<bool, ReductionError> {
+        let ptask = &batch[0].ptask;
+        let (id, kind, priority) = (ptask.id.get(), ptask.task.kind(), ptask.priority);
+        let edits = {
+            let history = self.edits.read()?;
+            history
+                .current()
+                .mutate_clone(|e| batch.iter().fold(e, |e, s| e.merge(&s.edits)))
+        };
+        let rendered = self.render(edits.get());
+        let measured = self.measure(&rendered.to_vec());
+        if self.new_errors(measured.0) || self.no_progress(measured.1) {
+            return Ok(false);
+        }
+        if !self.control.may_check() {
+            return Ok(false);
+        }
+        let slot = self.oracle_slots.acquire()?;
+        for s in batch {
+            self.notify(&s.ptask, &[], &rendered, None);
+        }
+        let state = self.check.start_rope(&rendered)?;
+        let ran = !self.check.is_cached(&state);
+        let interesting = self.control.verdict(self.check.wait(state)?);
+        drop(slot);
+        if ran {
+            self.checks.fetch_add(1, atomic::Ordering::Relaxed);
+            // Once for each pass with a reduction in the batch
+            let mut passes = HashSet::new();
+            for s in batch {
+                if let Some(pass) = self.pass(&s.ptask.task, &[]) {
+                    if passes.insert(pass) {
+                        self.ran(Some(pass), interesting)?;
+                    }
+                }
+            }
+        }
+        if !interesting {
+            debug!(id, kind, priority, "Uninteresting merge of {}", batch.len());
+            for s in batch {
+                self.notify(&s.ptask, &[], &rendered, Some(false));
+            }
+            return Ok(false);
+        }
+        self.found(ptask, &[], &rendered);
+        let mut w = self.edits.write()?;
+        if !w.current().old_version(&edits) {
+            return Ok(false);
+        }
+        w.push(edits);
+        debug!(id, kind, priority, "Merged {} reductions", batch.len());
+        self.accept(ptask, &[], &rendered, measured)?;
+        for s in &batch[1..] {
+            self.notify(&s.ptask, &[], &rendered, Some(true));
+        }
+        Ok(true)
+    }
+}
+
+/// A short description of a reduction, e.g., ``delete `statement` at 10..25``
+fn describe(task: &Task, nodes: &[Node]) -> String {
+    let mut desc = task.kind();
+    match nodes {
+        [] => {}
+        [node] => desc.push_str(&format!(
+            " `{}` at {}..{}",
+            node.kind(),
+            node.start_byte(),
+            node.end_byte()
+        )),
+        _ => desc.push_str(&format!(" {} nodes", nodes.len())),
+    }
+    if let Task::Reduce(Reduction::Replace { with, .. } | Reduction::Rename { with, .. }) = task {
+        desc.push_str(&format!(" with `{}`", with));
+    }
+    desc
 }
 
 // TODO(#15): Refine with access to node-types.json
@@ -413,21 +1054,97 @@ fn explore<T: Check + Send + Sync>(
 ) -> Result<(), ReductionError> {
     // TODO(lb): Include kind in explore task to avoid find
     let node = tctx.find(&node_id);
-    let _span = debug_span!("Exploring", id = node_id.get());
+    let _span = debug_span!("Exploring", id = node_id.get()).entered();
     debug!("Exploring {}...", tctx.find(&node_id).kind());
-    if let Some(replaces) = tctx.ctx.replacements.get(node.kind()) {
-        // TODO(lb): Benchmark locking tasks and pushing all at once
-        for replace in *replaces {
-            tctx.ctx.push_task(
-                &node,
-                Task::Reduce(Reduction::Replace {
-                    node_id,
-                    with: String::from(*replace),
-                }),
+    // Replacements for this kind, or for any of its supertypes
+    let mut replaces: Vec<&str> = Vec::new();
+    let kinds = std::iter::once(node.kind()).chain(
+        tctx.ctx
+            .node_types
+            .supertypes(node.kind())
+            .iter()
+            .map(String::as_str),
+    );
+    for kind in kinds {
+        for replace in tctx.ctx.replacements.get(kind).copied().unwrap_or_default() {
+            if !replaces.contains(replace) {
+                replaces.push(*replace);
+            }
+        }
+    }
+    if !tctx.ctx.delete {
+        replaces.clear();
+    }
+    // TODO(lb): Benchmark locking tasks and pushing all at once
+    for replace in &replaces {
+        tctx.ctx.push_task(
+            &node,
+            Task::Reduce(Reduction::Replace {
+                node_id,
+                with: String::from(*replace),
+            }),
+        )?;
+    }
+    if tctx.ctx.shrink_literals && node.is_named() {
+        let text = &tctx.ctx.orig.text[node.start_byte()..node.end_byte()];
+        for with in literal::shrink(node.kind(), text) {
+            if replaces.contains(&with.as_str()) {
+                continue;
+            }
+            tctx.ctx.push_weighted_task(
+                Some(node.kind()),
+                node_size(&node).saturating_sub(with.len()),
+                Task::Reduce(Reduction::Replace { node_id, with }),
+            )?;
+        }
+    }
+    if tctx.ctx.delete {
+        let text = &tctx.ctx.orig.text;
+        let mut proposed: Vec<String> = Vec::new();
+        for transform in tctx.ctx.transforms.iter() {
+            for with in transform.replacements(&node, text) {
+                // E.g., a Python function body stub that's also a replacement
+                if replaces.contains(&with.as_str()) || proposed.contains(&with) {
+                    continue;
+                }
+                proposed.push(with.clone());
+                debug!(transform = transform.name(), "Transform: {}", with);
+                tctx.ctx.push_weighted_task(
+                    Some(node.kind()),
+                    node_size(&node).saturating_sub(with.len()),
+                    Task::Reduce(Reduction::Replace { node_id, with }),
+                )?;
+            }
+        }
+    }
+    if tctx.ctx.hoist && node.is_named() {
+        for descendant in tctx.ctx.hoistable(&node) {
+            tctx.ctx.push_weighted_task(
+                Some(node.kind()),
+                node_size(&node) - node_size(&descendant),
+                Task::Reduce(Reduction::Hoist(node_id, NodeId::new(&descendant))),
             )?;
         }
     }
-    if tctx.ctx.node_types.optional_node(&node) || tctx.ctx.delete_non_optional {
+    if !tctx.ctx.delete {
+        tctx.ctx.push_explore_children(node)?;
+    } else if tctx.ctx.node_types.optional_node(&node) || tctx.ctx.delete_non_optional {
+        // Along with deleting the node alone, which explores its children if
+        // it isn't interesting
+        for transform in tctx.ctx.transforms.iter() {
+            let dependents = transform.dependents(&node, &tctx.ctx.orig.text);
+            if dependents.is_empty() {
+                continue;
+            }
+            let size = node_size(&node) + dependents.iter().map(node_size).sum::<usize>();
+            let mut ids = vec![node_id];
+            ids.extend(dependents.iter().map(NodeId::new));
+            tctx.ctx.push_weighted_task(
+                Some(node.kind()),
+                size,
+                Task::Reduce(Reduction::DeleteAll(ids)),
+            )?;
+        }
         tctx.ctx
             .push_task(&node, Task::Reduce(Reduction::Delete(node_id)))?;
     } else {
@@ -447,8 +1164,11 @@ fn explore<T: Check + Send + Sync>(
                         }
                     }
                 }
-                tctx.ctx
-                    .push_prioritized_task(batch_size, Task::Reduce(Reduction::DeleteAll(batch)))?;
+                tctx.ctx.push_weighted_task(
+                    Some(&node_kind),
+                    batch_size,
+                    Task::Reduce(Reduction::DeleteAll(batch)),
+                )?;
             }
         }
         tctx.ctx.push_explore_children(node)?;
@@ -460,15 +1180,20 @@ fn dispatch<T: Check + Send + Sync>(
     tctx: &ThreadCtx<T>,
     ptask: PrioritizedTask,
 ) -> Result<(), ReductionError> {
+    let nodes: Vec<Node> = match &ptask.task {
+        Task::Explore(_) => Vec::new(),
+        Task::Reduce(r) => r.node_ids().iter().map(|id| tctx.find(id)).collect(),
+    };
     match ptask.task {
         Task::Explore(node_id) => explore(tctx, node_id),
         Task::Reduce(Reduction::Delete(node_id)) => {
-            let _span = debug_span!("Reducing", id = node_id.get());
-            match tctx.ctx.interesting(&ptask)? {
+            let _span = debug_span!("Reducing", id = node_id.get()).entered();
+            match tctx.ctx.interesting(&ptask, &nodes)? {
                 Interesting::Yes => {
                     // This tree was deleted, no need to recurse on children
                     Ok(())
                 }
+                Interesting::No if tctx.ctx.tokens_only => Ok(()),
                 Interesting::No => {
                     tctx.ctx.push_explore_children(tctx.find(&node_id))?;
                     Ok(())
@@ -481,12 +1206,12 @@ fn dispatch<T: Check + Send + Sync>(
         Task::Reduce(Reduction::DeleteAll(_)) => {
             // No need to check whether it was interesting, because the children will be
             // individually handled by `delete`.
-            let _ = tctx.ctx.interesting(&ptask);
+            let _ = tctx.ctx.interesting(&ptask, &nodes);
             Ok(())
         }
         Task::Reduce(Reduction::Replace { node_id, .. }) => {
-            let _span = debug_span!("Reducing", id = node_id.get());
-            match tctx.ctx.interesting(&ptask)? {
+            let _span = debug_span!("Reducing", id = node_id.get()).entered();
+            match tctx.ctx.interesting(&ptask, &nodes)? {
                 Interesting::Yes => {
                     // This tree was replaced, no need to recurse on children
                     Ok(())
@@ -498,25 +1223,136 @@ fn dispatch<T: Check + Send + Sync>(
                 Interesting::Stale => Ok(()),
             }
         }
+        Task::Reduce(Reduction::Rename { .. }) => {
+            let _ = tctx.ctx.interesting(&ptask, &nodes)?;
+            Ok(())
+        }
+        Task::Reduce(Reduction::Hoist(node_id, descendant_id)) => {
+            let _span = debug_span!("Reducing", id = node_id.get()).entered();
+            match tctx.ctx.interesting(&ptask, &nodes)? {
+                // Only the descendant remains, keep reducing it
+                Interesting::Yes => explore(tctx, descendant_id),
+                // Other tasks for this node explore its children
+                Interesting::No | Interesting::Stale => Ok(()),
+            }
+        }
+        Task::Reduce(Reduction::Batch(ref reductions)) => {
+            match tctx.ctx.interesting(&ptask, &nodes)? {
+                Interesting::Yes | Interesting::Stale => Ok(()),
+                // Fall back to trying each one (never batching them again)
+                Interesting::No => {
+                    for r in reductions {
+                        let priority = r
+                            .node_ids()
+                            .iter()
+                            .map(|id| node_size(&tctx.find(id)))
+                            .sum();
+                        let task = Task::Reduce(r.clone());
+                        let tiebreak = tctx.ctx.tiebreak(&task);
+                        dispatch(
+                            tctx,
+                            PrioritizedTask {
+                                task,
+                                id: ptask.id,
+                                priority,
+                                rank: ptask.rank,
+                                tiebreak,
+                            },
+                        )?;
+                    }
+                    Ok(())
+                }
+            }
+        }
     }
 }
 
+fn batchable(task: &Task) -> bool {
+    matches!(
+        task,
+        Task::Reduce(Reduction::Delete(_)) | Task::Reduce(Reduction::DeleteAll(_))
+    )
+}
+
+/// Combine `ptask` with the next few deletions if they touch disjoint parts of
+/// the tree, so that they can be tried all at once.
+fn batch<T: Check + Send + Sync>(
+    tctx: &ThreadCtx<T>,
+    ptask: PrioritizedTask,
+) -> Result<PrioritizedTask, ReductionError> {
+    if tctx.ctx.batch <= 1 || !batchable(&ptask.task) {
+        return Ok(ptask);
+    }
+    let ranges = |t: &Task| -> Vec<Range<usize>> {
+        match t {
+            Task::Reduce(r) => r
+                .node_ids()
+                .iter()
+                .map(|id| tctx.find(id))
+                .map(|n| n.start_byte()..n.end_byte())
+                .collect(),
+            Task::Explore(_) => Vec::new(),
+        }
+    };
+    let mut taken = ranges(&ptask.task);
+    let mut batch = vec![ptask];
+    while batch.len() < tctx.ctx.batch {
+        let next = tctx.ctx.tasks.pop_if(|pt| {
+            batchable(&pt.task)
+                && ranges(&pt.task).iter().all(|r| {
+                    !r.is_empty() && taken.iter().all(|t| r.end <= t.start || t.end <= r.start)
+                })
+        })?;
+        match next {
+            None => break,
+            Some(pt) => {
+                taken.extend(ranges(&pt.task));
+                batch.push(pt);
+            }
+        }
+    }
+    if batch.len() == 1 {
+        return Ok(batch.remove(0));
+    }
+    let id = batch[0].id;
+    let priority = batch.iter().map(|pt| pt.priority).sum();
+    let rank = batch[0].rank;
+    let tiebreak = batch[0].tiebreak;
+    let reductions = batch
+        .into_iter()
+        .filter_map(|pt| match pt.task {
+            Task::Reduce(r) => Some(r),
+            Task::Explore(_) => None,
+        })
+        .collect();
+    Ok(PrioritizedTask {
+        task: Task::Reduce(Reduction::Batch(reductions)),
+        id,
+        priority,
+        rank,
+        tiebreak,
+    })
+}
+
 /// Main function for each thread
 fn work<T: Check + Send + Sync>(ctx: &Ctx<T>, num_threads: usize) -> Result<(), ReductionError> {
-    static IDLE_THREADS: AtomicUsize = AtomicUsize::new(0);
     let tctx = ThreadCtx::new(ctx);
     let mut idle = false;
     // Quit if all threads are idle and there are no remaining tasks
-    while IDLE_THREADS.load(atomic::Ordering::Acquire) < num_threads {
+    while ctx.idle_threads.load(atomic::Ordering::Acquire) < num_threads {
         if idle {
             // TODO(lb): Integrate waiting into pop?
             // TODO(lb): Benchmark the duration
             // let point_o_one_seconds = Duration::new(0, 10000000);
             let not_long = Duration::new(0, 100000);
             tctx.ctx.tasks.wait_for_push(not_long)?;
-            IDLE_THREADS.fetch_sub(1, atomic::Ordering::Release);
+            ctx.idle_threads.fetch_sub(1, atomic::Ordering::Release);
         }
         while let Some(ptask) = tctx.ctx.pop_task()? {
+            if !ctx.control.proceed() {
+                // Drop the rest of the tasks, so that the pass ends
+                continue;
+            }
             debug!(
                 id = ptask.id.get(),
                 kind = ptask.task.kind(),
@@ -524,9 +1360,15 @@ fn work<T: Check + Send + Sync>(ctx: &Ctx<T>, num_threads: usize) -> Result<(),
                 "Popped {}",
                 ptask
             );
+            let ptask = batch(&tctx, ptask)?;
             dispatch(&tctx, ptask)?;
         }
-        let num_idle = IDLE_THREADS.fetch_add(1, atomic::Ordering::Release);
+        // Stale reductions with nothing to merge with are tried alone
+        if ctx.flush_stale()? {
+            idle = false;
+            continue;
+        }
+        let num_idle = ctx.idle_threads.fetch_add(1, atomic::Ordering::Release);
         debug!(
             idle = num_idle + 1,
             threads = num_threads,
@@ -539,57 +1381,720 @@ fn work<T: Check + Send + Sync>(ctx: &Ctx<T>, num_threads: usize) -> Result<(),
     Ok(())
 }
 
+/// Check the candidates of the next few tasks at once, assuming that none of
+/// them are accepted in the meantime
+fn speculate<T: Check + Send + Sync>(
+    tctx: &ThreadCtx<T>,
+    jobs: usize,
+) -> Result<(), ReductionError> {
+    let ctx = tctx.ctx;
+    let mut next = Vec::new();
+    while next.len() < jobs {
+        // Exploring may push tasks that come before the rest
+        match ctx.tasks.pop_if(|pt| matches!(pt.task, Task::Reduce(_)))? {
+            None => break,
+            Some(pt) => next.push(pt),
+        }
+    }
+    let mut candidates = Vec::new();
+    {
+        let speculated = ctx.speculated.lock()?;
+        for pt in &next {
+            let nodes: Vec<Node> = match &pt.task {
+                Task::Reduce(r) => r.node_ids().iter().map(|id| tctx.find(id)).collect(),
+                Task::Explore(_) => continue,
+            };
+            if let Some(edits) = ctx.add_task_edit(&pt.task, &nodes)? {
+                let text = ctx.render(edits.get()).to_vec();
+                let (errors, measured) = ctx.measure(&text);
+                if ctx.new_errors(errors) || ctx.no_progress(measured) {
+                    continue;
+                }
+                if !speculated.contains_key(&text) && !candidates.contains(&text) {
+                    candidates.push(text);
+                }
+            }
+        }
+    }
+    ctx.tasks.restore(next)?;
+    if candidates.len() <= 1 {
+        // Not worth another thread
+        return Ok(());
+    }
+    let verdicts = thread::scope(|s| -> Result<Vec<_>, ReductionError> {
+        let checks: Vec<_> = candidates
+            .into_iter()
+            .map(|text| {
+                s.spawn(move || -> Result<_, ReductionError> {
+                    let _slot = ctx.oracle_slots.acquire()?;
+                    // Checked once there's a slot, after the earlier runs
+                    if !ctx.control.may_check() {
+                        return Ok(None);
+                    }
+                    let state = ctx.check.start(&text)?;
+                    let ran = !ctx.check.is_cached(&state);
+                    let interesting = ctx.control.verdict(ctx.check.wait(state)?);
+                    if ran {
+                        // Whether or not the verdict is used
+                        ctx.checks.fetch_add(1, atomic::Ordering::Relaxed);
+                    }
+                    Ok(Some((text, (interesting, ran))))
+                })
+            })
+            .collect();
+        checks
+            .into_iter()
+            .map(|c| c.join().expect("Worker thread panicked"))
+            .collect()
+    })?;
+    ctx.speculated
+        .lock()?
+        .extend(verdicts.into_iter().flatten());
+    Ok(())
+}
+
+/// Like [`work`], but tries each task in turn, so that the result doesn't
+/// depend on which check finishes first. Checks still run in parallel, see
+/// [`speculate`].
+fn work_deterministic<T: Check + Send + Sync>(
+    ctx: &Ctx<T>,
+    jobs: usize,
+) -> Result<(), ReductionError> {
+    let tctx = ThreadCtx::new(ctx);
+    loop {
+        if ctx.control.proceed() {
+            speculate(&tctx, jobs)?;
+        }
+        match ctx.pop_task()? {
+            None => break,
+            // Drop the rest of the tasks, so that the pass ends
+            Some(_) if !ctx.control.proceed() => {}
+            Some(ptask) => {
+                let ptask = batch(&tctx, ptask)?;
+                dispatch(&tctx, ptask)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How to search for reductions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Algorithm {
+    /// Run the pipeline, trying the most promising reductions first
+    #[default]
+    Treereduce,
+    /// Hierarchical delta debugging: delete nodes level by level with ddmin,
+    /// ignoring the pipeline
+    Hdd,
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Algorithm::Treereduce => write!(f, "treereduce"),
+            Algorithm::Hdd => write!(f, "hdd"),
+        }
+    }
+}
+
+/// How to order the stages of the pipeline in each round
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Schedule {
+    /// Run every stage, in order
+    #[default]
+    Static,
+    /// After each stage has run once, run the stages whose passes found the
+    /// most of their candidates interesting first (counting runs of the
+    /// check, not cached verdicts), and leave out passes that found nothing
+    /// until the input changes (skipping stages with none left)
+    Adaptive,
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::Static => write!(f, "static"),
+            Schedule::Adaptive => write!(f, "adaptive"),
+        }
+    }
+}
+
+/// How to measure the size of a candidate
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Metric {
+    #[default]
+    Bytes,
+    /// Leaves of the syntax tree
+    Tokens,
+    /// Named nodes of the syntax tree
+    Nodes,
+    /// Lines that aren't blank
+    Lines,
+}
+
+/// Number of lines in `text` that aren't blank
+fn lines(text: &[u8]) -> usize {
+    text.split(|b| *b == b'\n')
+        .filter(|l| !l.iter().all(u8::is_ascii_whitespace))
+        .count()
+}
+
+impl Metric {
+    fn needs_tree(&self) -> bool {
+        matches!(self, Metric::Tokens | Metric::Nodes)
+    }
+
+    /// The size of `text`, which parses to `tree`
+    pub fn measure(&self, tree: &Tree, text: &[u8]) -> usize {
+        match self {
+            Metric::Bytes => text.len(),
+            Metric::Lines => lines(text),
+            Metric::Tokens | Metric::Nodes => {
+                let mut count = 0;
+                let mut cursor = tree.walk();
+                let mut stack = vec![tree.root_node()];
+                while let Some(node) = stack.pop() {
+                    let counts = match self {
+                        Metric::Tokens => node.child_count() == 0 && node_size(&node) > 0,
+                        _ => node.is_named(),
+                    };
+                    count += usize::from(counts);
+                    stack.extend(node.children(&mut cursor));
+                }
+                count
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::Bytes => write!(f, "bytes"),
+            Metric::Tokens => write!(f, "tokens"),
+            Metric::Nodes => write!(f, "nodes"),
+            Metric::Lines => write!(f, "lines"),
+        }
+    }
+}
+
+/// The order in which to try reductions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Priority {
+    /// Largest first
+    #[default]
+    Size,
+    /// Deepest first, then largest
+    Depth,
+    /// Shallowest first, then largest
+    Breadth,
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Priority::Size => write!(f, "size"),
+            Priority::Depth => write!(f, "depth"),
+            Priority::Breadth => write!(f, "breadth"),
+        }
+    }
+}
+
+fn depths(tree: &Tree) -> HashMap<NodeId, usize> {
+    let mut depths = HashMap::new();
+    let mut stack = vec![(tree.root_node(), 0)];
+    while let Some((node, depth)) = stack.pop() {
+        depths.insert(NodeId::new(&node), depth);
+        stack.extend(node.children(&mut node.walk()).map(|c| (c, depth + 1)));
+    }
+    depths
+}
+
+/// Chunks of the children of an `ERROR` node, given the rows they start on:
+/// chunks of whole lines, each line, and chunks of children regardless of
+/// lines, as in delta debugging.
+fn error_chunks(rows: &[usize]) -> Vec<Range<usize>> {
+    let mut lines: Vec<Range<usize>> = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        match lines.last_mut() {
+            Some(line) if rows[line.start] == *row => line.end = i + 1,
+            _ => lines.push(i..i + 1),
+        }
+    }
+    let mut chunks: Vec<Range<usize>> = ddmin::chunks(lines.len())
+        .into_iter()
+        .map(|c| lines[c.start].start..lines[c.end - 1].end)
+        .collect();
+    chunks.extend(lines.iter().filter(|l| l.len() > 1).cloned());
+    chunks.extend(ddmin::chunks(rows.len()));
+    chunks.sort_by_key(|c| (c.start, c.end));
+    chunks.dedup();
+    chunks
+}
+
+/// Number of `ERROR` and `MISSING` nodes
+pub(crate) fn count_errors(tree: &Tree) -> usize {
+    let root = tree.root_node();
+    if !root.has_error() {
+        return 0;
+    }
+    let mut errors = 0;
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.is_error() || node.is_missing() {
+            errors += 1;
+        }
+        // Only subtrees with errors can contain them
+        stack.extend(node.children(&mut node.walk()).filter(|c| c.has_error()));
+    }
+    errors
+}
+
+fn positions(tree: &Tree) -> HashMap<NodeId, usize> {
+    let mut positions = HashMap::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        positions.insert(NodeId::new(&node), positions.len());
+        let children: Vec<Node> = node.children(&mut node.walk()).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    positions
+}
+
+/// How to reduce, see [`crate::Reducer`], which builds one. New fields may
+/// be added, so it can't be built outside of this crate.
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct Config<T> {
     pub check: T,
     pub delete_non_optional: bool,
+    /// Re-run the whole pipeline until a round makes no progress
+    pub fixpoint: bool,
+    /// Number of worker threads
     pub jobs: usize,
+    /// Maximum number of concurrent interestingness checks; 0 means `jobs`
+    pub oracle_jobs: usize,
+    /// Also take a token from this jobserver for each concurrent
+    /// interestingness check past the first
+    pub jobserver: Option<Arc<Jobserver>>,
     // TODO(lb): Maybe per-pass, benchmark
     pub min_reduction: usize,
+    /// Try up to this many independent deletions at once, falling back to
+    /// trying them one at a time
+    pub batch: usize,
+    /// Only reduce nodes captured by this tree-sitter query (and their
+    /// descendants)
+    pub only_query: Option<String>,
+    pub pipeline: Pipeline,
+    pub priority: Priority,
+    /// Regions that must not be changed
+    pub preserve: Vec<Preserve>,
+    /// Never delete comments (or docstrings) matching this
+    pub keep_comments: Option<Regex>,
+    /// Names for the rename pass to use first, in order, e.g., from a
+    /// template
+    pub names: Vec<String>,
     pub replacements: HashMap<&'static str, &'static [&'static str]>,
+    /// Scale the priorities (sizes) of reductions of nodes of these kinds,
+    /// e.g., by 2 to try them sooner, or by 0 to try them only after all
+    /// others
+    pub weights: HashMap<String, f64>,
+    /// Never change nodes captured by this tree-sitter query
+    pub skip_query: Option<String>,
+    pub observers: Observers,
+    /// Additional replacements to try, with the delete pass
+    pub transforms: Transforms,
+    /// Skip the passes before this point, e.g., to resume from a checkpoint
+    pub resume: Option<Position>,
+    /// Pause, skip passes, or stop early
+    pub control: Arc<Control>,
+    /// Reject candidates with more `ERROR` or `MISSING` nodes than the
+    /// smallest interesting one so far, without checking them
+    pub no_new_errors: bool,
+    /// Finally, delete any remaining node (that the delete pass would try) if
+    /// the result is still interesting without it, until there are none
+    pub one_minimal: bool,
+    /// After each pass, check its result again (without the cache), and back
+    /// out of the pass's accepted edits until it's interesting, in case the
+    /// check's behavior drifted
+    pub verify_passes: bool,
+    /// Try tasks in a fixed order, breaking ties with this seed, so that runs
+    /// with the same input and (deterministic) check have the same result
+    pub deterministic: Option<u64>,
+    /// Before the first round, cheaply cut lines from the start and end
+    /// of the input, without parsing it
+    pub bisect: bool,
+    pub algorithm: Algorithm,
+    pub schedule: Schedule,
+    /// What to minimize: only candidates that are smaller by this metric are
+    /// accepted
+    pub metric: Metric,
+    /// Afterwards, reduce regions in other languages with their grammars
+    pub injections: Option<Injections>,
 }
 
+impl<T> Config<T> {
+    /// The same options, with a different interestingness check
+    pub fn with_check<U>(self, check: U) -> Config<U> {
+        Config {
+            check,
+            delete_non_optional: self.delete_non_optional,
+            fixpoint: self.fixpoint,
+            jobs: self.jobs,
+            oracle_jobs: self.oracle_jobs,
+            jobserver: self.jobserver,
+            min_reduction: self.min_reduction,
+            batch: self.batch,
+            only_query: self.only_query,
+            pipeline: self.pipeline,
+            priority: self.priority,
+            preserve: self.preserve,
+            keep_comments: self.keep_comments,
+            names: self.names,
+            replacements: self.replacements,
+            weights: self.weights,
+            skip_query: self.skip_query,
+            observers: self.observers,
+            transforms: self.transforms,
+            resume: self.resume,
+            control: self.control,
+            no_new_errors: self.no_new_errors,
+            one_minimal: self.one_minimal,
+            verify_passes: self.verify_passes,
+            deterministic: self.deterministic,
+            bisect: self.bisect,
+            algorithm: self.algorithm,
+            schedule: self.schedule,
+            metric: self.metric,
+            injections: self.injections,
+        }
+    }
+}
+
+/// Run one iteration of the passes in `stage`.
+///
+/// The tokens pass tries deleting each token (leaf), regardless of whether
+/// it's optional.
 pub fn treereduce<T: Check + Debug + Send + Sync + 'static>(
     node_types: &NodeTypes,
     orig: Original,
     conf: &Config<T>,
+    stage: &Stage,
 ) -> Result<(Original, Edits), ReductionError> {
+    let preserved = preserved_ranges(conf, &orig.text);
+    let (orig, edits, _, _) = reduce(node_types, orig, conf, stage, &preserved)?;
+    Ok((orig, edits))
+}
+
+fn preserved_ranges<T>(conf: &Config<T>, text: &[u8]) -> Vec<Range<usize>> {
+    conf.preserve.iter().filter_map(|p| p.bytes(text)).collect()
+}
+
+/// Nodes captured by these queries must not be changed
+fn skip_queries<T>(conf: &Config<T>) -> Vec<&str> {
+    conf.skip_query
+        .iter()
+        .map(String::as_str)
+        .chain(conf.preserve.iter().filter_map(|p| match p {
+            Preserve::Query(q) => Some(q.as_str()),
+            _ => None,
+        }))
+        .collect()
+}
+
+/// Find a node that the delete pass would try deleting, but that can still be
+/// deleted without making the input uninteresting
+fn one_minimal_violation<T: Check>(
+    node_types: &NodeTypes,
+    orig: &Original,
+    conf: &Config<T>,
+    preserved: &[Range<usize>],
+) -> Result<Option<Edits>, ReductionError> {
+    let targets = target::Targets::new(
+        &orig.tree,
+        &orig.text,
+        conf.only_query.as_deref(),
+        &skip_queries(conf),
+        preserved,
+    )?;
+    let errors = count_errors(&orig.tree);
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(orig.tree.language())
+        .expect("Failed to set tree-sitter parser language");
+    let mut stack = vec![orig.tree.root_node()];
+    while let Some(node) = stack.pop() {
+        stack.extend(node.children(&mut node.walk()));
+        if node_size(&node) == 0
+            || !targets.allows(&[NodeId::new(&node)])
+            || !(conf.delete_non_optional || node_types.optional_node(&node))
+        {
+            continue;
+        }
+        let edits = Edits::new().omit(&node);
+        let text = edits.render_rope(&orig.text, &orig.tree).to_vec();
+        if conf.no_new_errors
+            && parser
+                .parse(&text, None)
+                .map(|t| count_errors(&t) > errors)
+                .unwrap_or(true)
+        {
+            continue;
+        }
+        if !conf.control.may_check() {
+            return Ok(None);
+        }
+        if conf.control.verdict(conf.check.interesting(&text)?) {
+            warn!(
+                "Deleting `{}` at {}..{} is still interesting",
+                node.kind(),
+                node.start_byte(),
+                node.end_byte()
+            );
+            return Ok(Some(edits));
+        }
+    }
+    Ok(None)
+}
+
+fn reduce<T: Check + Debug + Send + Sync + 'static>(
+    node_types: &NodeTypes,
+    orig: Original,
+    conf: &Config<T>,
+    stage: &Stage,
+    preserved: &[Range<usize>],
+) -> Result<
+    (
+        Original,
+        Edits,
+        (usize, usize, usize),
+        HashMap<Pass, Verdicts>,
+    ),
+    ReductionError,
+> {
+    let tokens_only = stage.has(Pass::Tokens);
     if orig.text.is_empty() {
-        return Ok((orig, Edits::new()));
+        return Ok((orig, Edits::new(), (0, 0, 0), HashMap::new()));
     }
 
-    let _span = debug_span!("Pass");
     info!("Original size: {}", orig.text.len());
     // eprintln!("{}", orig.tree.root_node().to_sexp());
     // TODO(#25): SIGHUP handler to save intermediate progress
     let jobs = std::cmp::max(1, conf.jobs);
-    let min_reduction = std::cmp::max(1, conf.min_reduction);
-    let tasks = Tasks::new();
-    let root = orig.tree.root_node();
-    let root_id = NodeId::new(&root);
-    tasks.push(Task::Explore(root_id), node_size(&root))?;
+    let min_reduction = if tokens_only {
+        1
+    } else {
+        std::cmp::max(1, conf.min_reduction)
+    };
+    let skip = skip_queries(conf);
+    let mut preserved = preserved.to_vec();
+    if let Some(keep) = &conf.keep_comments {
+        preserved.extend(comments::kept(
+            orig.tree.root_node(),
+            &orig.text,
+            keep,
+            &conf.transforms,
+        ));
+    }
+    let targets = target::Targets::new(
+        &orig.tree,
+        &orig.text,
+        conf.only_query.as_deref(),
+        &skip,
+        &preserved,
+    )?;
+    let depths = match conf.priority {
+        Priority::Size => HashMap::new(),
+        Priority::Depth | Priority::Breadth => depths(&orig.tree),
+    };
+    let positions = match conf.deterministic {
+        None => HashMap::new(),
+        Some(_) => positions(&orig.tree),
+    };
+    let errors = if conf.no_new_errors {
+        count_errors(&orig.tree)
+    } else {
+        0
+    };
+    let orig_size = orig.text.len();
     let ctx = Ctx {
+        delete: stage.has(Pass::Delete),
         delete_non_optional: conf.delete_non_optional,
+        hoist: stage.has(Pass::Hoist),
+        shrink_literals: stage.has(Pass::Literals),
+        comments: stage.has(Pass::Comments),
+        tokens_only,
         node_types,
-        tasks,
-        edits: RwLock::new(Versioned::new(Edits::new())),
+        tasks: Tasks::new(),
+        edits: RwLock::new(History::new(Versioned::new(Edits::new()), EDIT_HISTORY)),
+        merges: Mutex::new(merge::Merges::default()),
         orig,
         check: &conf.check,
         min_task_size: min_reduction,
+        batch: conf.batch,
+        priority: conf.priority,
+        depths,
+        deterministic: conf.deterministic,
+        positions,
+        speculated: Mutex::new(HashMap::new()),
+        no_new_errors: conf.no_new_errors,
+        errors: AtomicUsize::new(errors),
+        metric: conf.metric,
+        current_metric: AtomicUsize::new(conf.metric.measure(&orig.tree, &orig.text)),
+        oracle_slots: slots::Slots::new(
+            if conf.oracle_jobs == 0 {
+                jobs
+            } else {
+                conf.oracle_jobs
+            },
+            conf.jobserver.clone(),
+        ),
+        idle_threads: AtomicUsize::new(0),
         replacements: &conf.replacements,
+        weights: &conf.weights,
+        targets,
+        observers: &conf.observers,
+        transforms: &conf.transforms,
+        control: &conf.control,
+        current_size: AtomicUsize::new(orig_size),
+        accepted: AtomicUsize::new(0),
+        rejected: AtomicUsize::new(0),
+        checks: AtomicUsize::new(0),
+        verdicts: Mutex::new(HashMap::new()),
     };
 
-    thread::scope(|s| {
-        s.spawn(|| work(&ctx, jobs));
-    });
+    let root = ctx.orig.tree.root_node();
+    if tokens_only {
+        ctx.tasks.push_all(
+            tokens::tokens(root)
+                .into_iter()
+                .filter(|t| node_size(t) > 0 && ctx.targets.allows(&[NodeId::new(t)]))
+                .map(|t| {
+                    let task = Task::Reduce(Reduction::Delete(NodeId::new(&t)));
+                    let rank = ctx.rank(&task);
+                    let tiebreak = ctx.tiebreak(&task);
+                    let priority = ctx.weigh(Some(t.kind()), node_size(&t));
+                    (task, priority, rank, tiebreak)
+                }),
+        )?;
+    } else if [Pass::Delete, Pass::Hoist, Pass::Literals]
+        .iter()
+        .any(|p| stage.has(*p))
+    {
+        let task = Task::Explore(NodeId::new(&root));
+        let rank = ctx.rank(&task);
+        let tiebreak = ctx.tiebreak(&task);
+        ctx.tasks.push(task, node_size(&root), rank, tiebreak)?;
+    }
+
+    if stage.has(Pass::Comments) {
+        // All at once first, since they rarely matter
+        let comments: Vec<Node> = comments::comments(root, &ctx.orig.text, ctx.transforms)
+            .into_iter()
+            .filter(|c| ctx.targets.allows(&[NodeId::new(c)]))
+            .collect();
+        if comments.len() > 1 {
+            let ids: Vec<NodeId> = comments.iter().map(NodeId::new).collect();
+            let size = comments.iter().map(node_size).sum();
+            ctx.push_prioritized_task(size, Task::Reduce(Reduction::DeleteAll(ids)))?;
+        }
+        for c in &comments {
+            ctx.push_weighted_task(
+                Some(c.kind()),
+                node_size(c),
+                Task::Reduce(Reduction::Delete(NodeId::new(c))),
+            )?;
+        }
+    }
+
+    if stage.has(Pass::Rename) {
+        for r in rename::renames(root, &ctx.orig.text, &conf.names) {
+            ctx.push_prioritized_task(
+                r.savings,
+                Task::Reduce(Reduction::Rename {
+                    node_ids: r.node_ids,
+                    with: r.with,
+                }),
+            )?;
+        }
+    }
+
+    if jobs == 1 {
+        // Some targets (e.g., wasm32-unknown-unknown) can't spawn threads
+        work(&ctx, 1)?;
+    } else if conf.deterministic.is_some() {
+        work_deterministic(&ctx, jobs)?;
+    } else {
+        // So that events in workers are in the span of the pass
+        let span = tracing::Span::current();
+        thread::scope(|s| -> Result<(), ReductionError> {
+            let workers: Vec<_> = (0..jobs)
+                .map(|_| {
+                    s.spawn(|| {
+                        let _span = span.enter();
+                        work(&ctx, jobs)
+                    })
+                })
+                .collect();
+            for worker in workers {
+                worker.join().expect("Worker thread panicked")?;
+            }
+            Ok(())
+        })?;
+    }
 
     debug_assert!(ctx.tasks.heap.read()?.is_empty());
-    let edits = ctx.edits.read()?.clone();
-    Ok((ctx.orig, edits.extract()))
+    if conf.verify_passes && !conf.control.is_stopped() {
+        roll_back(&ctx)?;
+    }
+    let edits = ctx.edits.read()?.current().clone();
+    let verdicts = (
+        ctx.accepted.into_inner(),
+        ctx.rejected.into_inner(),
+        ctx.checks.into_inner(),
+    );
+    let passes = ctx.verdicts.into_inner().unwrap_or_else(|e| e.into_inner());
+    Ok((ctx.orig, edits.extract(), verdicts, passes))
+}
+
+/// Back out of the latest accepted edits until the result is interesting
+/// again, or of all of them if none of the versions kept are
+fn roll_back<T: Check + Send + Sync>(ctx: &Ctx<T>) -> Result<(), ReductionError> {
+    let mut history = ctx.edits.write()?;
+    let mut back = 0;
+    for version in history.versions().rev() {
+        let text = ctx.render(version.get()).to_vec();
+        ctx.checks.fetch_add(1, atomic::Ordering::Relaxed);
+        // After an interrupt, keep what's left rather than guess
+        if ctx.check.recheck(&text)? || ctx.control.is_interrupted() {
+            break;
+        }
+        back += 1;
+    }
+    if back == 0 {
+        return Ok(());
+    }
+    if history.rollback(back) < back {
+        // Even the oldest version kept isn't interesting
+        warn!("The result of the pass isn't interesting anymore, backing out of all of it");
+        let empty = history.current().mutate_clone(|_| Edits::new());
+        history.push(empty);
+    } else {
+        warn!(
+            "The result of the pass isn't interesting anymore, backing out of the last {} candidates it accepted",
+            back
+        );
+    }
+    Ok(())
 }
 
 // Don't care about parse errors, we're maintaining the interestingness
-fn parse(language: tree_sitter::Language, code: &str) -> tree_sitter::Tree {
+fn parse(language: tree_sitter::Language, code: impl AsRef<[u8]>) -> tree_sitter::Tree {
     let mut parser = tree_sitter::Parser::new();
     parser
         .set_language(language)
@@ -597,6 +2102,183 @@ fn parse(language: tree_sitter::Language, code: &str) -> tree_sitter::Tree {
     parser.parse(code, None).expect("Failed to parse code")
 }
 
+/// Row and column of each byte offset, for [`tree_sitter::InputEdit`]
+struct Points {
+    line_starts: Vec<usize>,
+}
+
+impl Points {
+    fn new(text: &[u8]) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(
+                text.iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b == b'\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        Points { line_starts }
+    }
+
+    fn point(&self, offset: usize) -> Point {
+        let row = self.line_starts.partition_point(|s| *s <= offset) - 1;
+        Point::new(row, offset - self.line_starts[row])
+    }
+}
+
+/// The point reached after writing `text` starting at `start`
+fn advance(start: Point, text: &[u8]) -> Point {
+    match text.iter().rposition(|b| *b == b'\n') {
+        None => Point::new(start.row, start.column + text.len()),
+        Some(i) => Point::new(
+            start.row + text.iter().filter(|b| **b == b'\n').count(),
+            text.len() - i - 1,
+        ),
+    }
+}
+
+/// Apply the edits and incrementally reparse
+fn reparse(
+    language: tree_sitter::Language,
+    mut orig: Original,
+    edits: &Edits,
+) -> Result<Original, MultiPassReductionError> {
+    if edits.is_empty() {
+        return Ok(orig);
+    }
+    let mut new_src = Vec::with_capacity(orig.text.len());
+    render(&mut new_src, &orig.tree, orig.text.as_slice(), edits)?;
+    let points = Points::new(&orig.text);
+    // Back to front, so that the offsets of the remaining edits stay valid
+    for (range, with) in edits.byte_edits(&orig.text, &orig.tree).iter().rev() {
+        let start_position = points.point(range.start);
+        orig.tree.edit(&InputEdit {
+            start_byte: range.start,
+            old_end_byte: range.end,
+            new_end_byte: range.start + with.len(),
+            start_position,
+            old_end_position: points.point(range.end),
+            new_end_position: advance(start_position, with),
+        });
+    }
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(language)
+        .expect("Failed to set tree-sitter parser language");
+    let tree = parser
+        .parse(&new_src, Some(&orig.tree))
+        .expect("Failed to parse code");
+    Ok(Original::new(tree, new_src))
+}
+
+/// Collapse whitespace between tokens, if that's still interesting
+fn collapse_whitespace<T: Check>(
+    language: tree_sitter::Language,
+    orig: Original,
+    check: &T,
+    control: &Control,
+    provenance: &mut Provenance,
+) -> Result<(Original, bool, usize), MultiPassReductionError> {
+    let (collapsed, retained) = tokens::collapse_whitespace(orig.tree.root_node(), &orig.text);
+    if collapsed.len() >= orig.text.len() || !control.may_check() {
+        return Ok((orig, false, 0));
+    }
+    let (interesting, ran) = run(check, &collapsed)?;
+    let checks = usize::from(ran);
+    if control.verdict(interesting) {
+        provenance.follow(&retained);
+        let new = Original::new(parse(language, &collapsed), collapsed);
+        return Ok((new, true, checks));
+    }
+    Ok((orig, false, checks))
+}
+
+/// Check `text`, and whether the check ran (rather than finding its verdict
+/// in the cache)
+pub(super) fn run<T: Check>(check: &T, text: &[u8]) -> std::io::Result<(bool, bool)> {
+    let state = check.start(text)?;
+    let ran = !check.is_cached(&state);
+    Ok((check.wait(state)?, ran))
+}
+
+/// Chop whole lines from the start and end of the input, keeping any
+/// preserved ranges, if that's still interesting
+fn bisect<T: Check>(
+    language: tree_sitter::Language,
+    orig: Original,
+    conf: &Config<T>,
+    preserved: &mut [Range<usize>],
+    provenance: &mut Provenance,
+) -> Result<Original, MultiPassReductionError> {
+    if orig.text.is_empty() || conf.only_query.is_some() || !skip_queries(conf).is_empty() {
+        return Ok(orig);
+    }
+    let _span = info_span!("bisect").entered();
+    let keep = preserved
+        .iter()
+        .cloned()
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+    let errors = count_errors(&orig.tree);
+    let mut checks = 0;
+    let range = bisect::bisect(&orig.text, keep, |text| {
+        if conf.no_new_errors && count_errors(&parse(language, text)) > errors {
+            return Ok(Some(false));
+        }
+        if !conf.control.may_check() {
+            return Ok(None);
+        }
+        let (interesting, ran) = run(&conf.check, text)?;
+        checks += usize::from(ran);
+        Ok(Some(conf.control.verdict(interesting)))
+    })?;
+    info!(
+        "Bisection kept bytes {}..{} of {}, after {} checks",
+        range.start,
+        range.end,
+        orig.text.len(),
+        checks
+    );
+    if range.len() == orig.text.len() {
+        return Ok(orig);
+    }
+    for r in preserved {
+        *r = r.start - range.start..r.end - range.start;
+    }
+    provenance.follow(&[(range.clone(), 0..range.len())]);
+    let text = orig.text[range].to_vec();
+    conf.control.reduced_to(text.len());
+    Ok(Original::new(parse(language, &text), text))
+}
+
+/// Like [`Instant`], but always zero on wasm32-unknown-unknown, which has no
+/// clock
+struct Timer {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    start: Instant,
+}
+
+impl Timer {
+    fn start() -> Self {
+        Timer {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            start: Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        {
+            self.start.elapsed()
+        }
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Run each stage of the pipeline in turn. Stages without an iteration limit
+/// run for at most `max_passes` iterations (unbounded, if `None`).
 pub fn treereduce_multi_pass<T: Clone + Check + Debug + Send + Sync + 'static>(
     language: tree_sitter::Language,
     node_types: &NodeTypes,
@@ -606,46 +2288,295 @@ pub fn treereduce_multi_pass<T: Clone + Check + Debug + Send + Sync + 'static>(
 ) -> Result<(Original, Stats), MultiPassReductionError> {
     let mut stats = Stats::new();
     stats.start_size = orig.text.len();
-    let reduce_start = Instant::now();
-    let mut passes_done = 0;
-    while passes_done < max_passes.unwrap_or(usize::MAX) {
-        let pass_start_size = orig.text.len();
-        info!(
-            "Starting pass {} / {}",
-            passes_done + 1,
-            max_passes
-                .map(|n| n.to_string())
-                .unwrap_or_else(|| "?".to_string())
-        );
-        let pass_start = Instant::now();
-
-        let (new, edits) = treereduce(node_types, orig, conf)?;
-        orig = new;
-        let mut new_src = Vec::new();
-        tree_sitter_edit::render(&mut new_src, &orig.tree, orig.text.as_slice(), &edits)?;
-        let text = std::str::from_utf8(&new_src)?.to_string();
-        orig = Original::new(parse(language, &text), new_src);
-
-        passes_done += 1;
-        let pass_stats = stats::Pass {
-            duration: pass_start.elapsed(),
-            start_size: pass_start_size,
-            end_size: orig.text.len(),
-        };
-        debug!(
-            "Pass {} duration: {}ms",
-            passes_done,
-            pass_stats.duration.as_millis()
-        );
-        stats.passes.push(pass_stats);
+    stats.provenance = Provenance::new(orig.text.len());
+    let reduce_start = Timer::start();
+    conf.control.reduced_to(orig.text.len());
+    // Preserved ranges shift as the text before them is reduced
+    let mut preserved = preserved_ranges(conf, &orig.text);
+    if conf.bisect && conf.resume.is_none() {
+        orig = bisect(language, orig, conf, &mut preserved, &mut stats.provenance)?;
+    }
+    if conf.algorithm == Algorithm::Hdd {
+        loop {
+            let pass_start_size = orig.text.len();
+            let pass_start = Timer::start();
+            let (new, (accepted, rejected, checks)) = {
+                let _span = info_span!("hdd").entered();
+                hdd::hdd(
+                    language,
+                    node_types,
+                    orig,
+                    conf,
+                    &mut preserved,
+                    &mut stats.provenance,
+                )?
+            };
+            orig = new;
+            stats.passes.push(stats::Pass {
+                stage: String::from("hdd"),
+                duration: pass_start.elapsed(),
+                start_size: pass_start_size,
+                end_size: orig.text.len(),
+                accepted,
+                rejected,
+                checks,
+            });
+            conf.control.pass_done();
+            if !conf.fixpoint || orig.text.len() >= pass_start_size || conf.control.is_stopped() {
+                break;
+            }
+        }
+    } else {
+        let mut round = conf.resume.map(|p| p.round.saturating_sub(1)).unwrap_or(0);
+        let mut scheduler = Scheduler::new(conf.schedule, conf.pipeline.stages.len());
+        'rounds: loop {
+            round += 1;
+            let resume = conf.resume.filter(|p| p.round == round);
+            // Passes before the checkpoint may have made progress
+            let mut progress = resume.is_some();
+            let order = scheduler.order(&conf.pipeline.stages);
+            if order.len() < conf.pipeline.stages.len() {
+                info!(
+                    "Skipping {} stages that found nothing since the last change",
+                    conf.pipeline.stages.len() - order.len()
+                );
+            }
+            for (stage_index, stage) in order.iter().map(|(i, s)| (*i, s)) {
+                if stage != &conf.pipeline.stages[stage_index] {
+                    info!(
+                        "Running only {} of {}, the rest found nothing since the last change",
+                        stage, conf.pipeline.stages[stage_index]
+                    );
+                }
+                let max_passes = stage.max_iterations.or(max_passes);
+                let mut passes_done = 0;
+                let mut stage_verdicts: HashMap<Pass, Verdicts> = HashMap::new();
+                let mut stage_changed = false;
+                if let Some(p) = resume {
+                    if stage_index < p.stage {
+                        continue;
+                    }
+                    if stage_index == p.stage {
+                        passes_done = p.pass;
+                    }
+                }
+                while passes_done < max_passes.unwrap_or(usize::MAX) {
+                    let pass_start_size = orig.text.len();
+                    let pass_start_metric = conf.metric.measure(&orig.tree, &orig.text);
+                    info!(
+                        "Starting pass {} / {} ({})",
+                        passes_done + 1,
+                        max_passes
+                            .map(|n| n.to_string())
+                            .unwrap_or_else(|| "?".to_string()),
+                        stage
+                    );
+                    let _span = info_span!(
+                        "pass",
+                        round,
+                        stage = %stage,
+                        iteration = passes_done + 1
+                    )
+                    .entered();
+                    let pass_start = Timer::start();
 
-        if edits.is_empty() {
-            info!("Qutting after pass {} found no reductions", passes_done);
-            break;
+                    let (mut collapsed, mut collapse_checks) = (false, 0);
+                    // Whitespace isn't part of any node, so can't be targeted
+                    if stage.has(Pass::Tokens)
+                        && conf.only_query.is_none()
+                        && conf.skip_query.is_none()
+                        && conf.preserve.is_empty()
+                    {
+                        (orig, collapsed, collapse_checks) = collapse_whitespace(
+                            language,
+                            orig,
+                            &conf.check,
+                            &conf.control,
+                            &mut stats.provenance,
+                        )?;
+                    }
+                    let (new, edits, (accepted, rejected, checks), verdicts) =
+                        reduce(node_types, orig, conf, stage, &preserved)?;
+                    orig = new;
+                    for (pass, v) in verdicts {
+                        stage_verdicts.entry(pass).or_default().add(v);
+                    }
+                    if collapsed {
+                        stage_verdicts.entry(Pass::Tokens).or_default().accepted += 1;
+                    }
+                    stage_changed |= !edits.is_empty() || collapsed;
+                    for range in &mut preserved {
+                        *range = edits.map_offset(&orig.text, &orig.tree, range.start)
+                            ..edits.map_offset(&orig.text, &orig.tree, range.end);
+                    }
+                    stats
+                        .provenance
+                        .follow(&edits.retained(&orig.text, &orig.tree));
+                    orig = reparse(language, orig, &edits)?;
+
+                    passes_done += 1;
+                    let pass_stats = stats::Pass {
+                        stage: stage.to_string(),
+                        duration: pass_start.elapsed(),
+                        start_size: pass_start_size,
+                        end_size: orig.text.len(),
+                        accepted,
+                        rejected,
+                        checks: checks + collapse_checks,
+                    };
+                    debug!(
+                        "Pass {} duration: {}ms",
+                        passes_done,
+                        pass_stats.duration.as_millis()
+                    );
+                    conf.observers.pass_done(&PassDone {
+                        stage,
+                        iteration: passes_done,
+                        position: Position {
+                            round,
+                            stage: stage_index,
+                            pass: passes_done,
+                        },
+                        start_size: pass_stats.start_size,
+                        end_size: pass_stats.end_size,
+                        duration: pass_stats.duration,
+                        text: &orig.text,
+                    });
+                    stats.passes.push(pass_stats);
+
+                    conf.control.pass_done();
+                    if conf.control.is_stopped() {
+                        info!("Stopping early");
+                        break 'rounds;
+                    }
+                    if conf.control.take_skip() {
+                        info!("Skipping the rest of stage {}", stage);
+                        break;
+                    }
+
+                    // Only count strict size reductions, so that rounds terminate
+                    if conf.metric.measure(&orig.tree, &orig.text) < pass_start_metric {
+                        progress = true;
+                    }
+                    if edits.is_empty() && !collapsed {
+                        info!("Qutting after pass {} found no reductions", passes_done);
+                        break;
+                    }
+                }
+                scheduler.ran(stage_index, &stage.passes, &stage_verdicts, stage_changed);
+            }
+            if !conf.fixpoint || !progress {
+                break;
+            }
+            info!(
+                "Round {} made progress, starting round {}",
+                round,
+                round + 1
+            );
+        }
+    }
+
+    if let Some(injections) = conf
+        .injections
+        .as_ref()
+        .filter(|_| !conf.control.is_stopped())
+    {
+        let _span = info_span!("injections").entered();
+        orig = injection::reduce_injections(
+            language,
+            orig,
+            conf,
+            injections,
+            &mut preserved,
+            &mut stats.provenance,
+            max_passes,
+        )?;
+    }
+
+    if conf.one_minimal && !conf.control.is_stopped() {
+        let _span = info_span!("one_minimal").entered();
+        let mut violations = 0;
+        while let Some(edits) = one_minimal_violation(node_types, &orig, conf, &preserved)? {
+            violations += 1;
+            for range in &mut preserved {
+                *range = edits.map_offset(&orig.text, &orig.tree, range.start)
+                    ..edits.map_offset(&orig.text, &orig.tree, range.end);
+            }
+            stats
+                .provenance
+                .follow(&edits.retained(&orig.text, &orig.tree));
+            orig = reparse(language, orig, &edits)?;
         }
+        info!(
+            "Result is 1-minimal, after deleting {} more nodes",
+            violations
+        );
     }
+
     stats.duration = reduce_start.elapsed();
     info!("Total time: {}ms", stats.duration.as_millis());
     stats.end_size = orig.text.len();
     Ok((orig, stats))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reparse() {
+        let language = tree_sitter_c::language();
+        let text = "int x;\nint f() {\n  return 0;\n}\nint y;\n";
+        let tree = parse(language, text);
+        let root = tree.root_node();
+        let func = root.child(1).unwrap();
+        let ret = func
+            .child_by_field_name("body")
+            .unwrap()
+            .named_child(0)
+            .unwrap();
+        let edits = Edits::new()
+            .omit(&root.child(0).unwrap())
+            .replace(&ret, String::from("f();\n  f();"));
+        let expected = "\nint f() {\n  f();\n  f();\n}\nint y;\n";
+        assert_eq!(
+            edits.render_rope(text.as_bytes(), &tree).to_vec(),
+            expected.as_bytes()
+        );
+        let orig = Original::new(tree.clone(), text.as_bytes().to_vec());
+        let reparsed = reparse(language, orig, &edits).unwrap();
+        assert_eq!(std::str::from_utf8(&reparsed.text).unwrap(), expected);
+        assert_eq!(
+            reparsed.tree.root_node().to_sexp(),
+            parse(language, expected).root_node().to_sexp()
+        );
+    }
+
+    #[test]
+    fn test_metric() {
+        let text = "int x;\n\nint f() { return 0; }\n";
+        let tree = parse(tree_sitter_c::language(), text);
+        let measure = |m: Metric| m.measure(&tree, text.as_bytes());
+        assert_eq!(measure(Metric::Bytes), text.len());
+        assert_eq!(measure(Metric::Lines), 2);
+        assert_eq!(measure(Metric::Tokens), 12);
+        assert_eq!(measure(Metric::Nodes), 12);
+    }
+
+    #[test]
+    fn test_error_chunks() {
+        assert_eq!(
+            error_chunks(&[0, 0, 0, 1, 1, 2]),
+            vec![0..2, 0..3, 0..5, 2..4, 3..5, 3..6, 4..6]
+        );
+        assert!(error_chunks(&[4]).is_empty());
+    }
+
+    #[test]
+    fn test_count_errors() {
+        let language = tree_sitter_c::language();
+        assert_eq!(count_errors(&parse(language, "int x;\nint f() { }\n")), 0);
+        assert_ne!(count_errors(&parse(language, "int x\n")), 0);
+        assert_ne!(count_errors(&parse(language, "int x = ;\nint f( { }\n")), 0);
+    }
+}
@@ -0,0 +1,94 @@
+use std::io;
+use std::ops::Range;
+
+/// Offsets at which the text may be cut: the start of each line, and the end
+fn cut_points(text: &[u8]) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            text.iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+                .map(|(i, _)| i + 1),
+        )
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+/// The last index in `0..=hi` for which `ok` holds, assuming that it holds
+/// for `0` and (roughly) stops holding at some point. Returns `None` if `ok`
+/// does.
+fn last_ok(
+    hi: usize,
+    mut ok: impl FnMut(usize) -> io::Result<Option<bool>>,
+) -> io::Result<Option<usize>> {
+    let (mut lo, mut hi) = (0, hi);
+    while lo < hi {
+        let mid = lo + (hi - lo).div_ceil(2);
+        match ok(mid)? {
+            None => return Ok(None),
+            Some(true) => lo = mid,
+            Some(false) => hi = mid - 1,
+        }
+    }
+    Ok(Some(lo))
+}
+
+/// Bisect for the largest whole-line prefix and suffix of `text` that can be
+/// removed while `interesting` holds, without cutting into `keep`. Takes
+/// about two checks per doubling of the number of lines. `interesting`
+/// returns `None` to stop early.
+///
+/// Returns the range of `text` to keep.
+pub(super) fn bisect(
+    text: &[u8],
+    keep: Option<Range<usize>>,
+    mut interesting: impl FnMut(&[u8]) -> io::Result<Option<bool>>,
+) -> io::Result<Range<usize>> {
+    let cuts = cut_points(text);
+    let keep = keep.unwrap_or(text.len()..0);
+    // Cut at most up to the start (and from the end) of `keep`
+    let max_start = cuts.partition_point(|c| *c <= keep.start.min(text.len())) - 1;
+    let start = match last_ok(max_start, |i| interesting(&text[cuts[i]..]))? {
+        None => return Ok(0..text.len()),
+        Some(i) => cuts[i],
+    };
+    let ends: Vec<usize> = cuts
+        .iter()
+        .rev()
+        .copied()
+        .take_while(|c| *c >= start.max(keep.end))
+        .collect();
+    let end = match last_ok(ends.len().saturating_sub(1), |i| {
+        interesting(&text[start..ends[i]])
+    })? {
+        None => text.len(),
+        Some(i) => ends.get(i).copied().unwrap_or(text.len()),
+    };
+    Ok(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bisected(text: &str, keep: Option<Range<usize>>, needle: &str) -> String {
+        let range = bisect(text.as_bytes(), keep, |t| {
+            Ok(Some(
+                t.windows(needle.len()).any(|w| w == needle.as_bytes()),
+            ))
+        })
+        .unwrap();
+        text[range].to_string()
+    }
+
+    #[test]
+    fn test_bisect() {
+        let text = "a\nb\nc\nd\ne\nf\ng\n";
+        assert_eq!(bisected(text, None, "d"), "d\n");
+        assert_eq!(bisected(text, None, "b\nc"), "b\nc\n");
+        assert_eq!(bisected(text, None, "a"), "a\n");
+        assert_eq!(bisected(text, None, "g"), "g\n");
+        assert_eq!(bisected(text, Some(2..3), "d"), "b\nc\nd\n");
+        assert_eq!(bisected(text, Some(12..13), "b"), "b\nc\nd\ne\nf\ng\n");
+    }
+}
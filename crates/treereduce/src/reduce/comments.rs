@@ -0,0 +1,97 @@
+//! Comments and docstrings, for the comments pass.
+
+use std::ops::Range;
+
+use regex::Regex;
+use tree_sitter::Node;
+
+use crate::transform::Transforms;
+
+/// A comment, or a node that one of `transforms` treats like one (e.g., a
+/// Python docstring)
+pub(super) fn is_comment(node: &Node, text: &[u8], transforms: &Transforms) -> bool {
+    node.kind().contains("comment") || transforms.iter().any(|t| t.is_comment(node, text))
+}
+
+/// The comments (and docstrings) in the tree, outermost only, in order
+pub(super) fn comments<'tree>(
+    root: Node<'tree>,
+    text: &[u8],
+    transforms: &Transforms,
+) -> Vec<Node<'tree>> {
+    let mut comments = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if is_comment(&node, text, transforms) {
+            comments.push(node);
+            continue;
+        }
+        // Reversed, so that nodes are popped in textual order
+        let children: Vec<Node> = node.children(&mut node.walk()).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    comments
+}
+
+/// The byte ranges of comments and docstrings matching `keep`, which should
+/// never change
+pub(super) fn kept(
+    root: Node,
+    text: &[u8],
+    keep: &Regex,
+    transforms: &Transforms,
+) -> Vec<Range<usize>> {
+    comments(root, text, transforms)
+        .into_iter()
+        .filter(|c| keep.is_match(&String::from_utf8_lossy(&text[c.byte_range()])))
+        .map(|c| c.byte_range())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::languages::PythonDocstrings;
+
+    fn parse(language: tree_sitter::Language, text: &str) -> tree_sitter::Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(language).unwrap();
+        parser.parse(text, None).unwrap()
+    }
+
+    #[test]
+    fn test_comments() {
+        let text = "// KEEP: license\nint x; /* y */\n";
+        let tree = parse(tree_sitter_c::language(), text);
+        let none = Transforms::default();
+        let found =
+            |tree: &tree_sitter::Tree, text: &[u8], transforms: &Transforms| -> Vec<String> {
+                comments(tree.root_node(), text, transforms)
+                    .iter()
+                    .map(|c| String::from_utf8_lossy(&text[c.byte_range()]).into_owned())
+                    .collect()
+            };
+        assert_eq!(
+            found(&tree, text.as_bytes(), &none),
+            vec!["// KEEP: license", "/* y */"]
+        );
+        let keep = Regex::new("KEEP").unwrap();
+        assert_eq!(
+            kept(tree.root_node(), text.as_bytes(), &keep, &none),
+            vec![0..16]
+        );
+
+        let text = "\"Mod\"\ndef f():\n    \"\"\"Doc\"\"\"\n    \"not a docstring\"\n    if x:\n        \"nor this\"\nclass C:\n    \"Cls\"\n    for y in z:\n        \"nor this\"\n";
+        let tree = parse(tree_sitter_python::language(), text);
+        // Only with the Python transforms
+        assert!(found(&tree, text.as_bytes(), &none).is_empty());
+        let mut python = Transforms::default();
+        python.push(Arc::new(PythonDocstrings));
+        assert_eq!(
+            found(&tree, text.as_bytes(), &python),
+            vec!["\"Mod\"", "\"\"\"Doc\"\"\"", "\"Cls\""]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::io;
+use std::ops::Range;
+
+/// Contiguous chunks of a list of length `len`, in halving granularity (as in
+/// delta debugging). Chunks of a single element are omitted, since those are
+/// handled by single-node deletion.
+pub(super) fn chunks(len: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut size = len.div_ceil(2);
+    while size >= 2 {
+        let mut start = 0;
+        while start < len {
+            let end = std::cmp::min(start + size, len);
+            if end - start >= 2 {
+                ranges.push(start..end);
+            }
+            start = end;
+        }
+        size = size.div_ceil(2);
+    }
+    ranges
+}
+
+/// Delta debugging over `0..len`: try deleting each of `n` chunks of the
+/// remaining elements, starting with `n = 2` and doubling `n` when no chunk
+/// can be deleted.
+///
+/// Each [`Ddmin::candidate`] is tested by the caller, and the verdict passed
+/// to [`Ddmin::record`], so the tests can be run by a loop (as in [`ddmin`])
+/// or awaited.
+pub(crate) struct Ddmin {
+    kept: Vec<usize>,
+    deleted: Vec<usize>,
+    n: usize,
+    /// Of the chunks in this round
+    size: usize,
+    /// Index into `kept` of the next chunk
+    start: usize,
+    progress: bool,
+    done: bool,
+}
+
+impl Ddmin {
+    pub(crate) fn new(len: usize) -> Self {
+        Ddmin {
+            kept: (0..len).collect(),
+            deleted: Vec::new(),
+            n: 2,
+            size: len.div_ceil(2),
+            start: 0,
+            progress: false,
+            done: len == 0,
+        }
+    }
+
+    fn end(&self) -> usize {
+        std::cmp::min(self.start + self.size, self.kept.len())
+    }
+
+    /// Start a round with new chunks, or finish
+    fn next_round(&mut self) {
+        if self.progress {
+            self.n = std::cmp::max(self.n - 1, 2);
+        } else if self.size == 1 {
+            self.done = true;
+            return;
+        } else {
+            self.n = std::cmp::min(2 * self.n, self.kept.len());
+        }
+        if self.kept.is_empty() {
+            self.done = true;
+            return;
+        }
+        self.size = self.kept.len().div_ceil(self.n);
+        self.start = 0;
+        self.progress = false;
+    }
+
+    /// The elements to try deleting next, or `None` when done
+    pub(crate) fn candidate(&mut self) -> Option<Vec<usize>> {
+        while !self.done && self.start >= self.kept.len() {
+            self.next_round();
+        }
+        if self.done {
+            return None;
+        }
+        let mut candidate = self.deleted.clone();
+        candidate.extend_from_slice(&self.kept[self.start..self.end()]);
+        Some(candidate)
+    }
+
+    /// Whether the last candidate was interesting
+    pub(crate) fn record(&mut self, interesting: bool) {
+        let end = self.end();
+        if interesting {
+            self.deleted.extend(self.kept.drain(self.start..end));
+            self.progress = true;
+        } else {
+            self.start = end;
+        }
+    }
+
+    pub(crate) fn deleted(self) -> Vec<usize> {
+        self.deleted
+    }
+}
+
+/// Run [`Ddmin`] to completion. `test` gets the elements to delete, and
+/// returns `None` to stop early. Returns the deleted elements.
+pub(crate) fn ddmin(
+    len: usize,
+    mut test: impl FnMut(&[usize]) -> io::Result<Option<bool>>,
+) -> io::Result<Vec<usize>> {
+    let mut search = Ddmin::new(len);
+    while let Some(candidate) = search.candidate() {
+        match test(&candidate)? {
+            None => break,
+            Some(interesting) => search.record(interesting),
+        }
+    }
+    Ok(search.deleted())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        assert!(chunks(0).is_empty());
+        assert!(chunks(2).is_empty());
+        assert_eq!(chunks(4), vec![0..2, 2..4]);
+        assert_eq!(chunks(5), vec![0..3, 3..5, 0..2, 2..4]);
+        assert_eq!(chunks(8), vec![0..4, 4..8, 0..2, 2..4, 4..6, 6..8]);
+    }
+
+    #[test]
+    fn test_ddmin() {
+        // Keep 2 and 5
+        let mut deleted = ddmin(8, |ids| Ok(Some(!ids.contains(&2) && !ids.contains(&5)))).unwrap();
+        deleted.sort();
+        assert_eq!(deleted, vec![0, 1, 3, 4, 6, 7]);
+        assert_eq!(ddmin(3, |_| Ok(Some(true))).unwrap().len(), 3);
+        assert!(ddmin(3, |_| Ok(None)).unwrap().is_empty());
+    }
+}
@@ -11,6 +11,8 @@ pub enum ReductionError {
     Json(#[from] serde_json::Error),
     #[error("Lock poisoned")]
     LockError(String),
+    #[error("Invalid tree-sitter query")]
+    Query(#[from] tree_sitter::QueryError),
 }
 
 impl<T> From<PoisonError<T>> for ReductionError {
@@ -0,0 +1,230 @@
+use std::ops::Range;
+
+use tracing::info;
+use tree_sitter::Node;
+
+use super::ddmin::{ddmin, Ddmin};
+use super::{
+    count_errors, node_size, parse, preserved_ranges, reparse, run, skip_queries, target, Config,
+    MultiPassReductionError, ReductionError, Timer,
+};
+use crate::async_check::AsyncCheck;
+use crate::check::Check;
+use crate::edits::Edits;
+use crate::id::NodeId;
+use crate::node_types::NodeTypes;
+use crate::original::Original;
+use crate::provenance::Provenance;
+use crate::stats::{self, Stats};
+
+/// The nodes at `depth` below `root`
+fn level(root: Node, depth: usize) -> Vec<Node> {
+    let mut level = vec![root];
+    for _ in 0..depth {
+        level = level
+            .iter()
+            .flat_map(|n| n.children(&mut n.walk()).collect::<Vec<_>>())
+            .collect();
+    }
+    level
+}
+
+/// The nodes at `depth` that may be deleted, or `None` if the tree isn't
+/// that deep
+fn deletable<T>(
+    node_types: &NodeTypes,
+    orig: &Original,
+    conf: &Config<T>,
+    preserved: &[Range<usize>],
+    depth: usize,
+) -> Result<Option<Vec<NodeId>>, ReductionError> {
+    let targets = target::Targets::new(
+        &orig.tree,
+        &orig.text,
+        conf.only_query.as_deref(),
+        &skip_queries(conf),
+        preserved,
+    )?;
+    let level = level(orig.tree.root_node(), depth);
+    if level.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        level
+            .into_iter()
+            .filter(|n| {
+                node_size(n) > 0
+                    && targets.allows(&[NodeId::new(n)])
+                    && (conf.delete_non_optional || node_types.optional_node(n))
+            })
+            .map(|n| NodeId::new(&n))
+            .collect(),
+    ))
+}
+
+fn omit(nodes: &[NodeId], ids: &[usize]) -> Edits {
+    ids.iter().fold(Edits::new(), |e, i| e.omit_id(nodes[*i]))
+}
+
+/// Delete the `deleted` ones of the `nodes` at `depth`, and reparse
+fn delete(
+    language: tree_sitter::Language,
+    orig: Original,
+    nodes: &[NodeId],
+    deleted: &[usize],
+    depth: usize,
+    preserved: &mut [Range<usize>],
+    provenance: &mut Provenance,
+) -> Result<Original, MultiPassReductionError> {
+    info!(
+        "Deleted {} of {} nodes at depth {}",
+        deleted.len(),
+        nodes.len(),
+        depth
+    );
+    let edits = omit(nodes, deleted);
+    for range in preserved.iter_mut() {
+        *range = edits.map_offset(&orig.text, &orig.tree, range.start)
+            ..edits.map_offset(&orig.text, &orig.tree, range.end);
+    }
+    provenance.follow(&edits.retained(&orig.text, &orig.tree));
+    reparse(language, orig, &edits)
+}
+
+/// Hierarchical delta debugging: for each level of the tree, from the root
+/// down, delete as many of its nodes as [`ddmin`] can, then reparse. Runs
+/// checks one at a time. Returns the result, the numbers of interesting and
+/// uninteresting candidates, and the number of runs of the check (not
+/// counting cached verdicts).
+pub(super) fn hdd<T: Check>(
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    mut orig: Original,
+    conf: &Config<T>,
+    preserved: &mut [Range<usize>],
+    provenance: &mut Provenance,
+) -> Result<(Original, (usize, usize, usize)), MultiPassReductionError> {
+    let (mut accepted, mut rejected, mut checks) = (0, 0, 0);
+    for depth in 0.. {
+        let Some(nodes) = deletable(node_types, &orig, conf, preserved, depth)? else {
+            break;
+        };
+        let errors = count_errors(&orig.tree);
+        let deleted = ddmin(nodes.len(), |ids| {
+            let text = omit(&nodes, ids)
+                .render_rope(&orig.text, &orig.tree)
+                .to_vec();
+            if conf.no_new_errors && count_errors(&parse(language, &text)) > errors {
+                return Ok(Some(false));
+            }
+            if !conf.control.may_check() {
+                return Ok(None);
+            }
+            let (interesting, ran) = run(&conf.check, &text)?;
+            checks += usize::from(ran);
+            let interesting = conf.control.verdict(interesting);
+            if interesting {
+                accepted += 1;
+                conf.control.reduced_to(text.len());
+            } else {
+                rejected += 1;
+            }
+            Ok(Some(interesting))
+        })?;
+        orig = delete(
+            language, orig, &nodes, &deleted, depth, preserved, provenance,
+        )?;
+        if conf.control.is_stopped() {
+            break;
+        }
+    }
+    Ok((orig, (accepted, rejected, checks)))
+}
+
+/// Reduce with hierarchical delta debugging (like [`super::Algorithm::Hdd`]),
+/// awaiting the verdicts of an [`AsyncCheck`], e.g., in an async service
+/// that shouldn't set aside threads for the reduction. The reduction runs in
+/// the task that awaits it, one check at a time, and doesn't start any
+/// threads; the work between checks (rendering and parsing candidates) is
+/// done when the future is polled.
+///
+/// Each check counts as a run for [`crate::control::Control::limit_checks`].
+/// Of the rest of `conf`, only the options for which nodes to delete (e.g.,
+/// [`Config::only_query`] and [`Config::preserve`]), [`Config::no_new_errors`],
+/// and [`Config::fixpoint`] apply; the pipeline doesn't.
+pub async fn treereduce_async<T: AsyncCheck>(
+    language: tree_sitter::Language,
+    node_types: &NodeTypes,
+    mut orig: Original,
+    conf: &Config<T>,
+) -> Result<(Original, Stats), MultiPassReductionError> {
+    let mut stats = Stats::new();
+    stats.start_size = orig.text.len();
+    stats.provenance = Provenance::new(orig.text.len());
+    let reduce_start = Timer::start();
+    conf.control.reduced_to(orig.text.len());
+    let mut preserved = preserved_ranges(conf, &orig.text);
+    loop {
+        let pass_start_size = orig.text.len();
+        let pass_start = Timer::start();
+        let (mut accepted, mut rejected) = (0, 0);
+        for depth in 0.. {
+            // No tree-sitter nodes are kept across awaits, they aren't `Send`
+            let Some(nodes) = deletable(node_types, &orig, conf, &preserved, depth)? else {
+                break;
+            };
+            let errors = count_errors(&orig.tree);
+            let mut search = Ddmin::new(nodes.len());
+            while let Some(ids) = search.candidate() {
+                let text = omit(&nodes, &ids)
+                    .render_rope(&orig.text, &orig.tree)
+                    .to_vec();
+                if conf.no_new_errors && count_errors(&parse(language, &text)) > errors {
+                    search.record(false);
+                    continue;
+                }
+                if !conf.control.may_check() {
+                    break;
+                }
+                conf.control.count_run();
+                let size = text.len();
+                let interesting = conf.control.verdict(conf.check.interesting(text).await?);
+                if interesting {
+                    accepted += 1;
+                    conf.control.reduced_to(size);
+                } else {
+                    rejected += 1;
+                }
+                search.record(interesting);
+            }
+            orig = delete(
+                language,
+                orig,
+                &nodes,
+                &search.deleted(),
+                depth,
+                &mut preserved,
+                &mut stats.provenance,
+            )?;
+            if conf.control.is_stopped() {
+                break;
+            }
+        }
+        stats.passes.push(stats::Pass {
+            stage: String::from("hdd"),
+            duration: pass_start.elapsed(),
+            start_size: pass_start_size,
+            end_size: orig.text.len(),
+            accepted,
+            rejected,
+            checks: accepted + rejected,
+        });
+        conf.control.pass_done();
+        if !conf.fixpoint || orig.text.len() >= pass_start_size || conf.control.is_stopped() {
+            break;
+        }
+    }
+    stats.duration = reduce_start.elapsed();
+    stats.end_size = orig.text.len();
+    Ok((orig, stats))
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
+
+use tracing::{debug, info, info_span};
+use tree_sitter::{Language, Query, QueryCursor};
+
+use super::{parse, treereduce_multi_pass, Config, MultiPassReductionError, ReductionError};
+use crate::check::Check;
+use crate::control::Control;
+use crate::node_types::NodeTypes;
+use crate::observer::Observers;
+use crate::original::Original;
+use crate::provenance::Provenance;
+use crate::rope::Rope;
+use crate::transform::Transforms;
+
+/// Grammars for regions of the input in other languages (e.g., SQL in string
+/// literals), found with a tree-sitter injection query like those of editors:
+/// each `@injection.content` capture is a region, in the language named by
+/// the text of the `@injection.language` capture, or else by the
+/// `injection.language` property (set with `#set!`).
+#[derive(Clone, Debug)]
+pub struct Injections {
+    pub query: String,
+    /// Grammars and their node types, by language name
+    pub languages: HashMap<String, (Language, NodeTypes)>,
+}
+
+/// Checks a region of the input, with the rest of the input around it
+#[derive(Clone, Debug)]
+struct Splice<T> {
+    check: T,
+    before: Arc<Vec<u8>>,
+    after: Arc<Vec<u8>>,
+}
+
+impl<T: Check> Check for Splice<T> {
+    type State = T::State;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        self.start_rope(&Rope::from(stdin))
+    }
+
+    fn start_rope(&self, stdin: &Rope) -> io::Result<Self::State> {
+        let mut rope = Rope::new();
+        rope.push_borrowed(&self.before);
+        for chunk in stdin.chunks() {
+            rope.push_borrowed(chunk);
+        }
+        rope.push_borrowed(&self.after);
+        self.check.start_rope(&rope)
+    }
+
+    fn cancel(&self, state: Self::State) -> io::Result<()> {
+        self.check.cancel(state)
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        self.check.try_wait(state)
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        self.check.wait(state)
+    }
+
+    fn is_cached(&self, state: &Self::State) -> bool {
+        self.check.is_cached(state)
+    }
+}
+
+/// Sets the offset of the sizes that `control` reports back to zero when
+/// dropped, even if the region's reduction fails
+struct Offset<'a>(&'a Control);
+
+impl Drop for Offset<'_> {
+    fn drop(&mut self) {
+        self.0.set_offset(0);
+    }
+}
+
+/// The regions captured by `query`, with the names of their languages, in
+/// order. Regions inside others are left out.
+fn regions(orig: &Original, query: &str) -> Result<Vec<(Range<usize>, String)>, ReductionError> {
+    let query = Query::new(orig.tree.language(), query)?;
+    let content = query.capture_index_for_name("injection.content");
+    let language = query.capture_index_for_name("injection.language");
+    let mut regions = Vec::new();
+    let mut cursor = QueryCursor::new();
+    for m in cursor.matches(&query, orig.tree.root_node(), orig.text.as_slice()) {
+        let captured = m
+            .captures
+            .iter()
+            .find(|c| Some(c.index) == language)
+            .and_then(|c| c.node.utf8_text(&orig.text).ok())
+            .map(String::from);
+        let property = query
+            .property_settings(m.pattern_index)
+            .iter()
+            .find(|p| &*p.key == "injection.language")
+            .and_then(|p| p.value.as_deref().map(String::from));
+        let Some(name) = captured.or(property) else {
+            continue;
+        };
+        for c in m.captures.iter().filter(|c| Some(c.index) == content) {
+            regions.push((c.node.byte_range(), name.clone()));
+        }
+    }
+    regions.sort_by_key(|(r, _)| (r.start, std::cmp::Reverse(r.end)));
+    let mut end = 0;
+    regions.retain(|(r, _)| {
+        let outer = r.start >= end && !r.is_empty();
+        if outer {
+            end = r.end;
+        }
+        outer
+    });
+    Ok(regions)
+}
+
+/// Reduce each region in another language with its own grammar, with the
+/// rest of the input as it is. Regions that overlap `preserved` ones are
+/// left alone.
+pub(super) fn reduce_injections<T: Clone + Check + Debug + Send + Sync + 'static>(
+    language: Language,
+    orig: Original,
+    conf: &Config<T>,
+    injections: &Injections,
+    preserved: &mut [Range<usize>],
+    provenance: &mut Provenance,
+    max_passes: Option<usize>,
+) -> Result<Original, MultiPassReductionError> {
+    let regions = regions(&orig, &injections.query)?;
+    let mut text = orig.text;
+    let mut reduced_any = false;
+    // Last first, so that the earlier ranges stay valid
+    for (range, name) in regions.into_iter().rev() {
+        if conf.control.is_stopped() {
+            break;
+        }
+        let Some((lang, node_types)) = injections.languages.get(&name) else {
+            debug!("No grammar for injected language {}", name);
+            continue;
+        };
+        if preserved
+            .iter()
+            .any(|p| p.start < range.end && range.start < p.end)
+        {
+            continue;
+        }
+        let _span =
+            info_span!("injection", language = name.as_str(), start = range.start).entered();
+        let region = text[range.clone()].to_vec();
+        let splice = Splice {
+            check: conf.check.clone(),
+            before: Arc::new(text[..range.start].to_vec()),
+            after: Arc::new(text[range.end..].to_vec()),
+        };
+        // The queries, comment regex, names, replacements, weights,
+        // transforms, and observers are for the outer language or the whole
+        // input
+        let sub = Config {
+            only_query: None,
+            skip_query: None,
+            preserve: Vec::new(),
+            keep_comments: None,
+            names: Vec::new(),
+            replacements: HashMap::new(),
+            weights: HashMap::new(),
+            observers: Observers::default(),
+            transforms: Transforms::default(),
+            resume: None,
+            bisect: false,
+            injections: None,
+            ..conf.clone()
+        }
+        .with_check(splice);
+        conf.control.set_offset(text.len() - region.len());
+        let offset = Offset(&conf.control);
+        let tree = parse(*lang, &region);
+        let (reduced, stats) = treereduce_multi_pass(
+            *lang,
+            node_types,
+            Original::new(tree, region),
+            &sub,
+            max_passes,
+        )?;
+        drop(offset);
+        info!(
+            "Reduced {} region from {} to {} bytes",
+            name,
+            range.len(),
+            reduced.text.len()
+        );
+        let (old, new) = (range.len(), reduced.text.len());
+        for p in preserved.iter_mut().filter(|p| p.start >= range.end) {
+            *p = p.start + new - old..p.end + new - old;
+        }
+        reduced_any |= new < old;
+        // The inner provenance is relative to the region
+        let mut retained = vec![(0..range.start, 0..range.start)];
+        retained.extend(stats.provenance.segments().iter().map(|(i, t)| {
+            (
+                i.start + range.start..i.end + range.start,
+                t.start + range.start..t.end + range.start,
+            )
+        }));
+        retained.push((
+            range.end..text.len(),
+            range.start + new..text.len() + new - old,
+        ));
+        provenance.follow(&retained);
+        text.splice(range, reduced.text);
+    }
+    if reduced_any {
+        conf.control.reduced_to(text.len());
+    }
+    Ok(Original::new(parse(language, &text), text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions() {
+        let text = "/* sql: int x; */ int f() { /* c */ }\n";
+        let language = tree_sitter_c::language();
+        let orig = Original::new(parse(language, text), text.as_bytes().to_vec());
+        let query = r#"
+            ((comment) @injection.content (#set! injection.language "sql"))
+            ((function_definition
+               declarator: (function_declarator declarator: (identifier) @injection.language)
+               body: (_) @injection.content))
+        "#;
+        let regions = regions(&orig, query).unwrap();
+        assert_eq!(
+            regions,
+            vec![(0..17, String::from("sql")), (26..37, String::from("f"))]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+const QUOTES: &[char] = &['"', '\'', '`'];
+
+fn is_number(kind: &str) -> bool {
+    kind.contains("number") || kind.contains("integer") || kind.contains("float")
+}
+
+/// Split a string literal like `b"foo"` or `r#"foo"#` into its opening,
+/// contents, and closing.
+fn split_string(text: &str) -> Option<(&str, &str, &str)> {
+    let open = text.find(QUOTES)?;
+    let prefix = &text[..open];
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '#' || c == '@')
+    {
+        return None;
+    }
+    let quote = text[open..].chars().next()?;
+    let close = text.rfind(quote)?;
+    if close <= open || !text[close + 1..].chars().all(|c| c == '#') {
+        return None;
+    }
+    Some((&text[..=open], &text[open + 1..close], &text[close..]))
+}
+
+/// Smaller versions of a literal of kind `kind`, smallest first.
+pub(super) fn shrink(kind: &str, text: &[u8]) -> Vec<String> {
+    let text = match std::str::from_utf8(text) {
+        Ok(t) => t,
+        Err(_) => return Vec::new(),
+    };
+    let mut smaller = Vec::new();
+    if is_number(kind) {
+        for n in ["0", "1"] {
+            if text != n {
+                smaller.push(n.to_string());
+            }
+        }
+    } else if kind.contains("string") {
+        if let Some((open, contents, close)) = split_string(text) {
+            if contents.is_empty() {
+                return smaller;
+            }
+            smaller.push(format!("{}{}", open, close));
+            // Keep the first half, without splitting a character or escape
+            let mut half = contents.len() / 2;
+            while half > 0 && !contents.is_char_boundary(half) {
+                half -= 1;
+            }
+            let mut kept = &contents[..half];
+            while kept.ends_with('\\') {
+                kept = &kept[..kept.len() - 1];
+            }
+            if !kept.is_empty() {
+                smaller.push(format!("{}{}{}", open, kept, close));
+            }
+        }
+    }
+    smaller
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shrink() {
+        let shrink = |kind: &str, text: &str| shrink(kind, text.as_bytes());
+        assert_eq!(shrink("number_literal", "0xbad1dea"), vec!["0", "1"]);
+        assert_eq!(shrink("integer_literal", "1"), vec!["0"]);
+        assert_eq!(shrink("string_literal", "\"abcd\""), vec!["\"\"", "\"ab\""]);
+        assert_eq!(shrink("string_literal", "\"a\\nb\""), vec!["\"\"", "\"a\""]);
+        assert_eq!(
+            shrink("raw_string_literal", "r#\"ab\"#"),
+            vec!["r#\"\"#", "r#\"a\"#"]
+        );
+        assert_eq!(shrink("string_literal", "L\"\""), Vec::<String>::new());
+        assert_eq!(shrink("string_content", "abcd"), Vec::<String>::new());
+    }
+}
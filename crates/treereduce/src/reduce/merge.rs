@@ -0,0 +1,155 @@
+use std::ops::Range;
+
+use super::task::PrioritizedTask;
+use crate::edits::Edits;
+
+/// Disjoint byte ranges of the original text, in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct Intervals(Vec<Range<usize>>);
+
+impl Intervals {
+    pub(super) fn new(ranges: impl IntoIterator<Item = Range<usize>>) -> Self {
+        let mut intervals = Intervals::default();
+        for r in ranges {
+            intervals.insert(r);
+        }
+        intervals
+    }
+
+    /// Add `range`, joining it with the ranges it overlaps
+    pub(super) fn insert(&mut self, mut range: Range<usize>) {
+        let first = self.0.partition_point(|r| r.end < range.start);
+        let last = self.0.partition_point(|r| r.start <= range.end);
+        if first < last {
+            range.start = std::cmp::min(range.start, self.0[first].start);
+            range.end = std::cmp::max(range.end, self.0[last - 1].end);
+        }
+        self.0.splice(first..last, [range]);
+    }
+
+    /// Whether any range of `self` overlaps any of `other`. Ranges that only
+    /// touch don't overlap, so edits of adjacent nodes don't conflict.
+    pub(super) fn overlaps(&self, other: &Intervals) -> bool {
+        other.0.iter().any(|r| {
+            let i = self.0.partition_point(|s| s.end <= r.start);
+            self.0.get(i).is_some_and(|s| s.start < r.end)
+        })
+    }
+
+    pub(super) fn extend(&mut self, other: &Intervals) {
+        for r in &other.0 {
+            self.insert(r.clone());
+        }
+    }
+}
+
+/// An interesting reduction that was found on an old version of the edits
+#[derive(Debug)]
+pub(super) struct Stale {
+    pub(super) ptask: PrioritizedTask,
+    /// The reduction's edits, without the ones it was found on
+    pub(super) edits: Edits,
+    /// The parts of the original text that the edits change
+    pub(super) ranges: Intervals,
+}
+
+/// Stale reductions waiting to be merged into one candidate
+#[derive(Debug, Default)]
+pub(super) struct Merges {
+    /// Whether a thread is checking a merged candidate (and will take the
+    /// waiting reductions next)
+    pub(super) merging: bool,
+    pub(super) waiting: Vec<Stale>,
+}
+
+impl Merges {
+    /// Queue `stale`. If no thread is merging, and another reduction is
+    /// waiting to be merged with, start merging, and return the first batch.
+    pub(super) fn add(&mut self, stale: Stale) -> Option<Vec<Stale>> {
+        self.waiting.push(stale);
+        if self.merging || self.waiting.len() < 2 {
+            return None;
+        }
+        self.merging = true;
+        Some(self.take(&mut Intervals::default()))
+    }
+
+    /// Take all of the waiting reductions, unless a thread is merging
+    pub(super) fn flush(&mut self) -> Vec<Stale> {
+        if self.merging {
+            return Vec::new();
+        }
+        std::mem::take(&mut self.waiting)
+    }
+
+    /// Take waiting reductions that don't conflict with `taken` or each other
+    pub(super) fn take(&mut self, taken: &mut Intervals) -> Vec<Stale> {
+        let mut batch = Vec::new();
+        let mut rest = Vec::new();
+        for stale in self.waiting.drain(..) {
+            if taken.overlaps(&stale.ranges) {
+                rest.push(stale);
+            } else {
+                taken.extend(&stale.ranges);
+                batch.push(stale);
+            }
+        }
+        self.waiting = rest;
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::NodeId;
+    use crate::reduce::task::{Reduction, Task, TaskId};
+
+    #[test]
+    fn test_intervals() {
+        let mut intervals = Intervals::new([10..20, 0..5, 30..40]);
+        assert_eq!(intervals, Intervals(vec![0..5, 10..20, 30..40]));
+        intervals.insert(18..32);
+        assert_eq!(intervals, Intervals(vec![0..5, 10..40]));
+        assert!(intervals.overlaps(&Intervals::new([3..4])));
+        assert!(intervals.overlaps(&Intervals::new([7..8, 39..45])));
+        assert!(intervals.overlaps(&Intervals::new([12..12])));
+        assert!(!intervals.overlaps(&Intervals::new([5..10, 40..50])));
+        assert!(!intervals.overlaps(&Intervals::new([10..10])));
+    }
+
+    #[test]
+    fn test_merges() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        let tree = parser.parse("int x;", None).unwrap();
+        let stale = |id, ranges: &[Range<usize>]| Stale {
+            ptask: PrioritizedTask {
+                task: Task::Reduce(Reduction::Delete(NodeId::new(&tree.root_node()))),
+                id: TaskId { id },
+                priority: 0,
+                rank: 0,
+                tiebreak: 0,
+            },
+            edits: Edits::new(),
+            ranges: Intervals::new(ranges.iter().cloned()),
+        };
+        let ids = |batch: &[Stale]| batch.iter().map(|s| s.ptask.id.get()).collect::<Vec<_>>();
+
+        let mut merges = Merges::default();
+        assert!(merges.add(stale(0, &[0..3])).is_none());
+        // Two stale reductions are merged
+        let batch = merges.add(stale(1, &[4..6])).unwrap();
+        assert_eq!(ids(&batch), vec![0, 1]);
+        assert!(merges.merging);
+        // Others wait for the thread that's merging
+        assert!(merges.add(stale(2, &[0..1])).is_none());
+        assert!(merges.add(stale(3, &[0..2])).is_none());
+        assert!(merges.flush().is_empty());
+        // Conflicting ones are left for the next batch
+        assert_eq!(ids(&merges.take(&mut Intervals::default())), vec![2]);
+        assert_eq!(ids(&merges.waiting), vec![3]);
+        merges.merging = false;
+        assert_eq!(ids(&merges.flush()), vec![3]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::{HashMap, HashSet};
+
+use tree_sitter::Node;
+
+use crate::id::NodeId;
+
+/// Short names that are keywords in at least one supported language
+const KEYWORDS: &[&str] = &[
+    "as", "do", "fn", "if", "in", "is", "or", "and", "box", "dyn", "end", "for", "int", "let",
+    "mod", "new", "nil", "not", "pub", "ref", "try", "use", "var",
+];
+
+pub(crate) fn is_identifier(node: &Node) -> bool {
+    node.child_count() == 0 && (node.kind() == "ident" || node.kind().ends_with("identifier"))
+}
+
+/// The `i`th short name: `a`, `b`, ..., `z`, `aa`, `ab`, ...
+fn short_name(mut i: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'a' + (i % 26) as u8);
+        if i < 26 {
+            break;
+        }
+        i = i / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+pub(super) struct Rename {
+    pub(super) node_ids: Vec<NodeId>,
+    pub(super) with: String,
+    /// Number of bytes this rename would save
+    pub(super) savings: usize,
+}
+
+/// Renamings of each identifier in the tree to a short name that doesn't
+/// already appear in it, in order of first occurrence. The `preferred` names
+/// (e.g., from a template) come first, even if they aren't shorter.
+pub(super) fn renames(root: Node, text: &[u8], preferred: &[String]) -> Vec<Rename> {
+    let mut order = Vec::new();
+    let mut occurrences: HashMap<&[u8], Vec<NodeId>> = HashMap::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if is_identifier(&node) {
+            let name = &text[node.start_byte()..node.end_byte()];
+            occurrences
+                .entry(name)
+                .or_insert_with(|| {
+                    order.push(name);
+                    Vec::new()
+                })
+                .push(NodeId::new(&node));
+        }
+        // Reversed, so that nodes are popped in textual order
+        let children: Vec<Node> = node.children(&mut node.walk()).collect();
+        stack.extend(children.into_iter().rev());
+    }
+
+    let taken: HashSet<&[u8]> = occurrences.keys().copied().collect();
+    let preferred_names: HashSet<&[u8]> = preferred.iter().map(|n| n.as_bytes()).collect();
+    let mut preferred = preferred
+        .iter()
+        .filter(|n| !taken.contains(n.as_bytes()))
+        .cloned();
+    let mut fresh = (0..)
+        .map(short_name)
+        .filter(|n| !taken.contains(n.as_bytes()) && !KEYWORDS.contains(&n.as_str()));
+    let mut renames = Vec::new();
+    for name in order {
+        if preferred_names.contains(name) {
+            // Already renamed
+            continue;
+        }
+        if let Some(with) = preferred.next() {
+            let node_ids = occurrences.remove(name).unwrap_or_default();
+            renames.push(Rename {
+                savings: (name.len().saturating_sub(with.len()) * node_ids.len()).max(1),
+                node_ids,
+                with,
+            });
+            continue;
+        }
+        if name.len() <= 1 {
+            continue;
+        }
+        // There are infinitely many fresh names
+        let with = fresh.next().unwrap();
+        if with.len() >= name.len() {
+            continue;
+        }
+        let node_ids = occurrences.remove(name).unwrap_or_default();
+        renames.push(Rename {
+            savings: (name.len() - with.len()) * node_ids.len(),
+            node_ids,
+            with,
+        });
+    }
+    renames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name(0), "a");
+        assert_eq!(short_name(25), "z");
+        assert_eq!(short_name(26), "aa");
+        assert_eq!(short_name(27), "ab");
+        assert_eq!(short_name(26 + 26 * 26), "aaa");
+    }
+
+    #[test]
+    fn test_preferred() {
+        let text = b"int foo(int bar) { return bar + x; }";
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        let preferred = [String::from("main"), String::from("x")];
+        let renames: Vec<(String, usize)> = renames(tree.root_node(), text, &preferred)
+            .into_iter()
+            .map(|r| (r.with, r.node_ids.len()))
+            .collect();
+        // x is already in the input, so bar gets a short name
+        assert_eq!(
+            renames,
+            vec![(String::from("main"), 1), (String::from("a"), 2)]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Ordering the stages of the pipeline in each round, and the passes to run
+//! in each, see [`Schedule`].
+
+use std::collections::HashMap;
+
+use super::Schedule;
+use crate::pipeline::{Pass, Stage};
+
+/// Verdicts on the candidates of a pass
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct Verdicts {
+    /// Candidates accepted, including those with cached verdicts
+    pub(super) accepted: usize,
+    /// Runs of the check (not counting verdicts found in the cache)
+    pub(super) checks: usize,
+    /// Runs of the check that found the candidate interesting
+    pub(super) interesting: usize,
+}
+
+impl Verdicts {
+    pub(super) fn add(&mut self, other: Verdicts) {
+        self.accepted += other.accepted;
+        self.checks += other.checks;
+        self.interesting += other.interesting;
+    }
+
+    fn rate(&self) -> f64 {
+        self.interesting as f64 / self.checks.max(1) as f64
+    }
+}
+
+/// The last run of a pass of a stage
+#[derive(Clone, Copy, Debug)]
+struct Run {
+    verdicts: Verdicts,
+    /// The number of changes to the input when the run ended, if it found
+    /// nothing
+    idle_at: Option<usize>,
+}
+
+#[derive(Debug)]
+pub(super) struct Scheduler {
+    schedule: Schedule,
+    /// By stage index, then by pass, empty until the stage runs
+    runs: Vec<HashMap<Pass, Run>>,
+    /// Number of stage runs that changed the input
+    changes: usize,
+}
+
+impl Scheduler {
+    pub(super) fn new(schedule: Schedule, stages: usize) -> Self {
+        Scheduler {
+            schedule,
+            runs: vec![HashMap::new(); stages],
+            changes: 0,
+        }
+    }
+
+    /// The indices of the stages to run in the next round, in order, each
+    /// with the passes to run. Until each stage has run once, that's all of
+    /// them in pipeline order.
+    pub(super) fn order(&self, stages: &[Stage]) -> Vec<(usize, Stage)> {
+        if self.schedule == Schedule::Static || self.runs.iter().any(HashMap::is_empty) {
+            return stages.iter().cloned().enumerate().collect();
+        }
+        let mut order: Vec<(usize, Stage, f64)> = Vec::new();
+        for (i, stage) in stages.iter().enumerate() {
+            // Passes that found nothing are promoted again once the input
+            // changes
+            let runs: Vec<(Pass, Option<&Run>)> = stage
+                .passes
+                .iter()
+                .map(|p| (*p, self.runs[i].get(p)))
+                .filter(|(_, r)| r.map_or(true, |r| r.idle_at != Some(self.changes)))
+                .collect();
+            if runs.is_empty() {
+                continue;
+            }
+            let rate = runs
+                .iter()
+                .map(|(_, r)| r.map_or(f64::INFINITY, |r| r.verdicts.rate()))
+                .fold(0.0, f64::max);
+            let passes = runs.into_iter().map(|(p, _)| p).collect();
+            order.push((i, Stage::new(passes, stage.max_iterations), rate));
+        }
+        // Stable, so ties keep the pipeline's order
+        order.sort_by(|a, b| b.2.total_cmp(&a.2));
+        order.into_iter().map(|(i, s, _)| (i, s)).collect()
+    }
+
+    /// Record a run of a stage (all of its passes in a round), with the
+    /// verdicts of each pass
+    pub(super) fn ran(
+        &mut self,
+        stage: usize,
+        passes: &[Pass],
+        verdicts: &HashMap<Pass, Verdicts>,
+        changed: bool,
+    ) {
+        if changed {
+            self.changes += 1;
+        }
+        for pass in passes {
+            let verdicts = verdicts.get(pass).copied().unwrap_or_default();
+            self.runs[stage].insert(
+                *pass,
+                Run {
+                    verdicts,
+                    idle_at: (verdicts.accepted == 0).then_some(self.changes),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verdicts(pass: Pass, accepted: usize, checks: usize) -> HashMap<Pass, Verdicts> {
+        HashMap::from([(
+            pass,
+            Verdicts {
+                accepted,
+                checks,
+                interesting: accepted,
+            },
+        )])
+    }
+
+    fn indices(order: &[(usize, Stage)]) -> Vec<usize> {
+        order.iter().map(|(i, _)| *i).collect()
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let stages = [
+            Stage::new(vec![Pass::Delete], None),
+            Stage::new(vec![Pass::Rename], None),
+            Stage::new(vec![Pass::Tokens], None),
+        ];
+        let mut s = Scheduler::new(Schedule::Adaptive, 3);
+        assert_eq!(indices(&s.order(&stages)), vec![0, 1, 2]);
+        s.ran(0, &[Pass::Delete], &verdicts(Pass::Delete, 1, 10), true);
+        s.ran(1, &[Pass::Rename], &verdicts(Pass::Rename, 0, 5), false);
+        assert_eq!(indices(&s.order(&stages)), vec![0, 1, 2]);
+        s.ran(2, &[Pass::Tokens], &verdicts(Pass::Tokens, 5, 10), true);
+        // Stage 1 found nothing, but stage 2 changed the input since
+        assert_eq!(indices(&s.order(&stages)), vec![2, 0, 1]);
+        s.ran(2, &[Pass::Tokens], &verdicts(Pass::Tokens, 0, 3), false);
+        s.ran(0, &[Pass::Delete], &verdicts(Pass::Delete, 0, 4), false);
+        s.ran(1, &[Pass::Rename], &verdicts(Pass::Rename, 0, 2), false);
+        assert!(s.order(&stages).is_empty());
+
+        let mut s = Scheduler::new(Schedule::Static, 2);
+        s.ran(0, &[Pass::Delete], &HashMap::new(), false);
+        s.ran(1, &[Pass::Rename], &verdicts(Pass::Rename, 1, 1), false);
+        assert_eq!(indices(&s.order(&stages[..2])), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_passes() {
+        let stages = [Stage::new(vec![Pass::Delete, Pass::Hoist], None)];
+        let mut s = Scheduler::new(Schedule::Adaptive, 1);
+        let mut v = verdicts(Pass::Delete, 3, 10);
+        v.insert(Pass::Hoist, Verdicts::default());
+        s.ran(0, &[Pass::Delete, Pass::Hoist], &v, true);
+        // Hoisting found nothing, and is left out until the input changes
+        assert_eq!(
+            s.order(&stages),
+            vec![(0, Stage::new(vec![Pass::Delete], None))]
+        );
+        s.ran(0, &[Pass::Delete], &verdicts(Pass::Delete, 1, 5), true);
+        assert_eq!(s.order(&stages), vec![(0, stages[0].clone())]);
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use tracing::warn;
+
+use crate::jobserver::{Jobserver, Token};
+
+/// A counting semaphore, to limit how many interestingness checks run at once
+#[derive(Debug)]
+pub(super) struct Slots {
+    free: Mutex<usize>,
+    freed: Condvar,
+    /// Checks past the first also need a token from the jobserver
+    jobserver: Option<Arc<Jobserver>>,
+    /// Whether no check is using this process's own (implicit) token
+    implicit: AtomicBool,
+}
+
+/// A held slot, released on drop
+pub(super) struct Slot<'a> {
+    slots: &'a Slots,
+    /// Whether this slot uses the implicit token
+    implicit: bool,
+    _token: Option<Token<'a>>,
+}
+
+impl Slots {
+    pub(super) fn new(n: usize, jobserver: Option<Arc<Jobserver>>) -> Self {
+        Slots {
+            free: Mutex::new(std::cmp::max(1, n)),
+            freed: Condvar::new(),
+            jobserver,
+            implicit: AtomicBool::new(true),
+        }
+    }
+
+    pub(super) fn acquire(&self) -> Result<Slot<'_>, PoisonError<MutexGuard<'_, usize>>> {
+        let mut free = self.free.lock()?;
+        while *free == 0 {
+            free = self.freed.wait(free)?;
+        }
+        *free -= 1;
+        drop(free);
+        let Some(jobserver) = &self.jobserver else {
+            return Ok(self.slot(false, None));
+        };
+        if self.implicit.swap(false, Ordering::AcqRel) {
+            return Ok(self.slot(true, None));
+        }
+        match jobserver.acquire() {
+            Ok(token) => Ok(self.slot(false, Some(token))),
+            Err(e) => {
+                warn!("Failed to get a token from the jobserver: {}", e);
+                Ok(self.slot(false, None))
+            }
+        }
+    }
+
+    fn slot(&self, implicit: bool, token: Option<Token<'_>>) -> Slot<'_> {
+        Slot {
+            slots: self,
+            implicit,
+            _token: token,
+        }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if self.implicit {
+            self.slots.implicit.store(true, Ordering::Release);
+        }
+        let mut free = self.slots.free.lock().unwrap_or_else(|e| e.into_inner());
+        *free += 1;
+        self.slots.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slots() {
+        let slots = Slots::new(2, None);
+        let a = slots.acquire().unwrap();
+        let _b = slots.acquire().unwrap();
+        assert_eq!(*slots.free.lock().unwrap(), 0);
+        drop(a);
+        assert_eq!(*slots.free.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use tree_sitter::{Node, Query, QueryCursor, QueryError, Tree};
+
+use crate::id::NodeId;
+
+/// A region of the input that must not be changed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Preserve {
+    /// Byte offsets into the original input
+    Bytes(Range<usize>),
+    /// 1-indexed lines of the original input, inclusive
+    Lines(usize, usize),
+    /// Nodes captured by a tree-sitter query (in any pass)
+    Query(String),
+}
+
+impl Preserve {
+    /// Byte range of the region in `text`, unless it's a query
+    pub(super) fn bytes(&self, text: &[u8]) -> Option<Range<usize>> {
+        match self {
+            Preserve::Bytes(r) => Some(r.start.min(text.len())..r.end.min(text.len())),
+            Preserve::Lines(first, last) => {
+                let mut line_starts = std::iter::once(0).chain(
+                    text.iter()
+                        .enumerate()
+                        .filter(|(_, b)| **b == b'\n')
+                        .map(|(i, _)| i + 1),
+                );
+                let start = line_starts
+                    .nth(first.saturating_sub(1))
+                    .unwrap_or(text.len());
+                let end = line_starts
+                    .nth(last.saturating_sub(*first))
+                    .unwrap_or(text.len());
+                Some(start..end.max(start))
+            }
+            Preserve::Query(_) => None,
+        }
+    }
+}
+
+/// Restrictions on which nodes may be reduced, from tree-sitter queries and
+/// preserved regions.
+#[derive(Debug, Default)]
+pub(super) struct Targets {
+    /// Captures of the `--only-query` and their descendants, if any
+    only: Option<HashSet<NodeId>>,
+    /// Captures of the `--skip-query`, nodes overlapping preserved ranges,
+    /// their ancestors, and their descendants
+    frozen: HashSet<NodeId>,
+}
+
+fn captures<'a>(tree: &'a Tree, text: &[u8], query: &str) -> Result<Vec<Node<'a>>, QueryError> {
+    let query = Query::new(tree.language(), query)?;
+    let mut cursor = QueryCursor::new();
+    Ok(cursor
+        .matches(&query, tree.root_node(), text)
+        .flat_map(|m| m.captures.iter().map(|c| c.node).collect::<Vec<_>>())
+        .collect())
+}
+
+fn add_descendants(node: Node, set: &mut HashSet<NodeId>) {
+    let mut stack = vec![node];
+    while let Some(n) = stack.pop() {
+        set.insert(NodeId::new(&n));
+        stack.extend(n.children(&mut n.walk()));
+    }
+}
+
+impl Targets {
+    pub(super) fn new(
+        tree: &Tree,
+        text: &[u8],
+        only: Option<&str>,
+        skip: &[&str],
+        preserved: &[Range<usize>],
+    ) -> Result<Self, QueryError> {
+        let mut targets = Targets::default();
+        if let Some(q) = only {
+            let mut allowed = HashSet::new();
+            for node in captures(tree, text, q)? {
+                add_descendants(node, &mut allowed);
+            }
+            targets.only = Some(allowed);
+        }
+        for q in skip {
+            for node in captures(tree, text, q)? {
+                add_descendants(node, &mut targets.frozen);
+                let mut parent = node.parent();
+                while let Some(p) = parent {
+                    targets.frozen.insert(NodeId::new(&p));
+                    parent = p.parent();
+                }
+            }
+        }
+        for range in preserved {
+            targets.freeze_range(tree.root_node(), range);
+        }
+        Ok(targets)
+    }
+
+    fn freeze_range(&mut self, node: Node, range: &Range<usize>) {
+        if node.start_byte() >= range.end || node.end_byte() <= range.start {
+            return;
+        }
+        if range.start <= node.start_byte() && node.end_byte() <= range.end {
+            add_descendants(node, &mut self.frozen);
+            return;
+        }
+        self.frozen.insert(NodeId::new(&node));
+        for child in node.children(&mut node.walk()) {
+            self.freeze_range(child, range);
+        }
+    }
+
+    /// Whether a reduction may change these nodes
+    pub(super) fn allows(&self, node_ids: &[NodeId]) -> bool {
+        node_ids.iter().all(|id| {
+            !self.frozen.contains(id) && self.only.as_ref().map(|o| o.contains(id)).unwrap_or(true)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        let text = "// keep\nint f() { return 0; }\nint x;";
+        let tree = parser.parse(text, None).unwrap();
+        let root = tree.root_node();
+        let targets = Targets::new(
+            &tree,
+            text.as_bytes(),
+            Some("(function_definition body: (_) @body)"),
+            &["(comment) @keep"],
+            &[],
+        )
+        .unwrap();
+        let id = |i| NodeId::new(&root.child(i).unwrap());
+        let body = root.child(1).unwrap().child_by_field_name("body").unwrap();
+        assert!(!targets.allows(&[NodeId::new(&root)]));
+        assert!(!targets.allows(&[id(0)]));
+        assert!(!targets.allows(&[id(1)]));
+        assert!(!targets.allows(&[id(2)]));
+        assert!(targets.allows(&[NodeId::new(&body.child(1).unwrap())]));
+    }
+
+    #[test]
+    fn test_preserve() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        let text = "int x;\nint y;\nint z;\n";
+        let tree = parser.parse(text, None).unwrap();
+        let root = tree.root_node();
+        let range = Preserve::Lines(2, 2).bytes(text.as_bytes()).unwrap();
+        assert_eq!(range, 7..14);
+        let targets = Targets::new(&tree, text.as_bytes(), None, &[], &[range]).unwrap();
+        let id = |i| NodeId::new(&root.child(i).unwrap());
+        assert!(targets.allows(&[id(0)]));
+        assert!(!targets.allows(&[id(1)]));
+        assert!(targets.allows(&[id(2)]));
+    }
+}
@@ -21,11 +21,36 @@ impl TaskId {
 pub enum Reduction {
     Delete(NodeId),
     DeleteAll(Vec<NodeId>),
-    Replace { node_id: NodeId, with: String },
-    // Hoist(NodeId, NodeId),
+    Replace {
+        node_id: NodeId,
+        with: String,
+    },
+    /// Replace a node with one of its descendants
+    Hoist(NodeId, NodeId),
+    /// Replace all occurrences of an identifier
+    Rename {
+        node_ids: Vec<NodeId>,
+        with: String,
+    },
+    /// Several independent deletions, tried all at once
+    Batch(Vec<Reduction>),
     // Delta(NodeId),
 }
 
+impl Reduction {
+    /// The nodes that this reduction edits
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        match self {
+            Reduction::Delete(node_id) => vec![*node_id],
+            Reduction::DeleteAll(node_ids) => node_ids.clone(),
+            Reduction::Replace { node_id, .. } => vec![*node_id],
+            Reduction::Hoist(node_id, _) => vec![*node_id],
+            Reduction::Rename { node_ids, .. } => node_ids.clone(),
+            Reduction::Batch(reductions) => reductions.iter().flat_map(Self::node_ids).collect(),
+        }
+    }
+}
+
 // Someday, this might be able to store Nodes directly:
 // https://github.com/tree-sitter/tree-sitter/issues/1241
 //
@@ -45,6 +70,9 @@ impl Task {
             Task::Reduce(Reduction::Delete(_)) => "delete".to_string(),
             Task::Reduce(Reduction::DeleteAll(_)) => "delete_all".to_string(),
             Task::Reduce(Reduction::Replace { .. }) => "replace".to_string(),
+            Task::Reduce(Reduction::Hoist(..)) => "hoist".to_string(),
+            Task::Reduce(Reduction::Rename { .. }) => "rename".to_string(),
+            Task::Reduce(Reduction::Batch(_)) => "batch".to_string(),
         }
     }
 }
@@ -55,11 +83,15 @@ pub struct PrioritizedTask {
     pub task: Task,
     pub id: TaskId,
     pub priority: usize,
+    /// Compared before `priority`
+    pub rank: usize,
+    /// Compared after `priority`, see [`crate::Config::deterministic`]
+    pub tiebreak: u64,
 }
 
 impl Ord for PrioritizedTask {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.priority.cmp(&other.priority)
+        (self.rank, self.priority, self.tiebreak).cmp(&(other.rank, other.priority, other.tiebreak))
     }
 }
 
@@ -0,0 +1,83 @@
+use std::ops::Range;
+
+use tree_sitter::Node;
+
+/// The leaves of the tree in textual order, treating string literals as
+/// leaves.
+pub(super) fn tokens(root: Node) -> Vec<Node> {
+    let mut leaves = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.child_count() == 0 || node.kind().contains("string") {
+            leaves.push(node);
+        } else {
+            let children: Vec<Node> = node.children(&mut node.walk()).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    leaves
+}
+
+/// What to replace a gap between tokens with, if it's whitespace that's
+/// longer than one space or newline
+fn collapse(gap: &[u8]) -> Option<u8> {
+    if gap.is_empty() || !gap.iter().all(u8::is_ascii_whitespace) {
+        return None;
+    }
+    let c = if gap.contains(&b'\n') { b'\n' } else { b' ' };
+    (gap != [c]).then_some(c)
+}
+
+/// Replace each run of whitespace between tokens with a single space or
+/// newline. Also returns the ranges of `text` that are kept, and where they
+/// are in the result.
+pub(super) fn collapse_whitespace(
+    root: Node,
+    text: &[u8],
+) -> (Vec<u8>, Vec<(Range<usize>, Range<usize>)>) {
+    let mut gaps = Vec::new();
+    let mut pos = 0;
+    for token in tokens(root) {
+        gaps.push(pos..token.start_byte());
+        pos = token.end_byte();
+    }
+    gaps.push(pos..text.len());
+    let mut out = Vec::with_capacity(text.len());
+    let mut retained = Vec::new();
+    let mut pos = 0;
+    for gap in gaps {
+        let Some(c) = collapse(&text[gap.clone()]) else {
+            continue;
+        };
+        if pos < gap.start {
+            retained.push((pos..gap.start, out.len()..out.len() + gap.start - pos));
+            out.extend_from_slice(&text[pos..gap.start]);
+        }
+        out.push(c);
+        pos = gap.end;
+    }
+    if pos < text.len() {
+        retained.push((pos..text.len(), out.len()..out.len() + text.len() - pos));
+        out.extend_from_slice(&text[pos..]);
+    }
+    (out, retained)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapse_whitespace() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        let text = "int   x ;\n\n\nchar *y = \"  \";\n";
+        let tree = parser.parse(text, None).unwrap();
+        let (collapsed, retained) = collapse_whitespace(tree.root_node(), text.as_bytes());
+        assert_eq!(
+            std::str::from_utf8(&collapsed).unwrap(),
+            "int x ;\nchar *y = \"  \";\n"
+        );
+        assert_eq!(retained, vec![(0..3, 0..3), (6..9, 4..7), (12..28, 8..24)]);
+    }
+}
@@ -0,0 +1,595 @@
+//! A builder for running reductions from other programs.
+//!
+//! ```no_run
+//! use treereduce::{FnCheck, Reducer};
+//!
+//! # fn main() -> Result<(), treereduce::ReducerError> {
+//! let check = FnCheck::new(|input: &[u8]| input.windows(4).any(|w| w == b"main"));
+//! let reduced = Reducer::new(
+//!     tree_sitter_c::language(),
+//!     tree_sitter_c::NODE_TYPES,
+//!     "int x; int main() { return 0; }",
+//!     check,
+//! )
+//! .jobs(4)
+//! .run()?;
+//! println!("{}", String::from_utf8_lossy(&reduced.text));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tree_sitter::Language;
+
+use crate::async_check::AsyncCheck;
+use crate::check::Check;
+use crate::control::Control;
+use crate::jobserver::Jobserver;
+use crate::node_types::NodeTypes;
+use crate::observer::{Observer, Observers};
+use crate::original::Original;
+use crate::pipeline::Pipeline;
+use crate::reduce::{
+    self, Algorithm, Config, Injections, Metric, MultiPassReductionError, Preserve, Priority,
+    Schedule,
+};
+use crate::stats::Stats;
+use crate::transform::{Transform, Transforms};
+
+/// Don't try reductions smaller than this many bytes
+pub const DEFAULT_MIN_REDUCTION: usize = 2;
+/// Run each stage of the pipeline at most this many times
+pub const DEFAULT_NUM_PASSES: usize = 2;
+
+#[derive(Debug, Error)]
+pub enum ReducerError {
+    #[error("Invalid node types")]
+    NodeTypes(#[from] serde_json::Error),
+    #[error("Failed to set the parser's language")]
+    Language(#[from] tree_sitter::LanguageError),
+    #[error("Failed to parse the input")]
+    Parse,
+    #[error("Reduction failed")]
+    Reduction(#[from] MultiPassReductionError),
+}
+
+/// The result of [`Reducer::run`]
+#[derive(Debug)]
+pub struct Reduced {
+    pub text: Vec<u8>,
+    pub tree: tree_sitter::Tree,
+    pub stats: Stats,
+}
+
+/// Configures and runs a reduction
+#[derive(Clone, Debug)]
+pub struct Reducer<T> {
+    language: Language,
+    node_types: String,
+    source: Vec<u8>,
+    config: Config<T>,
+    max_passes: Option<usize>,
+    max_time: Option<Duration>,
+    target_size: Option<usize>,
+    max_checks: Option<usize>,
+    max_total_passes: Option<usize>,
+}
+
+impl<T> Reducer<T> {
+    /// Reduce `source`, which is parsed with `language`. `node_types` is the
+    /// contents of the grammar's `node-types.json`.
+    pub fn new(language: Language, node_types: &str, source: impl Into<Vec<u8>>, check: T) -> Self {
+        Reducer {
+            language,
+            node_types: node_types.to_string(),
+            source: source.into(),
+            config: Config {
+                check,
+                delete_non_optional: false,
+                fixpoint: false,
+                jobs: std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1),
+                oracle_jobs: 0,
+                jobserver: None,
+                min_reduction: DEFAULT_MIN_REDUCTION,
+                batch: 1,
+                only_query: None,
+                pipeline: Pipeline::default(),
+                priority: Priority::default(),
+                preserve: Vec::new(),
+                keep_comments: None,
+                names: Vec::new(),
+                replacements: HashMap::new(),
+                weights: HashMap::new(),
+                skip_query: None,
+                observers: Observers::default(),
+                transforms: Transforms::default(),
+                resume: None,
+                control: Arc::default(),
+                no_new_errors: false,
+                one_minimal: false,
+                verify_passes: false,
+                deterministic: None,
+                bisect: false,
+                algorithm: Algorithm::default(),
+                schedule: Schedule::default(),
+                metric: Metric::default(),
+                injections: None,
+            },
+            max_passes: Some(DEFAULT_NUM_PASSES),
+            max_time: None,
+            target_size: None,
+            max_checks: None,
+            max_total_passes: None,
+        }
+    }
+
+    /// Number of worker threads; the default is the available parallelism
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.config.jobs = jobs.max(1);
+        self
+    }
+
+    /// Maximum number of concurrent checks; the default is the number of jobs
+    pub fn oracle_jobs(mut self, oracle_jobs: usize) -> Self {
+        self.config.oracle_jobs = oracle_jobs;
+        self
+    }
+
+    /// Share CPUs with a make jobserver, taking a token for each concurrent
+    /// check past the first; see [`Jobserver::from_env`]
+    pub fn jobserver(mut self, jobserver: Arc<Jobserver>) -> Self {
+        self.config.jobserver = Some(jobserver);
+        self
+    }
+
+    /// Which passes to run, and in what order
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.config.pipeline = pipeline;
+        self
+    }
+
+    /// Run stages without an iteration limit at most this many times, or
+    /// until they stop making progress if `None`
+    pub fn max_passes(mut self, max_passes: Option<usize>) -> Self {
+        self.max_passes = max_passes;
+        self
+    }
+
+    /// Stop after this long, with the smallest result so far. Checks in
+    /// progress finish first.
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// Stop once the result is at most this many bytes
+    pub fn target_size(mut self, size: usize) -> Self {
+        self.target_size = Some(size);
+        self
+    }
+
+    /// Stop after the check has run this many times, as counted with
+    /// [`Control::count_run`] (see [`Control::limit_checks`])
+    pub fn max_checks(mut self, max_checks: usize) -> Self {
+        self.max_checks = Some(max_checks);
+        self
+    }
+
+    /// Stop after this many passes, in total
+    pub fn max_total_passes(mut self, max_total_passes: usize) -> Self {
+        self.max_total_passes = Some(max_total_passes);
+        self
+    }
+
+    /// Produce the same result on every run, regardless of the number of
+    /// jobs. `seed` breaks ties between reductions of the same size.
+    pub fn deterministic(mut self, seed: u64) -> Self {
+        self.config.deterministic = Some(seed);
+        self
+    }
+
+    /// Before the first round, cut whole lines from the start and end of the
+    /// input by bisection, without regard to its syntax. Fast on huge
+    /// inputs.
+    pub fn bisect(mut self, bisect: bool) -> Self {
+        self.config.bisect = bisect;
+        self
+    }
+
+    /// Re-run the whole pipeline until a round makes no progress
+    pub fn fixpoint(mut self, fixpoint: bool) -> Self {
+        self.config.fixpoint = fixpoint;
+        self
+    }
+
+    /// Reject candidates with more parse errors than the smallest interesting
+    /// one so far, without checking them
+    pub fn no_new_errors(mut self, no_new_errors: bool) -> Self {
+        self.config.no_new_errors = no_new_errors;
+        self
+    }
+
+    /// Finally, make sure that deleting any one remaining node makes the
+    /// result uninteresting
+    pub fn one_minimal(mut self, one_minimal: bool) -> Self {
+        self.config.one_minimal = one_minimal;
+        self
+    }
+
+    /// After each pass, check the result again (bypassing the cache), and
+    /// back out of the pass's edits until it's interesting
+    pub fn verify_passes(mut self, verify_passes: bool) -> Self {
+        self.config.verify_passes = verify_passes;
+        self
+    }
+
+    /// Also try deleting nodes that the grammar doesn't mark as optional
+    pub fn delete_non_optional(mut self, delete_non_optional: bool) -> Self {
+        self.config.delete_non_optional = delete_non_optional;
+        self
+    }
+
+    /// Don't try reductions smaller than this many bytes
+    pub fn min_reduction(mut self, min_reduction: usize) -> Self {
+        self.config.min_reduction = min_reduction;
+        self
+    }
+
+    /// Try up to this many independent deletions at once
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.config.batch = batch.max(1);
+        self
+    }
+
+    /// How to search for reductions. [`Algorithm::Hdd`] ignores the
+    /// pipeline, batching, and the number of jobs.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.config.algorithm = algorithm;
+        self
+    }
+
+    /// How to order the stages of the pipeline in each round
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.config.schedule = schedule;
+        self
+    }
+
+    /// What to minimize: only candidates that are smaller by `metric` are
+    /// accepted
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.config.metric = metric;
+        self
+    }
+
+    /// Afterwards, reduce regions of the input in other languages with their
+    /// own grammars
+    pub fn injections(mut self, injections: Injections) -> Self {
+        self.config.injections = Some(injections);
+        self
+    }
+
+    /// Scale the priority (size) of reductions of nodes of kind `kind` by
+    /// `weight`, e.g., by 0 to try them only after all others
+    pub fn weight(mut self, kind: impl Into<String>, weight: f64) -> Self {
+        self.config.weights.insert(kind.into(), weight);
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.config.priority = priority;
+        self
+    }
+
+    /// Never change this region
+    pub fn preserve(mut self, region: Preserve) -> Self {
+        self.config.preserve.push(region);
+        self
+    }
+
+    /// Never delete comments (or docstrings) matching `regex`
+    pub fn keep_comments(mut self, regex: regex::Regex) -> Self {
+        self.config.keep_comments = Some(regex);
+        self
+    }
+
+    /// Have the rename pass try this name first, before short ones
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.config.names.push(name.into());
+        self
+    }
+
+    /// Only reduce nodes captured by this tree-sitter query
+    pub fn only_query(mut self, query: impl Into<String>) -> Self {
+        self.config.only_query = Some(query.into());
+        self
+    }
+
+    /// Never change nodes captured by this tree-sitter query
+    pub fn skip_query(mut self, query: impl Into<String>) -> Self {
+        self.config.skip_query = Some(query.into());
+        self
+    }
+
+    /// Nodes of each kind (or its subtypes) may be replaced by these texts
+    pub fn replacements(
+        mut self,
+        replacements: HashMap<&'static str, &'static [&'static str]>,
+    ) -> Self {
+        self.config.replacements = replacements;
+        self
+    }
+
+    /// Notify `observer` of the reduction's progress
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.config.observers.push(observer);
+        self
+    }
+
+    /// Also try the replacements proposed by `transform`
+    pub fn transform(mut self, transform: Arc<dyn Transform>) -> Self {
+        self.config.transforms.push(transform);
+        self
+    }
+
+    /// Pause, skip passes, or stop the reduction from another thread. The
+    /// limits set with this builder (e.g., [`Reducer::max_checks`]) are
+    /// applied to `control` when the reduction starts.
+    pub fn control(mut self, control: Arc<Control>) -> Self {
+        self.config.control = control;
+        self
+    }
+
+    pub fn config(&self) -> &Config<T> {
+        &self.config
+    }
+
+    /// For options without a builder method
+    pub fn config_mut(&mut self) -> &mut Config<T> {
+        &mut self.config
+    }
+
+    /// Apply the limits to the control, and parse the source
+    fn start(&mut self) -> Result<(NodeTypes, Original), ReducerError> {
+        let control = &self.config.control;
+        if let Some(t) = self.max_time {
+            control.stop_at(Instant::now() + t);
+        }
+        if let Some(size) = self.target_size {
+            control.stop_at_size(size);
+        }
+        if let Some(checks) = self.max_checks {
+            control.limit_checks(checks);
+        }
+        if let Some(passes) = self.max_total_passes {
+            control.limit_passes(passes);
+        }
+        let node_types = NodeTypes::new(&self.node_types)?;
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(self.language)?;
+        let source = std::mem::take(&mut self.source);
+        let tree = parser.parse(&source, None).ok_or(ReducerError::Parse)?;
+        Ok((node_types, Original::new(tree, source)))
+    }
+}
+
+impl<T: Check + Clone + Debug + Send + Sync + 'static> Reducer<T> {
+    pub fn run(mut self) -> Result<Reduced, ReducerError> {
+        let (node_types, orig) = self.start()?;
+        let (reduced, stats) = reduce::treereduce_multi_pass(
+            self.language,
+            &node_types,
+            orig,
+            &self.config,
+            self.max_passes,
+        )?;
+        Ok(Reduced {
+            text: reduced.text,
+            tree: reduced.tree,
+            stats,
+        })
+    }
+}
+
+impl<T: AsyncCheck> Reducer<T> {
+    /// Reduce with [`reduce::treereduce_async`], awaiting the check's
+    /// verdicts rather than blocking threads on them. Only some of the
+    /// options apply, see there.
+    pub async fn run_async(mut self) -> Result<Reduced, ReducerError> {
+        let (node_types, orig) = self.start()?;
+        let (reduced, stats) =
+            reduce::treereduce_async(self.language, &node_types, orig, &self.config).await?;
+        Ok(Reduced {
+            text: reduced.text,
+            tree: reduced.tree,
+            stats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::check::FnCheck;
+    use crate::observer::{Attempt, PassDone};
+
+    #[derive(Default)]
+    struct Counts {
+        accepted: AtomicUsize,
+        passes: AtomicUsize,
+    }
+
+    impl Observer for Counts {
+        fn accepted(&self, attempt: &Attempt<'_>) {
+            assert_eq!(attempt.text.len(), attempt.current_size);
+            self.accepted.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn pass_done(&self, _pass: &PassDone<'_>) {
+            self.passes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_reducer() {
+        let src = "int x;\nint main() { int y = 1; return 0; }\nint z;\n";
+        let check = FnCheck::new(|s: &[u8]| s.windows(4).any(|w| w == b"main"));
+        let counts = Arc::new(Counts::default());
+        let reduced = Reducer::new(
+            tree_sitter_c::language(),
+            tree_sitter_c::NODE_TYPES,
+            src,
+            check,
+        )
+        .jobs(2)
+        .observer(counts.clone())
+        .run()
+        .unwrap();
+        assert!(reduced.text.windows(4).any(|w| w == b"main"));
+        assert!(reduced.text.len() < src.len());
+        assert_eq!(reduced.stats.start_size, src.len());
+        assert!(counts.accepted.load(Ordering::Relaxed) > 0);
+        assert_eq!(
+            counts.passes.load(Ordering::Relaxed),
+            reduced.stats.passes.len()
+        );
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let src = "int x;\nint main() { int y = 1; int z = 2; return y; }\nint w;\n";
+        let reduce = |jobs| {
+            let check = FnCheck::new(|s: &[u8]| s.windows(6).any(|w| w == b"return"));
+            Reducer::new(
+                tree_sitter_c::language(),
+                tree_sitter_c::NODE_TYPES,
+                src,
+                check,
+            )
+            .jobs(jobs)
+            .deterministic(1)
+            .run()
+            .unwrap()
+            .text
+        };
+        let expected = reduce(1);
+        assert_eq!(reduce(4), expected);
+        assert_eq!(reduce(4), expected);
+    }
+
+    #[test]
+    fn test_weight() {
+        let src = "int a;\nint f() { return 0; }\n";
+        let reduce = |weight| {
+            let check = FnCheck::new(|s: &[u8]| {
+                s.windows(2).any(|w| w == b"a;") || s.windows(6).any(|w| w == b"return")
+            });
+            let reduced = Reducer::new(
+                tree_sitter_c::language(),
+                tree_sitter_c::NODE_TYPES,
+                src,
+                check,
+            )
+            .jobs(1)
+            .weight("function_definition", weight)
+            .run()
+            .unwrap();
+            String::from_utf8(reduced.text).unwrap()
+        };
+        // The function is bigger, so it's deleted first...
+        assert!(!reduce(1.0).contains("return"));
+        // ...unless it's weighted below the declaration
+        assert!(reduce(0.0).contains("return"));
+    }
+
+    #[test]
+    fn test_hdd() {
+        let src = "int x;\nint main() { int y = 1; return 0; }\nint z;\n";
+        let check = FnCheck::new(|s: &[u8]| s.windows(4).any(|w| w == b"main"));
+        let reduced = Reducer::new(
+            tree_sitter_c::language(),
+            tree_sitter_c::NODE_TYPES,
+            src,
+            check,
+        )
+        .algorithm(Algorithm::Hdd)
+        .run()
+        .unwrap();
+        let text = String::from_utf8(reduced.text).unwrap();
+        assert!(text.contains("main"));
+        assert!(!text.contains("x;") && !text.contains("z;") && !text.contains("y = 1"));
+    }
+
+    #[test]
+    fn test_one_minimal() {
+        let src = "int x;\nint main() { return 0; }\nint z;\n";
+        let check = FnCheck::new(|s: &[u8]| s.windows(4).any(|w| w == b"main"));
+        let reduced = Reducer::new(
+            tree_sitter_c::language(),
+            tree_sitter_c::NODE_TYPES,
+            src,
+            check,
+        )
+        .jobs(1)
+        .pipeline(Pipeline { stages: Vec::new() })
+        .one_minimal(true)
+        .run()
+        .unwrap();
+        let text = String::from_utf8(reduced.text).unwrap();
+        assert!(text.contains("main"));
+        assert!(!text.contains("x;") && !text.contains("z;") && !text.contains("return"));
+    }
+
+    #[test]
+    fn test_verify_passes() {
+        let src = "int x;\nint main() { return 0; }\n";
+        let reduce = |verify| {
+            // Accepts deleting x once, and then changes its mind
+            let drifted = AtomicBool::new(false);
+            let check = FnCheck::new(move |s: &[u8]| {
+                let has_x = s.windows(2).any(|w| w == b"x;");
+                s.windows(4).any(|w| w == b"main")
+                    && (has_x || !drifted.swap(true, Ordering::SeqCst))
+            });
+            let reduced = Reducer::new(
+                tree_sitter_c::language(),
+                tree_sitter_c::NODE_TYPES,
+                src,
+                check,
+            )
+            .jobs(1)
+            .verify_passes(verify)
+            .run()
+            .unwrap();
+            String::from_utf8(reduced.text).unwrap()
+        };
+        assert!(!reduce(false).contains("x;"));
+        let text = reduce(true);
+        assert!(text.contains("x;") && text.contains("main"));
+    }
+
+    #[test]
+    fn test_limits_with_control() {
+        let src = "int x;\nint main() { int y = 1; return 0; }\nint z;\n";
+        let check = FnCheck::new(|s: &[u8]| s.windows(4).any(|w| w == b"main"));
+        let reduced = Reducer::new(
+            tree_sitter_c::language(),
+            tree_sitter_c::NODE_TYPES,
+            src,
+            check,
+        )
+        .max_total_passes(1)
+        .control(Arc::default())
+        .run()
+        .unwrap();
+        assert_eq!(reduced.stats.passes.len(), 1);
+    }
+}
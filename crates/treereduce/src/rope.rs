@@ -0,0 +1,69 @@
+//! A chunked text representation, for rendering candidates without copying
+//! the whole source.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// A sequence of byte chunks, mostly borrowed from the original source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rope<'a> {
+    chunks: Vec<Cow<'a, [u8]>>,
+    len: usize,
+}
+
+impl<'a> Rope<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_borrowed(&mut self, chunk: &'a [u8]) {
+        if !chunk.is_empty() {
+            self.len += chunk.len();
+            self.chunks.push(Cow::Borrowed(chunk));
+        }
+    }
+
+    pub fn push_owned(&mut self, chunk: Vec<u8>) {
+        if !chunk.is_empty() {
+            self.len += chunk.len();
+            self.chunks.push(Cow::Owned(chunk));
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> {
+        self.chunks.iter().map(|c| c.as_ref())
+    }
+
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        for chunk in self.chunks() {
+            w.write_all(chunk)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(self.len);
+        for chunk in self.chunks() {
+            v.extend_from_slice(chunk);
+        }
+        v
+    }
+}
+
+impl<'a> From<&'a [u8]> for Rope<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        let mut rope = Rope::new();
+        rope.push_borrowed(bytes);
+        rope
+    }
+}
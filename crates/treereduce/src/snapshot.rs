@@ -0,0 +1,121 @@
+//! Saving intermediate results of a reduction.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tracing::warn;
+
+use crate::observer::{Attempt, Observer};
+
+#[derive(Debug, Error)]
+#[error("Invalid interval {0}, expected a number of edits (e.g., 10) or seconds (e.g., 30s)")]
+pub struct EveryError(String);
+
+/// How often to save snapshots
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Every {
+    /// Every this many accepted edits
+    Edits(usize),
+    /// After an edit, if the last snapshot is at least this old
+    Duration(Duration),
+}
+
+impl FromStr for Every {
+    type Err = EveryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || EveryError(s.to_string());
+        let every = match s.strip_suffix('s') {
+            Some(secs) => Every::Duration(Duration::from_secs(secs.parse().map_err(|_| err())?)),
+            None => Every::Edits(s.parse().map_err(|_| err())?),
+        };
+        if every == Every::Edits(0) || every == Every::Duration(Duration::ZERO) {
+            return Err(err());
+        }
+        Ok(every)
+    }
+}
+
+#[derive(Debug)]
+struct Progress {
+    accepted: usize,
+    saved: Instant,
+    snapshots: usize,
+}
+
+/// Writes the smallest interesting input so far to numbered files in a
+/// directory
+#[derive(Debug)]
+pub struct Snapshotter {
+    dir: PathBuf,
+    /// Of the files, e.g., that of the input
+    extension: Option<String>,
+    every: Every,
+    progress: Mutex<Progress>,
+}
+
+impl Snapshotter {
+    /// Creates `dir` if it doesn't exist
+    pub fn new(dir: &Path, extension: Option<String>, every: Every) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Snapshotter {
+            dir: dir.to_path_buf(),
+            extension,
+            every,
+            progress: Mutex::new(Progress {
+                accepted: 0,
+                saved: Instant::now(),
+                snapshots: 0,
+            }),
+        })
+    }
+
+    fn path(&self, n: usize) -> PathBuf {
+        let path = self.dir.join(format!("{:04}", n));
+        match &self.extension {
+            Some(e) => path.with_extension(e),
+            None => path,
+        }
+    }
+}
+
+impl Observer for Snapshotter {
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        let mut progress = self.progress.lock().unwrap_or_else(|e| e.into_inner());
+        progress.accepted += 1;
+        let due = match self.every {
+            Every::Edits(n) => progress.accepted % n == 0,
+            Every::Duration(d) => progress.saved.elapsed() >= d,
+        };
+        if !due {
+            return;
+        }
+        progress.snapshots += 1;
+        progress.saved = Instant::now();
+        let path = self.path(progress.snapshots);
+        if let Err(e) = File::create(&path).and_then(|mut f| attempt.text.write_to(&mut f)) {
+            warn!("Failed to write snapshot {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every() {
+        assert_eq!("10".parse::<Every>().unwrap(), Every::Edits(10));
+        assert_eq!(
+            "30s".parse::<Every>().unwrap(),
+            Every::Duration(Duration::from_secs(30))
+        );
+        assert!("0".parse::<Every>().is_err());
+        assert!("ten".parse::<Every>().is_err());
+    }
+}
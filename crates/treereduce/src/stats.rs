@@ -2,19 +2,41 @@ use std::io;
 use std::io::Write;
 use std::time::Duration;
 
+use serde::{Serialize, Serializer};
+
+use crate::provenance::Provenance;
+
+fn secs<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(d.as_secs_f64())
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct Pass {
+    /// The stage of the pipeline (e.g., `delete+hoist`), or `hdd`
+    pub stage: String,
+    #[serde(rename = "seconds", serialize_with = "secs")]
     pub duration: Duration,
     pub start_size: usize,
     pub end_size: usize,
+    /// Number of candidates that were interesting
+    pub accepted: usize,
+    /// Number of candidates that weren't interesting
+    pub rejected: usize,
+    /// Runs of the check (not counting verdicts found in the cache)
+    pub checks: usize,
 }
 
 impl Pass {
     pub fn write_text(&self, w: &mut impl Write) -> io::Result<()> {
         debug_assert!(self.end_size <= self.start_size);
 
+        writeln!(w, "Stage      : {}", self.stage)?;
         writeln!(w, "Duration   : {}s", self.duration.as_secs())?;
         writeln!(w, "Start size : {} bytes", self.start_size)?;
         writeln!(w, "End size   : {} bytes", self.end_size)?;
+        writeln!(w, "Accepted   : {}", self.accepted)?;
+        writeln!(w, "Rejected   : {}", self.rejected)?;
+        writeln!(w, "Checks     : {}", self.checks)?;
         writeln!(
             w,
             "Reduction  : {:.2}%",
@@ -38,11 +60,28 @@ impl Pass {
     }
 }
 
+/// The totals of all passes of one stage
+#[derive(Clone, Debug, Serialize)]
+pub struct StageTotal {
+    pub stage: String,
+    pub passes: usize,
+    #[serde(rename = "seconds", serialize_with = "secs")]
+    pub duration: Duration,
+    pub bytes_removed: usize,
+    /// Runs of the check (not counting verdicts found in the cache)
+    pub checks: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct Stats {
+    #[serde(rename = "seconds", serialize_with = "secs")]
     pub duration: Duration,
     pub start_size: usize,
     pub end_size: usize,
     pub passes: Vec<Pass>,
+    /// Where the bytes of the result came from in the input
+    #[serde(skip)]
+    pub provenance: Provenance,
 }
 
 impl Default for Stats {
@@ -58,7 +97,39 @@ impl Stats {
             start_size: 0,
             end_size: 0,
             passes: Vec::new(),
+            provenance: Provenance::default(),
+        }
+    }
+
+    /// Number of candidates checked in all passes
+    pub fn checks(&self) -> usize {
+        self.passes.iter().map(|p| p.accepted + p.rejected).sum()
+    }
+
+    /// Totals for each stage, in the order they first ran
+    pub fn by_stage(&self) -> Vec<StageTotal> {
+        let mut totals: Vec<StageTotal> = Vec::new();
+        for pass in &self.passes {
+            let i = match totals.iter().position(|t| t.stage == pass.stage) {
+                Some(i) => i,
+                None => {
+                    totals.push(StageTotal {
+                        stage: pass.stage.clone(),
+                        passes: 0,
+                        duration: Duration::ZERO,
+                        bytes_removed: 0,
+                        checks: 0,
+                    });
+                    totals.len() - 1
+                }
+            };
+            let total = &mut totals[i];
+            total.passes += 1;
+            total.duration += pass.duration;
+            total.bytes_removed += pass.start_size.saturating_sub(pass.end_size);
+            total.checks += pass.checks;
         }
+        totals
     }
 
     pub fn write_text(&self, w: &mut impl Write) -> io::Result<()> {
@@ -71,6 +142,25 @@ impl Stats {
             pass.write_text(w)?;
         }
 
+        let totals = self.by_stage();
+        if !totals.is_empty() {
+            writeln!(w)?;
+            writeln!(w, "By stage")?;
+            writeln!(w, "--------")?;
+            let width = totals.iter().map(|t| t.stage.len()).max().unwrap_or(0);
+            for t in &totals {
+                writeln!(
+                    w,
+                    "{:width$} : {} bytes in {} checks, {} passes, {}s",
+                    t.stage,
+                    t.bytes_removed,
+                    t.checks,
+                    t.passes,
+                    t.duration.as_secs()
+                )?;
+            }
+        }
+
         writeln!(w)?;
         writeln!(w, "Total")?;
         writeln!(w, "-----")?;
@@ -0,0 +1,223 @@
+//! Recording the edits that a reduction accepted, and replaying them on the
+//! same (or a slightly different) input.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::observer::{Attempt, Observer, PassDone};
+
+/// One accepted edit: replacing `old`, at `start..end` of the previous text,
+/// with `new`. The texts are bytes (JSON arrays), since inputs needn't be
+/// UTF-8.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Step {
+    /// Kind of reduction (see [`Attempt::kind`]), or `pass` for changes made
+    /// between reductions (e.g., collapsing whitespace), or `other`
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub start: usize,
+    pub end: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Step {step} ({description}) doesn't apply: the text it replaces isn't there")]
+    Missing { step: usize, description: String },
+}
+
+/// Back off to the start of a UTF-8 character
+fn char_start(text: &[u8], mut i: usize) -> usize {
+    while i > 0 && i < text.len() && text[i] & 0xc0 == 0x80 {
+        i -= 1;
+    }
+    i
+}
+
+/// The range of `old` that differs from `new`, and what it's replaced with
+fn diff<'a>(old: &[u8], new: &'a [u8]) -> Option<(Range<usize>, &'a [u8])> {
+    if old == new {
+        return None;
+    }
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let prefix = char_start(old, prefix);
+    let max_suffix = std::cmp::min(old.len(), new.len()) - prefix;
+    let mut suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while suffix > 0 && old[old.len() - suffix] & 0xc0 == 0x80 {
+        suffix -= 1;
+    }
+    Some((prefix..old.len() - suffix, &new[prefix..new.len() - suffix]))
+}
+
+/// Writes a [`Step`] for each accepted edit to a file, one JSON object per
+/// line
+#[derive(Debug)]
+pub struct Tracer {
+    /// The file, and the text after the last step
+    state: Mutex<(BufWriter<File>, Vec<u8>)>,
+}
+
+impl Tracer {
+    /// Trace a reduction of `text` to `path`
+    pub fn new(path: &Path, text: Vec<u8>) -> io::Result<Self> {
+        Ok(Tracer {
+            state: Mutex::new((BufWriter::new(File::create(path)?), text)),
+        })
+    }
+
+    fn record(&self, kind: &str, description: &str, text: &[u8]) -> io::Result<()> {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (out, last) = &mut *guard;
+        let Some((range, new)) = diff(last, text) else {
+            return Ok(());
+        };
+        let step = Step {
+            kind: kind.to_string(),
+            description: description.to_string(),
+            start: range.start,
+            end: range.end,
+            old: last[range].to_vec(),
+            new: new.to_vec(),
+        };
+        serde_json::to_writer(&mut *out, &step)?;
+        writeln!(out)?;
+        *last = text.to_vec();
+        Ok(())
+    }
+
+    fn record_or_warn(&self, kind: &str, description: &str, text: &[u8]) {
+        if let Err(e) = self.record(kind, description, text) {
+            warn!("Failed to write trace: {}", e);
+        }
+    }
+
+    /// Record the changes up to the result of the reduction, e.g., by
+    /// passes that aren't observed, and flush the file
+    pub fn finish(&self, result: &[u8]) -> io::Result<()> {
+        self.record("other", "", result)?;
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        guard.0.flush()
+    }
+}
+
+impl Observer for Tracer {
+    fn accepted(&self, attempt: &Attempt<'_>) {
+        self.record_or_warn(attempt.kind, attempt.description, &attempt.text.to_vec());
+    }
+
+    fn pass_done(&self, pass: &PassDone<'_>) {
+        self.record_or_warn("pass", &pass.stage.to_string(), pass.text);
+    }
+}
+
+/// Read a trace written by a [`Tracer`]
+pub fn load(path: &Path) -> io::Result<Vec<Step>> {
+    fs::read(path)?
+        .split(|b| *b == b'\n')
+        .filter(|l| l.iter().any(|b| !b.is_ascii_whitespace()))
+        .map(|l| Ok(serde_json::from_slice(l)?))
+        .collect()
+}
+
+/// Where `needle` occurs in `text` closest to `near`
+fn nearest(text: &[u8], needle: &[u8], near: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(std::cmp::min(near, text.len()));
+    }
+    text.windows(needle.len())
+        .enumerate()
+        .filter(|(_, w)| *w == needle)
+        .map(|(i, _)| i)
+        .min_by_key(|i| i.abs_diff(near))
+}
+
+/// Apply the steps of a trace to `text`, in order. Each step replaces the
+/// text it recorded at its offset, or else at the nearest place that text
+/// occurs, so that traces still apply to slightly changed inputs.
+pub fn replay(mut text: Vec<u8>, steps: &[Step]) -> Result<Vec<u8>, ReplayError> {
+    for (i, step) in steps.iter().enumerate() {
+        let old = step.old.as_slice();
+        let start = if text.get(step.start..step.start + old.len()) == Some(old) {
+            step.start
+        } else {
+            nearest(&text, old, step.start).ok_or_else(|| ReplayError::Missing {
+                step: i + 1,
+                description: step.description.clone(),
+            })?
+        };
+        text.splice(start..start + old.len(), step.new.iter().copied());
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(old: &[u8], new: &[u8]) -> Step {
+        let (range, new) = diff(old, new).unwrap();
+        Step {
+            kind: String::from("delete"),
+            description: String::new(),
+            start: range.start,
+            end: range.end,
+            old: old[range].to_vec(),
+            new: new.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        assert_eq!(diff(b"abc", b"abc"), None);
+        assert_eq!(diff(b"a b c", b"a c"), Some((2..4, &b""[..])));
+        assert_eq!(
+            diff("é".as_bytes(), "è".as_bytes()),
+            Some((0..2, "è".as_bytes()))
+        );
+
+        let texts: [&[u8]; 3] = [
+            b"int x;\nint y;\nint z;\n",
+            b"int x;\nint z;\n",
+            b"int z;\n",
+        ];
+        let steps: Vec<Step> = texts.windows(2).map(|w| step(w[0], w[1])).collect();
+        let replayed = replay(texts[0].to_vec(), &steps).unwrap();
+        assert_eq!(replayed, texts[2]);
+        // Steps apply to a changed input, where their text is
+        let replayed = replay(b"// new\nint x;\nint y;\nint z;\n".to_vec(), &steps).unwrap();
+        assert_eq!(replayed, b"// new\nint z;\n");
+        assert!(replay(b"int w;\n".to_vec(), &steps).is_err());
+
+        // Bytes that aren't UTF-8 survive a round trip through the file
+        let texts: [&[u8]; 2] = [b"char c = '\xff';\nint x;\n", b"char c = '\xff';\n"];
+        let steps = vec![step(texts[0], texts[1]), step(texts[1], b"\xfe")];
+        let path = std::env::temp_dir().join(format!(
+            "treereduce-test-trace-{}.jsonl",
+            std::process::id()
+        ));
+        let lines: Vec<String> = steps
+            .iter()
+            .map(|s| serde_json::to_string(s).unwrap())
+            .collect();
+        fs::write(&path, lines.join("\n")).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, steps);
+        assert_eq!(replay(texts[0].to_vec(), &loaded).unwrap(), b"\xfe");
+    }
+}
@@ -0,0 +1,96 @@
+//! User-defined reductions, run alongside the built-in ones.
+
+use std::fmt;
+use std::sync::Arc;
+
+use tree_sitter::Node;
+
+/// Proposes replacements for nodes, e.g., replacing C++ template arguments
+/// with `int`.
+///
+/// Each replacement is tried like the built-in ones (largest savings first),
+/// and kept if the result is still interesting.
+pub trait Transform: Send + Sync {
+    /// Used in logs
+    fn name(&self) -> &str;
+
+    /// Candidate replacements for the text of `node`, which is in `text`
+    fn replacements(&self, node: &Node<'_>, text: &[u8]) -> Vec<String>;
+
+    /// Nodes to also delete when deleting `node`, e.g., the uses of a
+    /// variable that it declares. If there are any, deleting them all at once
+    /// is tried as well as deleting `node` alone.
+    fn dependents<'tree>(&self, _node: &Node<'tree>, _text: &[u8]) -> Vec<Node<'tree>> {
+        Vec::new()
+    }
+
+    /// Whether the comments pass should delete `node` like a comment (as it
+    /// does nodes whose kind contains `comment`), e.g., a Python docstring
+    fn is_comment(&self, _node: &Node<'_>, _text: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Transforms to apply to each node, in order
+#[derive(Clone, Default)]
+pub struct Transforms(Vec<Arc<dyn Transform>>);
+
+impl Transforms {
+    pub fn push(&mut self, transform: Arc<dyn Transform>) {
+        self.0.push(transform);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Transform> {
+        self.0.iter().map(|t| t.as_ref())
+    }
+}
+
+impl fmt::Debug for Transforms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|t| t.name()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::FnCheck;
+    use crate::reducer::Reducer;
+
+    struct EmptyBodies;
+
+    impl Transform for EmptyBodies {
+        fn name(&self) -> &str {
+            "empty-bodies"
+        }
+
+        fn replacements(&self, node: &Node<'_>, _text: &[u8]) -> Vec<String> {
+            if node.kind() == "compound_statement" {
+                vec![String::from("{}")]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform() {
+        let src = "int main() { int y = 1; return y; }\n";
+        let check = FnCheck::new(|s: &[u8]| {
+            s.windows(4).any(|w| w == b"main") && s.windows(2).any(|w| w == b"{}")
+        });
+        let reduced = Reducer::new(
+            tree_sitter_c::language(),
+            tree_sitter_c::NODE_TYPES,
+            src,
+            check,
+        )
+        .jobs(1)
+        .transform(Arc::new(EmptyBodies))
+        .run()
+        .unwrap();
+        assert!(reduced.text.windows(2).any(|w| w == b"{}"));
+    }
+}
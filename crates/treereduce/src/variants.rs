@@ -0,0 +1,152 @@
+//! Keeping a few of the smallest interesting inputs, not just the smallest.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tree_sitter::{Language, Parser};
+
+use crate::observer::{Attempt, Observer};
+
+/// The kinds of the nodes of the text's parse tree, in order, so that
+/// variants that only differ in their tokens (e.g., whitespace, names, or
+/// literals) count as the same
+fn key(parser: &mut Parser, text: &[u8]) -> Vec<u16> {
+    let mut kinds = Vec::new();
+    let Some(tree) = parser.parse(text, None) else {
+        return kinds;
+    };
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        kinds.push(node.kind_id());
+        let children: Vec<_> = node.children(&mut node.walk()).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    kinds
+}
+
+/// Where to write the `n`th variant of the result in `output`, e.g.,
+/// `out.2.c` for `out.c`
+pub fn numbered(output: &Path, n: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, n),
+    };
+    output.with_file_name(name)
+}
+
+/// Keeps the smallest structurally distinct inputs that a reduction found
+/// interesting, whether or not they were accepted
+pub struct Variants {
+    keep: usize,
+    parser: Mutex<Parser>,
+    /// Smallest first, with their keys
+    variants: Mutex<Vec<(Vec<u8>, Vec<u16>)>>,
+}
+
+impl Variants {
+    /// Keep `keep` variants besides the result, telling them apart by their
+    /// parse trees in `language`
+    pub fn new(keep: usize, language: Language) -> Self {
+        let mut parser = Parser::new();
+        parser
+            .set_language(language)
+            .expect("Error loading language");
+        Variants {
+            keep,
+            parser: Mutex::new(parser),
+            variants: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn key(&self, text: &[u8]) -> Vec<u16> {
+        key(
+            &mut self.parser.lock().unwrap_or_else(|e| e.into_inner()),
+            text,
+        )
+    }
+
+    fn add(&self, text: Vec<u8>) {
+        let key = self.key(&text);
+        let mut variants = self.variants.lock().unwrap_or_else(|e| e.into_inner());
+        match variants.iter().position(|(_, k)| *k == key) {
+            Some(i) if variants[i].0.len() <= text.len() => return,
+            // Keep the smallest of the same structure
+            Some(i) => {
+                variants.remove(i);
+            }
+            None => {}
+        }
+        let i = variants.partition_point(|(t, _)| t.len() <= text.len());
+        variants.insert(i, (text, key));
+        // One more, in case the result is among them
+        variants.truncate(self.keep + 1);
+    }
+
+    /// The smallest variants other than `result`, smallest first
+    pub fn variants(&self, result: &[u8]) -> Vec<Vec<u8>> {
+        let key = self.key(result);
+        let variants = self.variants.lock().unwrap_or_else(|e| e.into_inner());
+        variants
+            .iter()
+            .filter(|(_, k)| *k != key)
+            .take(self.keep)
+            .map(|(t, _)| t.clone())
+            .collect()
+    }
+}
+
+impl fmt::Debug for Variants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Variants")
+            .field("keep", &self.keep)
+            .field("variants", &self.variants)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Observer for Variants {
+    fn interesting(&self, attempt: &Attempt<'_>) {
+        self.add(attempt.text.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbered() {
+        assert_eq!(numbered(Path::new("out.c"), 2), Path::new("out.2.c"));
+        assert_eq!(
+            numbered(Path::new("dir/out.tar.gz"), 1),
+            Path::new("dir/out.tar.1.gz")
+        );
+        assert_eq!(
+            numbered(Path::new("treereduce.out"), 3),
+            Path::new("treereduce.3.out")
+        );
+        assert_eq!(numbered(Path::new("out"), 1), Path::new("out.1"));
+    }
+
+    #[test]
+    fn test_variants() {
+        let variants = Variants::new(2, tree_sitter_c::language());
+        for text in [
+            "int x; int y;",
+            "int  x;",
+            "int y = 0;",
+            "int y;",
+            "x;",
+            "int z = 1;",
+        ] {
+            variants.add(text.as_bytes().to_vec());
+        }
+        // `int  x;` and `int y;` have the same structure as the result
+        assert_eq!(
+            variants.variants(b"int x;"),
+            vec![b"x;".to_vec(), b"int y = 0;".to_vec()]
+        );
+    }
+}
@@ -1,7 +1,17 @@
+//! Values that change over a reduction, with version numbers, so that edits
+//! computed from an old version can be detected, and a [`History`] of them
+//! to back out of changes with.
+
+use std::collections::VecDeque;
+
+use crate::edits::Edits;
+
 #[derive(Clone, Debug)]
 pub struct Versioned<T> {
     value: T,
     version: usize,
+    /// The version this one was made from
+    parent: Option<usize>,
 }
 
 impl<T> Versioned<T> {
@@ -13,6 +23,15 @@ impl<T> Versioned<T> {
         &self.value
     }
 
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// The version this one was made from, if any
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
     fn _mutate<F: FnOnce(T) -> T>(mut self, f: F) {
         self.value = f(self.value);
         self.inc();
@@ -30,13 +49,18 @@ impl<T> Versioned<T> {
     }
 
     pub fn new(value: T) -> Self {
-        Versioned { value, version: 0 }
+        Versioned {
+            value,
+            version: 0,
+            parent: None,
+        }
     }
 
     pub fn inc(self) -> Self {
         Versioned {
             value: self.value,
             version: self.version + 1,
+            parent: Some(self.version),
         }
     }
 
@@ -44,14 +68,151 @@ impl<T> Versioned<T> {
         Versioned {
             value,
             version: self.version + 1,
+            parent: Some(self.version),
         }
     }
 
+    /// Whether `other` was made from this version
     pub fn old_version(&self, other: &Versioned<T>) -> bool {
-        self.version + 1 == other.version
+        other.parent == Some(self.version)
     }
 
     fn _same_version(&self, other: &Versioned<T>) -> bool {
         self.version == other.version
     }
 }
+
+/// The last few versions of a value, so that a sequence of changes can be
+/// backed out of.
+///
+/// Version numbers are never reused, even after [`History::rollback`], so a
+/// value made from a version that was backed out of is never mistaken for
+/// one made from the current version.
+#[derive(Clone, Debug)]
+pub struct History<T> {
+    /// Oldest first, never empty
+    versions: VecDeque<Versioned<T>>,
+    /// Maximum number of versions to keep
+    limit: usize,
+    /// Number of the next version pushed
+    next: usize,
+}
+
+impl<T> History<T> {
+    /// Keep up to `limit` (at least one) versions, starting with `value`
+    pub fn new(value: Versioned<T>, limit: usize) -> Self {
+        History {
+            next: value.version + 1,
+            versions: VecDeque::from([value]),
+            limit: limit.max(1),
+        }
+    }
+
+    pub fn current(&self) -> &Versioned<T> {
+        // Unwrap: never empty
+        self.versions.back().unwrap()
+    }
+
+    /// Make `value` the current version, with a new version number,
+    /// forgetting the oldest one if there are too many
+    pub fn push(&mut self, mut value: Versioned<T>) {
+        value.version = self.next;
+        self.next += 1;
+        if self.versions.len() == self.limit {
+            self.versions.pop_front();
+        }
+        self.versions.push_back(value);
+    }
+
+    /// The value at `version`, if it's still kept
+    pub fn get(&self, version: usize) -> Option<&T> {
+        self.versions
+            .iter()
+            .find(|v| v.version == version)
+            .map(Versioned::get)
+    }
+
+    /// The versions that are kept, oldest first
+    pub fn versions(&self) -> impl DoubleEndedIterator<Item = &Versioned<T>> {
+        self.versions.iter()
+    }
+
+    /// Back out of the last `n` versions (or as many as are kept, except the
+    /// oldest). Returns the number of versions backed out of.
+    pub fn rollback(&mut self, n: usize) -> usize {
+        let n = std::cmp::min(n, self.versions.len() - 1);
+        self.versions.truncate(self.versions.len() - n);
+        n
+    }
+}
+
+impl History<Edits> {
+    /// The edits made from version `a` to `b`, if both are still kept
+    pub fn diff(&self, a: usize, b: usize) -> Option<Edits> {
+        Some(self.get(b)?.difference(self.get(a)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new(Versioned::new(0), 3);
+        for i in 1..5 {
+            let next = history.current().mutate_clone(|_| i * 10);
+            history.push(next);
+        }
+        assert_eq!(history.current().version(), 4);
+        assert_eq!(history.get(1), None);
+        assert_eq!(history.get(2), Some(&20));
+        assert_eq!(history.rollback(1), 1);
+        assert_eq!(*history.current().get(), 30);
+        assert_eq!(history.rollback(5), 1);
+        assert_eq!(*history.current().get(), 20);
+        // Edits based on the current version apply again
+        let next = history.current().mutate_clone(|_| 0);
+        assert!(history.current().old_version(&next));
+        assert_eq!(
+            history
+                .versions()
+                .map(Versioned::version)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_history_no_reuse() {
+        let mut history = History::new(Versioned::new(0), 4);
+        let next = history.current().mutate_clone(|_| 1);
+        history.push(next);
+        // Made from version 1, which is then backed out of
+        let stale = history.current().mutate_clone(|_| 2);
+        history.rollback(1);
+        let next = history.current().mutate_clone(|_| 3);
+        history.push(next);
+        assert_eq!(history.current().version(), 2);
+        assert!(!history.current().old_version(&stale));
+        assert_eq!(history.get(1), None);
+    }
+
+    #[test]
+    fn test_history_diff() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_c::language()).unwrap();
+        let tree = parser.parse("int x;\nint y;\n", None).unwrap();
+        let root = tree.root_node();
+        let (x, y) = (root.child(0).unwrap(), root.child(1).unwrap());
+        let mut history = History::new(Versioned::new(Edits::new()), 8);
+        let next = history.current().mutate_clone(|e| e.omit(&x));
+        history.push(next);
+        let next = history.current().mutate_clone(|e| e.omit(&y));
+        history.push(next);
+        let diff = history.diff(1, 2).unwrap();
+        assert!(diff.should_omit(&y) && !diff.should_omit(&x));
+        assert!(history.diff(0, 2).unwrap().should_omit(&x));
+        assert!(history.diff(0, 3).is_none());
+    }
+}
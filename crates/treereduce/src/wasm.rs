@@ -0,0 +1,126 @@
+//! Running reductions in a browser (or another WebAssembly host), with an
+//! interestingness test implemented by the host.
+//!
+//! Build for `wasm32-unknown-unknown` with `--no-default-features`, since
+//! that target can't run commands. The host provides the check as
+//! `treereduce_check(ptr, len) -> u32` in the `env` import module, which
+//! returns non-zero if the `len` bytes at `ptr` are interesting. Grammar
+//! crates export a reduction for their language with [`crate::wasm_export`].
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{io, slice};
+
+use tree_sitter::Language;
+
+use crate::check::Check;
+use crate::reducer::{Reducer, ReducerError};
+
+#[link(wasm_import_module = "env")]
+extern "C" {
+    fn treereduce_check(ptr: *const u8, len: usize) -> u32;
+}
+
+/// Calls the host's `treereduce_check`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HostCheck;
+
+impl Check for HostCheck {
+    type State = bool;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        // SAFETY: The host only reads `len` bytes from `ptr`
+        Ok(unsafe { treereduce_check(stdin.as_ptr(), stdin.len()) } != 0)
+    }
+
+    fn cancel(&self, _state: Self::State) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        Ok(Some(*state))
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        Ok(state)
+    }
+}
+
+/// Reduce `source` on the current thread, using [`HostCheck`]
+pub fn reduce(
+    language: Language,
+    node_types: &str,
+    source: &[u8],
+) -> Result<Vec<u8>, ReducerError> {
+    Ok(Reducer::new(language, node_types, source, HostCheck)
+        .jobs(1)
+        .run()?
+        .text)
+}
+
+/// Length of the last buffer returned by [`reduce_raw`]
+static OUTPUT_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Like [`reduce`], but for exporting to the host. Returns a buffer of
+/// [`treereduce_output_len`] bytes, to be freed with [`treereduce_free`], or
+/// null on failure.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes.
+pub unsafe fn reduce_raw(
+    language: Language,
+    node_types: &str,
+    ptr: *const u8,
+    len: usize,
+) -> *mut u8 {
+    let source = slice::from_raw_parts(ptr, len);
+    match reduce(language, node_types, source) {
+        Err(_) => std::ptr::null_mut(),
+        Ok(text) => {
+            let mut text = text.into_boxed_slice();
+            OUTPUT_LEN.store(text.len(), Ordering::Relaxed);
+            let out = text.as_mut_ptr();
+            std::mem::forget(text);
+            out
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn treereduce_output_len() -> usize {
+    OUTPUT_LEN.load(Ordering::Relaxed)
+}
+
+/// Allocate `len` bytes, e.g., for the host to write the input into
+#[no_mangle]
+pub extern "C" fn treereduce_alloc(len: usize) -> *mut u8 {
+    let mut buf = vec![0u8; len].into_boxed_slice();
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Free a buffer from [`treereduce_alloc`] or [`reduce_raw`]
+///
+/// # Safety
+///
+/// `ptr` and `len` must be from one of those functions, and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn treereduce_free(ptr: *mut u8, len: usize) {
+    drop(Box::from_raw(slice::from_raw_parts_mut(ptr, len)));
+}
+
+/// Export `$name(ptr, len) -> *mut u8` to the host, which reduces the input
+/// with the given grammar (see [`reduce_raw`]).
+#[macro_export]
+macro_rules! wasm_export {
+    ($name:ident, $language:expr, $node_types:expr) => {
+        /// # Safety
+        ///
+        /// `ptr` must point to `len` readable bytes.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(ptr: *const u8, len: usize) -> *mut u8 {
+            $crate::wasm::reduce_raw($language, $node_types, ptr, len)
+        }
+    };
+}
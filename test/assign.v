@@ -0,0 +1,10 @@
+// RUN: treereduce-verilog -q -j 1 -o - -s %s -- match-c 'assign' 2>&1 | FileCheck %s
+
+module m(input a, output b);
+// CHECK-NOT: reg
+  reg c;
+// CHECK: assign
+  assign b = a;
+// CHECK-NOT: always
+  always @(posedge a) c <= a;
+endmodule
@@ -0,0 +1,7 @@
+// RUN: treereduce-typescript -q -j 1 -o - -s %s -- match-c '<b>' 2>&1 | FileCheck %s
+
+// CHECK-NOT: const a
+const a = 1;
+// CHECK: <b>
+const e = <div><b>hi</b><i>no</i></div>;
+// CHECK-NOT: <i>
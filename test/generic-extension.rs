@@ -0,0 +1,9 @@
+// RUN: treereduce -q -j 1 -o - -s %s -- match-c 'static A:' 2>&1 | FileCheck %s
+
+// The language is detected from the `.rs` extension
+
+// CHECK-NOT: fn
+fn f() {}
+// CHECK: static A: () = LongStructTypeName;
+static A: LongStructTypeName = LongStructTypeName;
+// CHECK-EMPTY:
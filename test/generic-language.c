@@ -0,0 +1,9 @@
+// RUN: treereduce --language c -q -j 1 -o - -- match-c 'puts' < %s 2>&1 | FileCheck %s
+
+// The input is on stdin, so only --language says that it's C
+
+// CHECK-NOT: int x
+int x = 1;
+// CHECK: puts
+int main(void) { puts("hi"); x = 2; return 0; }
+// CHECK-NOT: return
@@ -0,0 +1,12 @@
+# RUN: treereduce-python -q -j 1 -o - -s %s -- match-py 'if x' 2>&1 | FileCheck %s
+
+import os
+# CHECK-NOT: import
+# CHECK: if x:
+if x:
+    y = os.getcwd()
+    print(y)
+else:
+    z = 3
+# CHECK-NOT: print
+# CHECK-NOT: else
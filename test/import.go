@@ -0,0 +1,12 @@
+// RUN: treereduce-go -q -j 1 -o - -s %s -- match-go fmt 'func main' 2>&1 | FileCheck %s
+
+package main
+
+// CHECK-NOT: fmt
+import "fmt"
+
+// CHECK: func main()
+func main() {
+	fmt.Println("hello")
+}
+// CHECK-NOT: fmt
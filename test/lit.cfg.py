@@ -2,4 +2,5 @@ import lit.formats
 
 config.name = "treereduce"
 config.test_format = lit.formats.ShTest(True)
-config.suffixes = [".c", ".dl", ".rs"]
+config.suffixes = [".c", ".dl", ".go", ".json", ".py", ".rs", ".sql", ".toml", ".ts", ".tsx", ".v", ".yaml"]
+config.excludes = ["lit.cfg.py"]
@@ -0,0 +1,7 @@
+-- RUN: treereduce-sql -q -j 1 -o - -s %s -- match-sql 'FROM t' 2>&1 | FileCheck %s
+
+-- CHECK-NOT: FROM u
+SELECT c FROM u;
+-- CHECK: FROM t
+SELECT a, b + 1 FROM t WHERE a > 2 ORDER BY b;
+-- CHECK-NOT: ORDER
@@ -0,0 +1,13 @@
+// RUN: treereduce-typescript -q -j 1 -o - -s %s -- match-c 'let x: number' 2>&1 | FileCheck %s
+
+// CHECK-NOT: interface
+interface Point {
+  x: number;
+  y: number;
+}
+// CHECK: let x: number
+let x: number = 1;
+// CHECK-NOT: function
+function f(p: Point): number {
+  return p.x;
+}